@@ -0,0 +1,16 @@
+use embed_manifest::{embed_manifest, empty_manifest, manifest::{AssemblyIdentity, Setting, SupportedOS}};
+
+fn main() {
+    if std::env::var_os("CARGO_CFG_WINDOWS").is_some() {
+        // 260 文字を超えるパスのファイルも開けるようにする
+        // コモンコントロール v6 を使い、ボタンやリストなどを今の Windows の見た目で描く
+        // Windows 10 以降と宣言しないと、Windows 11 でも古いバージョンとして扱われる
+        let manifest = empty_manifest()
+            .name("MetaView")
+            .dependency(AssemblyIdentity::new("Microsoft.Windows.Common-Controls", [6, 0, 0, 0], 0x6595b64144ccf1df))
+            .supported_os(SupportedOS::Windows7..=SupportedOS::Windows10)
+            .long_path_aware(Setting::Enabled);
+        embed_manifest(manifest).expect("unable to embed manifest file");
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
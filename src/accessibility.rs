@@ -0,0 +1,46 @@
+// スクリーンリーダー (ナレーター, NVDA など) 向けの名前と、読み込みやエラーの読み上げ
+// Dynamic Annotation (IAccPropServices) で標準のコントロールに UIA のプロパティを付け足す
+
+use std::mem::ManuallyDrop;
+use windows::{
+    core::*,
+    Win32::{
+        Foundation::*,
+        System::Com::*,
+        UI::{Accessibility::*, WindowsAndMessaging::*},
+    },
+};
+
+fn services() -> Result<IAccPropServices> {
+    unsafe { CoCreateInstance(&CAccPropServices, None, CLSCTX_INPROC_SERVER) }
+}
+
+// コントロールの読み上げられる名前を設定する
+pub fn set_name(hwnd: HWND, name: &str) -> Result<()> {
+    let services = services()?;
+    unsafe { services.SetHwndPropStr(hwnd, OBJID_CLIENT.0 as u32, CHILDID_SELF, PROPID_ACC_NAME, &HSTRING::from(name)) }
+}
+
+// 名前が変わったときに読み上げられるようにする (UIA のライブリージョン)
+pub fn make_live_region(hwnd: HWND) -> Result<()> {
+    let services = services()?;
+    let value = VARIANT {
+        Anonymous: VARIANT_0 {
+            Anonymous: ManuallyDrop::new(VARIANT_0_0 {
+                vt: VT_I4,
+                wReserved1: 0,
+                wReserved2: 0,
+                wReserved3: 0,
+                Anonymous: VARIANT_0_0_0 { lVal: Polite.0 },
+            }),
+        },
+    };
+    unsafe { services.SetHwndProp(hwnd, OBJID_CLIENT.0 as u32, CHILDID_SELF, LiveSetting_Property_GUID, &value) }
+}
+
+// make_live_region したコントロールの名前を変えて読み上げてもらう
+pub fn announce(hwnd: HWND, text: &str) {
+    if set_name(hwnd, text).is_ok() {
+        unsafe { NotifyWinEvent(EVENT_OBJECT_LIVEREGIONCHANGED, hwnd, OBJID_CLIENT.0, CHILDID_SELF as i32) };
+    }
+}
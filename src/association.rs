@@ -0,0 +1,156 @@
+// 拡張子の関連付け (現在のユーザーだけ)
+// ProgID と「既定のアプリ」用の Capabilities を登録し、既定にするかどうかは Windows の画面で選んでもらう
+
+use windows::{
+    core::*,
+    Win32::{
+        Foundation::*,
+        System::{Com::*, Registry::HKEY_CURRENT_USER},
+        UI::{Controls::*, Shell::*, WindowsAndMessaging::*},
+    },
+};
+use metaview_core::{delete_tree, delete_value, get_value, set_value};
+use crate::dialog::{self, DialogTemplate};
+use crate::i18n::{tr, Msg};
+
+const EXTENSIONS: &[&str] = &[".png", ".jpg", ".jpeg", ".bmp"];
+
+const APP_NAME: &str = "MetaView";
+const PROG_ID: &str = "MetaView.Image";
+const PROG_ID_KEY: &str = r"Software\Classes\MetaView.Image";
+const CAPABILITIES_KEY: &str = r"Software\MetaView\Capabilities";
+const REGISTERED_APPLICATIONS_KEY: &str = r"Software\RegisteredApplications";
+
+const IDC_EXTENSION_FIRST: i32 = 100;
+const IDC_REMOVE_ALL: i32 = 200;
+
+fn open_with_key(extension: &str) -> String {
+    format!(r"Software\Classes\{extension}\OpenWithProgids")
+}
+
+fn is_associated(extension: &str) -> bool {
+    get_value(HKEY_CURRENT_USER, &open_with_key(extension), Some(PROG_ID)).is_some()
+}
+
+// 選ばれていない拡張子の関連付けは外す
+fn register(extensions: &[&str]) -> anyhow::Result<()> {
+    let exe = std::env::current_exe()?.display().to_string();
+    set_value(HKEY_CURRENT_USER, PROG_ID_KEY, None, APP_NAME)?;
+    set_value(HKEY_CURRENT_USER, &format!(r"{PROG_ID_KEY}\DefaultIcon"), None, &format!("\"{exe}\",0"))?;
+    set_value(HKEY_CURRENT_USER, &format!(r"{PROG_ID_KEY}\shell\open\command"), None, &format!("\"{exe}\" \"%1\""))?;
+    set_value(HKEY_CURRENT_USER, CAPABILITIES_KEY, Some("ApplicationName"), APP_NAME)?;
+    set_value(HKEY_CURRENT_USER, CAPABILITIES_KEY, Some("ApplicationDescription"), tr(Msg::AppDescription))?;
+    set_value(HKEY_CURRENT_USER, REGISTERED_APPLICATIONS_KEY, Some(APP_NAME), CAPABILITIES_KEY)?;
+    let associations_key = format!(r"{CAPABILITIES_KEY}\FileAssociations");
+    for &extension in EXTENSIONS {
+        if extensions.contains(&extension) {
+            set_value(HKEY_CURRENT_USER, &open_with_key(extension), Some(PROG_ID), "")?;
+            set_value(HKEY_CURRENT_USER, &associations_key, Some(extension), PROG_ID)?;
+        } else {
+            delete_value(HKEY_CURRENT_USER, &open_with_key(extension), Some(PROG_ID));
+            delete_value(HKEY_CURRENT_USER, &associations_key, Some(extension));
+        }
+    }
+    Ok(())
+}
+
+// 登録したものをすべて消す
+fn unregister() {
+    for extension in EXTENSIONS {
+        delete_value(HKEY_CURRENT_USER, &open_with_key(extension), Some(PROG_ID));
+    }
+    delete_value(HKEY_CURRENT_USER, REGISTERED_APPLICATIONS_KEY, Some(APP_NAME));
+    // Software\MetaView には Capabilities しか置いていない
+    delete_tree(HKEY_CURRENT_USER, r"Software\MetaView");
+    delete_tree(HKEY_CURRENT_USER, PROG_ID_KEY);
+}
+
+fn notify_association_changed() {
+    unsafe { SHChangeNotify(SHCNE_ASSOCCHANGED, SHCNF_IDLIST, None, None) };
+}
+
+// Windows 10 以降では「既定のアプリ」の設定画面が開く
+fn launch_default_apps_ui() -> Result<()> {
+    let ui: IApplicationAssociationRegistrationUI = unsafe {
+        CoCreateInstance(&ApplicationAssociationRegistrationUI, None, CLSCTX_INPROC_SERVER)
+    }?;
+    unsafe { ui.LaunchAdvancedAssociationUI(&HSTRING::from(APP_NAME)) }
+}
+
+struct DialogState {
+    checked: Vec<bool>,
+    remove_all: bool,
+}
+
+pub fn show_dialog(parent: HWND) -> anyhow::Result<()> {
+    let mut state = DialogState {
+        checked: EXTENSIONS.iter().map(|extension| is_associated(extension)).collect(),
+        remove_all: false,
+    };
+    let button_y = 31 + 14 * EXTENSIONS.len() as i16 + 8;
+    let mut template = DialogTemplate::new(tr(Msg::FileAssociationsTitle), 240, button_y + 21)
+        .item(dialog::STATIC, tr(Msg::FileAssociationsDescription), -1, 0, 7, 7, 226, 20);
+    for (i, extension) in EXTENSIONS.iter().enumerate() {
+        let style = BS_AUTOCHECKBOX as u32 | WS_TABSTOP.0;
+        template = template.item(dialog::BUTTON, extension, IDC_EXTENSION_FIRST + i as i32, style, 7, 31 + 14 * i as i16, 100, 12);
+    }
+    let template = template
+        .item(dialog::BUTTON, tr(Msg::RemoveAllAssociations), IDC_REMOVE_ALL, WS_TABSTOP.0, 7, button_y, 80, 14)
+        .item(dialog::BUTTON, "OK", IDOK.0, BS_DEFPUSHBUTTON as u32 | WS_TABSTOP.0, 129, button_y, 50, 14)
+        .item(dialog::BUTTON, tr(Msg::Cancel), IDCANCEL.0, WS_TABSTOP.0, 183, button_y, 50, 14);
+    let ret = template.show(parent, Some(dialog_proc), LPARAM(&mut state as *mut _ as isize));
+    if ret != IDOK.0 as isize {
+        return Ok(());
+    }
+    let extensions: Vec<&str> = EXTENSIONS.iter().zip(&state.checked)
+        .filter_map(|(&extension, &checked)| checked.then_some(extension))
+        .collect();
+    if state.remove_all || extensions.is_empty() {
+        unregister();
+        notify_association_changed();
+        return Ok(());
+    }
+    let result = register(&extensions);
+    notify_association_changed();
+    result?;
+    // 既定のアプリにするかどうかはユーザーが選ぶ (アプリからは変えられない)
+    launch_default_apps_ui()?;
+    Ok(())
+}
+
+extern "system" fn dialog_proc(hdlg: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> isize {
+    match message {
+        WM_INITDIALOG => {
+            unsafe { SetWindowLongPtrW(hdlg, GWLP_USERDATA, lparam.0) };
+            let state = unsafe { (lparam.0 as *mut DialogState).as_mut() }.unwrap();
+            for (i, &checked) in state.checked.iter().enumerate() {
+                if checked {
+                    unsafe { CheckDlgButton(hdlg, IDC_EXTENSION_FIRST + i as i32, BST_CHECKED) };
+                }
+            }
+            1
+        }
+        WM_COMMAND => {
+            let state = unsafe { (GetWindowLongPtrW(hdlg, GWLP_USERDATA) as *mut DialogState).as_mut() };
+            let Some(state) = state else { return 0 };
+            let id = (wparam.0 & 0xffff) as i32;
+            if id == IDOK.0 {
+                for (i, checked) in state.checked.iter_mut().enumerate() {
+                    *checked = unsafe { IsDlgButtonChecked(hdlg, IDC_EXTENSION_FIRST + i as i32) } == BST_CHECKED.0;
+                }
+                unsafe { EndDialog(hdlg, IDOK.0 as isize) };
+                1
+            } else if id == IDC_REMOVE_ALL {
+                state.remove_all = true;
+                unsafe { EndDialog(hdlg, IDOK.0 as isize) };
+                1
+            } else if id == IDCANCEL.0 {
+                unsafe { EndDialog(hdlg, IDCANCEL.0 as isize) };
+                1
+            } else {
+                0
+            }
+        }
+        _ => 0,
+    }
+}
@@ -0,0 +1,176 @@
+// フォルダーの中の画像をまとめて読み、モデルやサンプラーなどを集計する
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use windows::Win32::{
+    Foundation::*,
+    UI::WindowsAndMessaging::*,
+};
+use crate::fsutil;
+use crate::i18n::{tr, Msg};
+use crate::metadata::Source;
+use crate::params;
+
+// wparam: 読み終えたファイル数, lparam: 全体のファイル数
+pub const WM_APP_SCAN_PROGRESS: u32 = WM_APP + 5;
+// lparam: Box<anyhow::Result<FolderStats>> のポインタ
+pub const WM_APP_SCAN_DONE: u32 = WM_APP + 6;
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "bmp", "svg"];
+// よく使われるプロンプトとして出す数
+const TOP_TOKENS: usize = 30;
+
+// サムネイルの一覧に並べる画像
+#[derive(Debug)]
+pub struct ScannedImage {
+    pub path: PathBuf,
+    pub orientation: Option<u16>,
+}
+
+#[derive(Debug, Default)]
+pub struct FolderStats {
+    pub folder: PathBuf,
+    pub images: usize,
+    // テキストチャンクや EXIF が 1 つでもあるもの
+    pub with_metadata: usize,
+    // 読めなかったファイル
+    pub errors: usize,
+    pub models: HashMap<String, usize>,
+    pub samplers: HashMap<String, usize>,
+    pub seeds: Vec<u64>,
+    pub prompt_tokens: HashMap<String, usize>,
+    // 読めた画像 (見つけた順)
+    pub files: Vec<ScannedImage>,
+}
+
+pub fn start(hwnd: HWND, folder: PathBuf) {
+    std::thread::spawn(move || {
+        let result = scan(hwnd, folder);
+        let result = Box::into_raw(Box::new(result));
+        let posted = unsafe { PostMessageW(hwnd, WM_APP_SCAN_DONE, WPARAM(0), LPARAM(result as isize)) };
+        if !posted.as_bool() {
+            drop(unsafe { Box::from_raw(result) });
+        }
+    });
+}
+
+// WM_APP_SCAN_DONE の lparam から結果を取り出す
+pub unsafe fn take_result(lparam: LPARAM) -> anyhow::Result<FolderStats> {
+    *Box::from_raw(lparam.0 as *mut anyhow::Result<FolderStats>)
+}
+
+pub fn is_image(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| IMAGE_EXTENSIONS.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+// サブフォルダーもたどる。シンボリックリンクのフォルダーはループしうるのでたどらない
+pub fn collect_images(dir: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    for entry in fs::read_dir(fsutil::long_path(dir))? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let path = dir.join(entry.file_name());
+        if file_type.is_dir() {
+            let _ = collect_images(&path, files);
+        } else if file_type.is_file() && is_image(&path) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn scan(hwnd: HWND, folder: PathBuf) -> anyhow::Result<FolderStats> {
+    let mut files = Vec::new();
+    collect_images(&folder, &mut files)?;
+    let mut stats = FolderStats { folder, ..Default::default() };
+    for (i, path) in files.iter().enumerate() {
+        match Source::File(path.clone().into_os_string()).read_metadata() {
+            Ok(metadata) => {
+                stats.images += 1;
+                if !metadata.text_chunks.is_empty() || metadata.exif.is_some() {
+                    stats.with_metadata += 1;
+                }
+                if let Some(params) = params::find_parameters(&metadata.text_chunks) {
+                    stats.add(&params);
+                }
+                stats.files.push(ScannedImage { path: path.clone(), orientation: metadata.orientation });
+            }
+            Err(_) => stats.errors += 1,
+        }
+        unsafe { PostMessageW(hwnd, WM_APP_SCAN_PROGRESS, WPARAM(i + 1), LPARAM(files.len() as isize)) };
+    }
+    Ok(stats)
+}
+
+// "(best quality:1.2)" や "<lora:x:1>" はまとめて数えられるように重みや括弧を外す
+fn normalize_token(token: &str) -> String {
+    let token = token.trim().trim_matches(|c| "()[]{}".contains(c));
+    let token = match token.rsplit_once(':') {
+        Some((text, weight)) if weight.trim().parse::<f32>().is_ok() => text,
+        _ => token,
+    };
+    token.trim().to_lowercase()
+}
+
+impl FolderStats {
+    fn add(&mut self, params: &params::Parameters) {
+        let model = params.get("Model").or_else(|| params.get("Model hash"));
+        if let Some(model) = model {
+            *self.models.entry(model.to_owned()).or_default() += 1;
+        }
+        if let Some(sampler) = params.get("Sampler") {
+            *self.samplers.entry(sampler.to_owned()).or_default() += 1;
+        }
+        if let Some(seed) = params.get("Seed").and_then(|seed| seed.parse().ok()) {
+            self.seeds.push(seed);
+        }
+        // 1 枚の中で同じ語が何度出ても 1 回と数える
+        let mut tokens: Vec<String> = params.prompt.split([',', '\n'])
+            .map(normalize_token)
+            .filter(|token| !token.is_empty())
+            .collect();
+        tokens.sort();
+        tokens.dedup();
+        for token in tokens {
+            *self.prompt_tokens.entry(token).or_default() += 1;
+        }
+    }
+
+    pub fn format(&self) -> String {
+        let mut ret = format!("【{}】\r\n", tr(Msg::FolderStats));
+        ret.push_str(&format!("{}: {}\r\n", tr(Msg::Folder), self.folder.display()));
+        ret.push_str(&format!("{}: {}\r\n", tr(Msg::Images), self.images));
+        ret.push_str(&format!("{}: {}\r\n", tr(Msg::WithMetadata), self.with_metadata));
+        ret.push_str(&format!("{}: {}\r\n", tr(Msg::WithoutMetadata), self.images - self.with_metadata));
+        if self.errors > 0 {
+            ret.push_str(&format!("{}: {}\r\n", tr(Msg::UnreadableFiles), self.errors));
+        }
+        ret.push_str("\r\n");
+        format_counts(&mut ret, tr(Msg::Models), &self.models, usize::MAX);
+        format_counts(&mut ret, tr(Msg::Samplers), &self.samplers, usize::MAX);
+        if let (Some(min), Some(max)) = (self.seeds.iter().min(), self.seeds.iter().max()) {
+            let mut unique = self.seeds.clone();
+            unique.sort_unstable();
+            unique.dedup();
+            ret.push_str(&format!("【{}】\r\n", tr(Msg::Seeds)));
+            ret.push_str(&format!("{}: {min} - {max}\r\n", tr(Msg::SeedRange)));
+            ret.push_str(&format!("{}: {}\r\n\r\n", tr(Msg::UniqueSeeds), unique.len()));
+        }
+        format_counts(&mut ret, tr(Msg::CommonPromptTokens), &self.prompt_tokens, TOP_TOKENS);
+        ret
+    }
+}
+
+// 多い順 (同じなら名前順) に並べる
+fn format_counts(ret: &mut String, title: &str, counts: &HashMap<String, usize>, limit: usize) {
+    if counts.is_empty() {
+        return;
+    }
+    let mut counts: Vec<(&String, &usize)> = counts.iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    ret.push_str(&format!("【{title}】\r\n"));
+    for (name, count) in counts.into_iter().take(limit) {
+        ret.push_str(&format!("{name}: {count}\r\n"));
+    }
+    ret.push_str("\r\n");
+}
@@ -0,0 +1,328 @@
+// フォルダーの中の画像 (サブフォルダーも) から選んだ種類のメタデータを取り除き、別のフォルダーに同じ構成で書き出す
+// 元のファイルは書き換えない
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use windows::{
+    core::*,
+    Win32::{
+        Foundation::*,
+        System::Com::*,
+        UI::{Controls::*, Shell::*, WindowsAndMessaging::*},
+    },
+};
+use crate::batch;
+use crate::dialog::{self, DialogTemplate};
+use crate::fsutil;
+use crate::i18n::{tr, Msg};
+use crate::jpeg;
+use crate::png_chunks::PNG_SIGNATURE;
+use crate::strip::{self, Category};
+
+// wparam: 処理し終えたファイル数, lparam: 全体のファイル数
+pub const WM_APP_STRIP_PROGRESS: u32 = WM_APP + 13;
+// lparam: Box<anyhow::Result<StripReport>> のポインタ
+pub const WM_APP_STRIP_DONE: u32 = WM_APP + 14;
+
+// 失敗したファイルを結果に並べる数
+const MAX_LISTED_ERRORS: usize = 20;
+
+const IDC_SOURCE: i32 = 100;
+const IDC_BROWSE_SOURCE: i32 = 101;
+const IDC_OUTPUT: i32 = 102;
+const IDC_BROWSE_OUTPUT: i32 = 103;
+const IDC_CATEGORY_FIRST: i32 = 110;
+
+fn category_name(category: Category) -> &'static str {
+    match category {
+        Category::Text => tr(Msg::StripText),
+        Category::Exif => "EXIF",
+        Category::Xmp => "XMP",
+        Category::Icc => tr(Msg::IccProfile),
+    }
+}
+
+#[derive(Debug)]
+pub struct StripOptions {
+    pub source: PathBuf,
+    pub output: PathBuf,
+    pub categories: Vec<Category>,
+}
+
+#[derive(Debug, Default)]
+pub struct StripReport {
+    pub source: PathBuf,
+    pub output: PathBuf,
+    pub written: usize,
+    // 種類ごとに、それを取り除いたファイルの数
+    pub removed: Vec<(Category, usize)>,
+    pub bytes_removed: u64,
+    // PNG と JPEG 以外 (BMP, SVG) は書き出さない
+    pub skipped: usize,
+    pub errors: Vec<(PathBuf, String)>,
+    pub cancelled: bool,
+}
+
+struct DialogState {
+    source: String,
+    output: String,
+    categories: Vec<Category>,
+}
+
+// 元のフォルダー、出力先、取り除く種類を選んでもらう
+pub fn show_dialog(parent: HWND, folder: Option<&Path>) -> Option<StripOptions> {
+    let mut state = DialogState {
+        source: folder.map(|folder| folder.display().to_string()).unwrap_or_default(),
+        output: String::new(),
+        categories: Category::ALL.to_vec(),
+    };
+    let mut template = DialogTemplate::new(tr(Msg::BatchStripTitle), 300, 124)
+        .item(dialog::STATIC, tr(Msg::Folder), -1, 0, 7, 9, 50, 10)
+        .item(dialog::EDIT, "", IDC_SOURCE, ES_AUTOHSCROLL as u32 | WS_BORDER.0 | WS_TABSTOP.0, 60, 7, 179, 14)
+        .item(dialog::BUTTON, tr(Msg::Browse), IDC_BROWSE_SOURCE, WS_TABSTOP.0, 243, 7, 50, 14)
+        .item(dialog::STATIC, tr(Msg::OutputFolder), -1, 0, 7, 27, 50, 10)
+        .item(dialog::EDIT, "", IDC_OUTPUT, ES_AUTOHSCROLL as u32 | WS_BORDER.0 | WS_TABSTOP.0, 60, 25, 179, 14)
+        .item(dialog::BUTTON, tr(Msg::Browse), IDC_BROWSE_OUTPUT, WS_TABSTOP.0, 243, 25, 50, 14)
+        .item(dialog::STATIC, tr(Msg::StripCategories), -1, 0, 7, 45, 50, 10);
+    for (i, category) in Category::ALL.into_iter().enumerate() {
+        let style = BS_AUTOCHECKBOX as u32 | WS_TABSTOP.0;
+        template = template.item(dialog::BUTTON, category_name(category), IDC_CATEGORY_FIRST + i as i32, style, 60, 44 + i as i16 * 13, 233, 12);
+    }
+    let template = template
+        .item(dialog::BUTTON, tr(Msg::Start), IDOK.0, BS_DEFPUSHBUTTON as u32 | WS_TABSTOP.0, 189, 103, 50, 14)
+        .item(dialog::BUTTON, tr(Msg::Cancel), IDCANCEL.0, WS_TABSTOP.0, 243, 103, 50, 14);
+    let ret = template.show(parent, Some(dialog_proc), LPARAM(&mut state as *mut _ as isize));
+    if ret != IDOK.0 as isize {
+        return None;
+    }
+    Some(StripOptions { source: PathBuf::from(state.source), output: PathBuf::from(state.output), categories: state.categories })
+}
+
+fn warn(hdlg: HWND, msg: Msg) {
+    let text = HSTRING::from(tr(msg));
+    unsafe { MessageBoxW(hdlg, &text, None, MB_OK | MB_ICONWARNING) };
+}
+
+// 出力先の既定値は元のフォルダーの隣の <name>_clean
+fn default_output(source: &str) -> String {
+    let source = Path::new(source.trim());
+    match source.file_name() {
+        Some(name) => source.with_file_name(format!("{}_clean", name.to_string_lossy())).display().to_string(),
+        None => String::new(),
+    }
+}
+
+fn set_source(hdlg: HWND, source: &str) {
+    unsafe { SetDlgItemTextW(hdlg, IDC_SOURCE, &HSTRING::from(source)) };
+    unsafe { SetDlgItemTextW(hdlg, IDC_OUTPUT, &HSTRING::from(default_output(source))) };
+}
+
+// ダイアログにドロップされたフォルダーを元のフォルダーにする
+fn drop_folder(hdlg: HWND, hdrop: HDROP) {
+    let mut buf = vec![0u16; 32768];
+    let len = unsafe { DragQueryFileW(hdrop, 0, Some(&mut buf)) } as usize;
+    unsafe { DragFinish(hdrop) };
+    let path = String::from_utf16_lossy(&buf[..len]);
+    if Path::new(&path).is_dir() {
+        set_source(hdlg, &path);
+    }
+}
+
+fn same_folder(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+extern "system" fn dialog_proc(hdlg: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> isize {
+    match message {
+        WM_INITDIALOG => {
+            unsafe { SetWindowLongPtrW(hdlg, GWLP_USERDATA, lparam.0) };
+            let state = unsafe { (lparam.0 as *mut DialogState).as_mut() }.unwrap();
+            set_source(hdlg, &state.source);
+            for (i, category) in Category::ALL.into_iter().enumerate() {
+                if state.categories.contains(&category) {
+                    unsafe { CheckDlgButton(hdlg, IDC_CATEGORY_FIRST + i as i32, BST_CHECKED) };
+                }
+            }
+            unsafe { DragAcceptFiles(hdlg, true) };
+            1
+        }
+        WM_DROPFILES => {
+            drop_folder(hdlg, HDROP(wparam.0 as isize));
+            1
+        }
+        WM_COMMAND => {
+            let state = unsafe { (GetWindowLongPtrW(hdlg, GWLP_USERDATA) as *mut DialogState).as_mut() };
+            let Some(state) = state else { return 0 };
+            let id = (wparam.0 & 0xffff) as i32;
+            if id == IDOK.0 {
+                let source = dialog::get_item_text(hdlg, IDC_SOURCE).trim().to_owned();
+                let output = dialog::get_item_text(hdlg, IDC_OUTPUT).trim().to_owned();
+                let categories: Vec<Category> = Category::ALL.into_iter().enumerate()
+                    .filter(|(i, _)| unsafe { IsDlgButtonChecked(hdlg, IDC_CATEGORY_FIRST + *i as i32) } == BST_CHECKED.0)
+                    .map(|(_, category)| category)
+                    .collect();
+                if !Path::new(&source).is_dir() {
+                    warn(hdlg, Msg::FolderNotFound);
+                    return 1;
+                }
+                // 元のフォルダーに書き出すと元のファイルを上書きしてしまう
+                if output.is_empty() || same_folder(Path::new(&source), Path::new(&output)) {
+                    warn(hdlg, Msg::OutputSameAsSource);
+                    return 1;
+                }
+                if categories.is_empty() {
+                    warn(hdlg, Msg::NoStripCategory);
+                    return 1;
+                }
+                (state.source, state.output, state.categories) = (source, output, categories);
+                unsafe { EndDialog(hdlg, IDOK.0 as isize) };
+                1
+            } else if id == IDC_BROWSE_SOURCE || id == IDC_BROWSE_OUTPUT {
+                if let Ok(Some(folder)) = crate::pick_folder(hdlg, Msg::BatchStripTitle) {
+                    if id == IDC_BROWSE_SOURCE {
+                        set_source(hdlg, &folder.display().to_string());
+                    } else {
+                        unsafe { SetDlgItemTextW(hdlg, IDC_OUTPUT, &HSTRING::from(folder.as_os_str())) };
+                    }
+                }
+                1
+            } else if id == IDCANCEL.0 {
+                unsafe { EndDialog(hdlg, IDCANCEL.0 as isize) };
+                1
+            } else {
+                0
+            }
+        }
+        _ => 0,
+    }
+}
+
+// 実行中の処理。進捗はシェルの進捗ダイアログに出し、そこでキャンセルされたら止める
+#[derive(Debug)]
+pub struct StripJob {
+    progress: Option<IProgressDialog>,
+    cancel: Arc<AtomicBool>,
+}
+
+pub fn start(hwnd: HWND, options: StripOptions) -> StripJob {
+    let progress: Option<IProgressDialog> = unsafe { CoCreateInstance(&CLSID_ProgressDialog, None, CLSCTX_INPROC_SERVER) }.ok();
+    if let Some(progress) = &progress {
+        let _ = unsafe { progress.SetTitle(&HSTRING::from(tr(Msg::BatchStripTitle))) };
+        let _ = unsafe { progress.SetLine(1, &HSTRING::from(options.source.as_os_str()), true, None) };
+        let _ = unsafe { progress.StartProgressDialog(hwnd, None, PROGDLG_NORMAL | PROGDLG_AUTOTIME, None) };
+    }
+    let cancel = Arc::new(AtomicBool::new(false));
+    let job = StripJob { progress, cancel: cancel.clone() };
+    std::thread::spawn(move || {
+        let result = strip_folder(hwnd, options, &cancel);
+        let result = Box::into_raw(Box::new(result));
+        let posted = unsafe { PostMessageW(hwnd, WM_APP_STRIP_DONE, WPARAM(0), LPARAM(result as isize)) };
+        if !posted.as_bool() {
+            drop(unsafe { Box::from_raw(result) });
+        }
+    });
+    job
+}
+
+impl StripJob {
+    // WM_APP_STRIP_PROGRESS を受けたときに呼ぶ
+    pub fn update(&self, done: usize, total: usize) {
+        let Some(progress) = &self.progress else { return };
+        let _ = unsafe { progress.SetProgress64(done as u64, total as u64) };
+        let _ = unsafe { progress.SetLine(2, &HSTRING::from(format!("{done} / {total}")), false, None) };
+        if unsafe { progress.HasUserCancelled() }.as_bool() {
+            self.cancel.store(true, Ordering::SeqCst);
+        }
+    }
+
+    pub fn finish(self) {
+        if let Some(progress) = self.progress {
+            let _ = unsafe { progress.StopProgressDialog() };
+        }
+    }
+}
+
+// WM_APP_STRIP_DONE の lparam から結果を取り出す
+pub unsafe fn take_result(lparam: LPARAM) -> anyhow::Result<StripReport> {
+    *Box::from_raw(lparam.0 as *mut anyhow::Result<StripReport>)
+}
+
+fn strip_folder(hwnd: HWND, options: StripOptions, cancel: &AtomicBool) -> anyhow::Result<StripReport> {
+    let mut files = Vec::new();
+    batch::collect_images(&options.source, &mut files)?;
+    let mut report = StripReport {
+        removed: options.categories.iter().map(|&category| (category, 0)).collect(),
+        ..Default::default()
+    };
+    for (i, path) in files.iter().enumerate() {
+        if cancel.load(Ordering::SeqCst) {
+            report.cancelled = true;
+            break;
+        }
+        let relative = path.strip_prefix(&options.source).unwrap_or(path);
+        match strip_file(path, &options.output.join(relative), &options.categories) {
+            Ok(Some((removed, bytes))) => {
+                report.written += 1;
+                report.bytes_removed += bytes;
+                for (category, count) in &mut report.removed {
+                    if removed.contains(category) {
+                        *count += 1;
+                    }
+                }
+            }
+            Ok(None) => report.skipped += 1,
+            Err(e) => report.errors.push((relative.to_owned(), e.to_string())),
+        }
+        unsafe { PostMessageW(hwnd, WM_APP_STRIP_PROGRESS, WPARAM(i + 1), LPARAM(files.len() as isize)) };
+    }
+    (report.source, report.output) = (options.source, options.output);
+    Ok(report)
+}
+
+// PNG と JPEG 以外は Ok(None)
+fn strip_file(path: &Path, out_path: &Path, categories: &[Category]) -> anyhow::Result<Option<(Vec<Category>, u64)>> {
+    let file = fsutil::read_shared(path)?;
+    if !file.starts_with(PNG_SIGNATURE) && !jpeg::is_jpeg(&file) {
+        return Ok(None);
+    }
+    let (stripped, removed) = strip::strip_categories(&file, categories)?;
+    if let Some(dir) = out_path.parent() {
+        fs::create_dir_all(fsutil::long_path(dir))?;
+    }
+    fs::write(fsutil::long_path(out_path), &stripped)?;
+    Ok(Some((removed, file.len().saturating_sub(stripped.len()) as u64)))
+}
+
+impl StripReport {
+    pub fn format(&self) -> String {
+        let mut ret = format!("【{}】\r\n", tr(Msg::BatchStripTitle));
+        if self.cancelled {
+            ret.push_str(&format!("⚠ {}\r\n", tr(Msg::Cancelled)));
+        }
+        ret.push_str(&format!("{}: {}\r\n", tr(Msg::Folder), self.source.display()));
+        ret.push_str(&format!("{}: {}\r\n", tr(Msg::OutputFolder), self.output.display()));
+        ret.push_str(&format!("{}: {}\r\n", tr(Msg::FilesWritten), self.written));
+        for (category, count) in &self.removed {
+            ret.push_str(&format!("    {}: {count}\r\n", category_name(*category)));
+        }
+        ret.push_str(&format!("{}: {} {}\r\n", tr(Msg::BytesRemoved), self.bytes_removed, tr(Msg::StatusBytes)));
+        if self.skipped > 0 {
+            ret.push_str(&format!("{}: {}\r\n", tr(Msg::SkippedUnsupported), self.skipped));
+        }
+        if !self.errors.is_empty() {
+            ret.push_str(&format!("{}: {}\r\n", tr(Msg::Error), self.errors.len()));
+            for (path, error) in self.errors.iter().take(MAX_LISTED_ERRORS) {
+                ret.push_str(&format!("    {}: {error}\r\n", path.display()));
+            }
+            if self.errors.len() > MAX_LISTED_ERRORS {
+                ret.push_str("    …\r\n");
+            }
+        }
+        ret
+    }
+}
@@ -0,0 +1,567 @@
+// C2PA (Content Credentials) のマニフェストを読む
+// JPEG は APP11 の JUMBF、PNG は caBX チャンクに入っている
+// 署名は Windows の CNG で検証し、証明書は x5chain の中間証明書と Windows の信頼されたルートでチェーンを確かめる
+
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use windows::Win32::Security::Cryptography::*;
+use crate::cbor::{self, Value};
+use crate::i18n::{tr, Msg};
+use crate::jpeg;
+use crate::png_chunks::{self, PNG_SIGNATURE};
+use crate::redact;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Validation {
+    Valid,
+    // 署名は合っているが、証明書のチェーンが信頼されたルートまでたどれない (理由)
+    Untrusted(String),
+    // 理由
+    Invalid(String),
+    // 対応していない方式などで確かめられなかった
+    NotChecked(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Signature {
+    pub status: Validation,
+    pub algorithm: Option<&'static str>,
+    // 署名した証明書の名前
+    pub signer: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Action {
+    pub action: String,
+    pub software_agent: Option<String>,
+    pub when: Option<String>,
+    // 生成 AI で作ったものなら .../trainedAlgorithmicMedia など
+    pub digital_source_type: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Ingredient {
+    pub title: Option<String>,
+    pub format: Option<String>,
+    // parentOf, componentOf, inputTo
+    pub relationship: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    pub label: String,
+    pub claim_generator: Option<String>,
+    pub title: Option<String>,
+    pub format: Option<String>,
+    pub signature: Signature,
+    // 画像データのハッシュ。現在のマニフェスト (最後のもの) だけ確かめる
+    pub data_hash: Option<Validation>,
+    pub assertions: Vec<String>,
+    // ハッシュが合わないか見つからないアサーション
+    pub mismatched_assertions: Vec<String>,
+    pub actions: Vec<Action>,
+    pub ingredients: Vec<Ingredient>,
+}
+
+// 最後のものが現在のマニフェスト。それより前は素材のマニフェスト
+#[derive(Debug, Clone, Default)]
+pub struct ManifestStore {
+    pub manifests: Vec<Manifest>,
+}
+
+const JUMBF_SUPERBOX: &[u8; 4] = b"jumb";
+const JUMBF_DESCRIPTION: &[u8; 4] = b"jumd";
+
+// JUMBF のボックス。payload はヘッダー (LBox, TBox, XLBox) を除いた部分
+struct JumbfBox<'a> {
+    kind: [u8; 4],
+    payload: &'a [u8],
+}
+
+// 壊れたボックスがあればそこまでを返す
+fn parse_boxes(mut data: &[u8]) -> Vec<JumbfBox<'_>> {
+    let mut boxes = Vec::new();
+    while data.len() >= 8 {
+        let len = u32::from_be_bytes(data[..4].try_into().unwrap()) as u64;
+        let kind: [u8; 4] = data[4..8].try_into().unwrap();
+        let (header, len) = match len {
+            0 => (8, data.len() as u64),
+            1 if data.len() >= 16 => (16, u64::from_be_bytes(data[8..16].try_into().unwrap())),
+            _ => (8, len),
+        };
+        let Some(len) = usize::try_from(len).ok().filter(|&len| len >= header && len <= data.len()) else { break };
+        boxes.push(JumbfBox { kind, payload: &data[header..len] });
+        data = &data[len..];
+    }
+    boxes
+}
+
+// ラベルの付いたスーパーボックス。children は説明ボックス (jumd) の後ろの中身
+struct SuperBox<'a> {
+    label: String,
+    payload: &'a [u8],
+    children: Vec<JumbfBox<'a>>,
+}
+
+impl<'a> SuperBox<'a> {
+    fn parse(jumbf: &JumbfBox<'a>) -> Option<SuperBox<'a>> {
+        (&jumbf.kind == JUMBF_SUPERBOX).then_some(())?;
+        let mut children = parse_boxes(jumbf.payload);
+        let description = (!children.is_empty() && &children[0].kind == JUMBF_DESCRIPTION).then(|| children.remove(0))?;
+        // 種類の UUID (16 バイト) とトグルの後ろに、NUL で終わるラベルがある
+        let toggles = *description.payload.get(16)?;
+        let label = if toggles & 0x02 != 0 {
+            let rest = description.payload.get(17..)?;
+            let end = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+            String::from_utf8_lossy(&rest[..end]).into_owned()
+        } else {
+            String::new()
+        };
+        Some(SuperBox { label, payload: jumbf.payload, children })
+    }
+
+    fn superboxes(&self) -> impl Iterator<Item = SuperBox<'a>> + '_ {
+        self.children.iter().filter_map(SuperBox::parse)
+    }
+
+    fn child(&self, label: &str) -> Option<SuperBox<'a>> {
+        self.superboxes().find(|child| child.label == label)
+    }
+
+    fn content(&self, kind: &[u8; 4]) -> Option<&'a [u8]> {
+        self.children.iter().find(|child| &child.kind == kind).map(|child| child.payload)
+    }
+
+    fn cbor(&self) -> Option<Value> {
+        cbor::decode(self.content(b"cbor")?)
+    }
+}
+
+// APP11 セグメントの通し番号と、その中の JUMBF の断片
+type JumbfPart<'a> = (u32, &'a [u8]);
+
+// JPEG では APP11 の "JP" の後ろに、インスタンス番号 (2 バイト) と通し番号 (4 バイト) が続く
+// 2 つ目以降のセグメントには先頭のボックスのヘッダー (8 バイト) が繰り返されているので除いてつなぐ
+fn jpeg_jumbf(data: &[u8]) -> Vec<Vec<u8>> {
+    let Ok(segments) = jpeg::parse_segments(data) else {
+        return Vec::new();
+    };
+    let mut instances: Vec<([u8; 2], Vec<JumbfPart>)> = Vec::new();
+    for segment in segments.iter().filter(|s| s.marker == jpeg::APP11) {
+        let payload = &data[segment.data.clone()];
+        if payload.len() < 16 || !payload.starts_with(b"JP") {
+            continue;
+        }
+        let instance: [u8; 2] = payload[2..4].try_into().unwrap();
+        let sequence = u32::from_be_bytes(payload[4..8].try_into().unwrap());
+        let part = if sequence <= 1 { &payload[8..] } else { &payload[16..] };
+        match instances.iter_mut().find(|(i, _)| *i == instance) {
+            Some((_, parts)) => parts.push((sequence, part)),
+            None => instances.push((instance, vec![(sequence, part)])),
+        }
+    }
+    instances.into_iter()
+        .map(|(_, mut parts)| {
+            parts.sort_by_key(|(sequence, _)| *sequence);
+            parts.into_iter().flat_map(|(_, part)| part.iter().copied()).collect()
+        })
+        .collect()
+}
+
+fn png_jumbf(data: &[u8]) -> Vec<Vec<u8>> {
+    let Ok(chunks) = png_chunks::parse_chunks(data) else {
+        return Vec::new();
+    };
+    chunks.iter().filter(|chunk| &chunk.kind == b"caBX").map(|chunk| data[chunk.data.clone()].to_vec()).collect()
+}
+
+// マニフェストストアがなければ None
+pub fn read(data: &[u8]) -> Option<ManifestStore> {
+    let jumbf = if data.starts_with(PNG_SIGNATURE) {
+        png_jumbf(data)
+    } else if jpeg::is_jpeg(data) {
+        jpeg_jumbf(data)
+    } else {
+        return None;
+    };
+    jumbf.iter().find_map(|jumbf| {
+        let store = parse_boxes(jumbf).iter().filter_map(SuperBox::parse).find(|store| store.label == "c2pa")?;
+        let manifests: Vec<SuperBox> = store.superboxes().collect();
+        let count = manifests.len();
+        let manifests = manifests.iter().enumerate()
+            .map(|(i, manifest)| read_manifest(manifest, (i + 1 == count).then_some(data)))
+            .collect();
+        Some(ManifestStore { manifests })
+    })
+}
+
+// "c2pa.actions__1" や "c2pa.ingredient.v2" のような付加部分を除いたラベル
+fn base_label(label: &str) -> &str {
+    let label = label.split("__").next().unwrap_or(label);
+    label.strip_suffix(".v2").or_else(|| label.strip_suffix(".v3")).unwrap_or(label)
+}
+
+fn text(value: Option<&Value>) -> Option<String> {
+    value.and_then(Value::as_str).map(str::to_owned)
+}
+
+// 名前とバージョンの組 (claim_generator_info や v2 の softwareAgent)
+fn name_and_version(value: &Value) -> Option<String> {
+    match value {
+        Value::Text(name) => Some(name.clone()),
+        Value::Array(items) => items.first().and_then(name_and_version),
+        Value::Map(_) => {
+            let name = value.get("name")?.as_str()?;
+            match value.get("version").and_then(Value::as_str) {
+                Some(version) => Some(format!("{name} {version}")),
+                None => Some(name.to_owned()),
+            }
+        }
+        _ => None,
+    }
+}
+
+fn hash(algorithm: &str, parts: &[&[u8]]) -> Option<Vec<u8>> {
+    fn digest<D: Digest>(parts: &[&[u8]]) -> Vec<u8> {
+        let mut hasher = D::new();
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finalize().to_vec()
+    }
+    match algorithm {
+        "sha256" => Some(digest::<Sha256>(parts)),
+        "sha384" => Some(digest::<Sha384>(parts)),
+        "sha512" => Some(digest::<Sha512>(parts)),
+        _ => None,
+    }
+}
+
+fn read_manifest(manifest: &SuperBox, data: Option<&[u8]>) -> Manifest {
+    let claim_box = manifest.child("c2pa.claim.v2").or_else(|| manifest.child("c2pa.claim"));
+    let claim_bytes = claim_box.as_ref().and_then(|claim| claim.content(b"cbor"));
+    let claim = claim_bytes.and_then(cbor::decode).unwrap_or(Value::Null);
+    let claim_alg = claim.get("alg").and_then(Value::as_str).unwrap_or("sha256");
+    let assertion_store = manifest.child("c2pa.assertions");
+    let assertions: Vec<SuperBox> = assertion_store.iter().flat_map(|store| store.superboxes()).collect();
+
+    // クレームに書かれたハッシュとアサーションの中身を突き合わせる
+    // ハッシュはアサーションのスーパーボックスのヘッダーを除いた部分 (jumd と中身のボックス) から計算する
+    let references = ["assertions", "created_assertions", "gathered_assertions"].iter()
+        .filter_map(|key| claim.get(key)?.as_array())
+        .flatten();
+    let mut mismatched_assertions = Vec::new();
+    for reference in references {
+        let Some(url) = reference.get("url").and_then(Value::as_str) else { continue };
+        let label = url.rsplit('/').next().unwrap_or(url);
+        let expected = reference.get("hash").and_then(Value::as_bytes);
+        let alg = reference.get("alg").and_then(Value::as_str).unwrap_or(claim_alg);
+        let actual = assertions.iter().find(|a| a.label == label).and_then(|a| hash(alg, &[a.payload]));
+        if expected.is_none() || actual.as_deref() != expected {
+            mismatched_assertions.push(label.to_owned());
+        }
+    }
+
+    let mut actions = Vec::new();
+    let mut ingredients = Vec::new();
+    let mut data_hash = None;
+    for assertion in &assertions {
+        let Some(content) = assertion.cbor() else { continue };
+        match base_label(&assertion.label) {
+            "c2pa.actions" => {
+                for action in content.get("actions").and_then(Value::as_array).unwrap_or_default() {
+                    let Some(name) = text(action.get("action")) else { continue };
+                    actions.push(Action {
+                        action: name,
+                        software_agent: action.get("softwareAgent").and_then(name_and_version),
+                        when: text(action.get("when")),
+                        digital_source_type: text(action.get("digitalSourceType")),
+                    });
+                }
+            }
+            "c2pa.ingredient" => ingredients.push(Ingredient {
+                title: text(content.get("dc:title")),
+                format: text(content.get("dc:format")),
+                relationship: text(content.get("relationship")),
+            }),
+            "c2pa.hash.data" => data_hash = data.map(|data| check_data_hash(&content, claim_alg, data)),
+            "c2pa.hash.boxes" | "c2pa.hash.bmff" => {
+                data_hash = data.map(|_| Validation::NotChecked(format!("{} is not supported", assertion.label)));
+            }
+            _ => {}
+        }
+    }
+
+    let signature = match (claim_bytes, manifest.child("c2pa.signature").and_then(|s| s.content(b"cbor"))) {
+        (Some(claim_bytes), Some(cose)) => verify_signature(cose, claim_bytes),
+        _ => Signature { status: Validation::Invalid("claim or signature not found".to_owned()), algorithm: None, signer: None },
+    };
+    Manifest {
+        label: manifest.label.clone(),
+        claim_generator: text(claim.get("claim_generator"))
+            .or_else(|| claim.get("claim_generator_info").and_then(name_and_version)),
+        title: text(claim.get("dc:title")),
+        format: text(claim.get("dc:format")),
+        signature,
+        data_hash,
+        assertions: assertions.iter().map(|a| a.label.clone()).collect(),
+        mismatched_assertions,
+        actions,
+        ingredients,
+    }
+}
+
+// 除外範囲 (マニフェストを入れた部分) 以外のファイル全体のハッシュ
+fn check_data_hash(assertion: &Value, claim_alg: &str, data: &[u8]) -> Validation {
+    let Some(expected) = assertion.get("hash").and_then(Value::as_bytes) else {
+        return Validation::Invalid("hash not found".to_owned());
+    };
+    let alg = assertion.get("alg").and_then(Value::as_str).unwrap_or(claim_alg);
+    let mut exclusions: Vec<(usize, usize)> = Vec::new();
+    for exclusion in assertion.get("exclusions").and_then(Value::as_array).unwrap_or_default() {
+        let field = |key| exclusion.get(key).and_then(Value::as_i128).and_then(|n| usize::try_from(n).ok());
+        let (Some(start), Some(length)) = (field("start"), field("length")) else {
+            return Validation::Invalid("invalid exclusion range".to_owned());
+        };
+        let Some(end) = start.checked_add(length).filter(|&end| end <= data.len()) else {
+            return Validation::Invalid("exclusion range is outside the file".to_owned());
+        };
+        exclusions.push((start, end));
+    }
+    exclusions.sort();
+    let mut parts = Vec::new();
+    let mut pos = 0;
+    for (start, end) in exclusions {
+        if start < pos {
+            return Validation::Invalid("overlapping exclusion ranges".to_owned());
+        }
+        parts.push(&data[pos..start]);
+        pos = end;
+    }
+    parts.push(&data[pos..]);
+    match hash(alg, &parts) {
+        Some(actual) if actual == expected => Validation::Valid,
+        Some(_) => Validation::Invalid("the image data has been modified".to_owned()),
+        None => Validation::NotChecked(format!("unsupported hash algorithm: {alg}")),
+    }
+}
+
+// COSE のアルゴリズム番号 → (名前, ハッシュ, RSA-PSS か)
+fn cose_algorithm(alg: i128) -> Option<(&'static str, &'static str, bool)> {
+    match alg {
+        -7 => Some(("ES256", "sha256", false)),
+        -35 => Some(("ES384", "sha384", false)),
+        -36 => Some(("ES512", "sha512", false)),
+        -37 => Some(("PS256", "sha256", true)),
+        -38 => Some(("PS384", "sha384", true)),
+        -39 => Some(("PS512", "sha512", true)),
+        _ => None,
+    }
+}
+
+// 署名は COSE_Sign1 で、クレームの CBOR が切り離されたペイロードになっている
+fn verify_signature(cose: &[u8], claim: &[u8]) -> Signature {
+    let invalid = |reason: &str| Signature { status: Validation::Invalid(reason.to_owned()), algorithm: None, signer: None };
+    let Some(Value::Array(items)) = cbor::decode(cose).map(|value| value.untagged().clone()) else {
+        return invalid("invalid COSE signature");
+    };
+    let [Value::Bytes(protected), unprotected, _, Value::Bytes(signature)] = &items[..] else {
+        return invalid("invalid COSE signature");
+    };
+    let protected_header = if protected.is_empty() { Value::Map(Vec::new()) } else { cbor::decode(protected).unwrap_or(Value::Null) };
+    // 証明書チェーン (x5chain, ラベル 33) の先頭が署名者の証明書
+    let chain: Vec<&[u8]> = match protected_header.get_int(33).or_else(|| unprotected.get_int(33)) {
+        Some(Value::Bytes(der)) => vec![der.as_slice()],
+        Some(Value::Array(certs)) => certs.iter().filter_map(Value::as_bytes).collect(),
+        _ => Vec::new(),
+    };
+    let Some((&certificate, intermediates)) = chain.split_first() else {
+        return invalid("signing certificate not found");
+    };
+    let alg = protected_header.get_int(1).and_then(Value::as_i128);
+    let Some((name, hash_alg, pss)) = alg.and_then(cose_algorithm) else {
+        return Signature {
+            status: Validation::NotChecked(format!("unsupported signature algorithm: {}", alg.map_or("none".to_owned(), |a| a.to_string()))),
+            algorithm: None,
+            signer: certificate_name(certificate),
+        };
+    };
+    // Sig_structure = ["Signature1", protected, external_aad (空), payload]
+    let mut to_be_signed = vec![0x84];
+    cbor::encode_header(3, 10, &mut to_be_signed);
+    to_be_signed.extend_from_slice(b"Signature1");
+    for bytes in [protected.as_slice(), &[], claim] {
+        cbor::encode_header(2, bytes.len() as u64, &mut to_be_signed);
+        to_be_signed.extend_from_slice(bytes);
+    }
+    let digest = hash(hash_alg, &[&to_be_signed]).unwrap_or_default();
+    let status = match verify(certificate, &digest, signature, hash_alg, pss) {
+        Ok(true) => match check_trust(certificate, intermediates) {
+            Ok(()) => Validation::Valid,
+            Err(e) => Validation::Untrusted(e.to_string()),
+        },
+        Ok(false) => Validation::Invalid("the signature does not match the claim".to_owned()),
+        Err(e) => Validation::NotChecked(e.to_string()),
+    };
+    Signature { status, algorithm: Some(name), signer: certificate_name(certificate) }
+}
+
+fn certificate_name(der: &[u8]) -> Option<String> {
+    let cert = unsafe { CertCreateCertificateContext(X509_ASN_ENCODING.0 | PKCS_7_ASN_ENCODING.0, der) };
+    if cert.is_null() {
+        return None;
+    }
+    let mut name = [0u16; 256];
+    let len = unsafe { CertGetNameStringW(cert, CERT_NAME_SIMPLE_DISPLAY_TYPE, 0, None, Some(&mut name)) } as usize;
+    unsafe { CertFreeCertificateContext(Some(cert)) };
+    // 長さには終わりの NUL を含む
+    let name = String::from_utf16_lossy(&name[..len.saturating_sub(1)]);
+    (!name.is_empty()).then_some(name)
+}
+
+// 署名が合わなければ Ok(false)。鍵を読めないなどで確かめられなければ Err
+fn verify(certificate: &[u8], digest: &[u8], signature: &[u8], hash_alg: &str, pss: bool) -> anyhow::Result<bool> {
+    let cert = unsafe { CertCreateCertificateContext(X509_ASN_ENCODING.0 | PKCS_7_ASN_ENCODING.0, certificate) };
+    anyhow::ensure!(!cert.is_null(), "invalid signing certificate");
+    let mut key = BCRYPT_KEY_HANDLE::default();
+    let info = unsafe { &(*(*cert).pCertInfo).SubjectPublicKeyInfo };
+    let imported = unsafe { CryptImportPublicKeyInfoEx2(X509_ASN_ENCODING.0, info, CRYPT_IMPORT_PUBLIC_KEY_FLAGS(0), None, &mut key) };
+    unsafe { CertFreeCertificateContext(Some(cert)) };
+    anyhow::ensure!(imported.as_bool(), "unsupported public key");
+    let padding = BCRYPT_PSS_PADDING_INFO {
+        pszAlgId: match hash_alg {
+            "sha384" => BCRYPT_SHA384_ALGORITHM,
+            "sha512" => BCRYPT_SHA512_ALGORITHM,
+            _ => BCRYPT_SHA256_ALGORITHM,
+        },
+        cbSalt: digest.len() as u32,
+    };
+    let result = if pss {
+        unsafe { BCryptVerifySignature(key, Some(&padding as *const _ as *const _), digest, signature, BCRYPT_PAD_PSS) }
+    } else {
+        unsafe { BCryptVerifySignature(key, None, digest, signature, NCRYPT_FLAGS(0)) }
+    };
+    unsafe { BCryptDestroyKey(key) }.ok();
+    Ok(result.is_ok())
+}
+
+// 証明書のチェーンを作り、信頼されたルートまでたどれるかを確かめる。失効は確認しない (オフラインでも使えるように)
+fn check_trust(certificate: &[u8], intermediates: &[&[u8]]) -> anyhow::Result<()> {
+    const ENCODING: u32 = X509_ASN_ENCODING.0 | PKCS_7_ASN_ENCODING.0;
+    let store = unsafe { CertOpenStore(CERT_STORE_PROV_MEMORY, CERT_QUERY_ENCODING_TYPE(0), HCRYPTPROV_LEGACY(0), CERT_OPEN_STORE_FLAGS(0), None) }?;
+    for intermediate in intermediates {
+        unsafe { CertAddEncodedCertificateToStore(store, ENCODING, intermediate, CERT_STORE_ADD_ALWAYS, None) };
+    }
+    let cert = unsafe { CertCreateCertificateContext(ENCODING, certificate) };
+    if cert.is_null() {
+        unsafe { CertCloseStore(store, 0) };
+        anyhow::bail!("invalid signing certificate");
+    }
+    let para = CERT_CHAIN_PARA { cbSize: std::mem::size_of::<CERT_CHAIN_PARA>() as u32, ..Default::default() };
+    let mut chain = std::ptr::null_mut();
+    let built = unsafe { CertGetCertificateChain(HCERTCHAINENGINE::default(), cert, None, store, &para, 0, None, &mut chain) };
+    unsafe { CertFreeCertificateContext(Some(cert)) };
+    unsafe { CertCloseStore(store, 0) };
+    anyhow::ensure!(built.as_bool() && !chain.is_null(), "the certificate chain could not be built");
+    let status = unsafe { (*chain).TrustStatus.dwErrorStatus };
+    unsafe { CertFreeCertificateChain(chain) };
+    let reason = match status {
+        CERT_TRUST_NO_ERROR => return Ok(()),
+        s if s & CERT_TRUST_IS_NOT_SIGNATURE_VALID != 0 => "a certificate in the chain has an invalid signature",
+        s if s & CERT_TRUST_IS_EXPLICIT_DISTRUST != 0 => "the certificate is explicitly distrusted",
+        s if s & CERT_TRUST_IS_PARTIAL_CHAIN != 0 => "the issuer certificate was not found",
+        s if s & CERT_TRUST_IS_UNTRUSTED_ROOT != 0 => "the root certificate is not trusted",
+        s if s & CERT_TRUST_IS_NOT_TIME_VALID != 0 => "the certificate has expired or is not yet valid",
+        s => anyhow::bail!("certificate chain error 0x{s:x}"),
+    };
+    anyhow::bail!(reason)
+}
+
+fn format_validation(validation: &Validation) -> String {
+    match validation {
+        Validation::Valid => format!("✓ {}", tr(Msg::C2paValid)),
+        Validation::Untrusted(reason) => format!("⚠ {} ({reason})", tr(Msg::C2paUntrusted)),
+        Validation::Invalid(reason) => format!("⚠ {} ({reason})", tr(Msg::C2paInvalid)),
+        Validation::NotChecked(reason) => format!("{} ({reason})", tr(Msg::C2paNotChecked)),
+    }
+}
+
+// 生成 AI で作ったことを示す digitalSourceType
+fn is_ai_generated(action: &Action) -> bool {
+    action.digital_source_type.as_deref()
+        .is_some_and(|t| t.ends_with("/trainedAlgorithmicMedia") || t.ends_with("/compositeWithTrainedAlgorithmicMedia"))
+}
+
+impl Manifest {
+    fn format(&self, active: bool, fields: &[redact::Field]) -> String {
+        let active = if active { format!(" ({})", tr(Msg::C2paActive)) } else { String::new() };
+        let mut ret = format!("{}: {}{active}\r\n", tr(Msg::C2paManifest), self.label);
+        if let Some(generator) = &self.claim_generator {
+            ret.push_str(&format!("  {}: {generator}\r\n", tr(Msg::C2paClaimGenerator)));
+        }
+        if let Some(title) = &self.title {
+            let format = self.format.as_ref().map(|format| format!(" ({format})")).unwrap_or_default();
+            ret.push_str(&format!("  {}: {title}{format}\r\n", tr(Msg::C2paTitle)));
+        }
+        let algorithm = self.signature.algorithm.map(|a| format!(" [{a}]")).unwrap_or_default();
+        ret.push_str(&format!("  {}: {}{algorithm}\r\n", tr(Msg::C2paSignature), format_validation(&self.signature.status)));
+        if let Some(signer) = &self.signature.signer {
+            // 署名者は個人の名前のことがあるので、所有者名と一緒に伏せる
+            let signer = if fields.contains(&redact::Field::Owner) { redact::MASK } else { signer };
+            ret.push_str(&format!("  {}: {signer}\r\n", tr(Msg::C2paSigner)));
+        }
+        if let Some(data_hash) = &self.data_hash {
+            ret.push_str(&format!("  {}: {}\r\n", tr(Msg::C2paContentHash), format_validation(data_hash)));
+        }
+        let assertions = if self.mismatched_assertions.is_empty() {
+            format!("✓ {} ({})", tr(Msg::C2paValid), self.assertions.join(", "))
+        } else {
+            format!("⚠ {} ({})", tr(Msg::C2paInvalid), self.mismatched_assertions.join(", "))
+        };
+        ret.push_str(&format!("  {}: {assertions}\r\n", tr(Msg::C2paAssertionHashes)));
+        if !self.actions.is_empty() {
+            ret.push_str(&format!("  {}:\r\n", tr(Msg::C2paActions)));
+            for action in &self.actions {
+                let mut line = format!("    {}", action.action);
+                if let Some(agent) = &action.software_agent {
+                    line.push_str(&format!(" — {agent}"));
+                }
+                if let Some(when) = &action.when {
+                    line.push_str(&format!(" ({when})"));
+                }
+                if let Some(source) = &action.digital_source_type {
+                    line.push_str(&format!(" [{}]", source.rsplit('/').next().unwrap_or(source)));
+                }
+                ret.push_str(&line);
+                ret.push_str("\r\n");
+            }
+        }
+        if !self.ingredients.is_empty() {
+            ret.push_str(&format!("  {}:\r\n", tr(Msg::C2paIngredients)));
+            for ingredient in &self.ingredients {
+                let details: Vec<&str> = [&ingredient.format, &ingredient.relationship].into_iter().flatten().map(String::as_str).collect();
+                let details = if details.is_empty() { String::new() } else { format!(" ({})", details.join(", ")) };
+                ret.push_str(&format!("    {}{details}\r\n", ingredient.title.as_deref().unwrap_or("-")));
+            }
+        }
+        ret
+    }
+}
+
+impl ManifestStore {
+    // 現在のマニフェストから順に出す。fields は伏せる値 (パスなどは redact::redact_text で伏せる)
+    pub fn format(&self, fields: &[redact::Field]) -> String {
+        let mut ret = format!("【{}】\r\n", tr(Msg::ContentCredentials));
+        if self.manifests.is_empty() {
+            ret.push_str(&format!("⚠ {}\r\n\r\n", tr(Msg::C2paUnreadable)));
+            return ret;
+        }
+        for (i, manifest) in self.manifests.iter().rev().enumerate() {
+            ret.push_str(&manifest.format(i == 0, fields));
+        }
+        if self.manifests.last().is_some_and(|m| m.actions.iter().any(is_ai_generated)) {
+            ret.push_str(&format!("⚠ {}\r\n", tr(Msg::C2paAiGenerated)));
+        }
+        ret.push_str(&format!("{}\r\n\r\n", tr(Msg::C2paTrustStore)));
+        redact::redact_text(&ret, fields).into_owned()
+    }
+}
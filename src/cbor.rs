@@ -0,0 +1,235 @@
+// 最小限の CBOR デコーダー (C2PA のマニフェストを読むのに使う)
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i128),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    // キーの順序を保つ
+    Map(Vec<(Value, Value)>),
+    Tag(u64, Box<Value>),
+    Bool(bool),
+    Null,
+    Float(f64),
+}
+
+impl Value {
+    // 文字列のキーで引く
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries.iter().find(|(k, _)| k.as_str() == Some(key)).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    // 整数のキーで引く (COSE のヘッダー)
+    pub fn get_int(&self, key: i128) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries.iter().find(|(k, _)| *k == Value::Integer(key)).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Text(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_i128(&self) -> Option<i128> {
+        match self {
+            Value::Integer(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    // タグを外した中身
+    pub fn untagged(&self) -> &Value {
+        match self {
+            Value::Tag(_, value) => value.untagged(),
+            value => value,
+        }
+    }
+}
+
+// 入れ子が深すぎる入力でスタックを使い切らないようにする
+const MAX_DEPTH: usize = 128;
+
+// 余分なバイトが続いていたら None
+pub fn decode(data: &[u8]) -> Option<Value> {
+    let mut decoder = Decoder { data, pos: 0 };
+    let value = decoder.value(0)?;
+    (decoder.pos == data.len()).then_some(value)
+}
+
+// 主要型と引数を書く (エンコードは COSE の署名対象を作るのにだけ使う)
+pub fn encode_header(major: u8, len: u64, out: &mut Vec<u8>) {
+    let major = major << 5;
+    match len {
+        0..=23 => out.push(major | len as u8),
+        24..=0xff => out.extend([major | 24, len as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend((len as u16).to_be_bytes());
+        }
+        0x10000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend((len as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend(len.to_be_bytes());
+        }
+    }
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Decoder<'_> {
+    fn take(&mut self, len: usize) -> Option<&[u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    // 引数。不定長 (31) なら None を返す
+    fn argument(&mut self, info: u8) -> Option<Option<u64>> {
+        let n = match info {
+            0..=23 => info as u64,
+            24 => self.take(1)?[0] as u64,
+            25 => u16::from_be_bytes(self.take(2)?.try_into().ok()?) as u64,
+            26 => u32::from_be_bytes(self.take(4)?.try_into().ok()?) as u64,
+            27 => u64::from_be_bytes(self.take(8)?.try_into().ok()?),
+            31 => return Some(None),
+            _ => return None,
+        };
+        Some(Some(n))
+    }
+
+    // 残りのバイト数より多い要素数は壊れたデータとみなす (巨大な確保をしないように)
+    fn count(&self, n: u64) -> Option<usize> {
+        let n = usize::try_from(n).ok()?;
+        (n <= self.data.len() - self.pos).then_some(n)
+    }
+
+    fn is_break(&mut self) -> bool {
+        let found = self.data.get(self.pos) == Some(&0xff);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    // 不定長の文字列は同じ型の断片をつなぐ
+    fn string(&mut self, major: u8, argument: Option<u64>) -> Option<Vec<u8>> {
+        if let Some(len) = argument {
+            return Some(self.take(self.count(len)?)?.to_vec());
+        }
+        let mut bytes = Vec::new();
+        while !self.is_break() {
+            let initial = *self.data.get(self.pos)?;
+            self.pos += 1;
+            (initial >> 5 == major).then_some(())?;
+            let len = self.argument(initial & 0x1f)??;
+            bytes.extend_from_slice(self.take(self.count(len)?)?);
+        }
+        Some(bytes)
+    }
+
+    fn value(&mut self, depth: usize) -> Option<Value> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let initial = *self.data.get(self.pos)?;
+        self.pos += 1;
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        if major == 7 {
+            return self.simple(info);
+        }
+        let argument = self.argument(info)?;
+        match major {
+            0 => Some(Value::Integer(argument? as i128)),
+            1 => Some(Value::Integer(-1 - argument? as i128)),
+            2 => self.string(2, argument).map(Value::Bytes),
+            3 => String::from_utf8(self.string(3, argument)?).ok().map(Value::Text),
+            4 => {
+                let mut items = Vec::new();
+                match argument {
+                    Some(n) => {
+                        for _ in 0..self.count(n)? {
+                            items.push(self.value(depth + 1)?);
+                        }
+                    }
+                    None => {
+                        while !self.is_break() {
+                            items.push(self.value(depth + 1)?);
+                        }
+                    }
+                }
+                Some(Value::Array(items))
+            }
+            5 => {
+                let mut entries = Vec::new();
+                match argument {
+                    Some(n) => {
+                        for _ in 0..self.count(n)? {
+                            entries.push((self.value(depth + 1)?, self.value(depth + 1)?));
+                        }
+                    }
+                    None => {
+                        while !self.is_break() {
+                            entries.push((self.value(depth + 1)?, self.value(depth + 1)?));
+                        }
+                    }
+                }
+                Some(Value::Map(entries))
+            }
+            _ => Some(Value::Tag(argument?, Box::new(self.value(depth + 1)?))),
+        }
+    }
+
+    fn simple(&mut self, info: u8) -> Option<Value> {
+        match info {
+            20 => Some(Value::Bool(false)),
+            21 => Some(Value::Bool(true)),
+            // undefined も null として扱う
+            22 | 23 => Some(Value::Null),
+            25 => Some(Value::Float(half_to_f64(u16::from_be_bytes(self.take(2)?.try_into().ok()?)))),
+            26 => Some(Value::Float(f32::from_be_bytes(self.take(4)?.try_into().ok()?) as f64)),
+            27 => Some(Value::Float(f64::from_be_bytes(self.take(8)?.try_into().ok()?))),
+            _ => None,
+        }
+    }
+}
+
+// 半精度の浮動小数点数
+fn half_to_f64(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = (bits >> 10) & 0x1f;
+    let fraction = (bits & 0x3ff) as f64;
+    sign * match exponent {
+        0 => fraction * 2f64.powi(-24),
+        31 if fraction == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (1.0 + fraction / 1024.0) * 2f64.powi(exponent as i32 - 15),
+    }
+}
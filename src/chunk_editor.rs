@@ -0,0 +1,236 @@
+// テキストチャンクを編集して PNG に書き戻す
+
+use std::fs;
+use std::path::Path;
+use windows::{
+    core::*,
+    Win32::{
+        Foundation::*,
+        UI::{Controls::*, WindowsAndMessaging::*},
+    },
+};
+use crate::dialog::{self, DialogTemplate};
+use crate::fsutil;
+use crate::i18n::{tr, Msg};
+use crate::png_chunks::{self, TextChunk, TextKind};
+
+const IDC_KEYWORD: i32 = 100;
+const IDC_TEXT: i32 = 101;
+const IDC_BACKUP: i32 = 102;
+const IDC_NEW_KEYWORD: i32 = 103;
+const IDC_TYPE_TEXT: i32 = 104;
+const IDC_TYPE_ITXT: i32 = 105;
+
+struct EditorState {
+    chunks: Vec<TextChunk>,
+    modified: Vec<bool>,
+    current: usize,
+    backup: bool,
+}
+
+pub struct EditResult {
+    // 変更されたチャンクのみ
+    pub chunks: Vec<TextChunk>,
+    pub backup: bool,
+}
+
+pub fn show(parent: HWND, chunks: Vec<TextChunk>, backup: bool) -> Option<EditResult> {
+    let mut state = EditorState {
+        modified: vec![false; chunks.len()],
+        chunks,
+        current: 0,
+        backup,
+    };
+    let template = DialogTemplate::new(tr(Msg::EditChunkTitle), 400, 280)
+        .item(dialog::COMBOBOX, "", IDC_KEYWORD, (CBS_DROPDOWNLIST as u32) | WS_VSCROLL.0 | WS_TABSTOP.0, 7, 7, 386, 200)
+        .item(dialog::EDIT, "", IDC_TEXT,
+            (ES_MULTILINE | ES_AUTOVSCROLL | ES_WANTRETURN) as u32 | WS_BORDER.0 | WS_VSCROLL.0 | WS_TABSTOP.0,
+            7, 25, 386, 226)
+        .item(dialog::BUTTON, tr(Msg::CreateBackup), IDC_BACKUP, BS_AUTOCHECKBOX as u32 | WS_TABSTOP.0, 7, 259, 200, 14)
+        .item(dialog::BUTTON, tr(Msg::Save), IDOK.0, BS_DEFPUSHBUTTON as u32 | WS_TABSTOP.0, 289, 259, 50, 14)
+        .item(dialog::BUTTON, tr(Msg::Cancel), IDCANCEL.0, WS_TABSTOP.0, 343, 259, 50, 14);
+    let ret = template.show(parent, Some(dialog_proc), LPARAM(&mut state as *mut _ as isize));
+    if ret != IDOK.0 as isize {
+        return None;
+    }
+    let chunks = state.chunks.into_iter()
+        .zip(state.modified)
+        .filter_map(|(chunk, modified)| modified.then_some(chunk))
+        .collect();
+    Some(EditResult { chunks, backup: state.backup })
+}
+
+fn to_crlf(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\n', "\r\n")
+}
+
+unsafe fn get_state<'a>(hdlg: HWND) -> Option<&'a mut EditorState> {
+    (GetWindowLongPtrW(hdlg, GWLP_USERDATA) as *mut EditorState).as_mut()
+}
+
+// 編集中のテキストを state に取り込む
+fn store_current(hdlg: HWND, state: &mut EditorState) {
+    let modified = unsafe { SendDlgItemMessageW(hdlg, IDC_TEXT, EM_GETMODIFY, WPARAM(0), LPARAM(0)) }.0 != 0;
+    if modified {
+        state.chunks[state.current].text = dialog::get_item_text(hdlg, IDC_TEXT).replace("\r\n", "\n");
+        state.modified[state.current] = true;
+    }
+}
+
+fn load_current(hdlg: HWND, state: &EditorState) {
+    let text = HSTRING::from(to_crlf(&state.chunks[state.current].text));
+    unsafe { SetDlgItemTextW(hdlg, IDC_TEXT, &text) };
+    unsafe { SendDlgItemMessageW(hdlg, IDC_TEXT, EM_SETMODIFY, WPARAM(0), LPARAM(0)) };
+}
+
+extern "system" fn dialog_proc(hdlg: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> isize {
+    match message {
+        WM_INITDIALOG => {
+            unsafe { SetWindowLongPtrW(hdlg, GWLP_USERDATA, lparam.0) };
+            let state = unsafe { get_state(hdlg) }.unwrap();
+            for chunk in &state.chunks {
+                let label = HSTRING::from(chunk.keyword.as_str());
+                unsafe { SendDlgItemMessageW(hdlg, IDC_KEYWORD, CB_ADDSTRING, WPARAM(0), LPARAM(label.as_ptr() as isize)) };
+            }
+            unsafe { SendDlgItemMessageW(hdlg, IDC_KEYWORD, CB_SETCURSEL, WPARAM(0), LPARAM(0)) };
+            load_current(hdlg, state);
+            if state.backup {
+                unsafe { CheckDlgButton(hdlg, IDC_BACKUP, BST_CHECKED) };
+            }
+            1
+        }
+        WM_COMMAND => {
+            let Some(state) = (unsafe { get_state(hdlg) }) else { return 0 };
+            let id = (wparam.0 & 0xffff) as i32;
+            let code = (wparam.0 >> 16) as u32;
+            match id {
+                IDC_KEYWORD if code == CBN_SELCHANGE => {
+                    store_current(hdlg, state);
+                    let sel = unsafe { SendDlgItemMessageW(hdlg, IDC_KEYWORD, CB_GETCURSEL, WPARAM(0), LPARAM(0)) }.0;
+                    state.current = sel.max(0) as usize;
+                    load_current(hdlg, state);
+                    1
+                }
+                id if id == IDOK.0 => {
+                    store_current(hdlg, state);
+                    state.backup = unsafe { IsDlgButtonChecked(hdlg, IDC_BACKUP) } == BST_CHECKED.0;
+                    unsafe { EndDialog(hdlg, IDOK.0 as isize) };
+                    1
+                }
+                id if id == IDCANCEL.0 => {
+                    unsafe { EndDialog(hdlg, IDCANCEL.0 as isize) };
+                    1
+                }
+                _ => 0,
+            }
+        }
+        _ => 0,
+    }
+}
+
+struct AddState {
+    chunk: Option<TextChunk>,
+    backup: bool,
+}
+
+// 新しいテキストチャンクの内容を入力してもらう
+pub fn show_add(parent: HWND, backup: bool) -> Option<EditResult> {
+    let mut state = AddState { chunk: None, backup };
+    let template = DialogTemplate::new(tr(Msg::AddChunkTitle), 400, 280)
+        .item(dialog::STATIC, tr(Msg::Keyword), -1, 0, 7, 9, 50, 10)
+        .item(dialog::EDIT, "", IDC_NEW_KEYWORD, ES_AUTOHSCROLL as u32 | WS_BORDER.0 | WS_TABSTOP.0, 60, 7, 160, 14)
+        .item(dialog::BUTTON, "tEXt", IDC_TYPE_TEXT, BS_AUTORADIOBUTTON as u32 | WS_GROUP.0 | WS_TABSTOP.0, 240, 8, 50, 12)
+        .item(dialog::BUTTON, "iTXt", IDC_TYPE_ITXT, BS_AUTORADIOBUTTON as u32, 295, 8, 50, 12)
+        .item(dialog::EDIT, "", IDC_TEXT,
+            (ES_MULTILINE | ES_AUTOVSCROLL | ES_WANTRETURN) as u32 | WS_BORDER.0 | WS_VSCROLL.0 | WS_GROUP.0 | WS_TABSTOP.0,
+            7, 25, 386, 226)
+        .item(dialog::BUTTON, tr(Msg::CreateBackup), IDC_BACKUP, BS_AUTOCHECKBOX as u32 | WS_TABSTOP.0, 7, 259, 200, 14)
+        .item(dialog::BUTTON, tr(Msg::Save), IDOK.0, BS_DEFPUSHBUTTON as u32 | WS_TABSTOP.0, 289, 259, 50, 14)
+        .item(dialog::BUTTON, tr(Msg::Cancel), IDCANCEL.0, WS_TABSTOP.0, 343, 259, 50, 14);
+    let ret = template.show(parent, Some(add_dialog_proc), LPARAM(&mut state as *mut _ as isize));
+    if ret != IDOK.0 as isize {
+        return None;
+    }
+    let chunk = state.chunk?;
+    Some(EditResult { chunks: vec![chunk], backup: state.backup })
+}
+
+extern "system" fn add_dialog_proc(hdlg: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> isize {
+    match message {
+        WM_INITDIALOG => {
+            unsafe { SetWindowLongPtrW(hdlg, GWLP_USERDATA, lparam.0) };
+            let state = unsafe { (lparam.0 as *mut AddState).as_mut() }.unwrap();
+            unsafe { CheckRadioButton(hdlg, IDC_TYPE_TEXT, IDC_TYPE_ITXT, IDC_TYPE_TEXT) };
+            if state.backup {
+                unsafe { CheckDlgButton(hdlg, IDC_BACKUP, BST_CHECKED) };
+            }
+            1
+        }
+        WM_COMMAND => {
+            let state = unsafe { (GetWindowLongPtrW(hdlg, GWLP_USERDATA) as *mut AddState).as_mut() };
+            let Some(state) = state else { return 0 };
+            let id = (wparam.0 & 0xffff) as i32;
+            if id == IDOK.0 {
+                let keyword = dialog::get_item_text(hdlg, IDC_NEW_KEYWORD);
+                if !png_chunks::is_valid_keyword(&keyword) {
+                    let text = HSTRING::from(tr(Msg::InvalidKeyword));
+                    unsafe { MessageBoxW(hdlg, &text, None, MB_OK | MB_ICONWARNING) };
+                    return 1;
+                }
+                let is_itxt = unsafe { IsDlgButtonChecked(hdlg, IDC_TYPE_ITXT) } == BST_CHECKED.0;
+                state.chunk = Some(TextChunk {
+                    index: 0,
+                    kind: if is_itxt { TextKind::ITXt } else { TextKind::TEXt },
+                    keyword,
+                    language_tag: String::new(),
+                    translated_keyword: String::new(),
+                    text: dialog::get_item_text(hdlg, IDC_TEXT).replace("\r\n", "\n"),
+                });
+                state.backup = unsafe { IsDlgButtonChecked(hdlg, IDC_BACKUP) } == BST_CHECKED.0;
+                unsafe { EndDialog(hdlg, IDOK.0 as isize) };
+                1
+            } else if id == IDCANCEL.0 {
+                unsafe { EndDialog(hdlg, IDCANCEL.0 as isize) };
+                1
+            } else {
+                0
+            }
+        }
+        _ => 0,
+    }
+}
+
+// IEND の直前にチャンクを追加して保存する
+pub fn add(path: &Path, chunk: &TextChunk, backup: bool) -> anyhow::Result<()> {
+    let path = &fsutil::long_path(path);
+    let file = fs::read(path)?;
+    let chunks = png_chunks::parse_chunks(&file)?;
+    let new_file = png_chunks::insert_before_iend(&file, &chunks, &chunk.encode())?;
+    write_with_backup(path, &new_file, backup)
+}
+
+// 変更されたチャンクを書き戻す。それ以外のバイト列はそのまま残す
+pub fn save(path: &Path, edited: &[TextChunk], backup: bool) -> anyhow::Result<()> {
+    let path = &fsutil::long_path(path);
+    let file = fs::read(path)?;
+    let chunks = png_chunks::parse_chunks(&file)?;
+    let replacements: Vec<(usize, Vec<u8>)> = edited.iter()
+        .map(|chunk| (chunk.index, chunk.encode()))
+        .collect();
+    let new_file = png_chunks::rewrite(&file, &chunks, &replacements);
+    write_with_backup(path, &new_file, backup)
+}
+
+pub fn write_with_backup(path: &Path, content: &[u8], backup: bool) -> anyhow::Result<()> {
+    if backup {
+        let mut backup_path = path.as_os_str().to_owned();
+        backup_path.push(".bak");
+        fs::copy(path, backup_path)?;
+    }
+    // 書き込み途中で失敗しても元のファイルが壊れないように一時ファイル経由で置き換える
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, content)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
@@ -0,0 +1,52 @@
+// Civitai の API でモデルのハッシュから名前を調べる (設定で有効にしたときだけ)
+
+use windows::Win32::{
+    Foundation::*,
+    UI::WindowsAndMessaging::*,
+};
+use crate::download;
+use crate::json;
+
+// lparam: Box<Vec<(ハッシュ, 名前)>> のポインタ。名前が None なら Civitai に登録されていない
+pub const WM_APP_CIVITAI_DONE: u32 = WM_APP + 4;
+
+const API_URL: &str = "https://civitai.com/api/v1/model-versions/by-hash/";
+
+pub fn start(hwnd: HWND, hashes: Vec<String>) {
+    std::thread::spawn(move || {
+        // 通信に失敗したものはキャッシュせず、次に開いたときにまた調べる
+        let results: Vec<(String, Option<String>)> = hashes.into_iter()
+            .filter_map(|hash| lookup(&hash).ok().map(|name| (hash, name)))
+            .collect();
+        let results = Box::into_raw(Box::new(results));
+        let posted = unsafe { PostMessageW(hwnd, WM_APP_CIVITAI_DONE, WPARAM(0), LPARAM(results as isize)) };
+        if !posted.as_bool() {
+            drop(unsafe { Box::from_raw(results) });
+        }
+    });
+}
+
+// WM_APP_CIVITAI_DONE の lparam から結果を取り出す
+pub unsafe fn take_result(lparam: LPARAM) -> Vec<(String, Option<String>)> {
+    *Box::from_raw(lparam.0 as *mut Vec<(String, Option<String>)>)
+}
+
+fn lookup(hash: &str) -> anyhow::Result<Option<String>> {
+    anyhow::ensure!(hash.chars().all(|c| c.is_ascii_hexdigit()), "invalid hash: {hash}");
+    let (status, body) = download::fetch(&format!("{API_URL}{hash}"), |_, _| {})?;
+    match status {
+        404 => Ok(None),
+        200 => {
+            let version = json::parse(&String::from_utf8_lossy(&body))
+                .ok_or_else(|| anyhow::anyhow!("invalid response from Civitai"))?;
+            let model = version.get("model").and_then(|model| model.get("name")).and_then(json::Value::as_str);
+            let name = match (model, version.get("name").and_then(json::Value::as_str)) {
+                (Some(model), Some(version)) => format!("{model} ({version})"),
+                (Some(name), None) | (None, Some(name)) => name.to_owned(),
+                (None, None) => anyhow::bail!("invalid response from Civitai"),
+            };
+            Ok(Some(name))
+        }
+        status => anyhow::bail!("HTTP {status}"),
+    }
+}
@@ -0,0 +1,30 @@
+// クリップボードへの書き込み
+
+use windows::Win32::{
+    Foundation::*,
+    System::{
+        DataExchange::{OpenClipboard, EmptyClipboard, SetClipboardData, CloseClipboard},
+        Memory::{GlobalAlloc, GlobalLock, GlobalUnlock, GMEM_MOVEABLE},
+        SystemServices::CF_UNICODETEXT,
+    },
+};
+
+pub fn set_text(hwnd: HWND, text: &str) -> anyhow::Result<()> {
+    let wide: Vec<u16> = text.replace("\r\n", "\n").replace('\n', "\r\n")
+        .encode_utf16().chain(Some(0)).collect();
+    let size = wide.len() * 2;
+    unsafe { OpenClipboard(hwnd) }.ok()?;
+    let result = (|| {
+        unsafe { EmptyClipboard() }.ok()?;
+        let hglobal = unsafe { GlobalAlloc(GMEM_MOVEABLE, size) };
+        anyhow::ensure!(hglobal != 0, "GlobalAlloc failed");
+        let ptr = unsafe { GlobalLock(hglobal) } as *mut u16;
+        anyhow::ensure!(!ptr.is_null(), "GlobalLock failed");
+        unsafe { std::ptr::copy_nonoverlapping(wide.as_ptr(), ptr, wide.len()) };
+        unsafe { GlobalUnlock(hglobal) };
+        unsafe { SetClipboardData(CF_UNICODETEXT.0, HANDLE(hglobal)) }?;
+        Ok(())
+    })();
+    unsafe { CloseClipboard() };
+    result
+}
@@ -0,0 +1,145 @@
+// 落ちたときの後始末。windows サブシステムでは panic もアクセス違反も何も表示されずにウィンドウが消えるので、
+// ログ (SEH の例外ではミニダンプも) を設定ファイルのフォルダーに書き、詳細をコピーできるダイアログを出す
+
+use std::backtrace::Backtrace;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::os::windows::io::AsRawHandle;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use windows::Win32::{
+    Foundation::*,
+    System::{
+        Diagnostics::Debug::*,
+        Threading::{GetCurrentProcess, GetCurrentProcessId, GetCurrentThreadId},
+    },
+    UI::WindowsAndMessaging::*,
+};
+use crate::clipboard;
+use crate::dialog::{self, DialogTemplate};
+use crate::history;
+use crate::i18n::{tr, Msg};
+use crate::settings;
+use crate::update;
+use crate::APP_TITLE;
+
+const LOG_FILE: &str = "crash.log";
+const DUMP_FILE: &str = "crash.dmp";
+const IDC_DETAILS: i32 = 100;
+const IDC_COPY: i32 = 101;
+// SetUnhandledExceptionFilter の戻り値。プロセスを終わらせる
+const EXCEPTION_EXECUTE_HANDLER: i32 = 1;
+
+// 後始末の途中でまた落ちたときは何もしない
+static CRASHED: AtomicBool = AtomicBool::new(false);
+
+pub fn install() {
+    std::panic::set_hook(Box::new(|info| {
+        if CRASHED.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let message = info.payload().downcast_ref::<&str>().copied()
+            .or_else(|| info.payload().downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("Box<dyn Any>");
+        let location = info.location().map(|l| format!(" ({}:{})", l.file(), l.line())).unwrap_or_default();
+        let thread = std::thread::current().name().unwrap_or("<unnamed>").to_owned();
+        let details = format!("panic in thread '{thread}': {message}{location}\r\n\r\n{}", Backtrace::force_capture());
+        report(&details, false);
+        std::process::exit(101);
+    }));
+    unsafe { SetUnhandledExceptionFilter(Some(exception_filter)) };
+}
+
+unsafe extern "system" fn exception_filter(pointers: *const EXCEPTION_POINTERS) -> i32 {
+    if CRASHED.swap(true, Ordering::SeqCst) {
+        return EXCEPTION_EXECUTE_HANDLER;
+    }
+    let record = (*pointers).ExceptionRecord.as_ref();
+    let details = match record {
+        Some(record) => format!("exception 0x{:08x} at {:p}", record.ExceptionCode.0 as u32, record.ExceptionAddress),
+        None => "unknown exception".to_owned(),
+    };
+    let dumped = write_minidump(pointers).is_ok();
+    report(&details, dumped);
+    EXCEPTION_EXECUTE_HANDLER
+}
+
+fn crash_file(name: &str) -> Option<PathBuf> {
+    let dir = settings::data_dir()?;
+    fs::create_dir_all(&dir).ok()?;
+    Some(dir.join(name))
+}
+
+fn write_minidump(pointers: *const EXCEPTION_POINTERS) -> anyhow::Result<()> {
+    let path = crash_file(DUMP_FILE).ok_or_else(|| anyhow::anyhow!("no data directory"))?;
+    let file = File::create(path)?;
+    let exception = MINIDUMP_EXCEPTION_INFORMATION {
+        ThreadId: unsafe { GetCurrentThreadId() },
+        ExceptionPointers: pointers as *mut EXCEPTION_POINTERS,
+        ClientPointers: false.into(),
+    };
+    let written = unsafe {
+        MiniDumpWriteDump(GetCurrentProcess(), GetCurrentProcessId(), HANDLE(file.as_raw_handle() as isize),
+            MiniDumpNormal, Some(&exception), None, None)
+    };
+    anyhow::ensure!(written.as_bool(), "MiniDumpWriteDump failed");
+    Ok(())
+}
+
+// ログに追記してからダイアログを出す
+fn report(details: &str, dumped: bool) {
+    let mut details = format!("{} {}\r\n{}\r\n", APP_TITLE, update::CURRENT_VERSION, details);
+    let log = crash_file(LOG_FILE);
+    if let Some(path) = &log {
+        if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+            let _ = write!(file, "==== {} ====\r\n{details}\r\n", history::now());
+        }
+    }
+    if dumped {
+        if let Some(path) = crash_file(DUMP_FILE) {
+            details.push_str(&format!("\r\n{}: {}\r\n", tr(Msg::CrashDump), path.display()));
+        }
+    }
+    if let Some(path) = &log {
+        details.push_str(&format!("{}: {}\r\n", tr(Msg::CrashLog), path.display()));
+    }
+    // 落ちたスレッドでモーダルループを回すと、そのスレッドのウィンドウにメッセージが配られて
+    // 壊れた状態のまま続きが動くので、別のスレッドで表示して終わるまで待つ
+    let _ = std::thread::spawn(move || show_dialog(&details)).join();
+}
+
+fn show_dialog(details: &str) {
+    let details = details.replace("\r\n", "\n").replace('\n', "\r\n");
+    let template = DialogTemplate::new(tr(Msg::CrashTitle), 360, 220)
+        .item(dialog::STATIC, tr(Msg::CrashDescription), -1, 0, 7, 7, 346, 20)
+        .item(dialog::EDIT, "", IDC_DETAILS,
+            WS_BORDER.0 | WS_VSCROLL.0 | WS_HSCROLL.0 | WS_TABSTOP.0 | (ES_MULTILINE | ES_READONLY | ES_AUTOVSCROLL | ES_AUTOHSCROLL) as u32,
+            7, 31, 346, 160)
+        .item(dialog::BUTTON, tr(Msg::CrashCopyDetails), IDC_COPY, WS_TABSTOP.0, 7, 199, 80, 14)
+        .item(dialog::BUTTON, tr(Msg::Close), IDOK.0, BS_DEFPUSHBUTTON as u32 | WS_TABSTOP.0, 303, 199, 50, 14);
+    template.show(HWND::default(), Some(dialog_proc), LPARAM(&details as *const String as isize));
+}
+
+extern "system" fn dialog_proc(hdlg: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> isize {
+    match message {
+        WM_INITDIALOG => {
+            let details = unsafe { &*(lparam.0 as *const String) };
+            unsafe { SetDlgItemTextW(hdlg, IDC_DETAILS, &windows::core::HSTRING::from(details.as_str())) };
+            unsafe { SetForegroundWindow(hdlg) };
+            1
+        }
+        WM_COMMAND => {
+            let id = (wparam.0 & 0xffff) as i32;
+            if id == IDC_COPY {
+                let _ = clipboard::set_text(hdlg, &dialog::get_item_text(hdlg, IDC_DETAILS));
+                1
+            } else if id == IDOK.0 || id == IDCANCEL.0 {
+                unsafe { EndDialog(hdlg, id as isize) };
+                1
+            } else {
+                0
+            }
+        }
+        _ => 0,
+    }
+}
@@ -0,0 +1,164 @@
+// リソースファイルを使わずにダイアログテンプレートをメモリ上で組み立てる
+
+use std::ffi::OsString;
+use std::os::windows::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use windows::{
+    core::*,
+    Win32::{
+        Foundation::*,
+        System::LibraryLoader::GetModuleHandleW,
+        UI::{Controls::Dialogs::*, WindowsAndMessaging::*},
+    },
+};
+
+// 定義済みウィンドウクラスのアトム
+pub const BUTTON: u16 = 0x0080;
+pub const EDIT: u16 = 0x0081;
+pub const STATIC: u16 = 0x0082;
+pub const LISTBOX: u16 = 0x0083;
+pub const COMBOBOX: u16 = 0x0085;
+
+pub struct DialogTemplate {
+    buf: Vec<u16>,
+    count: u16,
+}
+
+impl DialogTemplate {
+    // 位置と大きさはダイアログ単位
+    pub fn new(title: &str, cx: i16, cy: i16) -> DialogTemplate {
+        let style = WS_POPUP.0 | WS_CAPTION.0 | WS_SYSMENU.0
+            | (DS_MODALFRAME | DS_SETFONT | DS_CENTER) as u32;
+        let mut t = DialogTemplate { buf: Vec::new(), count: 0 };
+        t.push_u32(style);
+        t.push_u32(0);
+        t.buf.push(0); // 項目数 (build で埋める)
+        for v in [0, 0, cx, cy] {
+            t.buf.push(v as u16);
+        }
+        t.buf.push(0); // メニューなし
+        t.buf.push(0); // 既定のクラス
+        t.push_str(title);
+        t.buf.push(9);
+        t.push_str("Segoe UI");
+        t
+    }
+
+    fn push_u32(&mut self, v: u32) {
+        self.buf.push(v as u16);
+        self.buf.push((v >> 16) as u16);
+    }
+
+    fn push_str(&mut self, s: &str) {
+        self.buf.extend(s.encode_utf16());
+        self.buf.push(0);
+    }
+
+    fn push_item_header(&mut self, id: i32, style: u32, x: i16, y: i16, cx: i16, cy: i16) {
+        // 各項目は DWORD 境界から始まる
+        if !self.buf.len().is_multiple_of(2) {
+            self.buf.push(0);
+        }
+        self.push_u32(WS_CHILD.0 | WS_VISIBLE.0 | style);
+        self.push_u32(0);
+        for v in [x, y, cx, cy] {
+            self.buf.push(v as u16);
+        }
+        self.buf.push(id as u16);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn item(mut self, class: u16, text: &str, id: i32, style: u32, x: i16, y: i16, cx: i16, cy: i16) -> DialogTemplate {
+        self.push_item_header(id, style, x, y, cx, cy);
+        self.buf.push(0xffff);
+        self.buf.push(class);
+        self.push_str(text);
+        self.buf.push(0);
+        self.count += 1;
+        self
+    }
+
+    // コモンコントロールなど、定義済みでないクラスの項目
+    #[allow(clippy::too_many_arguments)]
+    pub fn custom_item(mut self, class: &str, text: &str, id: i32, style: u32, x: i16, y: i16, cx: i16, cy: i16) -> DialogTemplate {
+        self.push_item_header(id, style, x, y, cx, cy);
+        self.push_str(class);
+        self.push_str(text);
+        self.buf.push(0);
+        self.count += 1;
+        self
+    }
+
+    // モーダルダイアログを表示する。param は WM_INITDIALOG の lparam として渡される
+    pub fn show(mut self, parent: HWND, proc: DLGPROC, param: LPARAM) -> isize {
+        self.buf[4] = self.count;
+        // テンプレートは DWORD 境界に置く必要がある
+        let words: Vec<u32> = self.buf.chunks(2)
+            .map(|c| c[0] as u32 | (c.get(1).copied().unwrap_or(0) as u32) << 16)
+            .collect();
+        let instance = unsafe { GetModuleHandleW(None) }.unwrap_or_default();
+        unsafe { DialogBoxIndirectParamW(instance, words.as_ptr() as *const DLGTEMPLATE, parent, proc, param) }
+    }
+}
+
+pub fn get_item_text(hdlg: HWND, id: i32) -> String {
+    let hitem = unsafe { GetDlgItem(hdlg, id) };
+    let len = unsafe { GetWindowTextLengthW(hitem) } as usize;
+    let mut buf = vec![0u16; len + 1];
+    let len = unsafe { GetWindowTextW(hitem, &mut buf) } as usize;
+    String::from_utf16_lossy(&buf[..len])
+}
+
+// ファイル名を入れるバッファの長さ (長いパスも入るように)
+const FILE_BUFFER_LEN: usize = 32768;
+
+// 選ばれたパス (バッファの NUL の前まで)
+fn selected_path(file: &[u16]) -> PathBuf {
+    let len = file.iter().position(|&c| c == 0).unwrap_or(file.len());
+    PathBuf::from(OsString::from_wide(&file[..len]))
+}
+
+// 名前を付けて保存するダイアログ。filter は "名前\0パターン\0...\0" の形 (最後の \0 は付けなくてよい)
+// 選ばれたパスと、選ばれたファイルの種類 (nFilterIndex, 1 から数える) を返す。キャンセルされたら None
+pub fn save_file_dialog(hwnd: HWND, default_name: &str, filter: &str, def_ext: PCWSTR, initial_dir: Option<&Path>) -> Option<(PathBuf, u32)> {
+    let mut file: Vec<u16> = default_name.encode_utf16().collect();
+    file.resize(FILE_BUFFER_LEN, 0);
+    let filter: Vec<u16> = format!("{filter}\0").encode_utf16().collect();
+    let dir = initial_dir.map(|dir| HSTRING::from(dir.as_os_str()));
+    let mut ofn = OPENFILENAMEW {
+        lStructSize: std::mem::size_of::<OPENFILENAMEW>() as u32,
+        hwndOwner: hwnd,
+        lpstrFilter: PCWSTR(filter.as_ptr()),
+        lpstrFile: PWSTR(file.as_mut_ptr()),
+        nMaxFile: file.len() as u32,
+        lpstrInitialDir: dir.as_ref().map_or(PCWSTR::null(), |dir| PCWSTR(dir.as_ptr())),
+        lpstrDefExt: def_ext,
+        Flags: OFN_OVERWRITEPROMPT | OFN_PATHMUSTEXIST,
+        ..Default::default()
+    };
+    if !unsafe { GetSaveFileNameW(&mut ofn) }.as_bool() {
+        return None;
+    }
+    Some((selected_path(&file), ofn.nFilterIndex))
+}
+
+// 開くダイアログ。filter の形は save_file_dialog と同じ。キャンセルされたら None
+pub fn open_file_dialog(hwnd: HWND, filter: &str, initial_dir: Option<&Path>) -> Option<PathBuf> {
+    let mut file = vec![0u16; FILE_BUFFER_LEN];
+    let filter: Vec<u16> = format!("{filter}\0").encode_utf16().collect();
+    let dir = initial_dir.map(|dir| HSTRING::from(dir.as_os_str()));
+    let mut ofn = OPENFILENAMEW {
+        lStructSize: std::mem::size_of::<OPENFILENAMEW>() as u32,
+        hwndOwner: hwnd,
+        lpstrFilter: PCWSTR(filter.as_ptr()),
+        lpstrFile: PWSTR(file.as_mut_ptr()),
+        nMaxFile: file.len() as u32,
+        lpstrInitialDir: dir.as_ref().map_or(PCWSTR::null(), |dir| PCWSTR(dir.as_ptr())),
+        Flags: OFN_FILEMUSTEXIST | OFN_PATHMUSTEXIST,
+        ..Default::default()
+    };
+    if !unsafe { GetOpenFileNameW(&mut ofn) }.as_bool() {
+        return None;
+    }
+    Some(selected_path(&file))
+}
@@ -0,0 +1,79 @@
+// ファイルのハッシュ (ダウンロードの検証用) と画素の知覚ハッシュ (見た目が同じ画像を探す用)
+
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, Default)]
+pub struct FileDigests {
+    pub sha256: String,
+    pub blake3: String,
+    // 設定で有効にしたときだけ計算する
+    pub phash: Option<String>,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub fn sha256(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+pub fn blake3(data: &[u8]) -> String {
+    blake3::hash(data).to_hex().to_string()
+}
+
+// 知覚ハッシュは imagehash の phash と同じく 32x32 のグレースケールの DCT の低域 8x8 から作る
+const PHASH_SIZE: usize = 32;
+const PHASH_LOW: usize = 8;
+
+// 上から下に並んだ 32bpp BGRA の画素を、面積の平均で size x size のグレースケールに縮める
+fn grayscale_resized(pixels: &[u8], width: usize, height: usize, size: usize) -> Vec<f64> {
+    let mut out = vec![0.0; size * size];
+    for ty in 0..size {
+        let (y0, y1) = (ty * height / size, ((ty + 1) * height / size).max(ty * height / size + 1));
+        for tx in 0..size {
+            let (x0, x1) = (tx * width / size, ((tx + 1) * width / size).max(tx * width / size + 1));
+            let mut sum = 0.0;
+            for y in y0..y1.min(height) {
+                for x in x0..x1.min(width) {
+                    let p = &pixels[(y * width + x) * 4..];
+                    sum += 0.299 * p[2] as f64 + 0.587 * p[1] as f64 + 0.114 * p[0] as f64;
+                }
+            }
+            let count = (y1.min(height) - y0) * (x1.min(width) - x0);
+            out[ty * size + tx] = sum / count.max(1) as f64;
+        }
+    }
+    out
+}
+
+// 低域の係数だけを求める DCT-II (正規化しない。大小の比較にしか使わない)
+fn dct_low(values: &[f64], size: usize, low: usize) -> Vec<f64> {
+    let cos: Vec<f64> = (0..low * size)
+        .map(|i| (std::f64::consts::PI * (2 * (i % size) + 1) as f64 * (i / size) as f64 / (2 * size) as f64).cos())
+        .collect();
+    let mut out = vec![0.0; low * low];
+    for u in 0..low {
+        for v in 0..low {
+            let mut sum = 0.0;
+            for y in 0..size {
+                for x in 0..size {
+                    sum += cos[u * size + y] * cos[v * size + x] * values[y * size + x];
+                }
+            }
+            out[u * low + v] = sum;
+        }
+    }
+    out
+}
+
+// 64 ビットを 16 桁の 16 進数で返す
+pub fn phash(pixels: &[u8], width: u32, height: u32) -> String {
+    let gray = grayscale_resized(pixels, width as usize, height as usize, PHASH_SIZE);
+    let coefs = dct_low(&gray, PHASH_SIZE, PHASH_LOW);
+    let mut sorted = coefs.clone();
+    sorted.sort_by(f64::total_cmp);
+    let median = (sorted[sorted.len() / 2 - 1] + sorted[sorted.len() / 2]) / 2.0;
+    let bits = coefs.iter().fold(0u64, |bits, &c| bits << 1 | (c > median) as u64);
+    format!("{bits:016x}")
+}
@@ -0,0 +1,119 @@
+// URL で指定された画像をバックグラウンドでダウンロードする
+
+use std::ffi::{c_void, OsString};
+use std::time::{Duration, Instant};
+use windows::{
+    core::*,
+    Win32::{
+        Foundation::*,
+        Networking::WinInet::*,
+        UI::WindowsAndMessaging::*,
+    },
+};
+use crate::metadata::Source;
+
+// wparam: 受信済みバイト数, lparam: 全体のバイト数 (不明なら 0)
+pub const WM_APP_DOWNLOAD_PROGRESS: u32 = WM_APP + 1;
+// lparam: Box<anyhow::Result<Source>> のポインタ
+pub const WM_APP_DOWNLOAD_DONE: u32 = WM_APP + 2;
+
+const MAX_DOWNLOAD_SIZE: usize = 64 * 1024 * 1024;
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+struct InternetHandle(*mut c_void);
+
+impl Drop for InternetHandle {
+    fn drop(&mut self) {
+        unsafe { InternetCloseHandle(self.0) };
+    }
+}
+
+pub fn is_http_url(s: &str) -> bool {
+    let s = s.trim();
+    s.starts_with("http://") || s.starts_with("https://")
+}
+
+pub fn start(hwnd: HWND, url: String) {
+    std::thread::spawn(move || {
+        let result = download(hwnd, &url).map(|data| Source::Memory {
+            name: file_name_from_url(&url),
+            data,
+        });
+        let result = Box::into_raw(Box::new(result));
+        let posted = unsafe { PostMessageW(hwnd, WM_APP_DOWNLOAD_DONE, WPARAM(0), LPARAM(result as isize)) };
+        if !posted.as_bool() {
+            drop(unsafe { Box::from_raw(result) });
+        }
+    });
+}
+
+// WM_APP_DOWNLOAD_DONE の lparam から結果を取り出す
+pub unsafe fn take_result(lparam: LPARAM) -> anyhow::Result<Source> {
+    *Box::from_raw(lparam.0 as *mut anyhow::Result<Source>)
+}
+
+fn file_name_from_url(url: &str) -> OsString {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let name = path.rsplit('/').next().filter(|name| !name.is_empty()).unwrap_or(url);
+    OsString::from(name)
+}
+
+fn query_number(request: &InternetHandle, info_level: u32) -> Option<u32> {
+    let mut value = 0u32;
+    let mut len = std::mem::size_of::<u32>() as u32;
+    let ok = unsafe {
+        HttpQueryInfoW(request.0, info_level | HTTP_QUERY_FLAG_NUMBER, Some(&mut value as *mut u32 as _), &mut len, None)
+    };
+    ok.as_bool().then_some(value)
+}
+
+fn download(hwnd: HWND, url: &str) -> anyhow::Result<Vec<u8>> {
+    let (status, data) = fetch(url, |received, total| {
+        unsafe { PostMessageW(hwnd, WM_APP_DOWNLOAD_PROGRESS, WPARAM(received), LPARAM(total as isize)) };
+    })?;
+    anyhow::ensure!(status == 200, "HTTP {status}: {url}");
+    Ok(data)
+}
+
+// HTTP のステータスコードと本文を返す。progress には受信済みのバイト数と全体のバイト数 (不明なら 0) を渡す
+pub fn fetch(url: &str, progress: impl FnMut(usize, usize)) -> anyhow::Result<(u32, Vec<u8>)> {
+    fetch_with_timeout(url, DOWNLOAD_TIMEOUT, progress)
+}
+
+pub fn fetch_with_timeout(url: &str, timeout: Duration, mut progress: impl FnMut(usize, usize)) -> anyhow::Result<(u32, Vec<u8>)> {
+    let started = Instant::now();
+    let session = unsafe { InternetOpenW(w!("MetaView"), INTERNET_OPEN_TYPE_PRECONFIG.0, None, None, 0) };
+    anyhow::ensure!(!session.is_null(), "InternetOpenW failed");
+    let session = InternetHandle(session);
+
+    let timeout_ms = timeout.as_millis() as u32;
+    for option in [INTERNET_OPTION_CONNECT_TIMEOUT, INTERNET_OPTION_RECEIVE_TIMEOUT] {
+        unsafe { InternetSetOptionW(Some(session.0), option, Some(&timeout_ms as *const u32 as _), 4) };
+    }
+
+    let request = unsafe {
+        InternetOpenUrlW(session.0, &HSTRING::from(url), None, INTERNET_FLAG_NO_UI | INTERNET_FLAG_RELOAD, 0)
+    };
+    anyhow::ensure!(!request.is_null(), "failed to open {url}");
+    let request = InternetHandle(request);
+
+    let status = query_number(&request, HTTP_QUERY_STATUS_CODE).unwrap_or(200);
+    let total = query_number(&request, HTTP_QUERY_CONTENT_LENGTH).unwrap_or(0) as usize;
+    anyhow::ensure!(total <= MAX_DOWNLOAD_SIZE, "the file is too large ({total} bytes)");
+
+    let mut data = Vec::with_capacity(total);
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let mut read = 0u32;
+        let ok = unsafe { InternetReadFile(request.0, buf.as_mut_ptr() as _, buf.len() as u32, &mut read) };
+        anyhow::ensure!(ok.as_bool(), "failed to read {url}");
+        if read == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..read as usize]);
+        anyhow::ensure!(data.len() <= MAX_DOWNLOAD_SIZE, "the file is too large (over {MAX_DOWNLOAD_SIZE} bytes)");
+        anyhow::ensure!(started.elapsed() <= timeout, "download timed out");
+        progress(data.len(), total);
+    }
+    Ok((status, data))
+}
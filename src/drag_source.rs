@@ -0,0 +1,229 @@
+// OLE のドラッグ元。表示欄で選んだテキストを、テキストとしても仮想ファイル (prompt.txt) としても渡す
+// テキストエディターやブラウザーのフォームはテキストを、エクスプローラーのフォルダーはファイルを受け取る
+
+use std::cell::Cell;
+use std::mem;
+use windows::{
+    core::*,
+    Win32::{
+        Foundation::*,
+        Graphics::Gdi::ClientToScreen,
+        System::{
+            Com::*,
+            Memory::{GlobalAlloc, GlobalLock, GlobalUnlock, GMEM_MOVEABLE},
+            Ole::*,
+            SystemServices::{CF_UNICODETEXT, MK_LBUTTON, MODIFIERKEYS_FLAGS},
+        },
+        UI::{
+            Controls::{EM_CHARFROMPOS, RichEdit::*},
+            Input::KeyboardAndMouse::*,
+            Shell::*,
+            WindowsAndMessaging::*,
+        },
+    },
+};
+use crate::drop_target;
+
+// 仮想ファイルの名前
+const FILE_NAME: &str = "prompt.txt";
+const SUBCLASS_ID: usize = 1;
+
+thread_local! {
+    // 自分のウィンドウにドロップされたときに、画像として読もうとしないようにする
+    static DRAGGING: Cell<bool> = const { Cell::new(false) };
+}
+
+pub fn is_dragging() -> bool {
+    DRAGGING.with(Cell::get)
+}
+
+#[implement(IDataObject)]
+struct DataObject {
+    // 改行は \r\n にしておく
+    text: String,
+}
+
+// 渡せる形式。FileContents は lindex で何番目のファイルかを指定する
+fn formats() -> [FORMATETC; 3] {
+    [
+        drop_target::format_etc(CF_UNICODETEXT.0 as u16, TYMED_HGLOBAL, -1),
+        drop_target::format_etc(drop_target::registered_format(CFSTR_FILEDESCRIPTORW), TYMED_HGLOBAL, -1),
+        drop_target::format_etc(drop_target::registered_format(CFSTR_FILECONTENTS), TYMED_HGLOBAL, 0),
+    ]
+}
+
+fn global_from_bytes(bytes: &[u8]) -> Result<STGMEDIUM> {
+    let hglobal = unsafe { GlobalAlloc(GMEM_MOVEABLE, bytes.len()) };
+    if hglobal == 0 {
+        return Err(E_OUTOFMEMORY.into());
+    }
+    let ptr = unsafe { GlobalLock(hglobal) } as *mut u8;
+    if ptr.is_null() {
+        return Err(E_OUTOFMEMORY.into());
+    }
+    unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), ptr, bytes.len()) };
+    unsafe { GlobalUnlock(hglobal) };
+    Ok(STGMEDIUM {
+        tymed: TYMED_HGLOBAL,
+        Anonymous: STGMEDIUM_0 { hGlobal: hglobal },
+        pUnkForRelease: None,
+    })
+}
+
+impl DataObject {
+    // formats() の何番目に当たるか
+    fn find(&self, format: &FORMATETC) -> std::result::Result<usize, HRESULT> {
+        let index = formats().iter().position(|f| f.cfFormat == format.cfFormat).ok_or(DV_E_FORMATETC)?;
+        if format.tymed & TYMED_HGLOBAL.0 as u32 == 0 {
+            return Err(DV_E_TYMED);
+        }
+        Ok(index)
+    }
+
+    fn file_descriptor(&self) -> Vec<u8> {
+        // 1 バイト境界の構造体なので、名前は別に作ってから入れる
+        let mut name = [0u16; 260];
+        for (dst, src) in name.iter_mut().zip(FILE_NAME.encode_utf16()) {
+            *dst = src;
+        }
+        let descriptor = FILEDESCRIPTORW {
+            dwFlags: (FD_FILESIZE.0 | FD_PROGRESSUI.0 | FD_UNICODE.0) as u32,
+            nFileSizeLow: self.text.len() as u32,
+            cFileName: name,
+            ..Default::default()
+        };
+        let group = FILEGROUPDESCRIPTORW { cItems: 1, fgd: [descriptor] };
+        let ptr = &group as *const FILEGROUPDESCRIPTORW as *const u8;
+        unsafe { std::slice::from_raw_parts(ptr, mem::size_of::<FILEGROUPDESCRIPTORW>()) }.to_vec()
+    }
+}
+
+impl IDataObject_Impl for DataObject {
+    fn GetData(&self, pformatetcin: *const FORMATETC) -> Result<STGMEDIUM> {
+        let format = unsafe { &*pformatetcin };
+        match self.find(format)? {
+            0 => {
+                let wide: Vec<u16> = self.text.encode_utf16().chain(Some(0)).collect();
+                let bytes: Vec<u8> = wide.iter().flat_map(|c| c.to_le_bytes()).collect();
+                global_from_bytes(&bytes)
+            }
+            1 => global_from_bytes(&self.file_descriptor()),
+            _ if format.lindex != 0 => Err(DV_E_LINDEX.into()),
+            _ => global_from_bytes(self.text.as_bytes()),
+        }
+    }
+
+    fn GetDataHere(&self, _pformatetc: *const FORMATETC, _pmedium: *mut STGMEDIUM) -> Result<()> {
+        Err(E_NOTIMPL.into())
+    }
+
+    fn QueryGetData(&self, pformatetc: *const FORMATETC) -> HRESULT {
+        match self.find(unsafe { &*pformatetc }) {
+            Ok(_) => S_OK,
+            Err(hr) => hr,
+        }
+    }
+
+    fn GetCanonicalFormatEtc(&self, _pformatectin: *const FORMATETC, pformatetcout: *mut FORMATETC) -> HRESULT {
+        unsafe { (*pformatetcout).ptd = std::ptr::null_mut() };
+        DATA_S_SAMEFORMATETC
+    }
+
+    // エクスプローラーはドロップの結果などを書き込もうとするが、使わないので受け取らない
+    fn SetData(&self, _pformatetc: *const FORMATETC, _pmedium: *const STGMEDIUM, _frelease: BOOL) -> Result<()> {
+        Err(E_NOTIMPL.into())
+    }
+
+    fn EnumFormatEtc(&self, dwdirection: u32) -> Result<IEnumFORMATETC> {
+        if dwdirection != DATADIR_GET.0 as u32 {
+            return Err(E_NOTIMPL.into());
+        }
+        unsafe { SHCreateStdEnumFmtEtc(&formats()) }
+    }
+
+    fn DAdvise(&self, _pformatetc: *const FORMATETC, _advf: u32, _padvsink: &Option<IAdviseSink>) -> Result<u32> {
+        Err(OLE_E_ADVISENOTSUPPORTED.into())
+    }
+
+    fn DUnadvise(&self, _dwconnection: u32) -> Result<()> {
+        Err(OLE_E_ADVISENOTSUPPORTED.into())
+    }
+
+    fn EnumDAdvise(&self) -> Result<IEnumSTATDATA> {
+        Err(OLE_E_ADVISENOTSUPPORTED.into())
+    }
+}
+
+#[implement(IDropSource)]
+struct DropSource;
+
+impl IDropSource_Impl for DropSource {
+    fn QueryContinueDrag(&self, fescapepressed: BOOL, grfkeystate: MODIFIERKEYS_FLAGS) -> HRESULT {
+        if fescapepressed.as_bool() {
+            DRAGDROP_S_CANCEL
+        } else if grfkeystate.0 & MK_LBUTTON.0 == 0 {
+            DRAGDROP_S_DROP
+        } else {
+            S_OK
+        }
+    }
+
+    fn GiveFeedback(&self, _dweffect: DROPEFFECT) -> HRESULT {
+        DRAGDROP_S_USEDEFAULTCURSORS
+    }
+}
+
+// ドラッグが終わるまで戻らない
+pub fn drag_text(text: &str) {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n").replace('\n', "\r\n");
+    let data: IDataObject = DataObject { text }.into();
+    let source: IDropSource = DropSource.into();
+    let mut effect = DROPEFFECT_NONE;
+    DRAGGING.with(|dragging| dragging.set(true));
+    let _ = unsafe { DoDragDrop(&data, &source, DROPEFFECT_COPY, &mut effect) };
+    DRAGGING.with(|dragging| dragging.set(false));
+}
+
+// 表示欄 (リッチエディット) の選んだ範囲からドラッグを始められるようにする
+pub fn attach(hedit: HWND) {
+    unsafe { SetWindowSubclass(hedit, Some(edit_subclass_proc), SUBCLASS_ID, 0) };
+}
+
+fn selection(hedit: HWND) -> CHARRANGE {
+    let mut range = CHARRANGE::default();
+    unsafe { SendMessageW(hedit, EM_EXGETSEL, WPARAM(0), LPARAM(&mut range as *mut _ as isize)) };
+    range
+}
+
+fn text_range(hedit: HWND, range: CHARRANGE) -> String {
+    let len = (range.cpMax - range.cpMin).max(0) as usize;
+    let mut buf = vec![0u16; len + 1];
+    let mut text_range = TEXTRANGEW { chrg: range, lpstrText: PWSTR(buf.as_mut_ptr()) };
+    let copied = unsafe { SendMessageW(hedit, EM_GETTEXTRANGE, WPARAM(0), LPARAM(&mut text_range as *mut _ as isize)) }.0 as usize;
+    String::from_utf16_lossy(&buf[..copied.min(len)])
+}
+
+extern "system" fn edit_subclass_proc(hwnd: HWND, message: u32, wparam: WPARAM, lparam: LPARAM, _id: usize, _data: usize) -> LRESULT {
+    if message == WM_NCDESTROY {
+        unsafe { RemoveWindowSubclass(hwnd, Some(edit_subclass_proc), SUBCLASS_ID) };
+    } else if message == WM_LBUTTONDOWN {
+        let x = (lparam.0 & 0xffff) as i16 as i32;
+        let y = ((lparam.0 >> 16) & 0xffff) as i16 as i32;
+        let range = selection(hwnd);
+        let pt = POINTL { x, y };
+        let index = unsafe { SendMessageW(hwnd, EM_CHARFROMPOS, WPARAM(0), LPARAM(&pt as *const _ as isize)) }.0 as i32;
+        if range.cpMin < range.cpMax && (range.cpMin..range.cpMax).contains(&index) {
+            let mut screen = POINT { x, y };
+            unsafe { ClientToScreen(hwnd, &mut screen) };
+            unsafe { SetFocus(hwnd) };
+            if unsafe { DragDetect(hwnd, screen) }.as_bool() {
+                drag_text(&text_range(hwnd, range));
+                return LRESULT::default();
+            }
+            // ドラッグしなかったら普通のクリックとしてキャレットを動かす (ボタンを離したメッセージは DragDetect が受け取っている)
+            unsafe { DefSubclassProc(hwnd, WM_LBUTTONDOWN, wparam, lparam) };
+            return unsafe { DefSubclassProc(hwnd, WM_LBUTTONUP, WPARAM(0), lparam) };
+        }
+    }
+    unsafe { DefSubclassProc(hwnd, message, wparam, lparam) }
+}
@@ -0,0 +1,274 @@
+// OLE のドロップターゲット。ブラウザから画像を直接ドラッグしてきた場合にも対応する
+
+use std::cell::Cell;
+use std::ffi::OsString;
+use std::os::windows::ffi::OsStringExt;
+use windows::{
+    core::*,
+    Win32::{
+        Foundation::*,
+        System::{
+            Com::*,
+            DataExchange::RegisterClipboardFormatW,
+            Memory::{GlobalLock, GlobalSize, GlobalUnlock},
+            Ole::*,
+            SystemServices::{CF_DIB, CF_HDROP, CF_UNICODETEXT, MODIFIERKEYS_FLAGS},
+        },
+        UI::Shell::*,
+    },
+};
+use crate::download;
+use crate::drag_source;
+use crate::metadata::Source;
+
+pub fn drag_query_file(hdrop: HDROP, index: u32) -> OsString {
+    // 必要な長さ (終端の NUL を含まない) を先に問い合わせる
+    let len = unsafe { DragQueryFileW(hdrop, index, None) } as usize;
+    let mut buf: Vec<u16> = vec![0; len + 1];
+    let copied = unsafe { DragQueryFileW(hdrop, index, Some(&mut buf)) } as usize;
+    OsString::from_wide(&buf[..copied.min(len)])
+}
+
+#[implement(IDropTarget)]
+pub struct DropTarget {
+    hwnd: HWND,
+    acceptable: Cell<bool>,
+}
+
+impl DropTarget {
+    pub fn new(hwnd: HWND) -> DropTarget {
+        DropTarget {
+            hwnd,
+            acceptable: Cell::new(false),
+        }
+    }
+
+    fn effect(&self) -> DROPEFFECT {
+        if self.acceptable.get() { DROPEFFECT_COPY } else { DROPEFFECT_NONE }
+    }
+}
+
+impl IDropTarget_Impl for DropTarget {
+    fn DragEnter(&self, pdataobj: &Option<IDataObject>, _grfkeystate: MODIFIERKEYS_FLAGS, _pt: &POINTL, pdweffect: *mut DROPEFFECT) -> Result<()> {
+        // 表示欄からドラッグしたテキストは受け付けない
+        let acceptable = !drag_source::is_dragging() && pdataobj.as_ref().is_some_and(|data| {
+            supported_formats().iter().any(|&(format, tymed)| has_format(data, format, tymed))
+        });
+        self.acceptable.set(acceptable);
+        unsafe { *pdweffect = self.effect() };
+        Ok(())
+    }
+
+    fn DragOver(&self, _grfkeystate: MODIFIERKEYS_FLAGS, _pt: &POINTL, pdweffect: *mut DROPEFFECT) -> Result<()> {
+        unsafe { *pdweffect = self.effect() };
+        Ok(())
+    }
+
+    fn DragLeave(&self) -> Result<()> {
+        self.acceptable.set(false);
+        Ok(())
+    }
+
+    fn Drop(&self, pdataobj: &Option<IDataObject>, _grfkeystate: MODIFIERKEYS_FLAGS, _pt: &POINTL, pdweffect: *mut DROPEFFECT) -> Result<()> {
+        unsafe { *pdweffect = self.effect() };
+        if let Some(data) = pdataobj {
+            if let Some(source) = read_source(data) {
+                crate::open_source(self.hwnd, source);
+            }
+        }
+        self.acceptable.set(false);
+        Ok(())
+    }
+}
+
+pub fn format_etc(format: u16, tymed: TYMED, lindex: i32) -> FORMATETC {
+    FORMATETC {
+        cfFormat: format,
+        ptd: std::ptr::null_mut(),
+        dwAspect: DVASPECT_CONTENT.0,
+        lindex,
+        tymed: tymed.0 as u32,
+    }
+}
+
+pub fn registered_format(name: PCWSTR) -> u16 {
+    unsafe { RegisterClipboardFormatW(name) as u16 }
+}
+
+// 受け付ける形式 (優先度順)
+fn supported_formats() -> [(u16, TYMED); 7] {
+    [
+        (CF_HDROP.0 as u16, TYMED_HGLOBAL),
+        (registered_format(CFSTR_FILEDESCRIPTORW), TYMED_HGLOBAL),
+        (registered_format(w!("PNG")), TYMED(TYMED_HGLOBAL.0 | TYMED_ISTREAM.0)),
+        (CF_DIB.0 as u16, TYMED_HGLOBAL),
+        (registered_format(w!("HTML Format")), TYMED_HGLOBAL),
+        (registered_format(w!("UniformResourceLocatorW")), TYMED_HGLOBAL),
+        (CF_UNICODETEXT.0 as u16, TYMED_HGLOBAL),
+    ]
+}
+
+fn has_format(data: &IDataObject, format: u16, tymed: TYMED) -> bool {
+    (unsafe { data.QueryGetData(&format_etc(format, tymed, -1)) }) == S_OK
+}
+
+// STGMEDIUM の中身をバイト列として取り出す
+fn read_medium(medium: &STGMEDIUM) -> Option<Vec<u8>> {
+    if medium.tymed == TYMED_HGLOBAL {
+        let hglobal = unsafe { medium.Anonymous.hGlobal };
+        let size = unsafe { GlobalSize(hglobal) };
+        let ptr = unsafe { GlobalLock(hglobal) } as *const u8;
+        if ptr.is_null() {
+            return None;
+        }
+        let bytes = unsafe { std::slice::from_raw_parts(ptr, size) }.to_vec();
+        unsafe { GlobalUnlock(hglobal) };
+        Some(bytes)
+    } else if medium.tymed == TYMED_ISTREAM {
+        let stream = unsafe { medium.Anonymous.pstm.as_ref() }?;
+        let mut bytes = Vec::new();
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let mut read = 0u32;
+            let hr = unsafe { stream.Read(buf.as_mut_ptr() as _, buf.len() as u32, Some(&mut read)) };
+            if hr.is_err() || read == 0 {
+                break;
+            }
+            bytes.extend_from_slice(&buf[..read as usize]);
+        }
+        Some(bytes)
+    } else {
+        None
+    }
+}
+
+fn get_data(data: &IDataObject, format: u16, tymed: TYMED, lindex: i32) -> Option<Vec<u8>> {
+    let mut medium = unsafe { data.GetData(&format_etc(format, tymed, lindex)) }.ok()?;
+    let bytes = read_medium(&medium);
+    unsafe { ReleaseStgMedium(&mut medium) };
+    bytes
+}
+
+// ドロップされたデータやクリップボードの中身から読み込み元を決める
+pub fn read_source(data: &IDataObject) -> Option<anyhow::Result<Source>> {
+    // エクスプローラーなどからのファイル
+    if let Ok(mut medium) = unsafe { data.GetData(&format_etc(CF_HDROP.0 as u16, TYMED_HGLOBAL, -1)) } {
+        let hdrop = HDROP(unsafe { medium.Anonymous.hGlobal });
+        let n_files = unsafe { DragQueryFileW(hdrop, u32::MAX, None) };
+        let filename = (n_files > 0).then(|| drag_query_file(hdrop, 0));
+        unsafe { ReleaseStgMedium(&mut medium) };
+        if let Some(filename) = filename {
+            return Some(Ok(Source::File(filename)));
+        }
+    }
+
+    // 仮想ファイル (FileGroupDescriptorW + FileContents)
+    if let Some(descriptor) = get_data(data, registered_format(CFSTR_FILEDESCRIPTORW), TYMED_HGLOBAL, -1) {
+        if descriptor.len() >= std::mem::size_of::<FILEGROUPDESCRIPTORW>() {
+            let group = unsafe { std::ptr::read_unaligned(descriptor.as_ptr() as *const FILEGROUPDESCRIPTORW) };
+            let fgd = group.fgd;
+            let file_name = fgd[0].cFileName;
+            let last = file_name.iter().position(|&x| x == 0).unwrap_or(file_name.len());
+            let name = OsString::from_wide(&file_name[..last]);
+            let tymed = TYMED(TYMED_HGLOBAL.0 | TYMED_ISTREAM.0);
+            if let Some(bytes) = get_data(data, registered_format(CFSTR_FILECONTENTS), tymed, 0) {
+                return Some(Ok(Source::Memory { name, data: bytes }));
+            }
+        }
+    }
+
+    // PNG 形式の画像データ
+    let tymed = TYMED(TYMED_HGLOBAL.0 | TYMED_ISTREAM.0);
+    if let Some(bytes) = get_data(data, registered_format(w!("PNG")), tymed, -1) {
+        return Some(Ok(Source::Memory { name: OsString::from("image.png"), data: bytes }));
+    }
+
+    // DIB はファイルヘッダーを付けて BMP として扱う
+    if let Some(dib) = get_data(data, CF_DIB.0 as u16, TYMED_HGLOBAL, -1) {
+        return Some(Ok(Source::Memory { name: OsString::from("image.bmp"), data: dib_to_bmp(&dib) }));
+    }
+
+    // HTML 中の <img src="...">
+    if let Some(html) = get_data(data, registered_format(w!("HTML Format")), TYMED_HGLOBAL, -1) {
+        let html = String::from_utf8_lossy(&html);
+        if let Some(src) = find_img_src(&html) {
+            return Some(source_from_url(&src));
+        }
+    }
+
+    // リンクや URL のテキスト
+    for format in [registered_format(w!("UniformResourceLocatorW")), CF_UNICODETEXT.0 as u16] {
+        if let Some(text) = get_data(data, format, TYMED_HGLOBAL, -1) {
+            let text = wide_bytes_to_string(&text);
+            if download::is_http_url(&text) {
+                return Some(Ok(Source::Url(text.trim().to_owned())));
+            }
+        }
+    }
+
+    None
+}
+
+fn wide_bytes_to_string(bytes: &[u8]) -> String {
+    let wide: Vec<u16> = bytes.chunks_exact(2).map(|b| u16::from_le_bytes([b[0], b[1]])).collect();
+    let last = wide.iter().position(|&x| x == 0).unwrap_or(wide.len());
+    String::from_utf16_lossy(&wide[..last])
+}
+
+fn dib_to_bmp(dib: &[u8]) -> Vec<u8> {
+    const FILE_HEADER_SIZE: u32 = 14;
+    let header_size = dib.get(0..4).map_or(40, |b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    let mut bmp = Vec::with_capacity(dib.len() + FILE_HEADER_SIZE as usize);
+    bmp.extend_from_slice(b"BM");
+    bmp.extend_from_slice(&(dib.len() as u32 + FILE_HEADER_SIZE).to_le_bytes());
+    bmp.extend_from_slice(&[0; 4]);
+    bmp.extend_from_slice(&(FILE_HEADER_SIZE + header_size).to_le_bytes());
+    bmp.extend_from_slice(dib);
+    bmp
+}
+
+fn find_img_src(html: &str) -> Option<String> {
+    let img = html.find("<img")?;
+    let rest = &html[img..];
+    let src = rest.find("src=")?;
+    let rest = &rest[src + 4..];
+    let quote = rest.chars().next().filter(|&c| c == '"' || c == '\'')?;
+    let rest = &rest[1..];
+    let end = rest.find(quote)?;
+    Some(rest[..end].replace("&amp;", "&"))
+}
+
+fn source_from_url(url: &str) -> anyhow::Result<Source> {
+    if let Some(rest) = url.strip_prefix("data:") {
+        let (header, payload) = rest.split_once(',').ok_or_else(|| anyhow::anyhow!("invalid data URL"))?;
+        anyhow::ensure!(header.ends_with(";base64"), "unsupported data URL encoding");
+        let data = decode_base64(payload)?;
+        return Ok(Source::Memory { name: OsString::from("image"), data });
+    }
+    anyhow::ensure!(download::is_http_url(url), "unsupported URL: {url}");
+    Ok(Source::Url(url.to_owned()))
+}
+
+fn decode_base64(s: &str) -> anyhow::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for c in s.bytes() {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' | b'\r' | b'\n' | b' ' => continue,
+            _ => anyhow::bail!("invalid base64 character"),
+        };
+        acc = (acc << 6) | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
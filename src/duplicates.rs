@@ -0,0 +1,163 @@
+// フォルダーの中の画像 (サブフォルダーも) の知覚ハッシュを計算し、見た目が同じかほとんど同じものをまとめる
+// まとめた画像は生成パラメーターを並べて表示し、残すものを選べるようにする
+
+use std::path::{Path, PathBuf};
+use windows::Win32::{
+    Foundation::*,
+    UI::WindowsAndMessaging::*,
+};
+use crate::batch::{self, ScannedImage};
+use crate::hashing;
+use crate::i18n::{tr, Msg};
+use crate::infotext;
+use crate::metadata::{ImageMetadata, Source};
+use crate::params;
+
+// lparam: Box<anyhow::Result<DuplicateReport>> のポインタ
+pub const WM_APP_DUPLICATES_DONE: u32 = WM_APP + 16;
+
+// 知覚ハッシュ (64 ビット) の違うビットがこれ以下なら同じ画像とみなす
+const MAX_DISTANCE: u32 = 6;
+// 並べて表示するときにプロンプトを切り詰める文字数
+const MAX_PROMPT_CHARS: usize = 40;
+
+#[derive(Debug)]
+pub struct DuplicateImage {
+    pub image: ScannedImage,
+    phash: u64,
+    // 並べて表示する項目。順番はどの画像でも同じ
+    fields: Vec<(Msg, String)>,
+}
+
+#[derive(Debug)]
+pub struct DuplicateReport {
+    pub folder: PathBuf,
+    pub scanned: usize,
+    pub errors: usize,
+    // 2 枚以上あるまとまり (見つけた順)
+    pub groups: Vec<Vec<DuplicateImage>>,
+}
+
+// 進み具合は集計と同じ batch::WM_APP_SCAN_PROGRESS で知らせる
+pub fn start(hwnd: HWND, folder: PathBuf) {
+    std::thread::spawn(move || {
+        let result = find_duplicates(hwnd, folder);
+        let result = Box::into_raw(Box::new(result));
+        let posted = unsafe { PostMessageW(hwnd, WM_APP_DUPLICATES_DONE, WPARAM(0), LPARAM(result as isize)) };
+        if !posted.as_bool() {
+            drop(unsafe { Box::from_raw(result) });
+        }
+    });
+}
+
+// WM_APP_DUPLICATES_DONE の lparam から結果を取り出す
+pub unsafe fn take_result(lparam: LPARAM) -> anyhow::Result<DuplicateReport> {
+    *Box::from_raw(lparam.0 as *mut anyhow::Result<DuplicateReport>)
+}
+
+fn scan_image(path: &Path) -> Option<DuplicateImage> {
+    let metadata = Source::File(path.as_os_str().to_owned()).read_metadata().ok()?;
+    let phash = hashing::perceptual_hash(&metadata.data)?;
+    let phash = u64::from_str_radix(&phash, 16).ok()?;
+    let image = ScannedImage { path: path.to_owned(), orientation: metadata.orientation };
+    Some(DuplicateImage { image, phash, fields: fields(&metadata) })
+}
+
+fn fields(metadata: &ImageMetadata) -> Vec<(Msg, String)> {
+    let params = params::find_parameters(&metadata.text_chunks)
+        .or_else(|| infotext::to_infotext(&metadata.text_chunks).and_then(|text| params::parse_infotext(&text)));
+    let get = |key: &str| params.as_ref().and_then(|p| p.get(key)).unwrap_or("").to_owned();
+    let prompt = params.as_ref().map(|p| truncate(&p.prompt)).unwrap_or_default();
+    vec![
+        (Msg::Dimensions, format!("{} x {}", metadata.width, metadata.height)),
+        (Msg::FileSize, metadata.file_size.to_string()),
+        (Msg::HistoryModel, get("Model")),
+        (Msg::HistorySampler, get("Sampler")),
+        (Msg::HistorySteps, get("Steps")),
+        (Msg::HistoryCfgScale, get("CFG scale")),
+        (Msg::HistorySeed, get("Seed")),
+        (Msg::Prompt, prompt),
+    ]
+}
+
+fn truncate(text: &str) -> String {
+    let text = text.replace(['\r', '\n'], " ");
+    match text.char_indices().nth(MAX_PROMPT_CHARS) {
+        Some((i, _)) => format!("{}…", &text[..i]),
+        None => text,
+    }
+}
+
+fn find_duplicates(hwnd: HWND, folder: PathBuf) -> anyhow::Result<DuplicateReport> {
+    let mut files = Vec::new();
+    batch::collect_images(&folder, &mut files)?;
+    let mut images = Vec::new();
+    let mut errors = 0;
+    for (i, path) in files.iter().enumerate() {
+        match scan_image(path) {
+            Some(image) => images.push(image),
+            None => errors += 1,
+        }
+        unsafe { PostMessageW(hwnd, batch::WM_APP_SCAN_PROGRESS, WPARAM(i + 1), LPARAM(files.len() as isize)) };
+    }
+    let scanned = images.len();
+    Ok(DuplicateReport { folder, scanned, errors, groups: group(images) })
+}
+
+// 近いもの同士をつなげてまとめる (A と B、B と C が近ければ A と C が遠くても同じまとまりにする)
+fn group(images: Vec<DuplicateImage>) -> Vec<Vec<DuplicateImage>> {
+    let mut parent: Vec<usize> = (0..images.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    for i in 0..images.len() {
+        for j in i + 1..images.len() {
+            if (images[i].phash ^ images[j].phash).count_ones() <= MAX_DISTANCE {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+    }
+    let mut groups: Vec<(usize, Vec<DuplicateImage>)> = Vec::new();
+    for (i, image) in images.into_iter().enumerate() {
+        let r = root(&mut parent, i);
+        match groups.iter_mut().find(|(root, _)| *root == r) {
+            Some((_, group)) => group.push(image),
+            None => groups.push((r, vec![image])),
+        }
+    }
+    groups.into_iter().map(|(_, group)| group).filter(|group| group.len() > 1).collect()
+}
+
+impl DuplicateReport {
+    // まとまりごとに、項目を 1 行にして画像の値を " | " で並べる。値が違う項目には ≠ を付ける
+    pub fn format(&self) -> String {
+        let mut ret = format!("【{}】\r\n", tr(Msg::Duplicates));
+        ret.push_str(&format!("{}: {}\r\n", tr(Msg::Folder), self.folder.display()));
+        ret.push_str(&format!("{}: {}\r\n", tr(Msg::FilesChecked), self.scanned));
+        ret.push_str(&format!("{}: {}\r\n", tr(Msg::DuplicateGroups), self.groups.len()));
+        if self.errors > 0 {
+            ret.push_str(&format!("{}: {}\r\n", tr(Msg::UnreadableFiles), self.errors));
+        }
+        for (i, group) in self.groups.iter().enumerate() {
+            ret.push_str(&format!("\r\n【{} {}】\r\n", tr(Msg::Group), i + 1));
+            let names: Vec<String> = group.iter()
+                .map(|image| image.image.path.strip_prefix(&self.folder).unwrap_or(&image.image.path).display().to_string())
+                .collect();
+            ret.push_str(&format!("  {}\r\n", names.join(" | ")));
+            for (j, (msg, _)) in group[0].fields.iter().enumerate() {
+                let values: Vec<&str> = group.iter().map(|image| image.fields[j].1.as_str()).collect();
+                if values.iter().all(|value| value.is_empty()) {
+                    continue;
+                }
+                let mark = if values.iter().all(|value| *value == values[0]) { " " } else { "≠" };
+                ret.push_str(&format!("{mark} {}: {}\r\n", tr(*msg), values.join(" | ")));
+            }
+        }
+        ret
+    }
+}
@@ -0,0 +1,169 @@
+// tEXt チャンクの文字コードの推定と変換
+// 仕様では Latin-1 だが、Shift_JIS や UTF-8 のバイト列をそのまま入れるツールが多い
+
+use windows::core::PCSTR;
+use windows::Win32::Globalization::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    Latin1,
+    Utf8,
+    ShiftJis,
+}
+
+const CP_SHIFT_JIS: u32 = 932;
+
+impl TextEncoding {
+    pub const ALL: [TextEncoding; 3] = [TextEncoding::Latin1, TextEncoding::Utf8, TextEncoding::ShiftJis];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TextEncoding::Latin1 => "Latin-1",
+            TextEncoding::Utf8 => "UTF-8",
+            TextEncoding::ShiftJis => "Shift_JIS",
+        }
+    }
+}
+
+pub fn detect(bytes: &[u8]) -> TextEncoding {
+    if bytes.is_ascii() {
+        TextEncoding::Latin1
+    } else if std::str::from_utf8(bytes).is_ok() {
+        TextEncoding::Utf8
+    } else if looks_like_shift_jis(bytes) {
+        TextEncoding::ShiftJis
+    } else {
+        TextEncoding::Latin1
+    }
+}
+
+// 2 バイト文字の並びとして正しく、半角カナより全角文字が多ければ Shift_JIS とみなす
+fn looks_like_shift_jis(bytes: &[u8]) -> bool {
+    let mut double_byte = 0;
+    let mut kana = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            0x00..=0x7f => i += 1,
+            0xa1..=0xdf => {
+                kana += 1;
+                i += 1;
+            }
+            0x81..=0x9f | 0xe0..=0xfc => {
+                if !matches!(bytes.get(i + 1), Some(0x40..=0x7e | 0x80..=0xfc)) {
+                    return false;
+                }
+                double_byte += 1;
+                i += 2;
+            }
+            _ => return false,
+        }
+    }
+    double_byte > kana
+}
+
+pub fn decode(bytes: &[u8], encoding: TextEncoding) -> String {
+    match encoding {
+        TextEncoding::Latin1 => bytes.iter().map(|&b| b as char).collect(),
+        TextEncoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+        TextEncoding::ShiftJis => decode_code_page(CP_SHIFT_JIS, bytes),
+    }
+}
+
+fn decode_code_page(code_page: u32, bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        return String::new();
+    }
+    let len = unsafe { MultiByteToWideChar(code_page, MULTI_BYTE_TO_WIDE_CHAR_FLAGS(0), bytes, None) };
+    let mut buf = vec![0u16; len.max(0) as usize];
+    let len = unsafe { MultiByteToWideChar(code_page, MULTI_BYTE_TO_WIDE_CHAR_FLAGS(0), bytes, Some(&mut buf)) };
+    String::from_utf16_lossy(&buf[..len.max(0) as usize])
+}
+
+// テキストを書き出すときの文字コード。日本語のツールには BOM のない UTF-8 を読めないものがある
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputEncoding {
+    Utf8,
+    #[default]
+    Utf8Bom,
+    Utf16Le,
+    ShiftJis,
+}
+
+impl OutputEncoding {
+    pub const ALL: [OutputEncoding; 4] = [OutputEncoding::Utf8, OutputEncoding::Utf8Bom, OutputEncoding::Utf16Le, OutputEncoding::ShiftJis];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputEncoding::Utf8 => "UTF-8",
+            OutputEncoding::Utf8Bom => "UTF-8 (BOM)",
+            OutputEncoding::Utf16Le => "UTF-16 LE",
+            OutputEncoding::ShiftJis => "Shift_JIS",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<OutputEncoding> {
+        OutputEncoding::ALL.into_iter().find(|encoding| encoding.code() == code)
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            OutputEncoding::Utf8 => "utf-8",
+            OutputEncoding::Utf8Bom => "utf-8-bom",
+            OutputEncoding::Utf16Le => "utf-16le",
+            OutputEncoding::ShiftJis => "shift_jis",
+        }
+    }
+}
+
+// 書き出すテキストの改行
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Newline {
+    #[default]
+    CrLf,
+    Lf,
+}
+
+impl Newline {
+    pub fn from_code(code: &str) -> Option<Newline> {
+        match code {
+            "crlf" => Some(Newline::CrLf),
+            "lf" => Some(Newline::Lf),
+            _ => None,
+        }
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Newline::CrLf => "crlf",
+            Newline::Lf => "lf",
+        }
+    }
+}
+
+// 改行をそろえてからバイト列にする。Shift_JIS にない文字は ? になる
+pub fn encode_text(text: &str, encoding: OutputEncoding, newline: Newline) -> Vec<u8> {
+    let text = text.replace("\r\n", "\n");
+    let text = match newline {
+        Newline::CrLf => text.replace('\n', "\r\n"),
+        Newline::Lf => text,
+    };
+    match encoding {
+        OutputEncoding::Utf8 => text.into_bytes(),
+        OutputEncoding::Utf8Bom => [&[0xef, 0xbb, 0xbf], text.as_bytes()].concat(),
+        OutputEncoding::Utf16Le => [0xfeffu16].into_iter().chain(text.encode_utf16()).flat_map(u16::to_le_bytes).collect(),
+        OutputEncoding::ShiftJis => encode_code_page(CP_SHIFT_JIS, &text),
+    }
+}
+
+fn encode_code_page(code_page: u32, text: &str) -> Vec<u8> {
+    if text.is_empty() {
+        return Vec::new();
+    }
+    let wide: Vec<u16> = text.encode_utf16().collect();
+    let len = unsafe { WideCharToMultiByte(code_page, 0, &wide, None, PCSTR::null(), None) };
+    let mut buf = vec![0u8; len.max(0) as usize];
+    let len = unsafe { WideCharToMultiByte(code_page, 0, &wide, Some(&mut buf), PCSTR::null(), None) };
+    buf.truncate(len.max(0) as usize);
+    buf
+}
@@ -0,0 +1,187 @@
+// EXIF (TIFF 形式) の読み取り
+
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Entry {
+    pub tag: u16,
+    pub kind: u16,
+    pub count: u32,
+    // 値の位置。4 バイト以下の値はエントリの中に直接入っている
+    pub offset: usize,
+}
+
+pub struct Tiff<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+const BYTE: u16 = 1;
+const ASCII: u16 = 2;
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+const RATIONAL: u16 = 5;
+
+fn type_size(kind: u16) -> usize {
+    match kind {
+        1 | 2 | 6 | 7 => 1,
+        3 | 8 => 2,
+        4 | 9 | 11 => 4,
+        5 | 10 | 12 => 8,
+        _ => 0,
+    }
+}
+
+impl<'a> Tiff<'a> {
+    pub fn new(data: &'a [u8]) -> Option<Tiff<'a>> {
+        let big_endian = match data.get(..4)? {
+            b"II*\0" => false,
+            b"MM\0*" => true,
+            _ => return None,
+        };
+        Some(Tiff { data, big_endian })
+    }
+
+    fn u16_at(&self, pos: usize) -> Option<u16> {
+        let bytes: [u8; 2] = self.data.get(pos..pos.checked_add(2)?)?.try_into().ok()?;
+        Some(if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    }
+
+    fn u32_at(&self, pos: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(pos..pos.checked_add(4)?)?.try_into().ok()?;
+        Some(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+
+    pub fn first_ifd(&self) -> Option<usize> {
+        self.u32_at(4).map(|pos| pos as usize)
+    }
+
+    // IFD のエントリと、次の IFD の位置 (なければ 0) を返す
+    pub fn read_ifd(&self, pos: usize) -> Option<(Vec<Entry>, usize)> {
+        let n = self.u16_at(pos)? as usize;
+        let mut entries = Vec::with_capacity(n);
+        for i in 0..n {
+            let p = pos + 2 + i * 12;
+            let kind = self.u16_at(p + 2)?;
+            let count = self.u32_at(p + 4)?;
+            let size = type_size(kind).checked_mul(count as usize)?;
+            let offset = if size <= 4 { p + 8 } else { self.u32_at(p + 8)? as usize };
+            entries.push(Entry { tag: self.u16_at(p)?, kind, count, offset });
+        }
+        let next = self.u32_at(pos + 2 + n * 12)? as usize;
+        Some((entries, next))
+    }
+
+    pub fn u32_value(&self, entry: &Entry) -> Option<u32> {
+        match entry.kind {
+            BYTE => self.data.get(entry.offset).map(|&b| b as u32),
+            SHORT => self.u16_at(entry.offset).map(u32::from),
+            LONG => self.u32_at(entry.offset),
+            _ => None,
+        }
+    }
+
+    pub fn ascii(&self, entry: &Entry) -> Option<&'a str> {
+        if entry.kind != ASCII {
+            return None;
+        }
+        let bytes = self.data.get(entry.offset..entry.offset.checked_add(entry.count as usize)?)?;
+        std::str::from_utf8(bytes).ok().map(|s| s.trim_end_matches('\0'))
+    }
+
+    pub fn rationals(&self, entry: &Entry) -> Option<Vec<f64>> {
+        if entry.kind != RATIONAL {
+            return None;
+        }
+        (0..entry.count as usize).map(|i| {
+            let numerator = self.u32_at(entry.offset + i * 8)?;
+            let denominator = self.u32_at(entry.offset + i * 8 + 4)?;
+            (denominator != 0).then(|| numerator as f64 / denominator as f64)
+        }).collect()
+    }
+}
+
+const TAG_JPEG_OFFSET: u16 = 0x0201;
+const TAG_JPEG_LENGTH: u16 = 0x0202;
+
+// IFD1 に入っている JPEG のサムネイルの位置 (exif の中での位置)
+pub fn thumbnail(exif: &[u8]) -> Option<Range<usize>> {
+    let tiff = Tiff::new(exif)?;
+    let (_, ifd1) = tiff.read_ifd(tiff.first_ifd()?)?;
+    if ifd1 == 0 {
+        return None;
+    }
+    let (entries, _) = tiff.read_ifd(ifd1)?;
+    let value = |tag: u16| entries.iter().find(|e| e.tag == tag).and_then(|e| tiff.u32_value(e));
+    let start = value(TAG_JPEG_OFFSET)? as usize;
+    let range = start..start.checked_add(value(TAG_JPEG_LENGTH)? as usize)?;
+    exif.get(range.clone())?.starts_with(&[0xff, 0xd8]).then_some(range)
+}
+
+const TAG_ORIENTATION: u16 = 0x0112;
+
+// IFD0 の Orientation (1〜8)。1 はそのまま、6 は時計回りに 90 度回して表示する、など
+pub fn orientation(exif: &[u8]) -> Option<u16> {
+    let tiff = Tiff::new(exif)?;
+    let (entries, _) = tiff.read_ifd(tiff.first_ifd()?)?;
+    let value = entries.iter().find(|e| e.tag == TAG_ORIENTATION).and_then(|e| tiff.u32_value(e))?;
+    (1..=8).contains(&value).then_some(value as u16)
+}
+
+const TAG_COPYRIGHT: u16 = 0x8298;
+
+// IFD0 の Copyright。撮影者と編集者の著作権表示が \0 で区切られていることがある
+pub fn copyright(exif: &[u8]) -> Option<String> {
+    let tiff = Tiff::new(exif)?;
+    let (entries, _) = tiff.read_ifd(tiff.first_ifd()?)?;
+    let text = entries.iter().find(|e| e.tag == TAG_COPYRIGHT).and_then(|e| tiff.ascii(e))?;
+    let text = text.split('\0').map(str::trim).filter(|s| !s.is_empty()).collect::<Vec<_>>().join(" / ");
+    (!text.is_empty()).then_some(text)
+}
+
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_GPS_LATITUDE_REF: u16 = 1;
+const TAG_GPS_LATITUDE: u16 = 2;
+const TAG_GPS_LONGITUDE_REF: u16 = 3;
+const TAG_GPS_LONGITUDE: u16 = 4;
+const TAG_GPS_ALTITUDE_REF: u16 = 5;
+const TAG_GPS_ALTITUDE: u16 = 6;
+
+// 緯度・経度は度単位 (南緯・西経は負)、高度はメートル
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpsPosition {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: Option<f64>,
+}
+
+impl GpsPosition {
+    pub fn map_url(&self) -> String {
+        format!("https://www.google.com/maps/search/?api=1&query={:.6},{:.6}", self.latitude, self.longitude)
+    }
+}
+
+pub fn gps_position(exif: &[u8]) -> Option<GpsPosition> {
+    let tiff = Tiff::new(exif)?;
+    let (ifd0, _) = tiff.read_ifd(tiff.first_ifd()?)?;
+    let gps_ifd = ifd0.iter().find(|e| e.tag == TAG_GPS_IFD).and_then(|e| tiff.u32_value(e))?;
+    let (gps, _) = tiff.read_ifd(gps_ifd as usize)?;
+    let find = |tag: u16| gps.iter().find(|e| e.tag == tag);
+
+    // 度・分・秒の 3 つの有理数で入っている
+    let coordinate = |ref_tag: u16, tag: u16, negative: &str| -> Option<f64> {
+        let dms = tiff.rationals(find(tag)?)?;
+        let [degrees, minutes, seconds] = dms[..] else { return None };
+        let value = degrees + minutes / 60.0 + seconds / 3600.0;
+        let reference = find(ref_tag).and_then(|e| tiff.ascii(e)).unwrap_or("");
+        Some(if reference.starts_with(negative) { -value } else { value })
+    };
+    let latitude = coordinate(TAG_GPS_LATITUDE_REF, TAG_GPS_LATITUDE, "S").filter(|v| v.abs() <= 90.0)?;
+    let longitude = coordinate(TAG_GPS_LONGITUDE_REF, TAG_GPS_LONGITUDE, "W").filter(|v| v.abs() <= 180.0)?;
+    let altitude = find(TAG_GPS_ALTITUDE).and_then(|e| tiff.rationals(e)).and_then(|v| v.first().copied()).map(|altitude| {
+        // 1 なら海面下
+        let below_sea_level = find(TAG_GPS_ALTITUDE_REF).and_then(|e| tiff.u32_value(e)) == Some(1);
+        if below_sea_level { -altitude } else { altitude }
+    });
+    Some(GpsPosition { latitude, longitude, altitude })
+}
@@ -0,0 +1,249 @@
+// ExifTool の JSON 出力 (exiftool -j -G -n) と同じ形式での書き出しと、その形式のファイルとの比較
+// -n と同じく数値は変換せずに数値のまま出す。タグ名の前には -G と同じくファミリー 0 のグループ名を付ける
+
+use std::path::Path;
+use crate::i18n::{tr, Msg};
+use crate::json::{self, Value};
+use crate::metadata::ImageMetadata;
+use crate::redact;
+
+// 比較結果で長い値 (プロンプトなど) を切り詰める文字数
+const MAX_VALUE_CHARS: usize = 80;
+
+// ExifTool が PNG のキーワードからタグ名を作るのと同じ規則
+// 空白の後ろの文字を大文字にして空白を除き、英数字と - _ 以外を捨てて先頭を大文字にする
+fn tag_name(keyword: &str) -> String {
+    let mut name = String::new();
+    let mut upper = true;
+    for c in keyword.chars() {
+        if c.is_whitespace() {
+            upper = true;
+        } else if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+            name.push(if upper { c.to_ascii_uppercase() } else { c });
+            upper = false;
+        }
+    }
+    if name.len() < 2 {
+        name.insert_str(0, "Tag");
+    }
+    name
+}
+
+// 画像の大きさやテキストチャンクを入れるグループ。JPEG のコメントは ExifTool では File グループになる
+fn text_group(format: &str) -> &str {
+    match format {
+        "PNG" | "SVG" => format,
+        _ => "File",
+    }
+}
+
+// ExifTool と同じ順序 (SourceFile、ファイル、画像の大きさ、テキスト、Composite) に並べたタグ
+pub fn tags(metadata: &ImageMetadata) -> Vec<(String, Value)> {
+    let mut tags: Vec<(String, Value)> = Vec::new();
+    let mut add = |key: &str, value: Value| {
+        // 同じタグが繰り返されていれば ExifTool と同じく最初のものだけにする (-a を付けないとき)
+        if tags.iter().all(|(k, _)| k != key) {
+            tags.push((key.to_owned(), value));
+        }
+    };
+    let filename = metadata.filename.to_string_lossy();
+    // ExifTool はフォルダーを渡したときの区切りを / にする
+    let source = metadata.path.as_ref().map_or_else(|| filename.to_string(), |path| path.to_string_lossy().replace('\\', "/"));
+    add("SourceFile", Value::String(source));
+    add("File:FileName", Value::String(filename.into_owned()));
+    add("File:FileSize", Value::Number(metadata.file_size as f64));
+    add("File:FileType", Value::String(metadata.format.to_owned()));
+    let group = text_group(metadata.format);
+    add(&format!("{group}:ImageWidth"), Value::Number(metadata.width as f64));
+    add(&format!("{group}:ImageHeight"), Value::Number(metadata.height as f64));
+    if metadata.bit_depth != 0 {
+        let name = if metadata.format == "JPEG" { "BitsPerSample" } else { "BitDepth" };
+        add(&format!("{group}:{name}"), Value::Number(metadata.bit_depth as f64));
+    }
+    if let Some(color_type) = metadata.color_type {
+        add("PNG:ColorType", Value::Number(color_type as u8 as f64));
+    }
+    if let Some(interlaced) = metadata.interlaced {
+        add("PNG:Interlace", Value::Number(interlaced as u8 as f64));
+    }
+    if let Some(details) = &metadata.jpeg {
+        add("File:EncodingProcess", Value::Number((details.marker & 0x0f) as f64));
+        if let Some(jfif) = details.jfif {
+            add("JFIF:JFIFVersion", Value::String(format!("{} {}", jfif.version.0, jfif.version.1)));
+            add("JFIF:ResolutionUnit", Value::Number(jfif.units as f64));
+            add("JFIF:XResolution", Value::Number(jfif.x_density as f64));
+            add("JFIF:YResolution", Value::Number(jfif.y_density as f64));
+        }
+        if let Some(transform) = details.adobe_transform {
+            add("APP14:ColorTransform", Value::Number(transform as f64));
+        }
+    }
+    if let Some(description) = metadata.icc_profile.as_ref().and_then(|icc| icc.description.as_ref()) {
+        add("ICC_Profile:ProfileDescription", Value::String(description.clone()));
+    }
+    for (keyword, text) in &metadata.text_chunks {
+        // IPTC のように読み取るときにグループを付けたものはそのまま
+        let key = if keyword.contains(':') { keyword.clone() } else { format!("{}:{}", text_group(metadata.format), tag_name(keyword)) };
+        add(&key, Value::String(text.clone()));
+    }
+    if let Some(gps) = &metadata.gps {
+        add("Composite:GPSLatitude", Value::Number(gps.latitude));
+        add("Composite:GPSLongitude", Value::Number(gps.longitude));
+        if let Some(altitude) = gps.altitude {
+            add("Composite:GPSAltitude", Value::Number(altitude));
+        }
+    }
+    tags
+}
+
+// 伏せ字モードの置き換え。伏せるタグは数値でも文字列の ███ にする
+fn redact_tag(key: &str, value: Value, fields: &[redact::Field]) -> Value {
+    match value {
+        _ if redact::is_sensitive_key(key, fields) => Value::String(redact::MASK.to_owned()),
+        Value::String(s) => Value::String(redact::redact_text(&s, fields).into_owned()),
+        value => value,
+    }
+}
+
+// exiftool -j と同じく、1 つのファイルでも配列に入れる
+pub fn to_json(metadata: &ImageMetadata, redactions: &[redact::Field]) -> String {
+    let tags: Vec<(String, Value)> = tags(metadata).into_iter().map(|(key, value)| {
+        let value = redact_tag(&key, value, redactions);
+        (key, value)
+    }).collect();
+    let members: Vec<String> = tags.iter().map(|(key, value)| format!("  {}: {}", json::quote(key), json::to_string(value))).collect();
+    format!("[{{\n{}\n}}]\n", members.join(",\n"))
+}
+
+// 比べるための文字列にする。リスト (Keywords など) は MetaView と同じく ", " でつなぐ
+fn normalize(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.replace("\r\n", "\n").trim().to_owned(),
+        Value::Array(items) => items.iter().map(normalize).collect::<Vec<_>>().join(", "),
+        value => json::to_string(value),
+    }
+}
+
+// 数値は表示の桁数が違っても同じとみなす (GPS の座標など)
+fn same_value(a: &Value, b: &Value) -> bool {
+    let (a, b) = (normalize(a), normalize(b));
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(x), Ok(y)) => (x - y).abs() < 1e-6,
+        _ => a == b,
+    }
+}
+
+fn group(key: &str) -> &str {
+    key.split_once(':').map_or("", |(group, _)| group)
+}
+
+#[derive(Debug)]
+pub enum Difference {
+    Changed { key: String, ours: String, theirs: String },
+    OnlyOurs { key: String },
+    OnlyTheirs { key: String, value: String },
+}
+
+#[derive(Debug)]
+pub struct Comparison {
+    pub json_path: String,
+    pub matched: usize,
+    pub differences: Vec<Difference>,
+}
+
+// SourceFile のファイル名が同じものを探す。1 つしかなければファイル名が違ってもそれと比べる
+fn find_entry<'a>(entries: &'a [Value], filename: &str) -> Option<&'a [(String, Value)]> {
+    let objects: Vec<&[(String, Value)]> = entries.iter()
+        .filter_map(|entry| match entry {
+            Value::Object(members) => Some(&members[..]),
+            _ => None,
+        })
+        .collect();
+    let same_name = |members: &&[(String, Value)]| members.iter()
+        .find(|(key, _)| key == "SourceFile")
+        .and_then(|(_, value)| value.as_str())
+        .and_then(|source| source.rsplit(['/', '\\']).next())
+        .is_some_and(|name| name.eq_ignore_ascii_case(filename));
+    objects.iter().copied().find(same_name).or_else(|| (objects.len() == 1).then(|| objects[0]))
+}
+
+fn truncate(text: &str) -> String {
+    let text = text.replace('\n', " ");
+    match text.char_indices().nth(MAX_VALUE_CHARS) {
+        Some((i, _)) => format!("{}…", &text[..i]),
+        None => text,
+    }
+}
+
+// ExifTool で保存した JSON (サイドカー) と今のメタデータを比べる
+pub fn compare(metadata: &ImageMetadata, json_path: &Path, json_text: &str) -> anyhow::Result<Comparison> {
+    let json_text = json_text.strip_prefix('\u{feff}').unwrap_or(json_text);
+    let entries = match json::parse(json_text) {
+        Some(Value::Array(entries)) => entries,
+        Some(object @ Value::Object(_)) => vec![object],
+        _ => anyhow::bail!(tr(Msg::InvalidExiftoolJson)),
+    };
+    let theirs = find_entry(&entries, &metadata.filename.to_string_lossy())
+        .ok_or_else(|| anyhow::anyhow!(tr(Msg::ExiftoolNoEntry)))?;
+    let ours = tags(metadata);
+    let mut comparison = Comparison { json_path: json_path.display().to_string(), matched: 0, differences: Vec::new() };
+    for (key, value) in ours.iter().filter(|(key, _)| key != "SourceFile") {
+        match theirs.iter().find(|(k, _)| k == key) {
+            Some((_, other)) if same_value(value, other) => comparison.matched += 1,
+            Some((_, other)) => comparison.differences.push(Difference::Changed {
+                key: key.clone(),
+                ours: truncate(&normalize(value)),
+                theirs: truncate(&normalize(other)),
+            }),
+            None => comparison.differences.push(Difference::OnlyOurs { key: key.clone() }),
+        }
+    }
+    // ExifTool はファイルの日時や Composite のほか、PNG の Filter や Gamma のような技術的なタグも数値で出す
+    // それらは MetaView では読まないので、JSON にだけあるものとして挙げるのはテキストと同じグループの文字列だけにする
+    let text_groups: Vec<&str> = ours.iter()
+        .map(|(key, _)| group(key))
+        .filter(|group| !matches!(*group, "" | "File" | "Composite"))
+        .collect();
+    for (key, value) in theirs {
+        if matches!(value, Value::String(_) | Value::Array(_)) && text_groups.contains(&group(key)) && ours.iter().all(|(k, _)| k != key) {
+            comparison.differences.push(Difference::OnlyTheirs { key: key.clone(), value: truncate(&normalize(value)) });
+        }
+    }
+    Ok(comparison)
+}
+
+impl Difference {
+    // 一覧で入れる見出し
+    fn section(&self) -> Msg {
+        match self {
+            Difference::Changed { .. } => Msg::ExiftoolChanged,
+            Difference::OnlyOurs { .. } => Msg::ExiftoolOnlyOurs,
+            Difference::OnlyTheirs { .. } => Msg::ExiftoolOnlyTheirs,
+        }
+    }
+
+    fn line(&self) -> String {
+        match self {
+            Difference::Changed { key, ours, theirs } => format!("{key}: {ours} → {theirs}"),
+            Difference::OnlyOurs { key } => key.clone(),
+            Difference::OnlyTheirs { key, value } => format!("{key}: {value}"),
+        }
+    }
+}
+
+impl Comparison {
+    pub fn format(&self) -> String {
+        let mut ret = format!("【{}】\r\n{}\r\n\r\n", tr(Msg::ExiftoolCompare), self.json_path);
+        ret.push_str(&format!("{}: {}\r\n", tr(Msg::ExiftoolMatched), self.matched));
+        for section in [Msg::ExiftoolChanged, Msg::ExiftoolOnlyOurs, Msg::ExiftoolOnlyTheirs] {
+            let lines: Vec<String> = self.differences.iter().filter(|d| d.section() == section).map(Difference::line).collect();
+            if !lines.is_empty() {
+                ret.push_str(&format!("\r\n{} ({}):\r\n", tr(section), lines.len()));
+                for line in lines {
+                    ret.push_str(&format!("  {line}\r\n"));
+                }
+            }
+        }
+        ret
+    }
+}
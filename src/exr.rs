@@ -0,0 +1,291 @@
+// OpenEXR のヘッダー (属性の表) を読む。画素はデコードしない
+// 属性は 名前\0 型\0 大きさ (i32) 値 の並びで、名前が空 (\0 だけ) のところで終わる
+
+use crate::i18n::{tr, Msg};
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+// バージョン番号 (下位 8 ビット) の上のフラグ
+const FLAG_TILED: u32 = 0x200;
+const FLAG_DEEP: u32 = 0x800;
+const FLAG_MULTIPART: u32 = 0x1000;
+// 値を表示するのをあきらめる大きさ (preview などの大きな属性)
+const MAX_DISPLAY_SIZE: usize = 4096;
+
+#[derive(Debug, Clone)]
+pub struct Channel {
+    pub name: String,
+    // 0: UINT, 1: HALF, 2: FLOAT
+    pub pixel_type: i32,
+    pub sampling: (i32, i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Box2i {
+    pub min: (i32, i32),
+    pub max: (i32, i32),
+}
+
+impl Box2i {
+    pub fn size(&self) -> (u32, u32) {
+        let length = |min: i32, max: i32| (max as i64 - min as i64 + 1).clamp(0, u32::MAX as i64) as u32;
+        (length(self.min.0, self.max.0), length(self.min.1, self.max.1))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExrHeader {
+    pub version: u32,
+    pub flags: u32,
+    pub channels: Vec<Channel>,
+    pub compression: Option<u8>,
+    pub data_window: Option<Box2i>,
+    pub display_window: Option<Box2i>,
+    pub line_order: Option<u8>,
+    // 文字列の属性 (レンダラーが書くもの)。読み終えたらテキストチャンクに移す
+    pub strings: Vec<(String, String)>,
+    // それ以外の属性 ("pixelAspectRatio (float)" と値)
+    pub others: Vec<(String, String)>,
+    // マルチパートのときのパートの数
+    pub parts: usize,
+}
+
+pub fn is_exr(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        Some(i32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        Some(f32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    // NUL で終わる名前
+    fn name(&mut self) -> Option<String> {
+        let rest = self.data.get(self.pos..)?;
+        let len = rest.iter().position(|&b| b == 0)?;
+        self.pos += len + 1;
+        Some(String::from_utf8_lossy(&rest[..len]).into_owned())
+    }
+
+    fn box2i(&mut self) -> Option<Box2i> {
+        Some(Box2i { min: (self.i32()?, self.i32()?), max: (self.i32()?, self.i32()?) })
+    }
+}
+
+fn channels(value: &[u8]) -> Option<Vec<Channel>> {
+    let mut reader = Reader { data: value, pos: 0 };
+    let mut ret = Vec::new();
+    loop {
+        let name = reader.name()?;
+        if name.is_empty() {
+            return Some(ret);
+        }
+        let pixel_type = reader.i32()?;
+        // pLinear と予約の 3 バイト
+        reader.bytes(4)?;
+        let sampling = (reader.i32()?, reader.i32()?);
+        ret.push(Channel { name, pixel_type, sampling });
+    }
+}
+
+fn floats(value: &[u8]) -> String {
+    value.chunks_exact(4)
+        .map(|b| f32::from_le_bytes(b.try_into().unwrap()).to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn ints(value: &[u8]) -> String {
+    value.chunks_exact(4)
+        .map(|b| i32::from_le_bytes(b.try_into().unwrap()).to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// 型がわかる属性だけ値を文字列にする
+fn format_value(kind: &str, value: &[u8]) -> Option<String> {
+    let mut reader = Reader { data: value, pos: 0 };
+    let text = match kind {
+        "float" => reader.f32()?.to_string(),
+        "double" => f64::from_le_bytes(value.get(..8)?.try_into().unwrap()).to_string(),
+        "int" => reader.i32()?.to_string(),
+        "v2f" | "v3f" | "chromaticities" | "m33f" | "m44f" => floats(value),
+        "v2i" | "v3i" => ints(value),
+        "box2i" => {
+            let b = reader.box2i()?;
+            format!("({}, {}) - ({}, {})", b.min.0, b.min.1, b.max.0, b.max.1)
+        }
+        "box2f" => floats(value),
+        "rational" => format!("{}/{}", reader.i32()?, u32::from_le_bytes(value.get(4..8)?.try_into().unwrap())),
+        "envmap" => match value.first()? {
+            0 => "latlong".to_owned(),
+            1 => "cube".to_owned(),
+            n => n.to_string(),
+        },
+        "stringvector" => {
+            let mut items = Vec::new();
+            while reader.pos < value.len() {
+                let len = reader.i32()?;
+                items.push(String::from_utf8_lossy(reader.bytes(usize::try_from(len).ok()?)?).into_owned());
+            }
+            items.join(", ")
+        }
+        _ if value.len() <= 16 => value.iter().map(|b| format!("{b:02x}")).collect::<Vec<_>>().join(" "),
+        _ => return None,
+    };
+    Some(text)
+}
+
+// マルチパートでは最初のパートのヘッダーを読み、パートの数だけ数える
+pub fn parse(data: &[u8]) -> anyhow::Result<ExrHeader> {
+    parse_header(data).ok_or_else(|| anyhow::anyhow!("EXR header is truncated"))
+}
+
+fn parse_header(data: &[u8]) -> Option<ExrHeader> {
+    let version_field = u32::from_le_bytes(data.get(4..8)?.try_into().unwrap());
+    let mut header = ExrHeader { version: version_field & 0xff, flags: version_field & !0xff, parts: 1, ..Default::default() };
+    let mut reader = Reader { data, pos: 8 };
+    loop {
+        let name = reader.name()?;
+        if name.is_empty() {
+            break;
+        }
+        let kind = reader.name()?;
+        let size = usize::try_from(reader.i32()?).ok()?;
+        let value = reader.bytes(size)?;
+        match (name.as_str(), kind.as_str()) {
+            ("channels", "chlist") => header.channels = channels(value)?,
+            ("compression", "compression") => header.compression = value.first().copied(),
+            ("dataWindow", "box2i") => header.data_window = Reader { data: value, pos: 0 }.box2i(),
+            ("displayWindow", "box2i") => header.display_window = Reader { data: value, pos: 0 }.box2i(),
+            ("lineOrder", "lineOrder") => header.line_order = value.first().copied(),
+            (_, "string") => header.strings.push((name, String::from_utf8_lossy(value).into_owned())),
+            (_, "preview") => header.others.push((format!("{name} ({kind})"), preview_size(value))),
+            _ if size <= MAX_DISPLAY_SIZE => {
+                if let Some(text) = format_value(&kind, value) {
+                    header.others.push((format!("{name} ({kind})"), text));
+                }
+            }
+            _ => {}
+        }
+    }
+    if header.flags & FLAG_MULTIPART != 0 {
+        // 残りのパートのヘッダーは空の名前で区切られ、最後はもう一つの空の名前で終わる
+        while reader.data.get(reader.pos).is_some_and(|&b| b != 0) {
+            loop {
+                let name = reader.name()?;
+                if name.is_empty() {
+                    break;
+                }
+                reader.name()?;
+                let size = usize::try_from(reader.i32()?).ok()?;
+                reader.bytes(size)?;
+            }
+            header.parts += 1;
+        }
+    }
+    Some(header)
+}
+
+// プレビュー画像は大きさだけ表示する
+fn preview_size(value: &[u8]) -> String {
+    let width = value.get(..4).map_or(0, |b| u32::from_le_bytes(b.try_into().unwrap()));
+    let height = value.get(4..8).map_or(0, |b| u32::from_le_bytes(b.try_into().unwrap()));
+    format!("{width} x {height}")
+}
+
+fn pixel_type_name(pixel_type: i32) -> &'static str {
+    match pixel_type {
+        0 => "uint",
+        1 => "half",
+        2 => "float",
+        _ => "?",
+    }
+}
+
+fn compression_name(compression: u8) -> &'static str {
+    match compression {
+        0 => "NONE",
+        1 => "RLE",
+        2 => "ZIPS",
+        3 => "ZIP",
+        4 => "PIZ",
+        5 => "PXR24",
+        6 => "B44",
+        7 => "B44A",
+        8 => "DWAA",
+        9 => "DWAB",
+        _ => "?",
+    }
+}
+
+impl ExrHeader {
+    // 画像の大きさは dataWindow (なければ displayWindow) の大きさ
+    pub fn size(&self) -> (u32, u32) {
+        self.data_window.or(self.display_window).map_or((0, 0), |b| b.size())
+    }
+
+    // 最初のチャンネルの 1 サンプルのビット数
+    pub fn bit_depth(&self) -> u8 {
+        match self.channels.first().map(|c| c.pixel_type) {
+            Some(1) => 16,
+            Some(0 | 2) => 32,
+            _ => 0,
+        }
+    }
+
+    pub fn format(&self) -> String {
+        let mut kinds = vec![if self.flags & FLAG_TILED != 0 { tr(Msg::ExrTiled) } else { tr(Msg::ExrScanline) }];
+        if self.flags & FLAG_DEEP != 0 {
+            kinds.push(tr(Msg::ExrDeep));
+        }
+        let mut ret = format!("OpenEXR {}: {}\r\n", self.version, kinds.join(", "));
+        if self.parts > 1 {
+            ret.push_str(&format!("{}: {}\r\n", tr(Msg::ExrParts), self.parts));
+        }
+        if !self.channels.is_empty() {
+            let channels: Vec<String> = self.channels.iter()
+                .map(|c| {
+                    let sampling = if c.sampling == (1, 1) { String::new() } else { format!(" {}x{}", c.sampling.0, c.sampling.1) };
+                    format!("{} ({}{sampling})", c.name, pixel_type_name(c.pixel_type))
+                })
+                .collect();
+            ret.push_str(&format!("{}: {}\r\n", tr(Msg::ExrChannels), channels.join(", ")));
+        }
+        if let Some(compression) = self.compression {
+            ret.push_str(&format!("{}: {}\r\n", tr(Msg::ExrCompression), compression_name(compression)));
+        }
+        for (msg, window) in [(Msg::ExrDataWindow, self.data_window), (Msg::ExrDisplayWindow, self.display_window)] {
+            if let Some(b) = window {
+                ret.push_str(&format!("{}: ({}, {}) - ({}, {})\r\n", tr(msg), b.min.0, b.min.1, b.max.0, b.max.1));
+            }
+        }
+        if let Some(order) = self.line_order {
+            let name = match order {
+                0 => "INCREASING_Y",
+                1 => "DECREASING_Y",
+                2 => "RANDOM_Y",
+                _ => "?",
+            };
+            ret.push_str(&format!("{}: {name}\r\n", tr(Msg::ExrLineOrder)));
+        }
+        for (name, value) in &self.others {
+            ret.push_str(&format!("{name}: {value}\r\n"));
+        }
+        ret
+    }
+}
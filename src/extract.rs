@@ -0,0 +1,48 @@
+// 設定ファイルに書いた正規表現で、テキストチャンクから決まった形の文字列を抜き出す
+//
+//   extract.ticket=\b([A-Z]+-\d+)\b
+//   extract.ticket.label=Ticket $1
+//
+// label には $1, ${name} のようにキャプチャーを使える。省略するとマッチした全体 ($0) を出す
+
+use regex::Regex;
+use crate::i18n::{tr, Msg};
+
+#[derive(Debug, Clone)]
+pub struct ExtractRule {
+    pub name: String,
+    pub pattern: String,
+    pub label: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Extracted {
+    pub name: String,
+    pub value: String,
+}
+
+// 同じ値は 1 回だけ出す。パターンが正しくなければ、そのことを結果として出す
+pub fn run(rules: &[ExtractRule], text_chunks: &[(String, String)]) -> Vec<Extracted> {
+    let mut ret: Vec<Extracted> = Vec::new();
+    for rule in rules.iter().filter(|rule| !rule.pattern.is_empty()) {
+        let regex = match Regex::new(&rule.pattern) {
+            Ok(regex) => regex,
+            Err(e) => {
+                let value = format!("{}: {}", tr(Msg::InvalidPattern), e.to_string().lines().last().unwrap_or(""));
+                ret.push(Extracted { name: rule.name.clone(), value });
+                continue;
+            }
+        };
+        let label = if rule.label.is_empty() { "$0" } else { &rule.label };
+        for (_, text) in text_chunks {
+            for captures in regex.captures_iter(text) {
+                let mut value = String::new();
+                captures.expand(label, &mut value);
+                if !ret.iter().any(|e| e.name == rule.name && e.value == value) {
+                    ret.push(Extracted { name: rule.name.clone(), value });
+                }
+            }
+        }
+    }
+    ret
+}
@@ -0,0 +1,128 @@
+// ファイルシステムまわりの補助関数
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::Read;
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::os::windows::io::FromRawHandle;
+use std::path::{Component, Path, PathBuf, Prefix};
+use std::time::Duration;
+use windows::core::{Interface, HSTRING, PCWSTR};
+use windows::Win32::Foundation::*;
+use windows::Win32::Storage::FileSystem::*;
+use windows::Win32::System::Com::*;
+use windows::Win32::UI::Shell::*;
+
+const MAX_PATH: usize = 260;
+
+// MAX_PATH を超える絶対パスには \\?\ を付けて、長いパスでも Win32 API で扱えるようにする
+pub fn long_path(path: &Path) -> PathBuf {
+    let len = path.as_os_str().len();
+    if len < MAX_PATH || !path.is_absolute() {
+        return path.to_owned();
+    }
+    match path.components().next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(_) => {
+                let mut ret = OsString::from(r"\\?\");
+                ret.push(path.as_os_str());
+                PathBuf::from(ret)
+            }
+            Prefix::UNC(..) => {
+                let s = path.as_os_str().to_string_lossy();
+                PathBuf::from(format!(r"\\?\UNC\{}", s.trim_start_matches('\\')))
+            }
+            // すでに \\?\ などが付いている
+            _ => path.to_owned(),
+        },
+        _ => path.to_owned(),
+    }
+}
+
+// 生成ツールが書き込み中のファイルは共有違反で開けないことがあるので、少し待って開き直す
+const OPEN_RETRIES: u32 = 5;
+const OPEN_RETRY_INTERVAL: Duration = Duration::from_millis(200);
+
+// ほかのプログラムが書き込みや削除のために開いていても読めるように、すべての共有を許して開く
+fn open_shared(path: &Path) -> windows::core::Result<File> {
+    let path = HSTRING::from(long_path(path).as_os_str());
+    let handle = unsafe { CreateFileW(
+        &path,
+        FILE_GENERIC_READ,
+        FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+        None,
+        OPEN_EXISTING,
+        FILE_ATTRIBUTE_NORMAL,
+        None) }?;
+    Ok(unsafe { File::from_raw_handle(handle.0 as _) })
+}
+
+fn is_sharing_error(e: &windows::core::Error) -> bool {
+    [ERROR_SHARING_VIOLATION, ERROR_LOCK_VIOLATION].iter().any(|&code| e.code() == code.to_hresult())
+}
+
+// 共有違反で開けない間は少し待つ。待つので UI のスレッドからは呼ばない (フォルダーの監視スレッドで使う)
+pub fn wait_until_readable(path: &Path) {
+    for _ in 0..OPEN_RETRIES {
+        match open_shared(path) {
+            Err(e) if is_sharing_error(&e) => std::thread::sleep(OPEN_RETRY_INTERVAL),
+            _ => return,
+        }
+    }
+}
+
+// fs::read の代わり。長いパスにも対応する
+pub fn read_shared(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let mut file = open_shared(path).map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+// \\?\ 付きのパスを、短ければ普通の形に戻す
+fn strip_verbatim(path: PathBuf) -> PathBuf {
+    let s = path.as_os_str().to_string_lossy();
+    let stripped = if let Some(rest) = s.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{rest}")
+    } else if let Some(rest) = s.strip_prefix(r"\\?\") {
+        rest.to_owned()
+    } else {
+        return path;
+    };
+    if stripped.len() < MAX_PATH {
+        PathBuf::from(stripped)
+    } else {
+        path
+    }
+}
+
+// ショートカット (.lnk) のリンク先を読む
+fn resolve_shortcut(path: &Path) -> anyhow::Result<PathBuf> {
+    let link: IShellLinkW = unsafe { CoCreateInstance(&ShellLink, None, CLSCTX_INPROC_SERVER) }?;
+    let file: IPersistFile = link.cast()?;
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    unsafe { file.Load(PCWSTR(wide.as_ptr()), STGM_READ.0) }?;
+    // リンク先が移動していても UI を出さずに探せる範囲で探す
+    let _ = unsafe { link.Resolve(HWND(0), SLR_NO_UI.0 as u32 | SLR_NOUPDATE.0 as u32) };
+    let mut buf = vec![0u16; 32768];
+    unsafe { link.GetPath(&mut buf, std::ptr::null_mut(), 0) }?;
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    if len == 0 {
+        anyhow::bail!("shortcut does not point to a file: {}", path.display());
+    }
+    Ok(PathBuf::from(OsString::from_wide(&buf[..len])))
+}
+
+fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(long_path(path)).is_ok_and(|m| m.file_type().is_symlink())
+}
+
+// ショートカット・シンボリックリンク・ジャンクションをたどって実体のパスを返す
+pub fn resolve_link(path: &Path) -> anyhow::Result<PathBuf> {
+    let is_shortcut = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("lnk"));
+    let path = if is_shortcut { resolve_shortcut(path)? } else { path.to_owned() };
+    if path.ancestors().any(is_symlink) {
+        return Ok(strip_verbatim(fs::canonicalize(long_path(&path))?));
+    }
+    Ok(path)
+}
@@ -0,0 +1,190 @@
+// フォルダーを集計したあとに表示するサムネイルの一覧。選んだ画像のメタデータを表示欄に出す
+// サムネイルは別のスレッドで順に作り、できたものから差し替える
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use windows::{
+    core::*,
+    Win32::{
+        Foundation::*,
+        Graphics::Gdi::*,
+        System::Com::*,
+        UI::{Controls::*, WindowsAndMessaging::*},
+    },
+};
+use crate::batch::ScannedImage;
+use crate::fsutil;
+use crate::imaging::{self, Bitmap};
+
+// wparam: 一覧を作り直した回数, lparam: Box<(usize, Bitmap)> (項目の位置とサムネイル) のポインタ
+pub const WM_APP_GALLERY_THUMBNAIL: u32 = WM_APP + 10;
+
+const THUMBNAIL_SIZE: u32 = 128;
+// サムネイルの間隔 (ファイル名の分も空ける)
+const SPACING: (u32, u32) = (THUMBNAIL_SIZE + 16, THUMBNAIL_SIZE + 40);
+
+// commctrl.h の LVN_ITEMCHANGED (windows クレートには定義がない)
+const LVN_ITEMCHANGED: u32 = -101i32 as u32;
+
+// 作っている途中で一覧が作り直されたら、前のスレッドは止める
+static CURRENT_JOB: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug)]
+pub struct Gallery {
+    pub hwnd: HWND,
+    images: HIMAGELIST,
+    files: Vec<PathBuf>,
+    job: usize,
+}
+
+impl Default for Gallery {
+    fn default() -> Self {
+        Gallery { hwnd: HWND(0), images: HIMAGELIST(0), files: Vec::new(), job: 0 }
+    }
+}
+
+impl Gallery {
+    // 一覧を作り直すまでは表示しない
+    pub fn create(parent: HWND, instance: HINSTANCE, id: isize) -> Gallery {
+        let style = WS_CHILD.0 | WS_CLIPSIBLINGS.0 | WS_BORDER.0 | WS_TABSTOP.0 | LVS_ICON | LVS_AUTOARRANGE | LVS_SINGLESEL | LVS_SHOWSELALWAYS;
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            w!("SysListView32"),
+            None,
+            WINDOW_STYLE(style),
+            0, 0, 0, 0,
+            parent, HMENU(id), instance, None) };
+        // 一覧が壊されるときにイメージリストも解放される
+        let images = unsafe { ImageList_Create(THUMBNAIL_SIZE as i32, THUMBNAIL_SIZE as i32, ILC_COLOR32, 0, 64) };
+        unsafe { SendMessageW(hwnd, LVM_SETIMAGELIST, WPARAM(LVSIL_NORMAL as usize), LPARAM(images.0)) };
+        unsafe { SendMessageW(hwnd, LVM_SETEXTENDEDLISTVIEWSTYLE, WPARAM(0), LPARAM(LVS_EX_DOUBLEBUFFER as isize)) };
+        let spacing = (SPACING.1 << 16) | SPACING.0;
+        unsafe { SendMessageW(hwnd, LVM_SETICONSPACING, WPARAM(0), LPARAM(spacing as isize)) };
+        Gallery { hwnd, images, files: Vec::new(), job: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        unsafe { SendMessageW(self.hwnd, LVM_GETITEMCOUNT, WPARAM(0), LPARAM(0)) }.0 == 0
+    }
+
+    // files の index 番目の画像の、今の一覧での位置
+    fn find_item(&self, index: usize) -> Option<i32> {
+        let info = LVFINDINFOW { flags: LVFI_PARAM, lParam: LPARAM(index as isize), ..Default::default() };
+        let item = unsafe { SendMessageW(self.hwnd, LVM_FINDITEMW, WPARAM(usize::MAX), LPARAM(&info as *const _ as isize)) }.0;
+        (item >= 0).then_some(item as i32)
+    }
+
+    // ごみ箱に移した画像などを一覧から消す
+    pub fn remove(&mut self, path: &Path) {
+        let Some(index) = self.files.iter().position(|file| file == path) else { return };
+        if let Some(item) = self.find_item(index) {
+            unsafe { SendMessageW(self.hwnd, LVM_DELETEITEM, WPARAM(item as usize), LPARAM(0)) };
+        }
+    }
+
+    // 一覧を入れ替え、サムネイルを作り始める。できるまでは空白を表示する
+    pub fn set_files(&mut self, owner: HWND, files: Vec<ScannedImage>) {
+        self.job = CURRENT_JOB.fetch_add(1, Ordering::SeqCst) + 1;
+        unsafe { SendMessageW(self.hwnd, LVM_DELETEALLITEMS, WPARAM(0), LPARAM(0)) };
+        unsafe { ImageList_Remove(self.images, -1) };
+        let blank = vec![0u8; (THUMBNAIL_SIZE * THUMBNAIL_SIZE * 4) as usize];
+        if let Ok(bitmap) = imaging::create_dib(THUMBNAIL_SIZE, THUMBNAIL_SIZE, &blank) {
+            unsafe { ImageList_Add(self.images, bitmap, None) };
+            unsafe { DeleteObject(bitmap) };
+        }
+        self.files = files.iter().map(|file| file.path.clone()).collect();
+        for (i, path) in self.files.iter().enumerate() {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let mut name: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+            // 項目を消しても files の位置がわかるように、lParam に入れておく
+            let item = LVITEMW {
+                mask: LVIF_TEXT | LVIF_IMAGE | LVIF_PARAM,
+                iItem: i as i32,
+                pszText: PWSTR(name.as_mut_ptr()),
+                iImage: 0,
+                lParam: LPARAM(i as isize),
+                ..Default::default()
+            };
+            unsafe { SendMessageW(self.hwnd, LVM_INSERTITEMW, WPARAM(0), LPARAM(&item as *const _ as isize)) };
+        }
+        let job = self.job;
+        std::thread::spawn(move || make_thumbnails(owner, job, files));
+    }
+
+    // 作り直す前の一覧のサムネイルは捨てる
+    pub fn set_thumbnail(&mut self, job: usize, index: usize, mut bitmap: Bitmap) {
+        if job != self.job {
+            return;
+        }
+        let Some(item) = self.find_item(index) else { return };
+        // イメージリストは乗算済みのアルファを前提にしている
+        for pixel in bitmap.pixels.chunks_exact_mut(4) {
+            let alpha = pixel[3] as u32;
+            for c in &mut pixel[..3] {
+                *c = (*c as u32 * alpha / 255) as u8;
+            }
+        }
+        let pixels = imaging::pad_to_square(&bitmap, THUMBNAIL_SIZE);
+        let Ok(image) = imaging::create_dib(THUMBNAIL_SIZE, THUMBNAIL_SIZE, &pixels) else {
+            return;
+        };
+        let image_index = unsafe { ImageList_Add(self.images, image, None) };
+        unsafe { DeleteObject(image) };
+        if image_index < 0 {
+            return;
+        }
+        let item = LVITEMW {
+            mask: LVIF_IMAGE,
+            iItem: item,
+            iImage: image_index,
+            ..Default::default()
+        };
+        unsafe { SendMessageW(self.hwnd, LVM_SETITEMW, WPARAM(0), LPARAM(&item as *const _ as isize)) };
+    }
+
+    // WM_NOTIFY が一覧で新しく選ばれた画像の通知ならそのパスを返す
+    pub fn selected_file(&self, lparam: LPARAM) -> Option<&Path> {
+        let nmhdr = unsafe { &*(lparam.0 as *const NMHDR) };
+        if nmhdr.hwndFrom != self.hwnd || nmhdr.code != LVN_ITEMCHANGED {
+            return None;
+        }
+        let change = unsafe { &*(lparam.0 as *const NMLISTVIEW) };
+        let selected = LVIS_SELECTED.0;
+        if change.uNewState & selected == 0 || change.uOldState & selected != 0 {
+            return None;
+        }
+        self.files.get(usize::try_from(change.lParam.0).ok()?).map(PathBuf::as_path)
+    }
+}
+
+// WM_APP_GALLERY_THUMBNAIL の lparam から結果を取り出す
+pub unsafe fn take_result(lparam: LPARAM) -> (usize, Bitmap) {
+    *Box::from_raw(lparam.0 as *mut (usize, Bitmap))
+}
+
+fn make_thumbnail(image: &ScannedImage) -> anyhow::Result<Bitmap> {
+    let data = fsutil::read_shared(&image.path)?;
+    let bitmap = imaging::decode_scaled(&data, THUMBNAIL_SIZE, THUMBNAIL_SIZE)?;
+    Ok(imaging::apply_orientation(bitmap, image.orientation.unwrap_or(1)))
+}
+
+// 読めなかった画像は空白のままにする
+// WIC を使うのでこのスレッドでも COM を初期化する
+fn make_thumbnails(owner: HWND, job: usize, files: Vec<ScannedImage>) {
+    if unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) }.is_err() {
+        return;
+    }
+    for (i, image) in files.iter().enumerate() {
+        if CURRENT_JOB.load(Ordering::SeqCst) != job {
+            break;
+        }
+        let Ok(bitmap) = make_thumbnail(image) else { continue };
+        let result = Box::into_raw(Box::new((i, bitmap)));
+        let posted = unsafe { PostMessageW(owner, WM_APP_GALLERY_THUMBNAIL, WPARAM(job), LPARAM(result as isize)) };
+        if !posted.as_bool() {
+            drop(unsafe { Box::from_raw(result) });
+            break;
+        }
+    }
+    unsafe { CoUninitialize() };
+}
@@ -0,0 +1,133 @@
+// 生成パラメーターに入っているモデルや LoRA のハッシュを名前に解決する
+// ユーザーが書く一覧 (model_hashes.txt) と、Civitai に問い合わせた結果のキャッシュ (civitai_cache.txt) を使う
+
+use std::fs;
+use std::path::PathBuf;
+use crate::params::{self, Parameters};
+use crate::settings;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    Model,
+    Lora,
+    Embedding,
+}
+
+impl ResourceKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ResourceKind::Model => "Model",
+            ResourceKind::Lora => "LoRA",
+            ResourceKind::Embedding => "Embedding",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ModelHash {
+    pub kind: ResourceKind,
+    // パラメーターに書かれている名前
+    pub name: Option<String>,
+    pub hash: String,
+    // ハッシュから分かった名前
+    pub resolved: Option<String>,
+}
+
+// "Model hash", "Lora hashes", "TI hashes" の項目から集める
+pub fn find_hashes(params: &Parameters) -> Vec<ModelHash> {
+    let mut hashes = Vec::new();
+    if let Some(hash) = params.get("Model hash") {
+        hashes.push(ModelHash {
+            kind: ResourceKind::Model,
+            name: params.get("Model").map(str::to_owned),
+            hash: hash.to_owned(),
+            resolved: None,
+        });
+    }
+    // "name: hash, name: hash" の形で入っている
+    for (key, kind) in [("Lora hashes", ResourceKind::Lora), ("TI hashes", ResourceKind::Embedding)] {
+        for field in params.get(key).map(params::parse_settings_line).unwrap_or_default() {
+            hashes.push(ModelHash { kind, name: Some(field.key), hash: field.value, resolved: None });
+        }
+    }
+    hashes
+}
+
+// ハッシュの長さは種類やツールによって違う (AutoV2 は SHA-256 の先頭 10 文字など) ので前方一致で比べる
+const MIN_HASH_LEN: usize = 8;
+
+fn hash_matches(a: &str, b: &str) -> bool {
+    let len = a.len().min(b.len());
+    len >= MIN_HASH_LEN && a[..len].eq_ignore_ascii_case(&b[..len])
+}
+
+// "hash=name" の行を並べたファイル。name が空なら「見つからなかった」ことを表す
+#[derive(Debug, Clone, Default)]
+pub struct HashDatabase {
+    entries: Vec<(String, String)>,
+}
+
+impl HashDatabase {
+    pub fn load(path: Option<PathBuf>) -> HashDatabase {
+        let content = path.and_then(|path| fs::read_to_string(path).ok()).unwrap_or_default();
+        let entries = content.lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| line.split_once('='))
+            .map(|(hash, name)| (hash.trim().to_ascii_lowercase(), name.trim().to_owned()))
+            .filter(|(hash, _)| hash.is_ascii() && hash.len() >= MIN_HASH_LEN)
+            .collect();
+        HashDatabase { entries }
+    }
+
+    pub fn get(&self, hash: &str) -> Option<&str> {
+        self.entries.iter().find(|(h, _)| hash.is_ascii() && hash_matches(h, hash)).map(|(_, name)| name.as_str())
+    }
+
+    pub fn insert(&mut self, hash: &str, name: &str) {
+        self.entries.retain(|(h, _)| !h.eq_ignore_ascii_case(hash));
+        self.entries.push((hash.to_ascii_lowercase(), name.to_owned()));
+    }
+
+    pub fn save(&self, path: Option<PathBuf>) -> anyhow::Result<()> {
+        let path = path.ok_or_else(|| anyhow::anyhow!("APPDATA is not set"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let content: String = self.entries.iter().map(|(hash, name)| format!("{hash}={name}\r\n")).collect();
+        fs::write(path, content)?;
+        Ok(())
+    }
+}
+
+pub fn local_database_path() -> Option<PathBuf> {
+    settings::data_dir().map(|dir| dir.join("model_hashes.txt"))
+}
+
+pub fn civitai_cache_path() -> Option<PathBuf> {
+    settings::data_dir().map(|dir| dir.join("civitai_cache.txt"))
+}
+
+// ユーザーの一覧を優先し、なければ Civitai のキャッシュを見る
+pub fn resolve(hashes: &mut [ModelHash]) {
+    let local = HashDatabase::load(local_database_path());
+    let cache = HashDatabase::load(civitai_cache_path());
+    for hash in hashes {
+        hash.resolved = local.get(&hash.hash).or_else(|| cache.get(&hash.hash))
+            .filter(|name| !name.is_empty())
+            .map(str::to_owned);
+    }
+}
+
+// まだ Civitai に問い合わせていないハッシュ
+pub fn unresolved(hashes: &[ModelHash]) -> Vec<String> {
+    let local = HashDatabase::load(local_database_path());
+    let cache = HashDatabase::load(civitai_cache_path());
+    let mut ret: Vec<String> = Vec::new();
+    for hash in hashes.iter().map(|h| &h.hash) {
+        if local.get(hash).is_none() && cache.get(hash).is_none() && !ret.contains(hash) {
+            ret.push(hash.clone());
+        }
+    }
+    ret
+}
@@ -0,0 +1,42 @@
+// ファイルのハッシュと知覚ハッシュをバックグラウンドで計算する
+
+use windows::Win32::{
+    Foundation::*,
+    System::Com::*,
+    UI::WindowsAndMessaging::*,
+};
+use crate::digest::{self, FileDigests};
+use crate::imaging;
+
+// wparam: 計算を始めたときの番号, lparam: Box<FileDigests> のポインタ
+pub const WM_APP_DIGESTS_DONE: u32 = WM_APP + 7;
+
+// 知覚ハッシュは 32x32 に縮めてから計算するので、デコードもある程度縮めて行う
+const PHASH_DECODE_SIZE: u32 = 256;
+
+// job は結果が届くまでに別のファイルが開かれていないかを確かめるための番号
+pub fn start(hwnd: HWND, job: usize, data: Vec<u8>, perceptual: bool) {
+    std::thread::spawn(move || {
+        let phash = if perceptual { perceptual_hash(&data) } else { None };
+        let digests = FileDigests { sha256: digest::sha256(&data), blake3: digest::blake3(&data), phash };
+        let digests = Box::into_raw(Box::new(digests));
+        let posted = unsafe { PostMessageW(hwnd, WM_APP_DIGESTS_DONE, WPARAM(job), LPARAM(digests as isize)) };
+        if !posted.as_bool() {
+            drop(unsafe { Box::from_raw(digests) });
+        }
+    });
+}
+
+// WM_APP_DIGESTS_DONE の lparam から結果を取り出す
+pub unsafe fn take_result(lparam: LPARAM) -> FileDigests {
+    *Box::from_raw(lparam.0 as *mut FileDigests)
+}
+
+// WIC を使うのでこのスレッドでも COM を初期化する
+pub fn perceptual_hash(data: &[u8]) -> Option<String> {
+    unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) }.ok()?;
+    let bitmap = imaging::decode_scaled(data, PHASH_DECODE_SIZE, PHASH_DECODE_SIZE);
+    unsafe { CoUninitialize() };
+    let bitmap = bitmap.ok()?;
+    Some(digest::phash(&bitmap.pixels, bitmap.width, bitmap.height))
+}
@@ -0,0 +1,45 @@
+// 16 進表示。1 行に 16 バイトずつ、位置・16 進・ASCII を並べる
+// 位置の変換は RichEdit に表示したときの文字位置 (改行は 1 文字) で数える
+
+use std::ops::Range;
+
+pub const ROW_LEN: usize = 16;
+// "00000000  " の後ろに 16 進が始まる
+const HEX_START: usize = 10;
+// 16 進の後ろの 2 文字の空白の後ろに ASCII が始まる
+const ASCII_START: usize = HEX_START + ROW_LEN * 3 - 1 + 2;
+// ASCII と改行を含めた 1 行の長さ
+const LINE_LEN: usize = ASCII_START + ROW_LEN + 1;
+
+// offset は行の先頭のバイトのファイルの中の位置
+pub fn row(offset: usize, row: &[u8]) -> String {
+    let hex: Vec<String> = row.iter().map(|b| format!("{b:02x}")).collect();
+    let ascii: String = row.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }).collect();
+    format!("{offset:08x}  {:<47}  {ascii}\r\n", hex.join(" "))
+}
+
+pub fn dump(data: &[u8]) -> String {
+    data.chunks(ROW_LEN).enumerate().map(|(i, chunk)| row(i * ROW_LEN, chunk)).collect()
+}
+
+// バイトの範囲を、dump した文字列の中で 16 進が並んでいる範囲に変換する
+pub fn text_range(bytes: Range<usize>) -> Range<usize> {
+    let position = |offset: usize| offset / ROW_LEN * LINE_LEN + HEX_START + offset % ROW_LEN * 3;
+    if bytes.is_empty() {
+        return position(bytes.start)..position(bytes.start);
+    }
+    position(bytes.start)..position(bytes.end - 1) + 2
+}
+
+// dump した文字列の中の位置が指しているバイト。位置の列ではその行の先頭のバイトにする
+pub fn byte_at(position: usize) -> usize {
+    let column = position % LINE_LEN;
+    let index = if column >= ASCII_START {
+        column - ASCII_START
+    } else if column >= HEX_START {
+        (column - HEX_START) / 3
+    } else {
+        0
+    };
+    position / LINE_LEN * ROW_LEN + index.min(ROW_LEN - 1)
+}
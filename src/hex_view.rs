@@ -0,0 +1,152 @@
+// ファイルの 16 進表示。チャンクやセグメントの一覧で選んだ範囲を 16 進表示で選択し、
+// 16 進表示でカーソルを動かすと、その位置を含むものを一覧で選ぶ
+
+use std::ffi::OsStr;
+use std::mem;
+use std::ops::Range;
+use windows::{
+    core::*,
+    Win32::{
+        Foundation::*,
+        UI::{Controls::{*, RichEdit::*}, WindowsAndMessaging::*},
+    },
+};
+use metaview_core::hex;
+use crate::dialog::{self, DialogTemplate};
+use crate::i18n::{tr, Msg};
+
+const IDC_ITEMS: i32 = 100;
+const IDC_HEX: i32 = 101;
+
+// 大きなファイルは先頭だけを表示する (RichEdit に入れる文字数を抑えるため)
+const MAX_SHOWN: usize = 1 << 20;
+
+struct ViewState {
+    items: Vec<(String, Range<usize>)>,
+    // 表示しているバイト数
+    shown: usize,
+    select: Range<usize>,
+    text: String,
+    // 一覧から選んで 16 進表示の選択を変えている間は、EN_SELCHANGE で一覧を選び直さない
+    syncing: bool,
+}
+
+// items は一覧に並べる名前とファイルの中の範囲。select ははじめに選んでおく範囲
+pub fn show(parent: HWND, filename: &OsStr, data: &[u8], items: Vec<(String, Range<usize>)>, select: Range<usize>) {
+    let shown = data.len().min(MAX_SHOWN);
+    let mut title = format!("{} - {}", tr(Msg::HexViewTitle), filename.to_string_lossy());
+    if shown < data.len() {
+        title.push_str(&format!(" ({})", tr(Msg::HexViewTruncated)));
+    }
+    let mut state = ViewState { items, shown, select, text: hex::dump(&data[..shown]), syncing: false };
+    let template = DialogTemplate::new(&title, 520, 280)
+        .item(dialog::LISTBOX, "", IDC_ITEMS,
+            LBS_NOTIFY as u32 | WS_BORDER.0 | WS_VSCROLL.0 | WS_TABSTOP.0,
+            7, 7, 150, 246)
+        .custom_item("RICHEDIT50W", "", IDC_HEX,
+            (ES_MULTILINE | ES_READONLY | ES_NOHIDESEL) as u32 | WS_BORDER.0 | WS_VSCROLL.0 | WS_TABSTOP.0,
+            161, 7, 352, 246)
+        .item(dialog::BUTTON, tr(Msg::Close), IDCANCEL.0, BS_DEFPUSHBUTTON as u32 | WS_TABSTOP.0, 463, 259, 50, 14);
+    template.show(parent, Some(dialog_proc), LPARAM(&mut state as *mut _ as isize));
+}
+
+unsafe fn get_state<'a>(hdlg: HWND) -> Option<&'a mut ViewState> {
+    (GetWindowLongPtrW(hdlg, GWLP_USERDATA) as *mut ViewState).as_mut()
+}
+
+// 位置がずれないように等幅のフォントにする
+fn set_monospace(hedit: HWND) {
+    let mut cf = CHARFORMAT2W::default();
+    cf.Base.cbSize = mem::size_of::<CHARFORMAT2W>() as u32;
+    cf.Base.dwMask = CFM_FACE | CFM_SIZE;
+    // 10 ポイント (1/20 ポイント単位)
+    cf.Base.yHeight = 200;
+    for (dst, src) in cf.Base.szFaceName.iter_mut().zip("Consolas".encode_utf16()) {
+        *dst = src;
+    }
+    unsafe { SendMessageW(hedit, EM_SETCHARFORMAT, WPARAM(SCF_ALL as usize), LPARAM(&cf as *const _ as isize)) };
+}
+
+fn fill_items(hlist: HWND, items: &[(String, Range<usize>)]) {
+    for (name, range) in items {
+        let text: Vec<u16> = format!("{name}  0x{:x} ({} bytes)", range.start, range.len()).encode_utf16().chain(Some(0)).collect();
+        unsafe { SendMessageW(hlist, LB_ADDSTRING, WPARAM(0), LPARAM(text.as_ptr() as isize)) };
+    }
+}
+
+// 表示していない部分は選べないので、表示している範囲に収める
+fn select_bytes(hdlg: HWND, state: &mut ViewState, bytes: Range<usize>) {
+    let hedit = unsafe { GetDlgItem(hdlg, IDC_HEX) };
+    let range = hex::text_range(bytes.start.min(state.shown)..bytes.end.min(state.shown));
+    state.syncing = true;
+    // 先頭が見えるようにスクロールしてから範囲を選ぶ
+    let caret = CHARRANGE { cpMin: range.start as i32, cpMax: range.start as i32 };
+    unsafe { SendMessageW(hedit, EM_EXSETSEL, WPARAM(0), LPARAM(&caret as *const _ as isize)) };
+    unsafe { SendMessageW(hedit, EM_SCROLLCARET, WPARAM(0), LPARAM(0)) };
+    let range = CHARRANGE { cpMin: range.start as i32, cpMax: range.end as i32 };
+    unsafe { SendMessageW(hedit, EM_EXSETSEL, WPARAM(0), LPARAM(&range as *const _ as isize)) };
+    state.syncing = false;
+}
+
+// offset を含むもののうち、いちばん小さいもの (APP1 の中の EXIF など)
+fn item_at(items: &[(String, Range<usize>)], offset: usize) -> Option<usize> {
+    items.iter()
+        .enumerate()
+        .filter(|(_, (_, range))| range.contains(&offset))
+        .min_by_key(|(_, (_, range))| range.len())
+        .map(|(index, _)| index)
+}
+
+extern "system" fn dialog_proc(hdlg: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> isize {
+    match message {
+        WM_INITDIALOG => {
+            unsafe { SetWindowLongPtrW(hdlg, GWLP_USERDATA, lparam.0) };
+            let state = unsafe { get_state(hdlg) }.unwrap();
+            let hlist = unsafe { GetDlgItem(hdlg, IDC_ITEMS) };
+            fill_items(hlist, &state.items);
+            let hedit = unsafe { GetDlgItem(hdlg, IDC_HEX) };
+            let text = mem::take(&mut state.text);
+            unsafe { SendMessageW(hedit, EM_EXLIMITTEXT, WPARAM(0), LPARAM(text.len() as isize)) };
+            unsafe { SetWindowTextW(hedit, &HSTRING::from(text)) };
+            set_monospace(hedit);
+            unsafe { SendMessageW(hedit, EM_SETEVENTMASK, WPARAM(0), LPARAM(ENM_SELCHANGE as isize)) };
+            if let Some(index) = state.items.iter().position(|(_, range)| *range == state.select) {
+                unsafe { SendMessageW(hlist, LB_SETCURSEL, WPARAM(index), LPARAM(0)) };
+            }
+            select_bytes(hdlg, state, state.select.clone());
+            1
+        }
+        WM_NOTIFY => {
+            let Some(state) = (unsafe { get_state(hdlg) }) else { return 0 };
+            let nmhdr = unsafe { &*(lparam.0 as *const NMHDR) };
+            if nmhdr.idFrom == IDC_HEX as usize && nmhdr.code == EN_SELCHANGE && !state.syncing {
+                let change = unsafe { &*(lparam.0 as *const SELCHANGE) };
+                let offset = hex::byte_at(change.chrg.cpMin.max(0) as usize);
+                // 含むものがなければ一覧の選択を外す
+                let index = item_at(&state.items, offset).unwrap_or(usize::MAX);
+                unsafe { SendMessageW(GetDlgItem(hdlg, IDC_ITEMS), LB_SETCURSEL, WPARAM(index), LPARAM(0)) };
+            }
+            0
+        }
+        WM_COMMAND => {
+            let Some(state) = (unsafe { get_state(hdlg) }) else { return 0 };
+            let id = (wparam.0 & 0xffff) as i32;
+            let code = (wparam.0 >> 16) as u32;
+            match id {
+                IDC_ITEMS if code == LBN_SELCHANGE => {
+                    let index = unsafe { SendMessageW(GetDlgItem(hdlg, IDC_ITEMS), LB_GETCURSEL, WPARAM(0), LPARAM(0)) }.0;
+                    if let Some((_, range)) = usize::try_from(index).ok().and_then(|index| state.items.get(index)) {
+                        select_bytes(hdlg, state, range.clone());
+                    }
+                    1
+                }
+                id if id == IDOK.0 || id == IDCANCEL.0 => {
+                    unsafe { EndDialog(hdlg, id as isize) };
+                    1
+                }
+                _ => 0,
+            }
+        }
+        _ => 0,
+    }
+}
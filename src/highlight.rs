@@ -0,0 +1,160 @@
+// 表示テキストの色付け範囲を求める
+
+use crate::params::{self, TagKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Header,
+    JsonKey,
+    JsonString,
+    JsonNumber,
+    JsonLiteral,
+    ParamKey,
+    Lora,
+    Embedding,
+    Attention,
+    Link,
+}
+
+// start, end は UTF-16 単位の位置 (改行は 1 文字として数える)
+#[derive(Debug, Clone, Copy)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub style: Style,
+}
+
+fn utf16_len(s: &str) -> usize {
+    s.chars().map(char::len_utf16).sum()
+}
+
+fn is_header(line: &str) -> bool {
+    line.starts_with('【') && line.ends_with('】')
+}
+
+// 表示しているパラメーターの TI hashes から埋め込みの名前を拾う
+fn embedding_names(text: &str) -> Vec<String> {
+    text.lines()
+        .filter(|line| line.starts_with("Steps: "))
+        .flat_map(|line| params::parse_settings_line(line).into_iter().filter(|field| field.key == "TI hashes"))
+        .flat_map(|field| params::parse_settings_line(&field.value))
+        .map(|field| field.key)
+        .collect()
+}
+
+pub fn highlight(text: &str) -> Vec<Span> {
+    // RichEdit は \r\n を 1 文字として扱うので \n に揃えてから位置を数える
+    let text = text.replace("\r\n", "\n");
+    let embeddings = embedding_names(&text);
+    let mut spans = Vec::new();
+    let mut pos = 0;
+    let mut body_start = 0;
+    let mut body = String::new();
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_end_matches('\n');
+        if is_header(content) {
+            highlight_body(&body, body_start, &embeddings, &mut spans);
+            spans.push(Span { start: pos, end: pos + utf16_len(content), style: Style::Header });
+            body.clear();
+            body_start = pos + utf16_len(line);
+        } else {
+            body.push_str(line);
+        }
+        pos += utf16_len(line);
+    }
+    highlight_body(&body, body_start, &embeddings, &mut spans);
+    spans
+}
+
+fn highlight_body(body: &str, offset: usize, embeddings: &[String], spans: &mut Vec<Span>) {
+    let trimmed = body.trim_start();
+    if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        highlight_json(body, offset, spans);
+    } else {
+        highlight_params(body, offset, spans);
+        highlight_prompt_tags(body, offset, embeddings, spans);
+    }
+}
+
+// <lora:...> などのタグ、埋め込み、(text:1.2) の強調
+fn highlight_prompt_tags(body: &str, offset: usize, embeddings: &[String], spans: &mut Vec<Span>) {
+    for tag in params::prompt_tags(body, embeddings) {
+        let style = match tag.kind {
+            TagKind::Lora | TagKind::Hypernetwork => Style::Lora,
+            TagKind::Embedding => Style::Embedding,
+            TagKind::Attention => Style::Attention,
+        };
+        let start = offset + utf16_len(&body[..tag.range.start]);
+        let end = start + utf16_len(&body[tag.range.clone()]);
+        spans.push(Span { start, end, style });
+    }
+}
+
+fn highlight_json(body: &str, offset: usize, spans: &mut Vec<Span>) {
+    let chars: Vec<char> = body.chars().collect();
+    let mut pos = offset;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '"' {
+            let start = pos;
+            let begin = i;
+            i += 1;
+            while i < chars.len() && chars[i] != '"' {
+                if chars[i] == '\\' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            i = (i + 1).min(chars.len());
+            pos += chars[begin..i].iter().map(|c| c.len_utf16()).sum::<usize>();
+            let is_key = chars[i..].iter().find(|c| !c.is_whitespace()) == Some(&':');
+            let style = if is_key { Style::JsonKey } else { Style::JsonString };
+            spans.push(Span { start, end: pos, style });
+        } else if c == '-' || c.is_ascii_digit() {
+            let start = pos;
+            while i < chars.len() && (chars[i].is_ascii_digit() || "+-.eE".contains(chars[i])) {
+                pos += 1;
+                i += 1;
+            }
+            spans.push(Span { start, end: pos, style: Style::JsonNumber });
+        } else if c.is_ascii_alphabetic() {
+            let start = pos;
+            while i < chars.len() && chars[i].is_ascii_alphabetic() {
+                pos += 1;
+                i += 1;
+            }
+            spans.push(Span { start, end: pos, style: Style::JsonLiteral });
+        } else {
+            pos += c.len_utf16();
+            i += 1;
+        }
+    }
+}
+
+// "Steps: 20, Sampler: Euler a" のような行のキー部分を探す
+fn highlight_params(body: &str, offset: usize, spans: &mut Vec<Span>) {
+    let mut pos = offset;
+    for line in body.split_inclusive('\n') {
+        let mut key_start = 0;
+        let mut line_pos = pos;
+        let mut key_pos = pos;
+        for (i, c) in line.char_indices() {
+            if c == ':' && line[i..].starts_with(": ") {
+                let key = &line[key_start..i];
+                let is_key = !key.is_empty() && key.len() <= 40
+                    && key.starts_with(|c: char| c.is_alphabetic())
+                    && key.chars().all(|c| c.is_alphanumeric() || " _-".contains(c));
+                if is_key {
+                    spans.push(Span { start: key_pos, end: line_pos + 1, style: Style::ParamKey });
+                }
+            }
+            if c == ',' && line[i..].starts_with(", ") {
+                key_start = i + 2;
+                key_pos = line_pos + 2;
+            }
+            line_pos += c.len_utf16();
+        }
+        pos += utf16_len(line);
+    }
+}
@@ -0,0 +1,184 @@
+// このセッションで開いたファイルの履歴
+// 設定で有効にすると history.csv に追記し、次に起動したときに読み込む
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use windows::Win32::{Foundation::SYSTEMTIME, System::SystemInformation::GetLocalTime};
+use crate::encoding::{self, Newline, OutputEncoding};
+use crate::fsutil;
+use crate::i18n::{tr, Msg};
+use crate::metadata::ImageMetadata;
+use crate::params;
+use crate::settings;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryEntry {
+    // "2023-01-02 03:04:05" (ローカル時刻)
+    pub time: String,
+    // ファイルから開いたときはフルパス、それ以外は名前だけ
+    pub file: String,
+    pub size: String,
+    pub model: String,
+    pub sampler: String,
+    pub steps: String,
+    pub cfg_scale: String,
+    pub seed: String,
+}
+
+const COLUMNS: usize = 8;
+
+impl HistoryEntry {
+    pub fn new(metadata: &ImageMetadata) -> HistoryEntry {
+        let file = match &metadata.path {
+            Some(path) => path.display().to_string(),
+            None => metadata.filename.to_string_lossy().into_owned(),
+        };
+        let params = params::find_parameters(&metadata.text_chunks);
+        let get = |key: &str| params.as_ref().and_then(|p| p.get(key)).unwrap_or_default().to_owned();
+        let model = params.as_ref().and_then(|p| p.get("Model").or_else(|| p.get("Model hash"))).unwrap_or_default().to_owned();
+        HistoryEntry {
+            time: now(),
+            file,
+            size: format!("{}x{}", metadata.width, metadata.height),
+            model,
+            sampler: get("Sampler"),
+            steps: get("Steps"),
+            cfg_scale: get("CFG scale"),
+            seed: get("Seed"),
+        }
+    }
+
+    // 一覧や CSV の列の順
+    pub fn fields(&self) -> [&str; COLUMNS] {
+        [&self.time, &self.file, &self.size, &self.model, &self.sampler, &self.steps, &self.cfg_scale, &self.seed]
+    }
+
+    fn from_fields(fields: Vec<String>) -> Option<HistoryEntry> {
+        let [time, file, size, model, sampler, steps, cfg_scale, seed]: [String; COLUMNS] = fields.try_into().ok()?;
+        Some(HistoryEntry { time, file, size, model, sampler, steps, cfg_scale, seed })
+    }
+}
+
+// 列の見出し。CSV の 1 行目にも使う
+pub fn column_names() -> [&'static str; COLUMNS] {
+    [
+        tr(Msg::HistoryTime), tr(Msg::HistoryFile), tr(Msg::Dimensions), tr(Msg::HistoryModel),
+        tr(Msg::HistorySampler), tr(Msg::HistorySteps), tr(Msg::HistoryCfgScale), tr(Msg::HistorySeed),
+    ]
+}
+
+pub fn now() -> String {
+    let mut t = SYSTEMTIME::default();
+    unsafe { GetLocalTime(&mut t) };
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", t.wYear, t.wMonth, t.wDay, t.wHour, t.wMinute, t.wSecond)
+}
+
+// カンマ、引用符、改行を含むものだけ引用符で囲む (RFC 4180)
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+fn csv_line(fields: &[&str]) -> String {
+    let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+    format!("{}\r\n", fields.join(","))
+}
+
+pub fn to_csv(entries: &[HistoryEntry]) -> String {
+    let mut ret = csv_line(&column_names());
+    for entry in entries {
+        ret.push_str(&csv_line(&entry.fields()));
+    }
+    ret
+}
+
+// 空の項目は書かない
+pub fn to_text(entries: &[HistoryEntry]) -> String {
+    let names = column_names();
+    let mut ret = String::new();
+    for entry in entries {
+        ret.push_str(&format!("{}  {}\r\n", entry.time, entry.file));
+        for (name, value) in names.iter().zip(entry.fields()).skip(2) {
+            if !value.is_empty() {
+                ret.push_str(&format!("    {name}: {value}\r\n"));
+            }
+        }
+    }
+    ret
+}
+
+// 引用符の中の改行も扱う
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+fn history_path() -> Option<PathBuf> {
+    settings::data_dir().map(|dir| dir.join("history.csv"))
+}
+
+// 1 行目は見出しなので読み飛ばす
+pub fn load() -> Vec<HistoryEntry> {
+    let Some(content) = history_path().and_then(|path| fs::read_to_string(path).ok()) else {
+        return Vec::new();
+    };
+    parse_csv(&content).into_iter().skip(1).filter_map(HistoryEntry::from_fields).collect()
+}
+
+pub fn append(entry: &HistoryEntry) -> anyhow::Result<()> {
+    let path = history_path().ok_or_else(|| anyhow::anyhow!("APPDATA is not set"))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let is_new = !path.exists();
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    if is_new {
+        file.write_all(csv_line(&column_names()).as_bytes())?;
+    }
+    file.write_all(csv_line(&entry.fields()).as_bytes())?;
+    Ok(())
+}
+
+pub fn clear() -> anyhow::Result<()> {
+    match history_path() {
+        Some(path) if path.exists() => Ok(fs::remove_file(path)?),
+        _ => Ok(()),
+    }
+}
+
+// 書き出し先の拡張子が .csv なら CSV、それ以外はテキスト
+// Excel で文字化けしないように、文字コードは BOM 付きの UTF-8 か Shift_JIS にしておくとよい
+pub fn export(path: &Path, entries: &[HistoryEntry], output: OutputEncoding, newline: Newline) -> anyhow::Result<()> {
+    let is_csv = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+    let content = if is_csv { to_csv(entries) } else { to_text(entries) };
+    fs::write(fsutil::long_path(path), encoding::encode_text(&content, output, newline))?;
+    Ok(())
+}
@@ -0,0 +1,172 @@
+// 履歴の一覧。選んだファイルを開き直したり、CSV やテキストに書き出したりする
+
+use std::path::{Path, PathBuf};
+use windows::{
+    core::*,
+    Win32::{
+        Foundation::*,
+        UI::{Controls::*, WindowsAndMessaging::*},
+    },
+};
+use crate::dialog::{self, DialogTemplate};
+use crate::encoding::{Newline, OutputEncoding};
+use crate::history::{self, HistoryEntry};
+use crate::i18n::{tr, Msg};
+
+const IDC_LIST: i32 = 100;
+const IDC_EXPORT: i32 = 101;
+const IDC_CLEAR: i32 = 102;
+
+// commctrl.h の NM_DBLCLK (windows クレートには定義がない)
+const NM_DBLCLK: u32 = -3i32 as u32;
+
+// 列の幅 (ピクセル)
+const COLUMN_WIDTHS: [i32; 8] = [120, 260, 70, 140, 110, 50, 50, 90];
+
+struct ViewState {
+    entries: Vec<HistoryEntry>,
+    open: Option<PathBuf>,
+    cleared: bool,
+    // 書き出すときの文字コードと改行
+    encoding: OutputEncoding,
+    newline: Newline,
+}
+
+pub struct ViewResult {
+    // 開き直すファイル
+    pub open: Option<PathBuf>,
+    // 履歴が消去された
+    pub cleared: bool,
+}
+
+pub fn show(parent: HWND, entries: &[HistoryEntry], encoding: OutputEncoding, newline: Newline) -> ViewResult {
+    let mut state = ViewState { entries: entries.to_vec(), open: None, cleared: false, encoding, newline };
+    let template = DialogTemplate::new(tr(Msg::HistoryTitle), 480, 260)
+        .custom_item("SysListView32", "", IDC_LIST,
+            LVS_REPORT | LVS_SINGLESEL | LVS_SHOWSELALWAYS | WS_BORDER.0 | WS_TABSTOP.0,
+            7, 7, 466, 224)
+        .item(dialog::BUTTON, tr(Msg::HistoryExport), IDC_EXPORT, WS_TABSTOP.0, 7, 239, 60, 14)
+        .item(dialog::BUTTON, tr(Msg::HistoryClear), IDC_CLEAR, WS_TABSTOP.0, 71, 239, 50, 14)
+        .item(dialog::BUTTON, tr(Msg::HistoryOpen), IDOK.0, BS_DEFPUSHBUTTON as u32 | WS_TABSTOP.0, 369, 239, 50, 14)
+        .item(dialog::BUTTON, tr(Msg::Close), IDCANCEL.0, WS_TABSTOP.0, 423, 239, 50, 14);
+    template.show(parent, Some(dialog_proc), LPARAM(&mut state as *mut _ as isize));
+    ViewResult { open: state.open, cleared: state.cleared }
+}
+
+unsafe fn get_state<'a>(hdlg: HWND) -> Option<&'a mut ViewState> {
+    (GetWindowLongPtrW(hdlg, GWLP_USERDATA) as *mut ViewState).as_mut()
+}
+
+fn set_item_text(hlist: HWND, message: u32, row: usize, column: usize, text: &str) {
+    let mut text: Vec<u16> = text.encode_utf16().chain(Some(0)).collect();
+    let item = LVITEMW {
+        mask: LVIF_TEXT,
+        iItem: row as i32,
+        iSubItem: column as i32,
+        pszText: PWSTR(text.as_mut_ptr()),
+        ..Default::default()
+    };
+    unsafe { SendMessageW(hlist, message, WPARAM(row), LPARAM(&item as *const _ as isize)) };
+}
+
+// 新しいものを上に並べる
+fn fill_list(hlist: HWND, entries: &[HistoryEntry]) {
+    unsafe { SendMessageW(hlist, LVM_DELETEALLITEMS, WPARAM(0), LPARAM(0)) };
+    for (row, entry) in entries.iter().rev().enumerate() {
+        for (column, text) in entry.fields().into_iter().enumerate() {
+            let message = if column == 0 { LVM_INSERTITEMW } else { LVM_SETITEMTEXTW };
+            set_item_text(hlist, message, row, column, text);
+        }
+    }
+}
+
+fn init_list(hlist: HWND, entries: &[HistoryEntry]) {
+    unsafe { SendMessageW(hlist, LVM_SETEXTENDEDLISTVIEWSTYLE, WPARAM(0), LPARAM(LVS_EX_FULLROWSELECT as isize)) };
+    for (i, (name, width)) in history::column_names().into_iter().zip(COLUMN_WIDTHS).enumerate() {
+        let mut name: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+        let column = LVCOLUMNW {
+            mask: LVCF_TEXT | LVCF_WIDTH,
+            cx: width,
+            pszText: PWSTR(name.as_mut_ptr()),
+            ..Default::default()
+        };
+        unsafe { SendMessageW(hlist, LVM_INSERTCOLUMNW, WPARAM(i), LPARAM(&column as *const _ as isize)) };
+    }
+    fill_list(hlist, entries);
+}
+
+fn selected_entry(hdlg: HWND, state: &ViewState) -> Option<&HistoryEntry> {
+    let hlist = unsafe { GetDlgItem(hdlg, IDC_LIST) };
+    let row = unsafe { SendMessageW(hlist, LVM_GETNEXTITEM, WPARAM(usize::MAX), LPARAM(LVNI_SELECTED as isize)) }.0;
+    let row = usize::try_from(row).ok()?;
+    state.entries.iter().rev().nth(row)
+}
+
+// 貼り付けやブラウザからのドロップで開いたものはパスがないので開き直せない
+fn open_selected(hdlg: HWND, state: &mut ViewState) {
+    let Some(entry) = selected_entry(hdlg, state) else { return };
+    if Path::new(&entry.file).is_absolute() {
+        state.open = Some(PathBuf::from(&entry.file));
+        unsafe { EndDialog(hdlg, IDOK.0 as isize) };
+    }
+}
+
+fn export(hdlg: HWND, state: &ViewState) -> anyhow::Result<()> {
+    let filter = format!("{} (*.csv)\0*.csv\0{} (*.txt)\0*.txt\0", tr(Msg::CsvFiles), tr(Msg::TextFiles));
+    let Some((path, _)) = dialog::save_file_dialog(hdlg, "history.csv", &filter, w!("csv"), None) else {
+        return Ok(());
+    };
+    history::export(&path, &state.entries, state.encoding, state.newline)?;
+    let text = HSTRING::from(format!("{}: {}", tr(Msg::SavedTo), path.display()));
+    unsafe { MessageBoxW(hdlg, &text, &HSTRING::from(tr(Msg::HistoryTitle)), MB_OK | MB_ICONINFORMATION) };
+    Ok(())
+}
+
+extern "system" fn dialog_proc(hdlg: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> isize {
+    match message {
+        WM_INITDIALOG => {
+            unsafe { SetWindowLongPtrW(hdlg, GWLP_USERDATA, lparam.0) };
+            let state = unsafe { get_state(hdlg) }.unwrap();
+            init_list(unsafe { GetDlgItem(hdlg, IDC_LIST) }, &state.entries);
+            1
+        }
+        WM_NOTIFY => {
+            let Some(state) = (unsafe { get_state(hdlg) }) else { return 0 };
+            let nmhdr = unsafe { &*(lparam.0 as *const NMHDR) };
+            if nmhdr.idFrom == IDC_LIST as usize && nmhdr.code == NM_DBLCLK {
+                open_selected(hdlg, state);
+                return 1;
+            }
+            0
+        }
+        WM_COMMAND => {
+            let Some(state) = (unsafe { get_state(hdlg) }) else { return 0 };
+            let id = (wparam.0 & 0xffff) as i32;
+            match id {
+                IDC_EXPORT => {
+                    if let Err(e) = export(hdlg, state) {
+                        let text = HSTRING::from(format!("{}: {e}", tr(Msg::Error)));
+                        unsafe { MessageBoxW(hdlg, &text, &HSTRING::from(tr(Msg::HistoryTitle)), MB_OK | MB_ICONERROR) };
+                    }
+                    1
+                }
+                IDC_CLEAR => {
+                    state.entries.clear();
+                    state.cleared = true;
+                    fill_list(unsafe { GetDlgItem(hdlg, IDC_LIST) }, &state.entries);
+                    1
+                }
+                id if id == IDOK.0 => {
+                    open_selected(hdlg, state);
+                    1
+                }
+                id if id == IDCANCEL.0 => {
+                    unsafe { EndDialog(hdlg, IDCANCEL.0 as isize) };
+                    1
+                }
+                _ => 0,
+            }
+        }
+        _ => 0,
+    }
+}
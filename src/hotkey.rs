@@ -0,0 +1,100 @@
+// どこからでもウィンドウを呼び出すグローバルホットキー
+
+use windows::{
+    Win32::{
+        Foundation::*,
+        UI::{Controls::*, Input::KeyboardAndMouse::*, WindowsAndMessaging::*},
+    },
+};
+use crate::dialog::{self, DialogTemplate};
+use crate::i18n::{tr, Msg};
+use crate::settings::{self, Hotkey};
+
+pub const HOTKEY_ID: i32 = 1;
+const IDC_HOTKEY: i32 = 100;
+
+// 前に登録していたものは解除してから登録し直す
+pub fn register(hwnd: HWND, hotkey: Option<Hotkey>) -> anyhow::Result<()> {
+    unsafe { UnregisterHotKey(hwnd, HOTKEY_ID) };
+    let Some(hotkey) = hotkey else {
+        return Ok(());
+    };
+    let modifiers = HOT_KEY_MODIFIERS(hotkey.modifiers) | MOD_NOREPEAT;
+    if !unsafe { RegisterHotKey(hwnd, HOTKEY_ID, modifiers, hotkey.vk) }.as_bool() {
+        anyhow::bail!("{} ({hotkey})", tr(Msg::HotkeyInUse));
+    }
+    Ok(())
+}
+
+pub fn unregister(hwnd: HWND) {
+    unsafe { UnregisterHotKey(hwnd, HOTKEY_ID) };
+}
+
+// ホットキーコントロールの HOTKEYF_* と RegisterHotKey の MOD_* は Shift と Alt のビットが逆
+const MODIFIER_MAP: [(u32, u32); 3] = [
+    (HOTKEYF_SHIFT, settings::MOD_SHIFT),
+    (HOTKEYF_CONTROL, settings::MOD_CONTROL),
+    (HOTKEYF_ALT, settings::MOD_ALT),
+];
+
+fn to_control_value(hotkey: Hotkey) -> usize {
+    let flags = MODIFIER_MAP.iter()
+        .filter(|(_, modifier)| hotkey.modifiers & modifier != 0)
+        .fold(0, |flags, (flag, _)| flags | flag);
+    (hotkey.vk | flags << 8) as usize
+}
+
+fn from_control_value(value: usize) -> Option<Hotkey> {
+    let vk = (value & 0xff) as u32;
+    let flags = ((value >> 8) & 0xff) as u32;
+    let modifiers = MODIFIER_MAP.iter()
+        .filter(|(flag, _)| flags & flag != 0)
+        .fold(0, |modifiers, (_, modifier)| modifiers | modifier);
+    (vk != 0).then_some(Hotkey { modifiers, vk })
+}
+
+struct DialogState {
+    hotkey: Option<Hotkey>,
+}
+
+// キャンセルされたら None、ホットキーを消したときは Some(None) を返す
+pub fn show_dialog(parent: HWND, current: Option<Hotkey>) -> Option<Option<Hotkey>> {
+    let mut state = DialogState { hotkey: current };
+    let template = DialogTemplate::new(tr(Msg::HotkeyTitle), 240, 74)
+        .item(dialog::STATIC, tr(Msg::HotkeyDescription), -1, 0, 7, 7, 226, 20)
+        .custom_item("msctls_hotkey32", "", IDC_HOTKEY, WS_BORDER.0 | WS_TABSTOP.0, 7, 31, 226, 14)
+        .item(dialog::BUTTON, "OK", IDOK.0, BS_DEFPUSHBUTTON as u32 | WS_TABSTOP.0, 129, 53, 50, 14)
+        .item(dialog::BUTTON, tr(Msg::Cancel), IDCANCEL.0, WS_TABSTOP.0, 183, 53, 50, 14);
+    let ret = template.show(parent, Some(dialog_proc), LPARAM(&mut state as *mut _ as isize));
+    (ret == IDOK.0 as isize).then_some(state.hotkey)
+}
+
+extern "system" fn dialog_proc(hdlg: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> isize {
+    match message {
+        WM_INITDIALOG => {
+            unsafe { SetWindowLongPtrW(hdlg, GWLP_USERDATA, lparam.0) };
+            let state = unsafe { (lparam.0 as *mut DialogState).as_mut() }.unwrap();
+            if let Some(hotkey) = state.hotkey {
+                unsafe { SendDlgItemMessageW(hdlg, IDC_HOTKEY, HKM_SETHOTKEY, WPARAM(to_control_value(hotkey)), LPARAM(0)) };
+            }
+            1
+        }
+        WM_COMMAND => {
+            let state = unsafe { (GetWindowLongPtrW(hdlg, GWLP_USERDATA) as *mut DialogState).as_mut() };
+            let Some(state) = state else { return 0 };
+            let id = (wparam.0 & 0xffff) as i32;
+            if id == IDOK.0 {
+                let value = unsafe { SendDlgItemMessageW(hdlg, IDC_HOTKEY, HKM_GETHOTKEY, WPARAM(0), LPARAM(0)) };
+                state.hotkey = from_control_value(value.0 as usize);
+                unsafe { EndDialog(hdlg, IDOK.0 as isize) };
+                1
+            } else if id == IDCANCEL.0 {
+                unsafe { EndDialog(hdlg, IDCANCEL.0 as isize) };
+                1
+            } else {
+                0
+            }
+        }
+        _ => 0,
+    }
+}
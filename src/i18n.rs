@@ -0,0 +1,948 @@
+// UI に表示する文字列の日本語/英語対応
+
+use std::sync::atomic::{AtomicU8, Ordering};
+use windows::Win32::Globalization::GetUserDefaultUILanguage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Japanese,
+    English,
+}
+
+impl Language {
+    // ユーザーの UI 言語から決める
+    pub fn from_user_locale() -> Language {
+        const LANG_JAPANESE: u16 = 0x11;
+        let langid = unsafe { GetUserDefaultUILanguage() };
+        if langid & 0x3ff == LANG_JAPANESE {
+            Language::Japanese
+        } else {
+            Language::English
+        }
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::Japanese => "ja",
+            Language::English => "en",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Language> {
+        match code {
+            "ja" => Some(Language::Japanese),
+            "en" => Some(Language::English),
+            _ => None,
+        }
+    }
+}
+
+static CURRENT: AtomicU8 = AtomicU8::new(0);
+
+pub fn set_language(lang: Language) {
+    CURRENT.store(lang as u8, Ordering::Relaxed);
+}
+
+pub fn language() -> Language {
+    match CURRENT.load(Ordering::Relaxed) {
+        0 => Language::Japanese,
+        _ => Language::English,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Msg {
+    DropHere,
+    Error,
+    StatusBytes,
+    StatusBits,
+    StatusChunks,
+    Downloading,
+    NoClipboardData,
+    MenuFile,
+    MenuSaveCleanCopy,
+    MenuPrint,
+    MenuOpen,
+    ImageFiles,
+    AccessibleMetadata,
+    AccessibleThumbnail,
+    Loaded,
+    SavedTo,
+    MenuEdit,
+    MenuPaste,
+    MenuEditChunk,
+    EditChunkTitle,
+    MenuAddChunk,
+    AddChunkTitle,
+    Keyword,
+    InvalidKeyword,
+    CreateBackup,
+    Save,
+    Cancel,
+    NotAFile,
+    NoEditableChunks,
+    MenuCopy,
+    MenuCopyValue,
+    MenuCopyKeyValue,
+    MenuCopyPrompt,
+    MenuCopyMarkdown,
+    MenuCopyInfotext,
+    MenuEnableEditing,
+    MenuView,
+    MenuFilter,
+    MenuShowAllChunks,
+    MenuHideBinaryChunks,
+    MenuEncoding,
+    MenuShowPreview,
+    MenuZoomFit,
+    MenuZoomActual,
+    MenuZoomIn,
+    MenuZoomOut,
+    AccessiblePreview,
+    MenuShowGallery,
+    MenuRedact,
+    MenuBatchStrip,
+    BatchStripTitle,
+    OutputFolder,
+    StripCategories,
+    StripText,
+    Start,
+    OutputSameAsSource,
+    NoStripCategory,
+    Cancelled,
+    FilesWritten,
+    BytesRemoved,
+    SkippedUnsupported,
+    MenuCheckPolicy,
+    MenuCheckFolderPolicy,
+    MenuEditPolicy,
+    PolicyCheck,
+    PolicyHeader,
+    PolicyPassed,
+    PolicyMissing,
+    PolicyMissingField,
+    PolicyMissingPattern,
+    PolicyUnknownRule,
+    NoPolicyRules,
+    PolicyViolations,
+    FilesChecked,
+    Line,
+    Prompt,
+    MenuWatchNotify,
+    MenuSaveMetadataText,
+    MarkdownFiles,
+    MenuExportEncoding,
+    MenuNewlineCrLf,
+    MenuNewlineLf,
+    MenuTextZoomIn,
+    MenuTextZoomOut,
+    MenuTextZoomReset,
+    TextZoom,
+    MenuFindDuplicates,
+    MenuMoveToRecycleBin,
+    Duplicates,
+    DuplicateGroups,
+    Group,
+    TexturePixelFormat,
+    TextureKind,
+    TextureCube,
+    TextureVolume,
+    TextureDepth,
+    TextureArraySize,
+    TextureMipLevels,
+    TextureSupercompression,
+    TextureFiles,
+    ExrScanline,
+    ExrTiled,
+    ExrDeep,
+    ExrParts,
+    ExrChannels,
+    ExrCompression,
+    ExrDataWindow,
+    ExrDisplayWindow,
+    ExrLineOrder,
+    MotionPhoto,
+    Seconds,
+    MenuSaveMotionPhotoVideo,
+    Mp4Files,
+    TrailingData,
+    TrailingGuess,
+    TrailingZeros,
+    TrailingText,
+    TrailingUnknown,
+    TrailingDataFound,
+    CrashTitle,
+    CrashDescription,
+    CrashCopyDetails,
+    CrashLog,
+    CrashDump,
+    MenuAccentColors,
+    AccessibleGallery,
+    ImageInfo,
+    Format,
+    Dimensions,
+    BitDepth,
+    ColorType,
+    Interlace,
+    InterlaceNone,
+    Palette,
+    PaletteColors,
+    FileSize,
+    IccProfile,
+    JpegProcess,
+    JpegBaseline,
+    JpegExtended,
+    JpegProgressive,
+    JpegLossless,
+    JpegArithmetic,
+    JpegSubsampling,
+    JpegQuality,
+    JpegNonStandardTables,
+    JfifAspectRatio,
+    AdobeNoTransform,
+    InflateTooLarge,
+    InflateTooSlow,
+    ExpandAnyway,
+    ResourcesUsed,
+    Extracted,
+    InvalidPattern,
+    Watermark,
+    WatermarkFound,
+    ContentCredentials,
+    C2paManifest,
+    C2paActive,
+    C2paClaimGenerator,
+    C2paTitle,
+    C2paSignature,
+    C2paSigner,
+    C2paContentHash,
+    C2paAssertionHashes,
+    C2paActions,
+    C2paIngredients,
+    C2paValid,
+    C2paInvalid,
+    C2paNotChecked,
+    C2paAiGenerated,
+    C2paUntrusted,
+    C2paTrustStore,
+    C2paUnreadable,
+    MenuFolderStats,
+    Scanning,
+    FolderStats,
+    Folder,
+    Images,
+    WithMetadata,
+    WithoutMetadata,
+    UnreadableFiles,
+    Models,
+    Samplers,
+    Seeds,
+    SeedRange,
+    UniqueSeeds,
+    CommonPromptTokens,
+    MenuSizeBreakdown,
+    SizeBreakdown,
+    MenuHexView,
+    HexViewTitle,
+    HexViewTruncated,
+    MetadataTotal,
+    ImageData,
+    OtherData,
+    MetadataOverhead,
+    ModelHashes,
+    HashUnknown,
+    MenuCivitaiLookup,
+    MenuEditHashList,
+    FileHashes,
+    MenuCopyHash,
+    MenuPerceptualHash,
+    HashListHeader,
+    Altitude,
+    LocationEmbedded,
+    MenuOpenMap,
+    MenuSaveThumbnail,
+    JpegFiles,
+    MenuSaveWorkflow,
+    ComfyWorkflowFiles,
+    ComfyPromptFiles,
+    MenuExportExiftoolJson,
+    MenuCompareExiftoolJson,
+    ExiftoolJsonFiles,
+    ExiftoolCompare,
+    ExiftoolMatched,
+    ExiftoolChanged,
+    ExiftoolOnlyOurs,
+    ExiftoolOnlyTheirs,
+    ExiftoolNoEntry,
+    InvalidExiftoolJson,
+    MenuEncodingAuto,
+    MenuSettings,
+    MenuLanguage,
+    MenuLanguageAuto,
+    MenuMinimizeToTray,
+    MenuHotkey,
+    HotkeyTitle,
+    HotkeyDescription,
+    HotkeyInUse,
+    MenuTrayOpen,
+    MenuExit,
+    MenuHistory,
+    HistoryTitle,
+    HistoryTime,
+    HistoryFile,
+    HistoryModel,
+    HistorySampler,
+    HistorySteps,
+    HistoryCfgScale,
+    HistorySeed,
+    HistoryOpen,
+    HistoryExport,
+    HistoryClear,
+    Close,
+    CsvFiles,
+    TextFiles,
+    MenuKeepHistory,
+    MenuCheckUpdates,
+    MenuCheckUpdatesOnStartup,
+    UpdateAvailable,
+    CurrentVersion,
+    LatestVersion,
+    OpenDownloadPage,
+    UpToDate,
+    PluginLoadFailed,
+    AllFiles,
+    ScriptError,
+    ScriptLoadFailed,
+    MenuReloadScripts,
+    MenuFileAssociations,
+    FileAssociationsTitle,
+    FileAssociationsDescription,
+    RemoveAllAssociations,
+    AppDescription,
+    MenuWatchFolder,
+    MenuOpenInViewer,
+    MenuSearchFolder,
+    SearchFolderTitle,
+    SearchFor,
+    SearchTerm,
+    Browse,
+    Search,
+    EnterSearchTerm,
+    FolderNotFound,
+    SearchResults,
+    MatchingImages,
+    MenuIndex,
+    MenuIndexSearch,
+    MenuIndexAddFolder,
+    MenuIndexUpdate,
+    IndexFolderTitle,
+    IndexSearchTitle,
+    IndexQuery,
+    IndexQueryHelp,
+    InvalidQuery,
+    NoIndexedFolders,
+    IndexUpdated,
+    IndexUpdatedFiles,
+    IndexUnchangedFiles,
+    IndexRemovedFiles,
+    MenuShowInExplorer,
+    WatchFolder,
+    WatchingFolder,
+}
+
+pub fn tr(msg: Msg) -> &'static str {
+    use Language::*;
+    match (language(), msg) {
+        (_, Msg::DropHere) => "DRAG AND DROP HERE!!",
+        (Japanese, Msg::Error) => "エラー",
+        (English, Msg::Error) => "ERROR",
+        (Japanese, Msg::StatusBytes) => "バイト",
+        (English, Msg::StatusBytes) => "bytes",
+        (Japanese, Msg::StatusBits) => "ビット",
+        (English, Msg::StatusBits) => "bit",
+        (Japanese, Msg::StatusChunks) => "チャンク",
+        (English, Msg::StatusChunks) => "chunks",
+        (Japanese, Msg::Downloading) => "ダウンロード中",
+        (English, Msg::Downloading) => "Downloading",
+        (Japanese, Msg::NoClipboardData) => "クリップボードに画像や URL がありません",
+        (English, Msg::NoClipboardData) => "The clipboard contains no image or URL",
+        (Japanese, Msg::MenuFile) => "ファイル(&F)",
+        (English, Msg::MenuFile) => "&File",
+        (Japanese, Msg::MenuSaveCleanCopy) => "メタデータを除去したコピーを保存(&M)",
+        (English, Msg::MenuSaveCleanCopy) => "Save Copy Without &Metadata",
+        (Japanese, Msg::MenuPrint) => "印刷(&P)...\tCtrl+P",
+        (English, Msg::MenuPrint) => "&Print...\tCtrl+P",
+        (Japanese, Msg::MenuOpen) => "開く(&O)...\tCtrl+O",
+        (English, Msg::MenuOpen) => "&Open...\tCtrl+O",
+        (Japanese, Msg::ImageFiles) => "画像ファイル",
+        (English, Msg::ImageFiles) => "Image files",
+        (Japanese, Msg::AccessibleMetadata) => "メタデータ",
+        (English, Msg::AccessibleMetadata) => "Metadata",
+        (Japanese, Msg::AccessibleThumbnail) => "埋め込みサムネイル",
+        (English, Msg::AccessibleThumbnail) => "Embedded thumbnail",
+        (Japanese, Msg::Loaded) => "読み込みました",
+        (English, Msg::Loaded) => "Loaded",
+        (Japanese, Msg::SavedTo) => "保存しました",
+        (English, Msg::SavedTo) => "Saved to",
+        (Japanese, Msg::MenuEdit) => "編集(&E)",
+        (English, Msg::MenuEdit) => "&Edit",
+        (Japanese, Msg::MenuPaste) => "貼り付け(&P)\tCtrl+V",
+        (English, Msg::MenuPaste) => "&Paste\tCtrl+V",
+        (Japanese, Msg::MenuEditChunk) => "チャンクを編集(&C)...",
+        (English, Msg::MenuEditChunk) => "Edit &Chunk...",
+        (Japanese, Msg::EditChunkTitle) => "チャンクの編集",
+        (English, Msg::EditChunkTitle) => "Edit Chunk",
+        (Japanese, Msg::MenuAddChunk) => "チャンクを追加(&A)...",
+        (English, Msg::MenuAddChunk) => "&Add Chunk...",
+        (Japanese, Msg::AddChunkTitle) => "チャンクの追加",
+        (English, Msg::AddChunkTitle) => "Add Chunk",
+        (Japanese, Msg::Keyword) => "キーワード:",
+        (English, Msg::Keyword) => "Keyword:",
+        (Japanese, Msg::InvalidKeyword) => "キーワードは 1〜79 文字の Latin-1 文字列で、前後や連続した空白を含まないものにしてください",
+        (English, Msg::InvalidKeyword) => "The keyword must be 1-79 Latin-1 characters without leading, trailing or consecutive spaces",
+        (Japanese, Msg::CreateBackup) => "バックアップを作成する (.bak)",
+        (English, Msg::CreateBackup) => "Create a backup (.bak)",
+        (Japanese, Msg::Save) => "保存",
+        (English, Msg::Save) => "Save",
+        (Japanese, Msg::Cancel) => "キャンセル",
+        (English, Msg::Cancel) => "Cancel",
+        (Japanese, Msg::NotAFile) => "ファイルから開いた画像に対してのみ実行できます",
+        (English, Msg::NotAFile) => "This is only available for images opened from a file",
+        (Japanese, Msg::NoEditableChunks) => "編集できるテキストチャンクがありません",
+        (English, Msg::NoEditableChunks) => "There are no editable text chunks",
+        (Japanese, Msg::MenuCopy) => "コピー(&C)",
+        (English, Msg::MenuCopy) => "&Copy",
+        (Japanese, Msg::MenuCopyValue) => "値をコピー(&V)",
+        (English, Msg::MenuCopyValue) => "Copy &Value",
+        (Japanese, Msg::MenuCopyKeyValue) => "「キー: 値」をコピー(&K)",
+        (English, Msg::MenuCopyKeyValue) => "Copy &Key: Value",
+        (Japanese, Msg::MenuCopyPrompt) => "プロンプトのみコピー(&P)",
+        (English, Msg::MenuCopyPrompt) => "Copy &Prompt Only",
+        (Japanese, Msg::MenuCopyMarkdown) => "Markdown としてコピー(&D)",
+        (English, Msg::MenuCopyMarkdown) => "Copy as Mark&down",
+        (Japanese, Msg::MenuCopyInfotext) => "A1111 形式のパラメーターとしてコピー(&A)",
+        (English, Msg::MenuCopyInfotext) => "Copy as &A1111 Parameters",
+        (Japanese, Msg::MenuEnableEditing) => "表示欄の編集を有効にする(&N)",
+        (English, Msg::MenuEnableEditing) => "E&nable Editing",
+        (Japanese, Msg::MenuView) => "表示(&V)",
+        (English, Msg::MenuView) => "&View",
+        (Japanese, Msg::MenuFilter) => "表示するチャンク(&F)",
+        (English, Msg::MenuFilter) => "Chunk &Filter",
+        (Japanese, Msg::MenuShowAllChunks) => "すべて表示(&A)",
+        (English, Msg::MenuShowAllChunks) => "Show &All",
+        (Japanese, Msg::MenuHideBinaryChunks) => "バイナリや不明なチャンクを隠す(&B)",
+        (English, Msg::MenuHideBinaryChunks) => "Hide &Binary/Unknown Chunks",
+        (Japanese, Msg::ImageInfo) => "画像情報",
+        (English, Msg::ImageInfo) => "Image Info",
+        (Japanese, Msg::Format) => "形式",
+        (English, Msg::Format) => "Format",
+        (Japanese, Msg::Dimensions) => "サイズ",
+        (English, Msg::Dimensions) => "Dimensions",
+        (Japanese, Msg::BitDepth) => "ビット深度",
+        (English, Msg::BitDepth) => "Bit depth",
+        (Japanese, Msg::ColorType) => "カラータイプ",
+        (English, Msg::ColorType) => "Color type",
+        (Japanese, Msg::Interlace) => "インターレース",
+        (English, Msg::Interlace) => "Interlace",
+        (Japanese, Msg::InterlaceNone) => "なし",
+        (English, Msg::InterlaceNone) => "None",
+        (Japanese, Msg::Palette) => "パレット",
+        (English, Msg::Palette) => "Palette",
+        (Japanese, Msg::PaletteColors) => "色",
+        (English, Msg::PaletteColors) => "colors",
+        (Japanese, Msg::FileSize) => "ファイルサイズ",
+        (English, Msg::FileSize) => "File size",
+        (Japanese, Msg::IccProfile) => "ICC プロファイル",
+        (English, Msg::IccProfile) => "ICC profile",
+        (Japanese, Msg::JpegProcess) => "圧縮方式",
+        (English, Msg::JpegProcess) => "Encoding process",
+        (Japanese, Msg::JpegBaseline) => "ベースライン",
+        (English, Msg::JpegBaseline) => "Baseline DCT",
+        (Japanese, Msg::JpegExtended) => "拡張シーケンシャル",
+        (English, Msg::JpegExtended) => "Extended sequential DCT",
+        (Japanese, Msg::JpegProgressive) => "プログレッシブ",
+        (English, Msg::JpegProgressive) => "Progressive DCT",
+        (Japanese, Msg::JpegLossless) => "ロスレス",
+        (English, Msg::JpegLossless) => "Lossless",
+        (Japanese, Msg::JpegArithmetic) => "算術符号",
+        (English, Msg::JpegArithmetic) => "arithmetic coding",
+        (Japanese, Msg::JpegSubsampling) => "色差サブサンプリング",
+        (English, Msg::JpegSubsampling) => "Chroma subsampling",
+        (Japanese, Msg::JpegQuality) => "推定画質",
+        (English, Msg::JpegQuality) => "Estimated quality",
+        (Japanese, Msg::JpegNonStandardTables) => "標準と異なる量子化テーブル",
+        (English, Msg::JpegNonStandardTables) => "non-standard quantization tables",
+        (Japanese, Msg::JfifAspectRatio) => "縦横比のみ",
+        (English, Msg::JfifAspectRatio) => "aspect ratio only",
+        (Japanese, Msg::AdobeNoTransform) => "変換なし (RGB または CMYK)",
+        (English, Msg::AdobeNoTransform) => "no transform (RGB or CMYK)",
+        (Japanese, Msg::InflateTooLarge) => "展開後の大きさが上限を超えたので展開していません",
+        (English, Msg::InflateTooLarge) => "Not expanded because the inflated size exceeded the limit",
+        (Japanese, Msg::InflateTooSlow) => "展開に時間がかかりすぎたので中断しました",
+        (English, Msg::InflateTooSlow) => "Not expanded because inflating took too long",
+        (Japanese, Msg::ExpandAnyway) => "クリックしてそれでも展開する",
+        (English, Msg::ExpandAnyway) => "click to expand anyway",
+        (Japanese, Msg::MenuSearchFolder) => "フォルダーを検索(&F)...\tCtrl+F",
+        (English, Msg::MenuSearchFolder) => "Search &Folder...\tCtrl+F",
+        (Japanese, Msg::SearchFolderTitle) => "フォルダーの検索",
+        (English, Msg::SearchFolderTitle) => "Search Folder",
+        (Japanese, Msg::SearchFor) => "検索する語:",
+        (English, Msg::SearchFor) => "Search for:",
+        (Japanese, Msg::SearchTerm) => "検索した語",
+        (English, Msg::SearchTerm) => "Search term",
+        (Japanese, Msg::Browse) => "参照...",
+        (English, Msg::Browse) => "Browse...",
+        (Japanese, Msg::Search) => "検索",
+        (English, Msg::Search) => "Search",
+        (Japanese, Msg::EnterSearchTerm) => "検索する語を入力してください",
+        (English, Msg::EnterSearchTerm) => "Enter a search term",
+        (Japanese, Msg::FolderNotFound) => "フォルダーが見つかりません",
+        (English, Msg::FolderNotFound) => "The folder was not found",
+        (Japanese, Msg::SearchResults) => "検索結果",
+        (English, Msg::SearchResults) => "Search Results",
+        (Japanese, Msg::MatchingImages) => "一致した画像",
+        (English, Msg::MatchingImages) => "Matching images",
+        (Japanese, Msg::MenuIndex) => "索引(&X)",
+        (English, Msg::MenuIndex) => "Inde&x",
+        (Japanese, Msg::MenuIndexSearch) => "索引を検索(&S)...\tCtrl+Shift+F",
+        (English, Msg::MenuIndexSearch) => "&Search Index...\tCtrl+Shift+F",
+        (Japanese, Msg::MenuIndexAddFolder) => "フォルダーを索引に追加(&A)...",
+        (English, Msg::MenuIndexAddFolder) => "&Add Folder to Index...",
+        (Japanese, Msg::MenuIndexUpdate) => "索引を更新(&U)",
+        (English, Msg::MenuIndexUpdate) => "&Update Index",
+        (Japanese, Msg::IndexFolderTitle) => "索引に追加するフォルダー",
+        (English, Msg::IndexFolderTitle) => "Folder to Add to the Index",
+        (Japanese, Msg::IndexSearchTitle) => "索引の検索",
+        (English, Msg::IndexSearchTitle) => "Search Index",
+        (Japanese, Msg::IndexQuery) => "条件:",
+        (English, Msg::IndexQuery) => "Query:",
+        (Japanese, Msg::IndexQueryHelp) => "例: model=animagine AND steps>30\r\n名前: model, sampler, prompt, negative, text, path, steps, cfg, seed, width, height\r\n演算子: = != < <= > >= ~ (含む)。AND, OR, 括弧が使えます。演算子のない語はテキストから探します",
+        (English, Msg::IndexQueryHelp) => "Example: model=animagine AND steps>30\r\nNames: model, sampler, prompt, negative, text, path, steps, cfg, seed, width, height\r\nOperators: = != < <= > >= ~ (contains). AND, OR and parentheses are allowed. Words without an operator are searched for in the text",
+        (Japanese, Msg::InvalidQuery) => "条件が正しくありません",
+        (English, Msg::InvalidQuery) => "Invalid query",
+        (Japanese, Msg::NoIndexedFolders) => "索引にフォルダーがありません。先にフォルダーを追加してください",
+        (English, Msg::NoIndexedFolders) => "The index has no folders. Add a folder first",
+        (Japanese, Msg::IndexUpdated) => "索引を更新しました",
+        (English, Msg::IndexUpdated) => "The index has been updated",
+        (Japanese, Msg::IndexUpdatedFiles) => "追加・更新した画像",
+        (English, Msg::IndexUpdatedFiles) => "Added or updated",
+        (Japanese, Msg::IndexUnchangedFiles) => "変更のなかった画像",
+        (English, Msg::IndexUnchangedFiles) => "Unchanged",
+        (Japanese, Msg::IndexRemovedFiles) => "なくなったので削除した画像",
+        (English, Msg::IndexRemovedFiles) => "Removed",
+        (Japanese, Msg::MenuOpenInViewer) => "既定のアプリで開く(&D)",
+        (English, Msg::MenuOpenInViewer) => "Open in &Default Viewer",
+        (Japanese, Msg::MenuShowInExplorer) => "エクスプローラーで表示(&E)",
+        (English, Msg::MenuShowInExplorer) => "Show in &Explorer",
+        (Japanese, Msg::MenuWatchFolder) => "フォルダーを監視(&W)...",
+        (English, Msg::MenuWatchFolder) => "&Watch Folder...",
+        (Japanese, Msg::WatchFolder) => "監視するフォルダー",
+        (English, Msg::WatchFolder) => "Folder to Watch",
+        (Japanese, Msg::WatchingFolder) => "監視中",
+        (English, Msg::WatchingFolder) => "Watching",
+        (Japanese, Msg::MenuFolderStats) => "フォルダーを集計(&S)...",
+        (English, Msg::MenuFolderStats) => "Folder &Statistics...",
+        (Japanese, Msg::Scanning) => "集計中",
+        (English, Msg::Scanning) => "Scanning",
+        (Japanese, Msg::FolderStats) => "フォルダーの集計",
+        (English, Msg::FolderStats) => "Folder Statistics",
+        (Japanese, Msg::Folder) => "フォルダー",
+        (English, Msg::Folder) => "Folder",
+        (Japanese, Msg::Images) => "画像",
+        (English, Msg::Images) => "Images",
+        (Japanese, Msg::WithMetadata) => "メタデータあり",
+        (English, Msg::WithMetadata) => "With metadata",
+        (Japanese, Msg::WithoutMetadata) => "メタデータなし",
+        (English, Msg::WithoutMetadata) => "Without metadata",
+        (Japanese, Msg::UnreadableFiles) => "読めなかったファイル",
+        (English, Msg::UnreadableFiles) => "Unreadable files",
+        (Japanese, Msg::Models) => "モデル",
+        (English, Msg::Models) => "Models",
+        (Japanese, Msg::Samplers) => "サンプラー",
+        (English, Msg::Samplers) => "Samplers",
+        (Japanese, Msg::Seeds) => "シード",
+        (English, Msg::Seeds) => "Seeds",
+        (Japanese, Msg::SeedRange) => "範囲",
+        (English, Msg::SeedRange) => "Range",
+        (Japanese, Msg::UniqueSeeds) => "種類",
+        (English, Msg::UniqueSeeds) => "Unique",
+        (Japanese, Msg::CommonPromptTokens) => "よく使われているプロンプト",
+        (English, Msg::CommonPromptTokens) => "Most Common Prompt Tokens",
+        (Japanese, Msg::MenuSizeBreakdown) => "メタデータのサイズ(&Z)...",
+        (English, Msg::MenuSizeBreakdown) => "Metadata Si&ze Breakdown...",
+        (Japanese, Msg::SizeBreakdown) => "メタデータのサイズ",
+        (English, Msg::SizeBreakdown) => "Metadata Size Breakdown",
+        (Japanese, Msg::MenuHexView) => "16 進表示(&X)...",
+        (English, Msg::MenuHexView) => "He&x View...",
+        (Japanese, Msg::HexViewTitle) => "16 進表示",
+        (English, Msg::HexViewTitle) => "Hex View",
+        (Japanese, Msg::HexViewTruncated) => "先頭 1 MiB のみ",
+        (English, Msg::HexViewTruncated) => "first 1 MiB only",
+        (Japanese, Msg::MetadataTotal) => "メタデータの合計",
+        (English, Msg::MetadataTotal) => "Total metadata",
+        (Japanese, Msg::ImageData) => "画像データ",
+        (English, Msg::ImageData) => "Image data",
+        (Japanese, Msg::OtherData) => "その他",
+        (English, Msg::OtherData) => "Other",
+        (Japanese, Msg::MetadataOverhead) => "画像データに対するメタデータの割合",
+        (English, Msg::MetadataOverhead) => "Metadata overhead relative to image data",
+        (Japanese, Msg::Watermark) => "透かし",
+        (English, Msg::Watermark) => "Watermark",
+        (Japanese, Msg::WatermarkFound) => "この画像には AI 生成を示す見えない透かしが入っています",
+        (English, Msg::WatermarkFound) => "This image carries an invisible watermark marking it as AI-generated",
+        (Japanese, Msg::ContentCredentials) => "コンテンツ認証情報 (C2PA)",
+        (English, Msg::ContentCredentials) => "Content Credentials (C2PA)",
+        (Japanese, Msg::C2paManifest) => "マニフェスト",
+        (English, Msg::C2paManifest) => "Manifest",
+        (Japanese, Msg::C2paActive) => "現在のもの",
+        (English, Msg::C2paActive) => "active",
+        (Japanese, Msg::C2paClaimGenerator) => "作成したアプリ",
+        (English, Msg::C2paClaimGenerator) => "Claim generator",
+        (Japanese, Msg::C2paTitle) => "タイトル",
+        (English, Msg::C2paTitle) => "Title",
+        (Japanese, Msg::C2paSignature) => "署名",
+        (English, Msg::C2paSignature) => "Signature",
+        (Japanese, Msg::C2paSigner) => "署名者",
+        (English, Msg::C2paSigner) => "Signer",
+        (Japanese, Msg::C2paContentHash) => "画像データのハッシュ",
+        (English, Msg::C2paContentHash) => "Content hash",
+        (Japanese, Msg::C2paAssertionHashes) => "アサーションのハッシュ",
+        (English, Msg::C2paAssertionHashes) => "Assertion hashes",
+        (Japanese, Msg::C2paActions) => "操作",
+        (English, Msg::C2paActions) => "Actions",
+        (Japanese, Msg::C2paIngredients) => "素材",
+        (English, Msg::C2paIngredients) => "Ingredients",
+        (Japanese, Msg::C2paValid) => "一致",
+        (English, Msg::C2paValid) => "valid",
+        (Japanese, Msg::C2paInvalid) => "不一致",
+        (English, Msg::C2paInvalid) => "INVALID",
+        (Japanese, Msg::C2paNotChecked) => "未確認",
+        (English, Msg::C2paNotChecked) => "not checked",
+        (Japanese, Msg::C2paAiGenerated) => "マニフェストによると、この画像は生成 AI で作られています",
+        (English, Msg::C2paAiGenerated) => "According to its manifest, this image was made with generative AI",
+        (Japanese, Msg::C2paUntrusted) => "署名は一致、証明書は信頼されていません",
+        (English, Msg::C2paUntrusted) => "signature matches, certificate not trusted",
+        (Japanese, Msg::C2paTrustStore) => "※ 証明書は Windows が信頼するルート証明機関で確かめています (C2PA の信頼リストではありません)",
+        (English, Msg::C2paTrustStore) => "Note: certificates are checked against the Windows trusted root authorities, not the C2PA trust list",
+        (Japanese, Msg::C2paUnreadable) => "マニフェストを読み取れませんでした",
+        (English, Msg::C2paUnreadable) => "The manifest could not be read",
+        (Japanese, Msg::Extracted) => "抽出",
+        (English, Msg::Extracted) => "Extracted",
+        (Japanese, Msg::InvalidPattern) => "正規表現が正しくありません",
+        (English, Msg::InvalidPattern) => "invalid pattern",
+        (Japanese, Msg::ResourcesUsed) => "使用したリソース",
+        (English, Msg::ResourcesUsed) => "Resources Used",
+        (Japanese, Msg::ModelHashes) => "モデルのハッシュ",
+        (English, Msg::ModelHashes) => "Model Hashes",
+        (Japanese, Msg::HashUnknown) => "(不明)",
+        (English, Msg::HashUnknown) => "(unknown)",
+        (Japanese, Msg::MenuCivitaiLookup) => "モデルのハッシュを Civitai で調べる(&C)",
+        (English, Msg::MenuCivitaiLookup) => "Look Up Model Hashes on &Civitai",
+        (Japanese, Msg::MenuEditHashList) => "モデルのハッシュ一覧を編集(&E)...",
+        (English, Msg::MenuEditHashList) => "&Edit Model Hash List...",
+        (Japanese, Msg::FileHashes) => "ファイルのハッシュ",
+        (English, Msg::FileHashes) => "File Hashes",
+        (Japanese, Msg::MenuCopyHash) => "ハッシュをコピー(&H)",
+        (English, Msg::MenuCopyHash) => "Copy &Hash",
+        (Japanese, Msg::MenuPerceptualHash) => "知覚ハッシュ (pHash) も計算する(&P)",
+        (English, Msg::MenuPerceptualHash) => "Compute &Perceptual Hash (pHash)",
+        (Japanese, Msg::HashListHeader) => "# 1 行に 1 つ「ハッシュ=名前」の形で書きます。ハッシュは先頭の 8 文字以上が一致すれば使われます",
+        (English, Msg::HashListHeader) => "# Write one \"hash=name\" per line. A hash matches when at least its first 8 characters agree",
+        (Japanese, Msg::Altitude) => "高度",
+        (English, Msg::Altitude) => "Altitude",
+        (Japanese, Msg::LocationEmbedded) => "この画像には撮影場所の位置情報が含まれています",
+        (English, Msg::LocationEmbedded) => "This image contains embedded location data",
+        (Japanese, Msg::MenuOpenMap) => "撮影場所を地図で開く(&M)",
+        (English, Msg::MenuOpenMap) => "Open Location in &Map",
+        (Japanese, Msg::MenuSaveThumbnail) => "埋め込みサムネイルを保存(&T)...",
+        (English, Msg::MenuSaveThumbnail) => "Save Embedded &Thumbnail As...",
+        (Japanese, Msg::JpegFiles) => "JPEG 画像",
+        (English, Msg::JpegFiles) => "JPEG images",
+        (Japanese, Msg::MenuSaveWorkflow) => "ComfyUI のワークフローを保存(&U)...",
+        (English, Msg::MenuSaveWorkflow) => "Save Comfy&UI Workflow As...",
+        (Japanese, Msg::ComfyWorkflowFiles) => "ComfyUI のワークフロー",
+        (English, Msg::ComfyWorkflowFiles) => "ComfyUI workflow",
+        (Japanese, Msg::ComfyPromptFiles) => "ComfyUI の API 形式のプロンプト",
+        (English, Msg::ComfyPromptFiles) => "ComfyUI API prompt",
+        (Japanese, Msg::MenuExportExiftoolJson) => "ExifTool 形式の JSON で書き出す(&J)...",
+        (English, Msg::MenuExportExiftoolJson) => "Export as ExifTool &JSON...",
+        (Japanese, Msg::MenuCompareExiftoolJson) => "ExifTool の JSON と比較(&X)...",
+        (English, Msg::MenuCompareExiftoolJson) => "Compare with E&xifTool JSON...",
+        (Japanese, Msg::ExiftoolJsonFiles) => "ExifTool の JSON",
+        (English, Msg::ExiftoolJsonFiles) => "ExifTool JSON",
+        (Japanese, Msg::ExiftoolCompare) => "ExifTool の JSON との比較",
+        (English, Msg::ExiftoolCompare) => "Comparison with ExifTool JSON",
+        (Japanese, Msg::ExiftoolMatched) => "一致したタグ",
+        (English, Msg::ExiftoolMatched) => "Matching tags",
+        (Japanese, Msg::ExiftoolChanged) => "値が違うタグ (この画像 → JSON)",
+        (English, Msg::ExiftoolChanged) => "Different values (this image → JSON)",
+        (Japanese, Msg::ExiftoolOnlyOurs) => "この画像にだけあるタグ",
+        (English, Msg::ExiftoolOnlyOurs) => "Only in this image",
+        (Japanese, Msg::ExiftoolOnlyTheirs) => "JSON にだけあるタグ",
+        (English, Msg::ExiftoolOnlyTheirs) => "Only in the JSON",
+        (Japanese, Msg::ExiftoolNoEntry) => "JSON にこの画像の項目がありません",
+        (English, Msg::ExiftoolNoEntry) => "The JSON has no entry for this image",
+        (Japanese, Msg::InvalidExiftoolJson) => "ExifTool の JSON として読み取れません",
+        (English, Msg::InvalidExiftoolJson) => "Not a valid ExifTool JSON file",
+        (Japanese, Msg::MenuEncoding) => "tEXt の文字コード(&E)",
+        (English, Msg::MenuEncoding) => "&Reinterpret tEXt As",
+        (Japanese, Msg::MenuShowPreview) => "画像のプレビュー(&I)",
+        (English, Msg::MenuShowPreview) => "&Image Preview",
+        (Japanese, Msg::MenuZoomFit) => "全体を表示(&F)\tCtrl+0",
+        (English, Msg::MenuZoomFit) => "&Fit to Pane\tCtrl+0",
+        (Japanese, Msg::MenuZoomActual) => "等倍で表示(&A)\tCtrl+1",
+        (English, Msg::MenuZoomActual) => "&Actual Size\tCtrl+1",
+        (Japanese, Msg::MenuZoomIn) => "画像を拡大(&Z)",
+        (English, Msg::MenuZoomIn) => "&Zoom In Image",
+        (Japanese, Msg::MenuZoomOut) => "画像を縮小(&U)",
+        (English, Msg::MenuZoomOut) => "Zoom O&ut Image",
+        (Japanese, Msg::AccessiblePreview) => "画像のプレビュー",
+        (English, Msg::AccessiblePreview) => "Image preview",
+        (Japanese, Msg::MenuShowGallery) => "サムネイルの一覧(&G)",
+        (English, Msg::MenuShowGallery) => "Thumbnail &Gallery",
+        (Japanese, Msg::MenuRedact) => "個人情報を伏せ字にする(&K)",
+        (English, Msg::MenuRedact) => "Mas&k Personal Information",
+        (Japanese, Msg::MenuBatchStrip) => "フォルダーのメタデータをまとめて取り除く(&B)...",
+        (English, Msg::MenuBatchStrip) => "&Batch Strip Metadata...",
+        (Japanese, Msg::BatchStripTitle) => "メタデータをまとめて取り除く",
+        (English, Msg::BatchStripTitle) => "Batch Strip Metadata",
+        (Japanese, Msg::OutputFolder) => "出力先",
+        (English, Msg::OutputFolder) => "Output folder",
+        (Japanese, Msg::StripCategories) => "取り除くもの",
+        (English, Msg::StripCategories) => "Remove",
+        (Japanese, Msg::StripText) => "テキスト (tEXt, zTXt, iTXt, コメント, IPTC)",
+        (English, Msg::StripText) => "Text (tEXt, zTXt, iTXt, comments, IPTC)",
+        (Japanese, Msg::Start) => "開始",
+        (English, Msg::Start) => "Start",
+        (Japanese, Msg::OutputSameAsSource) => "出力先には元のフォルダーとは別のフォルダーを指定してください",
+        (English, Msg::OutputSameAsSource) => "Choose an output folder different from the source folder",
+        (Japanese, Msg::NoStripCategory) => "取り除くものを 1 つ以上選んでください",
+        (English, Msg::NoStripCategory) => "Select at least one kind of metadata to remove",
+        (Japanese, Msg::Cancelled) => "中止しました",
+        (English, Msg::Cancelled) => "Cancelled",
+        (Japanese, Msg::FilesWritten) => "書き出したファイル",
+        (English, Msg::FilesWritten) => "Files written",
+        (Japanese, Msg::BytesRemoved) => "取り除いた大きさ",
+        (English, Msg::BytesRemoved) => "Bytes removed",
+        (Japanese, Msg::SkippedUnsupported) => "対応していない形式のため飛ばしたファイル",
+        (English, Msg::SkippedUnsupported) => "Skipped (unsupported format)",
+        (Japanese, Msg::MenuCheckPolicy) => "メタデータのポリシーを確認(&L)",
+        (English, Msg::MenuCheckPolicy) => "Check Against Po&licy",
+        (Japanese, Msg::MenuCheckFolderPolicy) => "フォルダーをポリシーで確認(&Y)...",
+        (English, Msg::MenuCheckFolderPolicy) => "Check Folder Against Polic&y...",
+        (Japanese, Msg::MenuEditPolicy) => "メタデータのポリシーを編集(&O)...",
+        (English, Msg::MenuEditPolicy) => "Edit Metadata P&olicy...",
+        (Japanese, Msg::PolicyCheck) => "ポリシーの確認",
+        (English, Msg::PolicyCheck) => "Policy Check",
+        (Japanese, Msg::PolicyHeader) => "# 1 行に 1 つ規則を書きます。# から始まる行は無視されます\r\n#   require 項目          項目がなければ違反 (「require Author | Artist」のように | で区切るとどれか 1 つ)\r\n#   forbid 項目           項目があれば違反\r\n#   match 項目 正規表現   項目の値が正規表現に一致しなければ違反\r\n# 項目はテキストチャンクのキーワード (* が使えます) か gps, exif, prompt, c2pa, copyright\r\n#\r\n# require copyright\r\n# forbid gps\r\n# forbid prompt",
+        (English, Msg::PolicyHeader) => "# Write one rule per line. Lines starting with # are ignored\r\n#   require FIELD          violation if FIELD is missing (separate with | as in \"require Author | Artist\" to accept any one)\r\n#   forbid FIELD           violation if FIELD is present\r\n#   match FIELD REGEX      violation if a value of FIELD does not match REGEX\r\n# FIELD is a text chunk keyword (* allowed) or one of gps, exif, prompt, c2pa, copyright\r\n#\r\n# require copyright\r\n# forbid gps\r\n# forbid prompt",
+        (Japanese, Msg::PolicyPassed) => "✓ すべての規則を満たしています",
+        (English, Msg::PolicyPassed) => "✓ All rules are satisfied",
+        (Japanese, Msg::PolicyMissing) => "ありません",
+        (English, Msg::PolicyMissing) => "missing",
+        (Japanese, Msg::PolicyMissingField) => "項目がありません",
+        (English, Msg::PolicyMissingField) => "No field given",
+        (Japanese, Msg::PolicyMissingPattern) => "正規表現がありません",
+        (English, Msg::PolicyMissingPattern) => "No regular expression given",
+        (Japanese, Msg::PolicyUnknownRule) => "不明な規則",
+        (English, Msg::PolicyUnknownRule) => "Unknown rule",
+        (Japanese, Msg::NoPolicyRules) => "ポリシーに規則がありません。設定メニューの「メタデータのポリシーを編集」から書いてください",
+        (English, Msg::NoPolicyRules) => "The policy has no rules. Write them with \"Edit Metadata Policy\" in the Settings menu",
+        (Japanese, Msg::PolicyViolations) => "違反のあるファイル",
+        (English, Msg::PolicyViolations) => "Files with violations",
+        (Japanese, Msg::FilesChecked) => "確認したファイル",
+        (English, Msg::FilesChecked) => "Files checked",
+        (Japanese, Msg::Line) => "行",
+        (English, Msg::Line) => "line",
+        (Japanese, Msg::Prompt) => "プロンプト",
+        (English, Msg::Prompt) => "Prompt",
+        (Japanese, Msg::MenuWatchNotify) => "監視中のフォルダーに画像ができたら通知する(&N)",
+        (English, Msg::MenuWatchNotify) => "&Notify When the Watched Folder Gets a New Image",
+        (Japanese, Msg::MenuSaveMetadataText) => "メタデータをテキストで保存(&A)...",
+        (English, Msg::MenuSaveMetadataText) => "Save Metadata &As Text...",
+        (Japanese, Msg::MarkdownFiles) => "Markdown ファイル",
+        (English, Msg::MarkdownFiles) => "Markdown files",
+        (Japanese, Msg::MenuExportEncoding) => "書き出すテキストの文字コード(&X)",
+        (English, Msg::MenuExportEncoding) => "Te&xt Export Encoding",
+        (Japanese, Msg::MenuNewlineCrLf) => "改行 CR+LF (Windows)",
+        (English, Msg::MenuNewlineCrLf) => "CR+LF Line Endings (Windows)",
+        (Japanese, Msg::MenuNewlineLf) => "改行 LF (Unix)",
+        (English, Msg::MenuNewlineLf) => "LF Line Endings (Unix)",
+        (Japanese, Msg::MenuTextZoomIn) => "文字を大きく(&L)\tCtrl++",
+        (English, Msg::MenuTextZoomIn) => "&Larger Text\tCtrl++",
+        (Japanese, Msg::MenuTextZoomOut) => "文字を小さく(&M)\tCtrl+-",
+        (English, Msg::MenuTextZoomOut) => "S&maller Text\tCtrl+-",
+        (Japanese, Msg::MenuTextZoomReset) => "文字を元の大きさに戻す(&T)",
+        (English, Msg::MenuTextZoomReset) => "Rese&t Text Size",
+        (Japanese, Msg::TextZoom) => "文字の倍率",
+        (English, Msg::TextZoom) => "Text zoom",
+        (Japanese, Msg::MenuFindDuplicates) => "フォルダーの重複した画像を探す(&I)...",
+        (English, Msg::MenuFindDuplicates) => "Find Dupl&icate Images in Folder...",
+        (Japanese, Msg::MenuMoveToRecycleBin) => "ごみ箱に移動(&R)",
+        (English, Msg::MenuMoveToRecycleBin) => "Move to &Recycle Bin",
+        (Japanese, Msg::Duplicates) => "重複した画像",
+        (English, Msg::Duplicates) => "Duplicate Images",
+        (Japanese, Msg::DuplicateGroups) => "重複のまとまり",
+        (English, Msg::DuplicateGroups) => "Duplicate groups",
+        (Japanese, Msg::Group) => "まとまり",
+        (English, Msg::Group) => "Group",
+        (Japanese, Msg::TexturePixelFormat) => "ピクセル形式",
+        (English, Msg::TexturePixelFormat) => "Pixel format",
+        (Japanese, Msg::TextureKind) => "テクスチャの種類",
+        (English, Msg::TextureKind) => "Texture type",
+        (Japanese, Msg::TextureCube) => "キューブマップ",
+        (English, Msg::TextureCube) => "Cube map",
+        (Japanese, Msg::TextureVolume) => "ボリューム (3D)",
+        (English, Msg::TextureVolume) => "Volume (3D)",
+        (Japanese, Msg::TextureDepth) => "奥行き",
+        (English, Msg::TextureDepth) => "Depth",
+        (Japanese, Msg::TextureArraySize) => "配列の要素数",
+        (English, Msg::TextureArraySize) => "Array size",
+        (Japanese, Msg::TextureMipLevels) => "ミップマップの段数",
+        (English, Msg::TextureMipLevels) => "Mip levels",
+        (Japanese, Msg::TextureSupercompression) => "超圧縮",
+        (English, Msg::TextureSupercompression) => "Supercompression",
+        (Japanese, Msg::TextureFiles) => "テクスチャ",
+        (English, Msg::TextureFiles) => "Textures",
+        (Japanese, Msg::ExrScanline) => "スキャンライン",
+        (English, Msg::ExrScanline) => "Scanline",
+        (Japanese, Msg::ExrTiled) => "タイル",
+        (English, Msg::ExrTiled) => "Tiled",
+        (Japanese, Msg::ExrDeep) => "ディープ",
+        (English, Msg::ExrDeep) => "Deep",
+        (Japanese, Msg::ExrParts) => "パート",
+        (English, Msg::ExrParts) => "Parts",
+        (Japanese, Msg::ExrChannels) => "チャンネル",
+        (English, Msg::ExrChannels) => "Channels",
+        (Japanese, Msg::ExrCompression) => "圧縮方式",
+        (English, Msg::ExrCompression) => "Compression",
+        (Japanese, Msg::ExrDataWindow) => "データウィンドウ",
+        (English, Msg::ExrDataWindow) => "Data window",
+        (Japanese, Msg::ExrDisplayWindow) => "ディスプレイウィンドウ",
+        (English, Msg::ExrDisplayWindow) => "Display window",
+        (Japanese, Msg::ExrLineOrder) => "ラインの順序",
+        (English, Msg::ExrLineOrder) => "Line order",
+        (Japanese, Msg::MotionPhoto) => "モーションフォトの動画",
+        (English, Msg::MotionPhoto) => "Motion photo video",
+        (Japanese, Msg::Seconds) => "秒",
+        (English, Msg::Seconds) => "seconds",
+        (Japanese, Msg::MenuSaveMotionPhotoVideo) => "モーションフォトの動画を保存(&V)...",
+        (English, Msg::MenuSaveMotionPhotoVideo) => "Save Motion Photo &Video As...",
+        (Japanese, Msg::Mp4Files) => "MP4 動画",
+        (English, Msg::Mp4Files) => "MP4 videos",
+        (Japanese, Msg::TrailingData) => "画像の後ろのデータ",
+        (English, Msg::TrailingData) => "Data after end of image",
+        (Japanese, Msg::TrailingGuess) => "推定される形式",
+        (English, Msg::TrailingGuess) => "Probable format",
+        (Japanese, Msg::TrailingZeros) => "0 の詰め物",
+        (English, Msg::TrailingZeros) => "Zero padding",
+        (Japanese, Msg::TrailingText) => "テキスト",
+        (English, Msg::TrailingText) => "Text",
+        (Japanese, Msg::TrailingUnknown) => "不明",
+        (English, Msg::TrailingUnknown) => "Unknown",
+        (Japanese, Msg::TrailingDataFound) => "画像の終わり (IEND/EOI) の後ろに、ビューアーが表示しないデータがあります",
+        (English, Msg::TrailingDataFound) => "The file contains data after the end of the image (IEND/EOI) that viewers do not show",
+        (Japanese, Msg::CrashTitle) => "MetaView - 予期しないエラー",
+        (English, Msg::CrashTitle) => "MetaView - Unexpected Error",
+        (Japanese, Msg::CrashDescription) => "予期しないエラーが起きたため MetaView を終了します。\r\n不具合を報告するときは、下の詳細を添えてください。",
+        (English, Msg::CrashDescription) => "MetaView encountered an unexpected error and will close.\r\nPlease include the details below when reporting the problem.",
+        (Japanese, Msg::CrashCopyDetails) => "詳細をコピー(&C)",
+        (English, Msg::CrashCopyDetails) => "&Copy Details",
+        (Japanese, Msg::CrashLog) => "ログ",
+        (English, Msg::CrashLog) => "Log",
+        (Japanese, Msg::CrashDump) => "ミニダンプ",
+        (English, Msg::CrashDump) => "Minidump",
+        (Japanese, Msg::MenuAccentColors) => "アクセントカラーで色付けする(&C)",
+        (English, Msg::MenuAccentColors) => "Use Accent &Color",
+        (Japanese, Msg::AccessibleGallery) => "フォルダーの画像の一覧",
+        (English, Msg::AccessibleGallery) => "Images in the folder",
+        (Japanese, Msg::MenuEncodingAuto) => "自動判定(&A)",
+        (English, Msg::MenuEncodingAuto) => "&Auto-detect",
+        (Japanese, Msg::MenuSettings) => "設定(&S)",
+        (English, Msg::MenuSettings) => "&Settings",
+        (Japanese, Msg::MenuLanguage) => "言語(&L)",
+        (English, Msg::MenuLanguage) => "&Language",
+        (Japanese, Msg::MenuLanguageAuto) => "自動(&A)",
+        (English, Msg::MenuLanguageAuto) => "&Automatic",
+        (Japanese, Msg::MenuMinimizeToTray) => "最小化したら通知領域に入れる(&T)",
+        (English, Msg::MenuMinimizeToTray) => "Minimize to &Tray",
+        (Japanese, Msg::MenuHotkey) => "ホットキー(&H)",
+        (English, Msg::MenuHotkey) => "Global &Hotkey",
+        (Japanese, Msg::HotkeyTitle) => "ホットキー",
+        (English, Msg::HotkeyTitle) => "Global Hotkey",
+        (Japanese, Msg::HotkeyDescription) => "どこからでもウィンドウを表示してクリップボードを読み込むキーを押してください (Backspace で解除)",
+        (English, Msg::HotkeyDescription) => "Press the keys that bring up the window and read the clipboard from anywhere (Backspace to clear)",
+        (Japanese, Msg::HotkeyInUse) => "ホットキーを登録できませんでした。ほかのアプリが使っている可能性があります",
+        (English, Msg::HotkeyInUse) => "Could not register the hotkey. Another application may be using it",
+        (Japanese, Msg::MenuTrayOpen) => "開く(&O)",
+        (English, Msg::MenuTrayOpen) => "&Open",
+        (Japanese, Msg::MenuExit) => "終了(&X)",
+        (English, Msg::MenuExit) => "E&xit",
+        (Japanese, Msg::MenuHistory) => "履歴(&H)...\tCtrl+H",
+        (English, Msg::MenuHistory) => "Session &History...\tCtrl+H",
+        (Japanese, Msg::HistoryTitle) => "このセッションで開いたファイル",
+        (English, Msg::HistoryTitle) => "Session History",
+        (Japanese, Msg::HistoryTime) => "日時",
+        (English, Msg::HistoryTime) => "Time",
+        (Japanese, Msg::HistoryFile) => "ファイル",
+        (English, Msg::HistoryFile) => "File",
+        (Japanese, Msg::HistoryModel) => "モデル",
+        (English, Msg::HistoryModel) => "Model",
+        (Japanese, Msg::HistorySampler) => "サンプラー",
+        (English, Msg::HistorySampler) => "Sampler",
+        (Japanese, Msg::HistorySteps) => "ステップ数",
+        (English, Msg::HistorySteps) => "Steps",
+        (Japanese, Msg::HistoryCfgScale) => "CFG スケール",
+        (English, Msg::HistoryCfgScale) => "CFG scale",
+        (Japanese, Msg::HistorySeed) => "シード",
+        (English, Msg::HistorySeed) => "Seed",
+        (Japanese, Msg::HistoryOpen) => "開く(&O)",
+        (English, Msg::HistoryOpen) => "&Open",
+        (Japanese, Msg::HistoryExport) => "書き出す(&E)...",
+        (English, Msg::HistoryExport) => "&Export...",
+        (Japanese, Msg::HistoryClear) => "消去(&C)",
+        (English, Msg::HistoryClear) => "&Clear",
+        (Japanese, Msg::Close) => "閉じる",
+        (English, Msg::Close) => "Close",
+        (Japanese, Msg::CsvFiles) => "CSV ファイル",
+        (English, Msg::CsvFiles) => "CSV files",
+        (Japanese, Msg::TextFiles) => "テキストファイル",
+        (English, Msg::TextFiles) => "Text files",
+        (Japanese, Msg::MenuKeepHistory) => "履歴を次の起動でも残す(&K)",
+        (English, Msg::MenuKeepHistory) => "&Keep History Between Sessions",
+        (Japanese, Msg::MenuCheckUpdates) => "更新を確認(&U)...",
+        (English, Msg::MenuCheckUpdates) => "Check for &Updates...",
+        (Japanese, Msg::MenuCheckUpdatesOnStartup) => "起動時に更新を確認する(&S)",
+        (English, Msg::MenuCheckUpdatesOnStartup) => "Check for Updates at &Startup",
+        (Japanese, Msg::UpdateAvailable) => "新しいバージョンの MetaView があります",
+        (English, Msg::UpdateAvailable) => "A new version of MetaView is available",
+        (Japanese, Msg::CurrentVersion) => "今のバージョン",
+        (English, Msg::CurrentVersion) => "Current version",
+        (Japanese, Msg::LatestVersion) => "最新のバージョン",
+        (English, Msg::LatestVersion) => "Latest version",
+        (Japanese, Msg::OpenDownloadPage) => "ダウンロードページを開きますか?",
+        (English, Msg::OpenDownloadPage) => "Open the download page?",
+        (Japanese, Msg::UpToDate) => "最新のバージョンを使っています",
+        (English, Msg::UpToDate) => "You are using the latest version",
+        (Japanese, Msg::PluginLoadFailed) => "読み込めなかったプラグインがあります",
+        (English, Msg::PluginLoadFailed) => "Some plugins could not be loaded",
+        (Japanese, Msg::AllFiles) => "すべてのファイル",
+        (English, Msg::AllFiles) => "All files",
+        (Japanese, Msg::ScriptError) => "スクリプトのエラー",
+        (English, Msg::ScriptError) => "Script error",
+        (Japanese, Msg::ScriptLoadFailed) => "読み込めなかったスクリプトがあります",
+        (English, Msg::ScriptLoadFailed) => "Some scripts could not be loaded",
+        (Japanese, Msg::MenuReloadScripts) => "スクリプトを読み込み直す(&R)",
+        (English, Msg::MenuReloadScripts) => "&Reload Scripts",
+        (Japanese, Msg::MenuFileAssociations) => "ファイルの関連付け(&F)...",
+        (English, Msg::MenuFileAssociations) => "&File Associations...",
+        (Japanese, Msg::FileAssociationsTitle) => "ファイルの関連付け",
+        (English, Msg::FileAssociationsTitle) => "File Associations",
+        (Japanese, Msg::FileAssociationsDescription) => "MetaView で開けるようにする拡張子を選んでください。既定のアプリにするかどうかは、この後に開く Windows の画面で選びます",
+        (English, Msg::FileAssociationsDescription) => "Choose the extensions MetaView should open. Whether it becomes the default app is chosen in the Windows settings that open next",
+        (Japanese, Msg::RemoveAllAssociations) => "すべて解除(&R)",
+        (English, Msg::RemoveAllAssociations) => "&Remove All",
+        (Japanese, Msg::AppDescription) => "画像のメタデータを表示します",
+        (English, Msg::AppDescription) => "Views image metadata",
+    }
+}
@@ -0,0 +1,206 @@
+// WIC を使った画像のデコード
+
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::settings;
+use windows::{
+    core::*,
+    Win32::{
+        Foundation::SIZE,
+        Graphics::{Gdi::*, Imaging::*},
+        System::Com::*,
+        UI::{Shell::*, WindowsAndMessaging::*},
+    },
+};
+
+// 上から下に並んだ 32bpp BGRA (アルファは乗算済みでない)
+#[derive(Debug, Clone)]
+pub struct Bitmap {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+// デコードした画素 (32bpp) に使ってよいバイト数。超える大きさを求められたらさらに縮小する
+static MAX_DECODE_SIZE: AtomicUsize = AtomicUsize::new(settings::DEFAULT_DECODE_MAX_SIZE);
+
+pub fn set_max_decode_size(size: usize) {
+    MAX_DECODE_SIZE.store(size, Ordering::Relaxed);
+}
+
+pub fn max_decode_size() -> usize {
+    MAX_DECODE_SIZE.load(Ordering::Relaxed)
+}
+
+fn factory() -> Result<IWICImagingFactory> {
+    unsafe { CoCreateInstance(&CLSID_WICImagingFactory, None, CLSCTX_INPROC_SERVER) }
+}
+
+// max_width x max_height に収まるように縮小してデコードする (拡大はしない)
+pub fn decode_scaled(data: &[u8], max_width: u32, max_height: u32) -> anyhow::Result<Bitmap> {
+    let factory = factory()?;
+    let stream = unsafe { factory.CreateStream() }?;
+    unsafe { stream.InitializeFromMemory(data) }?;
+    let decoder = unsafe { factory.CreateDecoderFromStream(&stream, std::ptr::null(), WICDecodeMetadataCacheOnDemand) }?;
+    let frame = unsafe { decoder.GetFrame(0) }?;
+
+    let (mut width, mut height) = (0, 0);
+    unsafe { frame.GetSize(&mut width, &mut height) }?;
+    anyhow::ensure!(width > 0 && height > 0, "the image is empty");
+    // WIC の縮小は元の画像を行ごとに読むので、元の大きさの画素をまとめて確保することはない
+    let memory_scale = (max_decode_size() as f64 / (width as f64 * height as f64 * 4.0)).sqrt();
+    let scale = [1.0, max_width as f64 / width as f64, max_height as f64 / height as f64, memory_scale].into_iter().fold(f64::INFINITY, f64::min);
+    let scaled_width = ((width as f64 * scale).round() as u32).max(1);
+    let scaled_height = ((height as f64 * scale).round() as u32).max(1);
+
+    let scaler = unsafe { factory.CreateBitmapScaler() }?;
+    unsafe { scaler.Initialize(&frame, scaled_width, scaled_height, WICBitmapInterpolationModeFant) }?;
+    let converter = unsafe { factory.CreateFormatConverter() }?;
+    unsafe {
+        converter.Initialize(&scaler, &GUID_WICPixelFormat32bppBGRA, WICBitmapDitherTypeNone, None, 0.0, WICBitmapPaletteTypeCustom)
+    }?;
+
+    let stride = scaled_width * 4;
+    let mut pixels = vec![0u8; (stride * scaled_height) as usize];
+    unsafe { converter.CopyPixels(std::ptr::null(), stride, &mut pixels) }?;
+    Ok(Bitmap { width: scaled_width, height: scaled_height, pixels })
+}
+
+// エクスプローラーと同じサムネイル (サムネイルハンドラーがなければアイコン) を size x size に収まる大きさで取ってくる
+// WIC で読めない形式のプレビューに使う。回転はシェル側で済んでいる
+pub fn shell_thumbnail(path: &Path, size: u32) -> anyhow::Result<Bitmap> {
+    let factory: IShellItemImageFactory = unsafe { SHCreateItemFromParsingName(&HSTRING::from(path.as_os_str()), None) }?;
+    let hbitmap = unsafe { factory.GetImage(SIZE { cx: size as i32, cy: size as i32 }, SIIGBF_RESIZETOFIT) }?;
+    let bitmap = bitmap_pixels(hbitmap);
+    unsafe { DeleteObject(hbitmap) };
+    let mut bitmap = bitmap?;
+    // シェルのビットマップはアルファが乗算済み。アルファのない (すべて 0 の) ものは不透明にする
+    if bitmap.pixels.chunks_exact(4).all(|pixel| pixel[3] == 0) {
+        bitmap.pixels.chunks_exact_mut(4).for_each(|pixel| pixel[3] = 255);
+    } else {
+        for pixel in bitmap.pixels.chunks_exact_mut(4).filter(|pixel| pixel[3] != 0) {
+            let alpha = pixel[3] as u32;
+            for c in &mut pixel[..3] {
+                *c = (*c as u32 * 255 / alpha).min(255) as u8;
+            }
+        }
+    }
+    Ok(bitmap)
+}
+
+// HBITMAP の画素を上から下に並んだ 32bpp BGRA で取り出す
+fn bitmap_pixels(hbitmap: HBITMAP) -> anyhow::Result<Bitmap> {
+    let mut info = BITMAP::default();
+    let len = unsafe { GetObjectW(hbitmap, std::mem::size_of::<BITMAP>() as i32, Some(&mut info as *mut _ as *mut _)) };
+    anyhow::ensure!(len != 0 && info.bmWidth > 0 && info.bmHeight != 0, "invalid bitmap");
+    let (width, height) = (info.bmWidth as u32, info.bmHeight.unsigned_abs());
+    let mut bmi = BITMAPINFO {
+        bmiHeader: BITMAPINFOHEADER {
+            biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+            biWidth: width as i32,
+            biHeight: -(height as i32),
+            biPlanes: 1,
+            biBitCount: 32,
+            biCompression: BI_RGB,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut pixels = vec![0u8; (width * height * 4) as usize];
+    let hdc = unsafe { GetDC(None) };
+    let lines = unsafe { GetDIBits(hdc, hbitmap, 0, height, Some(pixels.as_mut_ptr() as *mut _), &mut bmi, DIB_RGB_COLORS) };
+    unsafe { ReleaseDC(None, hdc) };
+    anyhow::ensure!(lines == height as i32, "GetDIBits failed");
+    Ok(Bitmap { width, height, pixels })
+}
+
+// EXIF の Orientation に従って回転・反転する
+pub fn apply_orientation(bitmap: Bitmap, orientation: u16) -> Bitmap {
+    let (w, h) = (bitmap.width as usize, bitmap.height as usize);
+    // 5〜8 は縦横が入れ替わる
+    let (out_w, out_h) = if orientation >= 5 { (h, w) } else { (w, h) };
+    let source = |x: usize, y: usize| match orientation {
+        2 => (w - 1 - x, y),
+        3 => (w - 1 - x, h - 1 - y),
+        4 => (x, h - 1 - y),
+        5 => (y, x),
+        6 => (y, h - 1 - x),
+        7 => (w - 1 - y, h - 1 - x),
+        8 => (w - 1 - y, x),
+        _ => (x, y),
+    };
+    if !(2..=8).contains(&orientation) {
+        return bitmap;
+    }
+    let mut pixels = vec![0u8; bitmap.pixels.len()];
+    for y in 0..out_h {
+        for x in 0..out_w {
+            let (sx, sy) = source(x, y);
+            let src = (sy * w + sx) * 4;
+            let dst = (y * out_w + x) * 4;
+            pixels[dst..dst + 4].copy_from_slice(&bitmap.pixels[src..src + 4]);
+        }
+    }
+    Bitmap { width: out_w as u32, height: out_h as u32, pixels }
+}
+
+// size x size の中央に置いた画素を返す。余白は透明にする (bitmap は size 以下に縮小しておく)
+pub fn pad_to_square(bitmap: &Bitmap, size: u32) -> Vec<u8> {
+    let mut pixels = vec![0u8; (size * size * 4) as usize];
+    let x0 = (size - bitmap.width) / 2;
+    let y0 = (size - bitmap.height) / 2;
+    for y in 0..bitmap.height {
+        let src = (y * bitmap.width * 4) as usize;
+        let dst = (((y0 + y) * size + x0) * 4) as usize;
+        let len = (bitmap.width * 4) as usize;
+        pixels[dst..dst + len].copy_from_slice(&bitmap.pixels[src..src + len]);
+    }
+    pixels
+}
+
+// 正方形のアイコンを作る。縦横比は保って余白は透明にする
+// bitmap は decode_scaled(data, size, size) で縮小したもの
+pub fn create_icon(bitmap: &Bitmap, size: u32) -> anyhow::Result<HICON> {
+    let pixels = pad_to_square(bitmap, size);
+
+    let color = create_dib(size, size, &pixels)?;
+    let mask = unsafe { CreateBitmap(size as i32, size as i32, 1, 1, None) };
+
+    let info = ICONINFO {
+        fIcon: true.into(),
+        xHotspot: 0,
+        yHotspot: 0,
+        hbmMask: mask,
+        hbmColor: color,
+    };
+    let icon = unsafe { CreateIconIndirect(&info) };
+    unsafe { DeleteObject(color) };
+    unsafe { DeleteObject(mask) };
+    Ok(icon?)
+}
+
+// 上から下に並んだ 32bpp BGRA の DIB セクションを作る
+pub fn create_dib(width: u32, height: u32, pixels: &[u8]) -> Result<HBITMAP> {
+    let bmi = BITMAPINFO {
+        bmiHeader: BITMAPINFOHEADER {
+            biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+            biWidth: width as i32,
+            biHeight: -(height as i32),
+            biPlanes: 1,
+            biBitCount: 32,
+            biCompression: BI_RGB,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut bits = std::ptr::null_mut();
+    let bitmap = unsafe { CreateDIBSection(None, &bmi, DIB_RGB_COLORS, &mut bits, None, 0) }?;
+    unsafe { std::ptr::copy_nonoverlapping(pixels.as_ptr(), bits as *mut u8, pixels.len()) };
+    Ok(bitmap)
+}
+
+// max_width x max_height に収まるように縮小したビットマップを作る
+pub fn create_bitmap(data: &[u8], max_width: u32, max_height: u32) -> anyhow::Result<HBITMAP> {
+    let bitmap = decode_scaled(data, max_width, max_height)?;
+    Ok(create_dib(bitmap.width, bitmap.height, &bitmap.pixels)?)
+}
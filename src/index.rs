@@ -0,0 +1,546 @@
+// 大きな画像フォルダー向けの索引。メタデータを設定フォルダーの index.db (SQLite) に入れておき、条件ですぐに絞り込めるようにする
+// 更新するときは、更新日時と大きさが変わっていないファイルは読み直さない
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use windows::{
+    core::*,
+    Win32::{
+        Foundation::*,
+        UI::WindowsAndMessaging::*,
+    },
+};
+use crate::batch::{self, ScannedImage};
+use crate::dialog::{self, DialogTemplate};
+use crate::i18n::{tr, Msg};
+use crate::metadata::{ImageMetadata, Source};
+use crate::params;
+use crate::settings;
+use crate::sqlite::{Connection, Value};
+
+// lparam: Box<anyhow::Result<UpdateStats>> のポインタ
+pub const WM_APP_INDEX_DONE: u32 = WM_APP + 12;
+
+// 一覧に出しきれないほど一致したときは打ち切る
+const MAX_RESULTS: usize = 5000;
+
+const SCHEMA: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS folders (path TEXT PRIMARY KEY)",
+    "CREATE TABLE IF NOT EXISTS images (
+        path TEXT PRIMARY KEY,
+        folder TEXT NOT NULL,
+        mtime INTEGER NOT NULL,
+        size INTEGER NOT NULL,
+        width INTEGER,
+        height INTEGER,
+        orientation INTEGER,
+        prompt TEXT,
+        negative_prompt TEXT,
+        model TEXT,
+        sampler TEXT,
+        steps INTEGER,
+        cfg_scale REAL,
+        seed INTEGER,
+        text TEXT
+    )",
+    "CREATE INDEX IF NOT EXISTS images_folder ON images (folder)",
+];
+
+const IDC_QUERY: i32 = 100;
+
+pub fn index_path() -> Option<PathBuf> {
+    settings::data_dir().map(|dir| dir.join("index.db"))
+}
+
+fn open() -> anyhow::Result<Connection> {
+    let path = index_path().ok_or_else(|| anyhow::anyhow!("cannot find the settings folder"))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let connection = Connection::open(&path)?;
+    for sql in SCHEMA {
+        connection.execute(sql, &[])?;
+    }
+    Ok(connection)
+}
+
+#[derive(Debug, Default)]
+pub struct UpdateStats {
+    // 新しく入れたか、変わっていたので読み直した
+    pub updated: usize,
+    pub unchanged: usize,
+    // なくなったので索引から消した
+    pub removed: usize,
+    pub errors: usize,
+}
+
+impl UpdateStats {
+    pub fn format(&self) -> String {
+        let mut ret = format!("{}\r\n\r\n", tr(Msg::IndexUpdated));
+        ret.push_str(&format!("{}: {}\r\n", tr(Msg::IndexUpdatedFiles), self.updated));
+        ret.push_str(&format!("{}: {}\r\n", tr(Msg::IndexUnchangedFiles), self.unchanged));
+        ret.push_str(&format!("{}: {}\r\n", tr(Msg::IndexRemovedFiles), self.removed));
+        if self.errors > 0 {
+            ret.push_str(&format!("{}: {}\r\n", tr(Msg::UnreadableFiles), self.errors));
+        }
+        ret
+    }
+}
+
+// folder が None なら、これまでに追加したフォルダーをすべて更新する
+pub fn start_update(hwnd: HWND, folder: Option<PathBuf>) {
+    std::thread::spawn(move || {
+        let result = update(hwnd, folder);
+        let result = Box::into_raw(Box::new(result));
+        let posted = unsafe { PostMessageW(hwnd, WM_APP_INDEX_DONE, WPARAM(0), LPARAM(result as isize)) };
+        if !posted.as_bool() {
+            drop(unsafe { Box::from_raw(result) });
+        }
+    });
+}
+
+// WM_APP_INDEX_DONE の lparam から結果を取り出す
+pub unsafe fn take_result(lparam: LPARAM) -> anyhow::Result<UpdateStats> {
+    *Box::from_raw(lparam.0 as *mut anyhow::Result<UpdateStats>)
+}
+
+fn folder_key(folder: &Path) -> String {
+    folder.display().to_string()
+}
+
+fn update(hwnd: HWND, folder: Option<PathBuf>) -> anyhow::Result<UpdateStats> {
+    let connection = open()?;
+    let folders = match folder {
+        Some(folder) => {
+            connection.execute("INSERT OR IGNORE INTO folders (path) VALUES (?)", &[folder_key(&folder).into()])?;
+            vec![folder]
+        }
+        None => connection.query("SELECT path FROM folders ORDER BY path", &[])?
+            .into_iter()
+            .filter_map(|row| row[0].as_str().map(PathBuf::from))
+            .collect(),
+    };
+    anyhow::ensure!(!folders.is_empty(), "{}", tr(Msg::NoIndexedFolders));
+    let mut stats = UpdateStats::default();
+    connection.execute("BEGIN", &[])?;
+    let result = folders.iter().try_for_each(|folder| update_folder(hwnd, &connection, folder, &mut stats));
+    // 途中で失敗しても、そこまでに読んだ分は残す
+    connection.execute("COMMIT", &[])?;
+    result.map(|()| stats)
+}
+
+// 更新日時 (UNIX 時間のミリ秒) と大きさ
+fn file_stamp(path: &Path) -> Option<(i64, i64)> {
+    let metadata = fs::metadata(path).ok()?;
+    let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_millis() as i64;
+    Some((mtime, metadata.len() as i64))
+}
+
+fn update_folder(hwnd: HWND, connection: &Connection, folder: &Path, stats: &mut UpdateStats) -> anyhow::Result<()> {
+    let key = folder_key(folder);
+    let mut known: HashMap<String, (i64, i64)> = connection.query("SELECT path, mtime, size FROM images WHERE folder = ?", &[key.as_str().into()])?
+        .into_iter()
+        .filter_map(|row| Some((row[0].as_str()?.to_owned(), (row[1].as_i64()?, row[2].as_i64()?))))
+        .collect();
+    let mut files = Vec::new();
+    // フォルダーがなくなっていたら、中の画像はすべて索引から消す
+    let _ = batch::collect_images(folder, &mut files);
+    let mut seen = HashSet::new();
+    for (i, path) in files.iter().enumerate() {
+        let path_key = path.display().to_string();
+        let stamp = file_stamp(path);
+        seen.insert(path_key.clone());
+        if stamp.is_some() && known.get(&path_key) == stamp.as_ref() {
+            stats.unchanged += 1;
+        } else {
+            match (stamp, Source::File(path.clone().into_os_string()).read_metadata()) {
+                (Some(stamp), Ok(metadata)) => {
+                    insert(connection, &key, &path_key, stamp, &metadata)?;
+                    stats.updated += 1;
+                }
+                _ => {
+                    connection.execute("DELETE FROM images WHERE path = ?", &[path_key.as_str().into()])?;
+                    stats.errors += 1;
+                }
+            }
+        }
+        unsafe { PostMessageW(hwnd, batch::WM_APP_SCAN_PROGRESS, WPARAM(i + 1), LPARAM(files.len() as isize)) };
+    }
+    known.retain(|path, _| !seen.contains(path));
+    for path in known.into_keys() {
+        connection.execute("DELETE FROM images WHERE path = ?", &[path.into()])?;
+        stats.removed += 1;
+    }
+    Ok(())
+}
+
+fn insert(connection: &Connection, folder: &str, path: &str, (mtime, size): (i64, i64), metadata: &ImageMetadata) -> anyhow::Result<()> {
+    let params = params::find_parameters(&metadata.text_chunks);
+    let get = |key: &str| params.as_ref().and_then(|p| p.get(key));
+    let model = get("Model").or_else(|| get("Model hash"));
+    let text: Vec<String> = metadata.text_chunks.iter().map(|(key, value)| format!("{key}: {value}")).collect();
+    connection.execute(
+        "INSERT OR REPLACE INTO images
+            (path, folder, mtime, size, width, height, orientation, prompt, negative_prompt, model, sampler, steps, cfg_scale, seed, text)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        &[
+            path.into(),
+            folder.into(),
+            mtime.into(),
+            size.into(),
+            (metadata.width as i64).into(),
+            (metadata.height as i64).into(),
+            metadata.orientation.map(i64::from).into(),
+            params.as_ref().map(|p| p.prompt.as_str()).into(),
+            params.as_ref().map(|p| p.negative_prompt.as_str()).into(),
+            model.into(),
+            get("Sampler").into(),
+            get("Steps").and_then(|v| v.parse::<i64>().ok()).into(),
+            get("CFG scale").and_then(|v| v.parse::<f64>().ok()).into(),
+            get("Seed").and_then(|v| v.parse::<i64>().ok()).into(),
+            text.join("\n").into(),
+        ],
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Text,
+    Integer,
+    Real,
+}
+
+// 条件に書ける名前と列
+const FIELDS: &[(&str, &str, Kind)] = &[
+    ("model", "model", Kind::Text),
+    ("sampler", "sampler", Kind::Text),
+    ("prompt", "prompt", Kind::Text),
+    ("negative", "negative_prompt", Kind::Text),
+    ("text", "text", Kind::Text),
+    ("path", "path", Kind::Text),
+    ("steps", "steps", Kind::Integer),
+    ("cfg", "cfg_scale", Kind::Real),
+    ("seed", "seed", Kind::Integer),
+    ("width", "width", Kind::Integer),
+    ("height", "height", Kind::Integer),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    // "..." で囲んだもの (演算子や AND と区別する)
+    Quoted(String),
+    Operator(&'static str),
+    Open,
+    Close,
+}
+
+const OPERATORS: [&str; 7] = ["<=", ">=", "!=", "=", "<", ">", "~"];
+
+fn tokenize(query: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = query.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if c == '(' || c == ')' {
+            tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            rest = &rest[1..];
+        } else if c == '"' {
+            let end = rest[1..].find('"').ok_or_else(|| anyhow::anyhow!("{}: {rest}", tr(Msg::InvalidQuery)))?;
+            tokens.push(Token::Quoted(rest[1..end + 1].to_owned()));
+            rest = &rest[end + 2..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Operator(op));
+            rest = &rest[op.len()..];
+        } else {
+            // "!" は "!=" のときだけ区切りにする
+            let end = rest.char_indices()
+                .find(|&(i, c)| c.is_whitespace() || "()\"<>=~".contains(c) || rest[i..].starts_with("!="))
+                .map_or(rest.len(), |(i, _)| i);
+            tokens.push(Token::Word(rest[..end].to_owned()));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+fn is_keyword(token: Option<&Token>, keyword: &str) -> bool {
+    matches!(token, Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+}
+
+// LIKE で使う % と _ をそのままの文字として扱う
+fn like_pattern(value: &str) -> String {
+    let escaped = value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{escaped}%")
+}
+
+fn condition(field: &str, op: &str, value: &str, params: &mut Vec<Value>) -> anyhow::Result<String> {
+    let invalid = || anyhow::anyhow!("{}: {field}{op}{value}", tr(Msg::InvalidQuery));
+    let &(_, column, kind) = FIELDS.iter().find(|(name, _, _)| name.eq_ignore_ascii_case(field)).ok_or_else(invalid)?;
+    match kind {
+        Kind::Text => {
+            let sql = match op {
+                "=" => format!("{column} = ? COLLATE NOCASE"),
+                "!=" => format!("({column} IS NULL OR {column} <> ? COLLATE NOCASE)"),
+                "~" => {
+                    params.push(like_pattern(value).into());
+                    return Ok(format!("{column} LIKE ? ESCAPE '\\'"));
+                }
+                _ => return Err(invalid()),
+            };
+            params.push(value.into());
+            Ok(sql)
+        }
+        Kind::Integer | Kind::Real => {
+            if op == "~" {
+                return Err(invalid());
+            }
+            let value = if kind == Kind::Integer {
+                value.parse::<i64>().map(Value::from).map_err(|_| invalid())?
+            } else {
+                value.parse::<f64>().map(Value::from).map_err(|_| invalid())?
+            };
+            params.push(value);
+            let op = if op == "!=" { "<>" } else { op };
+            Ok(format!("{column} {op} ?"))
+        }
+    }
+}
+
+// "model=X AND steps>30" のような条件を SQL の WHERE 句にする。値はすべて ? で渡す
+// 演算子のない語は、どれかのテキストチャンクに含まれるものを探す。条件を並べただけなら AND とみなす
+fn compile_query(query: &str) -> anyhow::Result<(String, Vec<Value>)> {
+    let tokens = tokenize(query)?;
+    let mut sql = String::new();
+    let mut params = Vec::new();
+    let mut depth = 0usize;
+    // 直前が条件か ")" なら true
+    let mut after_operand = false;
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = &tokens[i];
+        if is_keyword(Some(token), "AND") || is_keyword(Some(token), "OR") {
+            anyhow::ensure!(after_operand, "{}: {query}", tr(Msg::InvalidQuery));
+            sql.push_str(if is_keyword(Some(token), "AND") { " AND " } else { " OR " });
+            after_operand = false;
+            i += 1;
+            continue;
+        }
+        if after_operand && *token != Token::Close {
+            sql.push_str(" AND ");
+        }
+        match token {
+            Token::Open => {
+                sql.push('(');
+                depth += 1;
+                after_operand = false;
+                i += 1;
+            }
+            Token::Close => {
+                anyhow::ensure!(after_operand && depth > 0, "{}: {query}", tr(Msg::InvalidQuery));
+                sql.push(')');
+                depth -= 1;
+                i += 1;
+            }
+            Token::Word(field) if matches!(tokens.get(i + 1), Some(Token::Operator(_))) => {
+                let Some(Token::Operator(op)) = tokens.get(i + 1) else { unreachable!() };
+                let value = match tokens.get(i + 2) {
+                    Some(Token::Word(value) | Token::Quoted(value)) => value,
+                    _ => anyhow::bail!("{}: {query}", tr(Msg::InvalidQuery)),
+                };
+                sql.push_str(&condition(field, op, value, &mut params)?);
+                after_operand = true;
+                i += 3;
+            }
+            Token::Word(word) | Token::Quoted(word) => {
+                sql.push_str("text LIKE ? ESCAPE '\\'");
+                params.push(like_pattern(word).into());
+                after_operand = true;
+                i += 1;
+            }
+            Token::Operator(_) => anyhow::bail!("{}: {query}", tr(Msg::InvalidQuery)),
+        }
+    }
+    anyhow::ensure!(depth == 0 && (after_operand || tokens.is_empty()), "{}: {query}", tr(Msg::InvalidQuery));
+    if sql.is_empty() {
+        sql.push('1');
+    }
+    Ok((sql, params))
+}
+
+#[derive(Debug)]
+pub struct IndexedImage {
+    pub image: ScannedImage,
+    pub model: Option<String>,
+    pub steps: Option<i64>,
+    pub seed: Option<i64>,
+}
+
+#[derive(Debug)]
+pub struct QueryResult {
+    pub query: String,
+    pub images: Vec<IndexedImage>,
+    // MAX_RESULTS で打ち切った
+    pub truncated: bool,
+}
+
+pub fn search(query: &str) -> anyhow::Result<QueryResult> {
+    let (condition, params) = compile_query(query)?;
+    let connection = open()?;
+    let sql = format!("SELECT path, orientation, model, steps, seed FROM images WHERE {condition} ORDER BY mtime DESC LIMIT {}", MAX_RESULTS + 1);
+    let mut images: Vec<IndexedImage> = connection.query(&sql, &params)?
+        .into_iter()
+        .filter_map(|row| Some(IndexedImage {
+            image: ScannedImage {
+                path: PathBuf::from(row[0].as_str()?),
+                orientation: row[1].as_i64().and_then(|v| u16::try_from(v).ok()),
+            },
+            model: row[2].as_str().map(str::to_owned),
+            steps: row[3].as_i64(),
+            seed: row[4].as_i64(),
+        }))
+        .collect();
+    let truncated = images.len() > MAX_RESULTS;
+    images.truncate(MAX_RESULTS);
+    Ok(QueryResult { query: query.to_owned(), images, truncated })
+}
+
+impl QueryResult {
+    pub fn format(&self) -> String {
+        let mut ret = format!("【{}】\r\n", tr(Msg::SearchResults));
+        ret.push_str(&format!("{}: {}\r\n", tr(Msg::IndexQuery).trim_end_matches(':'), self.query));
+        let more = if self.truncated { "+" } else { "" };
+        ret.push_str(&format!("{}: {}{more}\r\n\r\n", tr(Msg::MatchingImages), self.images.len()));
+        for found in &self.images {
+            ret.push_str(&format!("{}\r\n", found.image.path.display()));
+            let mut details = Vec::new();
+            if let Some(model) = &found.model {
+                details.push(format!("Model: {model}"));
+            }
+            if let Some(steps) = found.steps {
+                details.push(format!("Steps: {steps}"));
+            }
+            if let Some(seed) = found.seed {
+                details.push(format!("Seed: {seed}"));
+            }
+            if !details.is_empty() {
+                ret.push_str(&format!("    {}\r\n", details.join(", ")));
+            }
+        }
+        ret
+    }
+}
+
+struct DialogState {
+    query: String,
+}
+
+// 前回の条件を初めに入れておく
+pub fn show_query_dialog(parent: HWND, query: &str) -> Option<String> {
+    let mut state = DialogState { query: query.to_owned() };
+    let template = DialogTemplate::new(tr(Msg::IndexSearchTitle), 320, 92)
+        .item(dialog::STATIC, tr(Msg::IndexQuery), -1, 0, 7, 9, 40, 10)
+        .item(dialog::EDIT, "", IDC_QUERY, ES_AUTOHSCROLL as u32 | WS_BORDER.0 | WS_TABSTOP.0, 50, 7, 263, 14)
+        .item(dialog::STATIC, tr(Msg::IndexQueryHelp), -1, 0, 7, 27, 306, 40)
+        .item(dialog::BUTTON, tr(Msg::Search), IDOK.0, BS_DEFPUSHBUTTON as u32 | WS_TABSTOP.0, 209, 71, 50, 14)
+        .item(dialog::BUTTON, tr(Msg::Cancel), IDCANCEL.0, WS_TABSTOP.0, 263, 71, 50, 14);
+    let ret = template.show(parent, Some(dialog_proc), LPARAM(&mut state as *mut _ as isize));
+    (ret == IDOK.0 as isize).then_some(state.query)
+}
+
+extern "system" fn dialog_proc(hdlg: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> isize {
+    match message {
+        WM_INITDIALOG => {
+            unsafe { SetWindowLongPtrW(hdlg, GWLP_USERDATA, lparam.0) };
+            let state = unsafe { (lparam.0 as *mut DialogState).as_mut() }.unwrap();
+            unsafe { SetDlgItemTextW(hdlg, IDC_QUERY, &HSTRING::from(&state.query)) };
+            1
+        }
+        WM_COMMAND => {
+            let state = unsafe { (GetWindowLongPtrW(hdlg, GWLP_USERDATA) as *mut DialogState).as_mut() };
+            let Some(state) = state else { return 0 };
+            let id = (wparam.0 & 0xffff) as i32;
+            if id == IDOK.0 {
+                let query = dialog::get_item_text(hdlg, IDC_QUERY).trim().to_owned();
+                // 条件が間違っていたら閉じずに直してもらう
+                if let Err(e) = compile_query(&query) {
+                    let text = HSTRING::from(e.to_string());
+                    unsafe { MessageBoxW(hdlg, &text, None, MB_OK | MB_ICONWARNING) };
+                    return 1;
+                }
+                state.query = query;
+                unsafe { EndDialog(hdlg, IDOK.0 as isize) };
+                1
+            } else if id == IDCANCEL.0 {
+                unsafe { EndDialog(hdlg, IDCANCEL.0 as isize) };
+                1
+            } else {
+                0
+            }
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Value {
+        Value::Text(s.to_owned())
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(compile_query("model=foo").unwrap(), ("model = ? COLLATE NOCASE".to_owned(), vec![text("foo")]));
+        assert_eq!(compile_query("sampler!=Euler").unwrap(),
+            ("(sampler IS NULL OR sampler <> ? COLLATE NOCASE)".to_owned(), vec![text("Euler")]));
+        assert_eq!(compile_query("prompt~cat").unwrap(), ("prompt LIKE ? ESCAPE '\\'".to_owned(), vec![text("%cat%")]));
+        assert_eq!(compile_query("steps>=30").unwrap(), ("steps >= ?".to_owned(), vec![Value::Integer(30)]));
+        assert_eq!(compile_query("steps!=20").unwrap(), ("steps <> ?".to_owned(), vec![Value::Integer(20)]));
+        assert_eq!(compile_query("cfg<7.5").unwrap(), ("cfg_scale < ?".to_owned(), vec![Value::Real(7.5)]));
+        assert_eq!(compile_query("").unwrap(), ("1".to_owned(), vec![]));
+    }
+
+    #[test]
+    fn implicit_and() {
+        assert_eq!(compile_query("model=foo steps>20").unwrap(),
+            ("model = ? COLLATE NOCASE AND steps > ?".to_owned(), vec![text("foo"), Value::Integer(20)]));
+        assert_eq!(compile_query("cat dog").unwrap(),
+            ("text LIKE ? ESCAPE '\\' AND text LIKE ? ESCAPE '\\'".to_owned(), vec![text("%cat%"), text("%dog%")]));
+    }
+
+    #[test]
+    fn parentheses() {
+        assert_eq!(compile_query("(model=a OR model=b) AND steps>20").unwrap(), (
+            "(model = ? COLLATE NOCASE OR model = ? COLLATE NOCASE) AND steps > ?".to_owned(),
+            vec![text("a"), text("b"), Value::Integer(20)],
+        ));
+        assert_eq!(compile_query("(cat) dog").unwrap(),
+            ("(text LIKE ? ESCAPE '\\') AND text LIKE ? ESCAPE '\\'".to_owned(), vec![text("%cat%"), text("%dog%")]));
+    }
+
+    #[test]
+    fn quoted_values() {
+        assert_eq!(compile_query(r#"prompt~"a=b (c)""#).unwrap(),
+            ("prompt LIKE ? ESCAPE '\\'".to_owned(), vec![text("%a=b (c)%")]));
+        // 引用符で囲んだ AND は演算子ではなく検索する語
+        assert_eq!(compile_query(r#"cat "AND""#).unwrap(),
+            ("text LIKE ? ESCAPE '\\' AND text LIKE ? ESCAPE '\\'".to_owned(), vec![text("%cat%"), text("%AND%")]));
+    }
+
+    #[test]
+    fn like_escaping() {
+        assert_eq!(like_pattern("100%_a\\b"), "%100\\%\\_a\\\\b%");
+        assert_eq!(compile_query("path~50%").unwrap(), ("path LIKE ? ESCAPE '\\'".to_owned(), vec![text("%50\\%%")]));
+    }
+
+    #[test]
+    fn rejected() {
+        for query in ["steps>abc", "unknown=1", "steps~3", "AND cat", "cat AND", "cat OR", "(cat", "cat)", "()", "model=", "=x", "\"unterminated"] {
+            assert!(compile_query(query).is_err(), "{query}");
+        }
+    }
+}
@@ -0,0 +1,81 @@
+// zTXt, iTXt, iCCP の zlib 圧縮の展開
+// 細工されたファイルで大量のメモリや時間を使わないように、展開後の大きさと時間に上限を設ける
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use flate2::{Decompress, FlushDecompress, Status};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub max_size: usize,
+    pub max_time: Duration,
+}
+
+pub const DEFAULT_LIMITS: Limits = Limits { max_size: 16 * 1024 * 1024, max_time: Duration::from_secs(2) };
+
+// ユーザーが「それでも展開する」を選んだとき
+pub const UNLIMITED: Limits = Limits { max_size: usize::MAX, max_time: Duration::MAX };
+
+impl Default for Limits {
+    fn default() -> Self {
+        DEFAULT_LIMITS
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    Size,
+    Time,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InflateError {
+    Limit(LimitExceeded),
+    // 壊れている、または途中で切れている
+    Invalid,
+}
+
+// 設定から読んだ上限。言語と同じくプロセス全体で 1 つ
+static MAX_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_LIMITS.max_size);
+static MAX_TIME_MS: AtomicU64 = AtomicU64::new(DEFAULT_LIMITS.max_time.as_millis() as u64);
+
+pub fn set_limits(limits: Limits) {
+    MAX_SIZE.store(limits.max_size, Ordering::Relaxed);
+    MAX_TIME_MS.store(limits.max_time.as_millis().min(u64::MAX as u128) as u64, Ordering::Relaxed);
+}
+
+pub fn limits() -> Limits {
+    Limits {
+        max_size: MAX_SIZE.load(Ordering::Relaxed),
+        max_time: Duration::from_millis(MAX_TIME_MS.load(Ordering::Relaxed)),
+    }
+}
+
+// 一度に展開する大きさ。この単位で上限を確かめる
+const STEP: usize = 64 * 1024;
+
+pub fn inflate(data: &[u8], limits: Limits) -> Result<Vec<u8>, InflateError> {
+    let start = Instant::now();
+    let mut decompress = Decompress::new(true);
+    let mut out = Vec::new();
+    let mut buf = vec![0u8; STEP];
+    loop {
+        let (total_in, total_out) = (decompress.total_in(), decompress.total_out());
+        let input = &data[total_in as usize..];
+        let status = decompress.decompress(input, &mut buf, FlushDecompress::None).map_err(|_| InflateError::Invalid)?;
+        let produced = (decompress.total_out() - total_out) as usize;
+        if out.len() + produced > limits.max_size {
+            return Err(InflateError::Limit(LimitExceeded::Size));
+        }
+        out.extend_from_slice(&buf[..produced]);
+        if status == Status::StreamEnd {
+            return Ok(out);
+        }
+        if decompress.total_in() == total_in && produced == 0 {
+            return Err(InflateError::Invalid);
+        }
+        if start.elapsed() > limits.max_time {
+            return Err(InflateError::Limit(LimitExceeded::Time));
+        }
+    }
+}
@@ -0,0 +1,281 @@
+// ComfyUI、NovelAI、InvokeAI のメタデータを A1111 形式の生成パラメーター ("infotext") に変換する
+// web UI の PNG Info などにそのまま貼り付けて、同じ設定で生成し直せるようにする
+
+use std::path::Path;
+use crate::json::{self, Value};
+use crate::params;
+
+// ComfyUI のノードのリンクをたどる深さの上限 (循環していても止まるように)
+const MAX_LINK_DEPTH: usize = 32;
+
+#[derive(Debug, Default)]
+struct Generation {
+    prompt: String,
+    negative_prompt: String,
+    settings: Vec<(&'static str, String)>,
+}
+
+impl Generation {
+    fn set(&mut self, key: &'static str, value: Option<String>) {
+        if let Some(value) = value.filter(|value| !value.is_empty()) {
+            self.settings.push((key, value));
+        }
+    }
+
+    fn set_sampler(&mut self, name: Option<&str>) {
+        let Some(name) = name else { return };
+        let (sampler, schedule) = sampler_name(name);
+        self.set("Sampler", Some(sampler));
+        self.set("Schedule type", schedule.map(str::to_owned));
+    }
+
+    fn set_size(&mut self, width: Option<String>, height: Option<String>) {
+        if let (Some(width), Some(height)) = (width, height) {
+            self.set("Size", Some(format!("{width}x{height}")));
+        }
+    }
+
+    fn format(&self) -> String {
+        let mut ret = self.prompt.clone();
+        if !self.negative_prompt.is_empty() {
+            ret.push_str(&format!("\nNegative prompt: {}", self.negative_prompt));
+        }
+        let settings: Vec<String> = self.settings.iter().map(|(key, value)| format!("{key}: {}", quote(value))).collect();
+        ret.push('\n');
+        ret.push_str(&settings.join(", "));
+        ret
+    }
+}
+
+// 区切りの文字を含む値は A1111 と同じように引用符で囲む
+fn quote(value: &str) -> String {
+    let value = value.replace(['\r', '\n'], " ");
+    if value.contains([',', ':', '"']) {
+        format!("\"{}\"", value.replace('"', "\\\""))
+    } else {
+        value
+    }
+}
+
+// 整数は小数点を付けずに書く
+fn number(value: &Value) -> Option<String> {
+    match value {
+        Value::Number(n) if n.fract() == 0.0 && n.abs() < 1e16 => Some((*n as i64).to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::String(s) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+fn text(value: Option<&Value>) -> Option<String> {
+    value.and_then(Value::as_str).map(str::to_owned)
+}
+
+// 各ツールのサンプラー名を A1111 の表記と、スケジューラーの種類に分ける
+// "k_" で始まるのは NovelAI や古い InvokeAI、"_k" や "_karras" で終わるのは Karras のスケジュール
+fn sampler_name(name: &str) -> (String, Option<&'static str>) {
+    let base = name.strip_prefix("k_").unwrap_or(name);
+    let base = base.strip_suffix("_gpu").unwrap_or(base);
+    let (base, schedule) = match base.strip_suffix("_karras").or_else(|| base.strip_suffix("_k")) {
+        Some(base) => (base, Some("Karras")),
+        None => (base, None),
+    };
+    let sampler = match base {
+        "euler" => "Euler",
+        "euler_a" | "euler_ancestral" => "Euler a",
+        "heun" => "Heun",
+        "dpm_2" => "DPM2",
+        "dpm_2_a" | "dpm_2_ancestral" => "DPM2 a",
+        "lms" => "LMS",
+        "dpm_fast" => "DPM fast",
+        "dpm_adaptive" => "DPM adaptive",
+        "dpmpp_2s" | "dpmpp_2s_a" | "dpmpp_2s_ancestral" => "DPM++ 2S a",
+        "dpmpp_2m" => "DPM++ 2M",
+        "dpmpp_sde" => "DPM++ SDE",
+        "dpmpp_2m_sde" => "DPM++ 2M SDE",
+        "dpmpp_3m_sde" => "DPM++ 3M SDE",
+        "ddim" | "ddim_v3" => "DDIM",
+        "plms" => "PLMS",
+        "unipc" | "uni_pc" | "uni_pc_bh2" => "UniPC",
+        "lcm" => "LCM",
+        "ddpm" => "DDPM",
+        _ => return (name.to_owned(), None),
+    };
+    (sampler.to_owned(), schedule)
+}
+
+// ComfyUI のスケジューラー名。A1111 の既定 (Automatic) にあたるものは書かない
+fn schedule_type(name: &str) -> Option<&'static str> {
+    match name {
+        "karras" => Some("Karras"),
+        "exponential" => Some("Exponential"),
+        "sgm_uniform" => Some("SGM Uniform"),
+        "simple" => Some("Simple"),
+        "ddim_uniform" => Some("DDIM"),
+        "beta" => Some("Beta"),
+        _ => None,
+    }
+}
+
+// フォルダーと拡張子を除いたモデルのファイル名
+fn model_name(name: &str) -> String {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    Path::new(name).file_stem().map_or_else(|| name.to_owned(), |stem| stem.to_string_lossy().into_owned())
+}
+
+// NovelAI: "Comment" チャンクに JSON、"Description" チャンクにプロンプト
+fn novelai(text_chunks: &[(String, String)], comment: &Value) -> Option<Generation> {
+    comment.get("steps")?;
+    let caption = |key: &str| comment.get(key)?.get("caption")?.get("base_caption")?.as_str().map(str::to_owned);
+    let description = text_chunks.iter().find(|(keyword, _)| keyword == "Description").map(|(_, text)| text.clone());
+    let mut generation = Generation {
+        prompt: text(comment.get("prompt")).or_else(|| caption("v4_prompt")).or(description).unwrap_or_default(),
+        negative_prompt: text(comment.get("uc")).or_else(|| caption("v4_negative_prompt")).unwrap_or_default(),
+        settings: Vec::new(),
+    };
+    generation.set("Steps", comment.get("steps").and_then(number));
+    generation.set_sampler(comment.get("sampler").and_then(Value::as_str));
+    if generation.settings.iter().all(|(key, _)| *key != "Schedule type") {
+        generation.set("Schedule type", comment.get("noise_schedule").and_then(Value::as_str).and_then(schedule_type).map(str::to_owned));
+    }
+    generation.set("CFG scale", comment.get("scale").and_then(number));
+    generation.set("Seed", comment.get("seed").and_then(number));
+    generation.set_size(comment.get("width").and_then(number), comment.get("height").and_then(number));
+    Some(generation)
+}
+
+// InvokeAI 3 以降: "invokeai_metadata" チャンク
+fn invokeai(metadata: &Value) -> Option<Generation> {
+    let mut generation = Generation {
+        prompt: text(metadata.get("positive_prompt"))?,
+        negative_prompt: text(metadata.get("negative_prompt")).unwrap_or_default(),
+        settings: Vec::new(),
+    };
+    generation.set("Steps", metadata.get("steps").and_then(number));
+    generation.set_sampler(metadata.get("scheduler").and_then(Value::as_str));
+    generation.set("CFG scale", metadata.get("cfg_scale").and_then(number));
+    generation.set("Seed", metadata.get("seed").and_then(number));
+    generation.set_size(metadata.get("width").and_then(number), metadata.get("height").and_then(number));
+    let model = metadata.get("model").and_then(|model| model.get("name").or_else(|| model.get("model_name")));
+    generation.set("Model", text(model));
+    Some(generation)
+}
+
+// InvokeAI 2: "sd-metadata" チャンク。ネガティブプロンプトは [ ] で囲んでプロンプトに含まれている
+fn invokeai_legacy(metadata: &Value) -> Option<Generation> {
+    let image = metadata.get("image")?;
+    let prompt = match image.get("prompt")? {
+        Value::String(prompt) => prompt.clone(),
+        Value::Array(prompts) => prompts.iter().filter_map(|p| p.get("prompt")?.as_str()).collect::<Vec<_>>().join(" "),
+        _ => return None,
+    };
+    let mut generation = Generation { prompt, ..Default::default() };
+    generation.set("Steps", image.get("steps").and_then(number));
+    generation.set_sampler(image.get("sampler").and_then(Value::as_str));
+    generation.set("CFG scale", image.get("cfg_scale").and_then(number));
+    generation.set("Seed", image.get("seed").and_then(number));
+    generation.set_size(image.get("width").and_then(number), image.get("height").and_then(number));
+    generation.set("Model", text(metadata.get("model_weights")));
+    Some(generation)
+}
+
+// ComfyUI: "prompt" チャンクはノード ID からノードへの対応
+// ノードの入力は値か、[ノード ID, 出力の番号] の形のリンク
+fn linked_node<'a>(graph: &'a Value, input: &Value) -> Option<&'a Value> {
+    let Value::Array(link) = input else { return None };
+    match link.first()? {
+        Value::String(id) => graph.get(id),
+        id @ Value::Number(_) => graph.get(&number(id)?),
+        _ => None,
+    }
+}
+
+fn inputs(node: &Value) -> &[(String, Value)] {
+    match node.get("inputs") {
+        Some(Value::Object(inputs)) => inputs,
+        _ => &[],
+    }
+}
+
+// リンクをたどって、keys のどれかの入力に直接書かれた値を探す
+// 途中のノードでは、たどってきた入力と同じ名前 (positive など) のものを先にたどる
+fn find_input<'a>(graph: &'a Value, input: &Value, keys: &[&str], follow: &str, depth: usize) -> Option<&'a Value> {
+    if depth > MAX_LINK_DEPTH {
+        return None;
+    }
+    let inputs = inputs(linked_node(graph, input)?);
+    let direct = keys.iter().find_map(|key| {
+        inputs.iter().find(|(name, value)| name == key && linked_node(graph, value).is_none()).map(|(_, value)| value)
+    });
+    if direct.is_some() {
+        return direct;
+    }
+    let preferred = inputs.iter().filter(|(name, _)| name == follow);
+    let others = inputs.iter().filter(|(name, _)| name != follow);
+    preferred.chain(others).find_map(|(_, value)| find_input(graph, value, keys, follow, depth + 1))
+}
+
+// 値がリンクならたどる
+fn resolve<'a>(graph: &'a Value, value: &'a Value, keys: &[&str], follow: &str) -> Option<&'a Value> {
+    if linked_node(graph, value).is_some() {
+        find_input(graph, value, keys, follow, 0)
+    } else {
+        Some(value)
+    }
+}
+
+fn is_sampler(node: &Value) -> bool {
+    matches!(node.get("class_type").and_then(Value::as_str), Some("KSampler" | "KSamplerAdvanced"))
+}
+
+fn comfyui(graph: &Value) -> Option<Generation> {
+    let Value::Object(nodes) = graph else { return None };
+    // hires fix などでサンプラーが複数あるときは、空の潜在画像から始めるもの (最初の生成) を使う
+    let samplers: Vec<&Value> = nodes.iter().map(|(_, node)| node).filter(|node| is_sampler(node)).collect();
+    let from_empty_latent = |node: &&&Value| {
+        let latent = inputs(node).iter().find(|(name, _)| name == "latent_image");
+        latent.and_then(|(_, value)| linked_node(graph, value)).and_then(|node| node.get("class_type")?.as_str())
+            .is_some_and(|class| class.starts_with("Empty") && class.contains("Latent"))
+    };
+    let sampler = *samplers.iter().find(from_empty_latent).or_else(|| samplers.first())?;
+    let input = |name: &str| inputs(sampler).iter().find(|(key, _)| key == name).map(|(_, value)| value);
+    let text_keys = ["text", "text_g", "string", "value"];
+    let prompt = |name: &str| input(name).and_then(|value| resolve(graph, value, &text_keys, name)).and_then(Value::as_str).map(str::to_owned);
+    let mut generation = Generation {
+        prompt: prompt("positive").unwrap_or_default(),
+        negative_prompt: prompt("negative").unwrap_or_default(),
+        settings: Vec::new(),
+    };
+    let setting = |names: &[&str]| {
+        names.iter().find_map(|name| input(name)).and_then(|value| resolve(graph, value, names, "")).and_then(number)
+    };
+    generation.set("Steps", setting(&["steps"]));
+    generation.set_sampler(setting(&["sampler_name"]).as_deref());
+    if let Some(schedule) = setting(&["scheduler"]).as_deref().and_then(schedule_type) {
+        generation.settings.retain(|(key, _)| *key != "Schedule type");
+        generation.set("Schedule type", Some(schedule.to_owned()));
+    }
+    generation.set("CFG scale", setting(&["cfg"]));
+    generation.set("Seed", setting(&["seed", "noise_seed"]));
+    let latent = input("latent_image");
+    let size = |key: &str| latent.and_then(|value| find_input(graph, value, &[key], "latent_image", 0)).and_then(number);
+    generation.set_size(size("width"), size("height"));
+    let model = input("model").and_then(|value| find_input(graph, value, &["ckpt_name", "unet_name"], "model", 0));
+    generation.set("Model", model.and_then(Value::as_str).map(model_name));
+    Some(generation)
+}
+
+// どの形式でもなければ None。もともと A1111 形式ならそのまま返す
+pub fn to_infotext(text_chunks: &[(String, String)]) -> Option<String> {
+    let parameters = text_chunks.iter()
+        .find(|(keyword, text)| keyword == "parameters" && params::parse_infotext(text).is_some());
+    if let Some((_, text)) = parameters {
+        return Some(text.clone());
+    }
+    let chunk = |keyword: &str| text_chunks.iter().filter(|(k, _)| k == keyword).find_map(|(_, text)| json::parse(text));
+    let generation = chunk("Comment").and_then(|comment| novelai(text_chunks, &comment))
+        .or_else(|| chunk("invokeai_metadata").and_then(|metadata| invokeai(&metadata)))
+        .or_else(|| chunk("sd-metadata").and_then(|metadata| invokeai_legacy(&metadata)))
+        .or_else(|| chunk("prompt").and_then(|graph| comfyui(&graph)))?;
+    (!generation.settings.is_empty()).then(|| generation.format())
+}
@@ -0,0 +1,139 @@
+// JPEG の APP13 (Photoshop の画像リソース) に入っている IPTC IIM を読む
+// 報道写真やストックフォトの説明、キーワード、クレジット、著作権表示などが入っている
+
+use crate::encoding::{self, TextEncoding};
+
+const PHOTOSHOP_SIGNATURE: &[u8] = b"Photoshop 3.0\0";
+// IPTC-NAA のリソース ID
+const IPTC_RESOURCE: u16 = 0x0404;
+// 1:90 (Coded Character Set) の UTF-8 を表すエスケープシーケンス
+const UTF8_CHARSET: &[u8] = b"\x1b%G";
+
+// レコード 2 (アプリケーションレコード) のデータセット番号と名前 (ExifTool と同じ名前)
+const DATASETS: &[(u8, &str)] = &[
+    (5, "ObjectName"),
+    (15, "Category"),
+    (20, "SupplementalCategories"),
+    (25, "Keywords"),
+    (40, "SpecialInstructions"),
+    (55, "DateCreated"),
+    (60, "TimeCreated"),
+    (80, "By-line"),
+    (85, "By-lineTitle"),
+    (90, "City"),
+    (92, "Sub-location"),
+    (95, "Province-State"),
+    (100, "Country-PrimaryLocationCode"),
+    (101, "Country-PrimaryLocationName"),
+    (103, "OriginalTransmissionReference"),
+    (105, "Headline"),
+    (110, "Credit"),
+    (115, "Source"),
+    (116, "CopyrightNotice"),
+    (118, "Contact"),
+    (120, "Caption-Abstract"),
+    (122, "Writer-Editor"),
+];
+
+// APP13 のデータ部分 (複数のセグメントに分かれていればつないだもの) から IPTC のデータを取り出す
+// リソースは "8BIM"、ID (2 バイト)、偶数長にそろえた Pascal 文字列の名前、長さ (4 バイト)、偶数長にそろえたデータ
+fn iptc_resource(data: &[u8]) -> Option<&[u8]> {
+    let mut pos = PHOTOSHOP_SIGNATURE.len();
+    while pos + 12 <= data.len() && &data[pos..pos + 4] == b"8BIM" {
+        let id = u16::from_be_bytes([data[pos + 4], data[pos + 5]]);
+        let name_len = data[pos + 6] as usize;
+        // 長さのバイトを含めて偶数にする
+        let name_end = pos + 6 + ((name_len + 2) & !1);
+        let len = u32::from_be_bytes(data.get(name_end..name_end + 4)?.try_into().unwrap()) as usize;
+        let start = name_end + 4;
+        let end = start.checked_add(len).filter(|&end| end <= data.len())?;
+        if id == IPTC_RESOURCE {
+            return Some(&data[start..end]);
+        }
+        pos = end + (len & 1);
+    }
+    None
+}
+
+// 0x1c、レコード番号、データセット番号、長さ (2 バイト) の後ろにデータが続く
+fn datasets(data: &[u8]) -> Vec<(u8, u8, &[u8])> {
+    let mut ret = Vec::new();
+    let mut pos = 0;
+    while pos + 5 <= data.len() && data[pos] == 0x1c {
+        let (record, dataset) = (data[pos + 1], data[pos + 2]);
+        let len = u16::from_be_bytes([data[pos + 3], data[pos + 4]]) as usize;
+        pos += 5;
+        // 最上位ビットが立っていれば拡張形式で、続く何バイトかが長さ
+        let len = if len & 0x8000 != 0 {
+            let size = len & 0x7fff;
+            let Some(bytes) = data.get(pos..pos + size).filter(|_| size <= 8) else { break };
+            pos += size;
+            bytes.iter().fold(0usize, |n, &b| n << 8 | b as usize)
+        } else {
+            len
+        };
+        let Some(value) = pos.checked_add(len).and_then(|end| data.get(pos..end)) else { break };
+        ret.push((record, dataset, value));
+        pos += len;
+    }
+    ret
+}
+
+// CCYYMMDD → CCYY-MM-DD
+fn format_date(text: &str) -> String {
+    if text.len() != 8 || !text.bytes().all(|b| b.is_ascii_digit()) {
+        return text.to_owned();
+    }
+    format!("{}-{}-{}", &text[..4], &text[4..6], &text[6..])
+}
+
+// HHMMSS±HHMM → HH:MM:SS±HH:MM
+fn format_time(text: &str) -> String {
+    if text.len() != 11 || !text.is_ascii() {
+        return text.to_owned();
+    }
+    format!("{}:{}:{}{}:{}", &text[..2], &text[2..4], &text[4..6], &text[6..9], &text[9..])
+}
+
+// APP13 は大きいと複数のセグメントに分かれ、それぞれの先頭に "Photoshop 3.0\0" が付くので、2 つ目以降はそれを除いてつなぐ
+pub fn join_segments<'a>(segments: impl Iterator<Item = &'a [u8]>) -> Vec<u8> {
+    let mut ret = Vec::new();
+    for segment in segments.filter(|segment| segment.starts_with(PHOTOSHOP_SIGNATURE)) {
+        ret.extend_from_slice(if ret.is_empty() { segment } else { &segment[PHOTOSHOP_SIGNATURE.len()..] });
+    }
+    ret
+}
+
+// つないだ APP13 のデータ部分を受け取り、(名前, 値) を返す。繰り返されるもの (Keywords など) はまとめる
+pub fn read(app13: &[u8]) -> Vec<(String, String)> {
+    if !app13.starts_with(PHOTOSHOP_SIGNATURE) {
+        return Vec::new();
+    }
+    let Some(iptc) = iptc_resource(app13) else {
+        return Vec::new();
+    };
+    let datasets = datasets(iptc);
+    let utf8 = datasets.iter().any(|&(record, dataset, value)| record == 1 && dataset == 90 && value == UTF8_CHARSET);
+    let mut entries: Vec<(String, Vec<String>)> = Vec::new();
+    for (record, dataset, value) in datasets {
+        let Some((_, name)) = DATASETS.iter().find(|(number, _)| record == 2 && *number == dataset) else { continue };
+        // 文字コードの指定がなければ推定する (日本の報道写真では Shift_JIS のことがある)
+        let encoding = if utf8 { TextEncoding::Utf8 } else { encoding::detect(value) };
+        let text = encoding::decode(value, encoding);
+        let text = text.trim_end_matches('\0').trim();
+        if text.is_empty() {
+            continue;
+        }
+        let text = match dataset {
+            55 => format_date(text),
+            60 => format_time(text),
+            _ => text.to_owned(),
+        };
+        let key = format!("IPTC:{name}");
+        match entries.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, values)) => values.push(text),
+            None => entries.push((key, vec![text])),
+        }
+    }
+    entries.into_iter().map(|(key, values)| (key, values.join(", "))).collect()
+}
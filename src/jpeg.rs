@@ -0,0 +1,341 @@
+// JPEG のセグメント単位の読み取り
+
+use std::ops::Range;
+use crate::i18n::{tr, Msg};
+
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub marker: u8,
+    // マーカーを含むセグメント全体の位置
+    pub range: Range<usize>,
+    // 長さフィールドの後ろのデータ部分の位置
+    pub data: Range<usize>,
+}
+
+pub const SOI: u8 = 0xd8;
+pub const EOI: u8 = 0xd9;
+pub const SOS: u8 = 0xda;
+pub const APP0: u8 = 0xe0;
+pub const APP1: u8 = 0xe1;
+pub const APP11: u8 = 0xeb;
+pub const APP13: u8 = 0xed;
+pub const APP14: u8 = 0xee;
+pub const DQT: u8 = 0xdb;
+pub const COM: u8 = 0xfe;
+
+pub fn is_jpeg(file: &[u8]) -> bool {
+    file.starts_with(&[0xff, SOI])
+}
+
+fn is_sof(marker: u8) -> bool {
+    matches!(marker, 0xc0..=0xcf) && !matches!(marker, 0xc4 | 0xc8 | 0xcc)
+}
+
+// 16 進表示の一覧に出すマーカーの名前
+pub fn marker_name(marker: u8) -> String {
+    match marker {
+        SOI => "SOI".to_owned(),
+        EOI => "EOI".to_owned(),
+        SOS => "SOS".to_owned(),
+        DQT => "DQT".to_owned(),
+        COM => "COM".to_owned(),
+        0xc4 => "DHT".to_owned(),
+        0xdd => "DRI".to_owned(),
+        APP0..=0xef => format!("APP{}", marker - APP0),
+        marker if is_sof(marker) => format!("SOF{}", marker & 0x0f),
+        marker => format!("FF{marker:02X}"),
+    }
+}
+
+// SOI から SOS までのセグメントを返す。SOS 以降のエントロピー符号化データは最後の要素の range に含める
+pub fn parse_segments(file: &[u8]) -> anyhow::Result<Vec<Segment>> {
+    anyhow::ensure!(is_jpeg(file), "not a JPEG file");
+    let mut segments = vec![Segment { marker: SOI, range: 0..2, data: 2..2 }];
+    let mut pos = 2;
+    loop {
+        // マーカーの前には 0xff が何個あってもよい
+        while pos < file.len() && file[pos] == 0xff && file.get(pos + 1) == Some(&0xff) {
+            pos += 1;
+        }
+        anyhow::ensure!(pos + 2 <= file.len() && file[pos] == 0xff, "invalid JPEG marker at offset {pos}");
+        let marker = file[pos + 1];
+        if marker == EOI {
+            segments.push(Segment { marker, range: pos..pos + 2, data: pos + 2..pos + 2 });
+            return Ok(segments);
+        }
+        anyhow::ensure!(pos + 4 <= file.len(), "truncated JPEG segment at offset {pos}");
+        let len = u16::from_be_bytes([file[pos + 2], file[pos + 3]]) as usize;
+        anyhow::ensure!(len >= 2 && pos + 2 + len <= file.len(), "truncated JPEG segment at offset {pos}");
+        let end = pos + 2 + len;
+        if marker == SOS {
+            let scan_end = find_eoi(file, end);
+            segments.push(Segment { marker, range: pos..scan_end, data: pos + 4..end });
+            if scan_end + 2 <= file.len() {
+                segments.push(Segment { marker: EOI, range: scan_end..scan_end + 2, data: scan_end + 2..scan_end + 2 });
+            }
+            return Ok(segments);
+        }
+        segments.push(Segment { marker, range: pos..end, data: pos + 4..end });
+        pos = end;
+    }
+}
+
+// エントロピー符号化データの終わり (EOI の位置) を探す
+fn find_eoi(file: &[u8], start: usize) -> usize {
+    let mut pos = start;
+    while pos + 1 < file.len() {
+        if file[pos] == 0xff && file[pos + 1] == EOI {
+            return pos;
+        }
+        pos += 1;
+    }
+    file.len()
+}
+
+pub struct FrameInfo {
+    pub width: u32,
+    pub height: u32,
+    pub precision: u8,
+}
+
+pub fn frame_info(file: &[u8], segments: &[Segment]) -> Option<FrameInfo> {
+    let sof = segments.iter().find(|s| is_sof(s.marker))?;
+    let data = &file[sof.data.clone()];
+    if data.len() < 6 {
+        return None;
+    }
+    Some(FrameInfo {
+        precision: data[0],
+        height: u16::from_be_bytes([data[1], data[2]]) as u32,
+        width: u16::from_be_bytes([data[3], data[4]]) as u32,
+    })
+}
+
+// フレームの成分 (id と水平・垂直のサンプリング係数)
+#[derive(Debug, Clone, Copy)]
+struct Component {
+    id: u8,
+    horizontal: u8,
+    vertical: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Process {
+    Baseline,
+    Extended,
+    Progressive,
+    Lossless,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Jfif {
+    pub version: (u8, u8),
+    // 0: 単位なし (縦横比だけ), 1: dpi, 2: dpcm
+    pub units: u8,
+    pub x_density: u16,
+    pub y_density: u16,
+}
+
+// 画質の推定。IJG (libjpeg) の標準テーブルを品質 q で縮尺したものと比べる
+#[derive(Debug, Clone, Copy)]
+pub struct Quality {
+    pub quality: u8,
+    // すべてのテーブルが標準テーブルの縮尺と一致したか
+    pub exact: bool,
+}
+
+// ExifTool で見るような圧縮の細かい情報
+#[derive(Debug, Clone)]
+pub struct JpegDetails {
+    pub marker: u8,
+    pub process: Process,
+    pub arithmetic: bool,
+    components: Vec<Component>,
+    pub jfif: Option<Jfif>,
+    // APP14 "Adobe" の色変換 (0: なし (RGB/CMYK), 1: YCbCr, 2: YCCK)
+    pub adobe_transform: Option<u8>,
+    pub quality: Option<Quality>,
+}
+
+// IJG の標準テーブル (JPEG 規格の Annex K)。自然な順 (行ごと)
+const STANDARD_LUMINANCE: [u16; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61,
+    12, 12, 14, 19, 26, 58, 60, 55,
+    14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62,
+    18, 22, 37, 56, 68, 109, 103, 77,
+    24, 35, 55, 64, 81, 104, 113, 92,
+    49, 64, 78, 87, 103, 121, 120, 101,
+    72, 92, 95, 98, 112, 100, 103, 99,
+];
+const STANDARD_CHROMINANCE: [u16; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99,
+    18, 21, 26, 66, 99, 99, 99, 99,
+    24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+// DQT はジグザグ順なので、i 番目の値の自然な順での位置
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5,
+    12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+// libjpeg の jpeg_quality_scaling と同じ計算 (baseline なので 255 で打ち切る)
+fn scaled_table(standard: &[u16; 64], quality: u32) -> [u16; 64] {
+    let scale = if quality < 50 { 5000 / quality } else { 200 - quality * 2 };
+    standard.map(|value| ((value as u32 * scale + 50) / 100).clamp(1, 255) as u16)
+}
+
+// DQT のテーブル (番号, 値) をすべて読む。同じ番号が再定義されていれば後のもの
+fn quantization_tables(file: &[u8], segments: &[Segment]) -> Vec<(u8, [u16; 64])> {
+    let mut tables: Vec<(u8, [u16; 64])> = Vec::new();
+    for segment in segments.iter().filter(|s| s.marker == DQT) {
+        let mut data = &file[segment.data.clone()];
+        while let Some((&info, rest)) = data.split_first() {
+            let (precision, id) = (info >> 4, info & 0x0f);
+            let size = if precision == 0 { 64 } else { 128 };
+            let Some(values) = rest.get(..size) else { break };
+            let mut table = [0u16; 64];
+            for (i, &position) in ZIGZAG.iter().enumerate() {
+                table[position] = if precision == 0 { values[i] as u16 } else { u16::from_be_bytes([values[i * 2], values[i * 2 + 1]]) };
+            }
+            tables.retain(|(existing, _)| *existing != id);
+            tables.push((id, table));
+            data = &rest[size..];
+        }
+    }
+    tables
+}
+
+// テーブル 0 を輝度、それ以外を色差として、差の合計がいちばん小さい品質を選ぶ
+fn estimate_quality(tables: &[(u8, [u16; 64])]) -> Option<Quality> {
+    if tables.is_empty() {
+        return None;
+    }
+    let (distance, quality) = (1..=100u32).map(|quality| {
+        let luminance = scaled_table(&STANDARD_LUMINANCE, quality);
+        let chrominance = scaled_table(&STANDARD_CHROMINANCE, quality);
+        let distance: u32 = tables.iter().map(|(id, table)| {
+            let standard = if *id == 0 { &luminance } else { &chrominance };
+            table.iter().zip(standard).map(|(&a, &b)| a.abs_diff(b) as u32).sum::<u32>()
+        }).sum();
+        (distance, quality)
+    }).min()?;
+    Some(Quality { quality: quality as u8, exact: distance == 0 })
+}
+
+fn frame_components(data: &[u8]) -> Vec<Component> {
+    let count = data.get(5).copied().unwrap_or(0) as usize;
+    data.get(6..6 + count * 3).unwrap_or_default().chunks_exact(3)
+        .map(|c| Component { id: c[0], horizontal: c[1] >> 4, vertical: c[1] & 0x0f })
+        .collect()
+}
+
+pub fn details(file: &[u8], segments: &[Segment]) -> Option<JpegDetails> {
+    let sof = segments.iter().find(|s| is_sof(s.marker))?;
+    let marker = sof.marker;
+    // 0xc0–0xc3 は Huffman 符号、0xc9–0xcb は算術符号。0xc5 以降の各組は階層的 (differential)
+    let process = match marker & 0x03 {
+        0 => Process::Baseline,
+        1 => Process::Extended,
+        2 => Process::Progressive,
+        _ => Process::Lossless,
+    };
+    // 階層的の 0xc5 (sequential) は baseline ではない
+    let process = if process == Process::Baseline && marker != 0xc0 { Process::Extended } else { process };
+    let app = |marker: u8, signature: &[u8]| segments.iter()
+        .map(|s| (s.marker, &file[s.data.clone()]))
+        .find(|(m, data)| *m == marker && data.starts_with(signature))
+        .map(|(_, data)| &data[signature.len()..]);
+    let jfif = app(APP0, b"JFIF\0").filter(|data| data.len() >= 7).map(|data| Jfif {
+        version: (data[0], data[1]),
+        units: data[2],
+        x_density: u16::from_be_bytes([data[3], data[4]]),
+        y_density: u16::from_be_bytes([data[5], data[6]]),
+    });
+    // "Adobe" の後ろはバージョン (2 バイト)、フラグ (2 バイト × 2)、変換 (1 バイト)
+    let adobe_transform = app(APP14, b"Adobe").and_then(|data| data.get(6).copied());
+    Some(JpegDetails {
+        marker,
+        process,
+        arithmetic: marker & 0x08 != 0,
+        components: frame_components(&file[sof.data.clone()]),
+        jfif,
+        adobe_transform,
+        quality: if process == Process::Lossless { None } else { estimate_quality(&quantization_tables(file, segments)) },
+    })
+}
+
+impl JpegDetails {
+    // 輝度に対する色差の間引き方 ("4:2:0" など)。1 成分 (グレースケール) なら None
+    pub fn subsampling(&self) -> Option<String> {
+        let [luma, chroma, ..] = self.components[..] else { return None };
+        let factors = self.components.iter().map(|c| format!("{}x{}", c.horizontal, c.vertical)).collect::<Vec<_>>().join(",");
+        // 色差の成分どうしで係数が違うものや、輝度の係数が色差の係数で割り切れないものは名前を付けない
+        let uniform = self.components[1..].iter().all(|c| (c.horizontal, c.vertical) == (chroma.horizontal, chroma.vertical));
+        let ratio = (chroma.horizontal != 0 && chroma.vertical != 0
+            && luma.horizontal % chroma.horizontal == 0 && luma.vertical % chroma.vertical == 0)
+            .then(|| (luma.horizontal / chroma.horizontal, luma.vertical / chroma.vertical));
+        let name = match ratio.filter(|_| uniform) {
+            Some((1, 1)) => Some("4:4:4"),
+            Some((2, 1)) => Some("4:2:2"),
+            Some((2, 2)) => Some("4:2:0"),
+            Some((1, 2)) => Some("4:4:0"),
+            Some((4, 1)) => Some("4:1:1"),
+            Some((4, 2)) => Some("4:1:0"),
+            _ => None,
+        };
+        // 成分の id が 'R','G','B' なら色差ではない
+        let rgb = self.components.iter().map(|c| c.id).eq(*b"RGB");
+        Some(match name {
+            Some(name) if !rgb => format!("{name} ({factors})"),
+            _ => factors,
+        })
+    }
+
+    pub fn format(&self) -> String {
+        let mut process = match self.process {
+            Process::Baseline => tr(Msg::JpegBaseline),
+            Process::Extended => tr(Msg::JpegExtended),
+            Process::Progressive => tr(Msg::JpegProgressive),
+            Process::Lossless => tr(Msg::JpegLossless),
+        }.to_owned();
+        if self.arithmetic {
+            process.push_str(&format!(", {}", tr(Msg::JpegArithmetic)));
+        }
+        let mut ret = format!("{}: {process} (SOF{})\r\n", tr(Msg::JpegProcess), self.marker & 0x0f);
+        if let Some(subsampling) = self.subsampling() {
+            ret.push_str(&format!("{}: {subsampling}\r\n", tr(Msg::JpegSubsampling)));
+        }
+        if let Some(quality) = self.quality {
+            let note = if quality.exact { String::new() } else { format!(" ({})", tr(Msg::JpegNonStandardTables)) };
+            let approx = if quality.exact { "" } else { "≈ " };
+            ret.push_str(&format!("{}: {approx}{}{note}\r\n", tr(Msg::JpegQuality), quality.quality));
+        }
+        if let Some(jfif) = self.jfif {
+            let units = match jfif.units {
+                1 => " dpi".to_owned(),
+                2 => " dpcm".to_owned(),
+                _ => format!(" ({})", tr(Msg::JfifAspectRatio)),
+            };
+            ret.push_str(&format!("JFIF {}.{:02}: {} x {}{units}\r\n", jfif.version.0, jfif.version.1, jfif.x_density, jfif.y_density));
+        }
+        if let Some(transform) = self.adobe_transform {
+            let name = match transform {
+                0 => tr(Msg::AdobeNoTransform),
+                1 => "YCbCr",
+                2 => "YCCK",
+                _ => "?",
+            };
+            ret.push_str(&format!("Adobe APP14: {name} ({transform})\r\n"));
+        }
+        ret
+    }
+}
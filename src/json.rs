@@ -0,0 +1,208 @@
+// 最小限の JSON パーサー (Web API の応答を読むのに使う)
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    // キーの順序を保つ
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+// 入れ子が深すぎる入力でスタックを使い切らないようにする
+const MAX_DEPTH: usize = 128;
+
+pub fn parse(text: &str) -> Option<Value> {
+    let mut parser = Parser { chars: text.chars().collect(), pos: 0 };
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    (parser.pos == parser.chars.len()).then_some(value)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str) -> Option<()> {
+        for c in literal.chars() {
+            (self.next()? == c).then_some(())?;
+        }
+        Some(())
+    }
+
+    fn value(&mut self, depth: usize) -> Option<Value> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        self.skip_whitespace();
+        match self.peek()? {
+            '{' => self.object(depth),
+            '[' => self.array(depth),
+            '"' => self.string().map(Value::String),
+            't' => self.expect("true").map(|_| Value::Bool(true)),
+            'f' => self.expect("false").map(|_| Value::Bool(false)),
+            'n' => self.expect("null").map(|_| Value::Null),
+            _ => self.number(),
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Option<Value> {
+        self.expect("{")?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Some(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(":")?;
+            members.push((key, self.value(depth + 1)?));
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => {}
+                '}' => return Some(Value::Object(members)),
+                _ => return None,
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Option<Value> {
+        self.expect("[")?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Some(Value::Array(items));
+        }
+        loop {
+            items.push(self.value(depth + 1)?);
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => {}
+                ']' => return Some(Value::Array(items)),
+                _ => return None,
+            }
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.expect("\"")?;
+        let mut s = String::new();
+        loop {
+            match self.next()? {
+                '"' => return Some(s),
+                '\\' => match self.next()? {
+                    'n' => s.push('\n'),
+                    't' => s.push('\t'),
+                    'r' => s.push('\r'),
+                    'b' => s.push('\u{8}'),
+                    'f' => s.push('\u{c}'),
+                    'u' => s.push(self.unicode_escape()?),
+                    c => s.push(c),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let digits: String = (0..4).map(|_| self.next()).collect::<Option<_>>()?;
+        u32::from_str_radix(&digits, 16).ok()
+    }
+
+    // サロゲートペアは 2 つの \u をまとめて 1 文字にする
+    fn unicode_escape(&mut self) -> Option<char> {
+        let high = self.hex4()?;
+        if (0xd800..0xdc00).contains(&high) {
+            self.expect("\\u")?;
+            let low = self.hex4()?;
+            return char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low.checked_sub(0xdc00)? & 0x3ff));
+        }
+        Some(char::from_u32(high).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    fn number(&mut self) -> Option<Value> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(c)) {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        text.parse().ok().map(Value::Number)
+    }
+}
+
+// 文字列を JSON の文字列リテラルにする
+pub fn quote(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len() + 2);
+    ret.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            '\r' => ret.push_str("\\r"),
+            '\t' => ret.push_str("\\t"),
+            c if (c as u32) < 0x20 => ret.push_str(&format!("\\u{:04x}", c as u32)),
+            c => ret.push(c),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+// 書き出す。オブジェクトや配列も 1 行にする
+pub fn to_string(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_owned(),
+        Value::Bool(b) => b.to_string(),
+        // 整数は小数点を付けない
+        Value::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => (*n as i64).to_string(),
+        Value::Number(n) if n.is_finite() => n.to_string(),
+        Value::Number(_) => "null".to_owned(),
+        Value::String(s) => quote(s),
+        Value::Array(items) => format!("[{}]", items.iter().map(to_string).collect::<Vec<_>>().join(",")),
+        Value::Object(members) => {
+            let members: Vec<String> = members.iter().map(|(key, value)| format!("{}:{}", quote(key), to_string(value))).collect();
+            format!("{{{}}}", members.join(","))
+        }
+    }
+}
@@ -0,0 +1,203 @@
+// メタデータの読み取りなど、アプリ本体とエクスプローラー拡張で共有する部分
+// DLL としてビルドしたものは regsvr32 で登録するシェル拡張の COM サーバーになる
+
+pub mod c2pa;
+pub mod cbor;
+pub mod digest;
+pub mod encoding;
+pub mod exif;
+pub mod exr;
+pub mod exiftool;
+pub mod extract;
+pub mod fsutil;
+pub mod hashes;
+pub mod hex;
+pub mod history;
+pub mod i18n;
+pub mod inflate;
+pub mod infotext;
+pub mod iptc;
+pub mod jpeg;
+pub mod json;
+pub mod metadata;
+pub mod motion_photo;
+pub mod params;
+pub mod plugins;
+pub mod policy;
+pub mod png_chunks;
+pub mod redact;
+pub mod settings;
+pub mod svg;
+pub mod texture;
+pub mod trailer;
+pub mod watermark;
+pub mod xml;
+mod preview_handler;
+mod property_handler;
+
+use std::ffi::{c_void, OsString};
+use std::os::windows::ffi::OsStringExt;
+use std::sync::atomic::{AtomicIsize, Ordering};
+use windows::core::*;
+use windows::Win32::Foundation::*;
+use windows::Win32::System::Com::*;
+use windows::Win32::System::LibraryLoader::GetModuleFileNameW;
+use windows::Win32::System::Registry::*;
+use windows::Win32::System::SystemServices::DLL_PROCESS_ATTACH;
+use windows::Win32::UI::Shell::*;
+
+static INSTANCE: AtomicIsize = AtomicIsize::new(0);
+
+fn instance() -> HINSTANCE {
+    HINSTANCE(INSTANCE.load(Ordering::Relaxed))
+}
+
+fn read_stream(stream: &IStream) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let mut read = 0;
+        unsafe { stream.Read(buf.as_mut_ptr() as _, buf.len() as u32, Some(&mut read)) }.ok()?;
+        if read == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..read as usize]);
+    }
+    Ok(data)
+}
+
+fn guid_string(guid: &GUID) -> String {
+    format!("{{{:?}}}", guid)
+}
+
+fn module_path() -> Result<String> {
+    let mut buf = vec![0u16; 32768];
+    let len = unsafe { GetModuleFileNameW(instance(), &mut buf) } as usize;
+    if len == 0 {
+        return Err(Error::from_win32());
+    }
+    Ok(OsString::from_wide(&buf[..len]).to_string_lossy().into_owned())
+}
+
+// レジストリの読み書き。アプリ本体のファイルの関連付けでも使う
+pub fn set_value(hkey: HKEY, subkey: &str, name: Option<&str>, value: &str) -> Result<()> {
+    let name = name.map(HSTRING::from);
+    let value: Vec<u16> = value.encode_utf16().chain(Some(0)).collect();
+    HRESULT::from(unsafe { RegSetKeyValueW(
+        hkey,
+        &HSTRING::from(subkey),
+        name.as_ref().map_or(PCWSTR::null(), |name| PCWSTR(name.as_ptr())),
+        REG_SZ.0,
+        Some(value.as_ptr() as _),
+        (value.len() * 2) as u32) }).ok()
+}
+
+pub fn get_value(hkey: HKEY, subkey: &str, name: Option<&str>) -> Option<String> {
+    let subkey = HSTRING::from(subkey);
+    let name = name.map(HSTRING::from);
+    let name = name.as_ref().map_or(PCWSTR::null(), |name| PCWSTR(name.as_ptr()));
+    let mut size = 0;
+    unsafe { RegGetValueW(hkey, &subkey, name, RRF_RT_REG_SZ, None, None, Some(&mut size)) }.ok().ok()?;
+    let mut buf = vec![0u16; size as usize / 2];
+    unsafe { RegGetValueW(hkey, &subkey, name, RRF_RT_REG_SZ, None, Some(buf.as_mut_ptr() as _), Some(&mut size)) }.ok().ok()?;
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    Some(String::from_utf16_lossy(&buf[..len]))
+}
+
+pub fn delete_value(hkey: HKEY, subkey: &str, name: Option<&str>) {
+    let name = name.map(HSTRING::from);
+    unsafe { RegDeleteKeyValueW(
+        hkey,
+        &HSTRING::from(subkey),
+        name.as_ref().map_or(PCWSTR::null(), |name| PCWSTR(name.as_ptr()))) };
+}
+
+pub fn delete_tree(hkey: HKEY, subkey: &str) {
+    unsafe { RegDeleteTreeW(hkey, &HSTRING::from(subkey)) };
+}
+
+#[implement(IClassFactory)]
+struct ClassFactory(fn() -> IUnknown);
+
+impl IClassFactory_Impl for ClassFactory {
+    fn CreateInstance(&self, punkouter: &Option<IUnknown>, riid: *const GUID, ppvobject: *mut *mut c_void) -> Result<()> {
+        if punkouter.is_some() {
+            return Err(CLASS_E_NOAGGREGATION.into());
+        }
+        let object = (self.0)();
+        unsafe { object.query(&*riid, ppvobject as _) }.ok()
+    }
+
+    fn LockServer(&self, _flock: BOOL) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[no_mangle]
+#[allow(non_snake_case)]
+extern "system" fn DllMain(hinstance: HINSTANCE, reason: u32, _reserved: *mut c_void) -> BOOL {
+    if reason == DLL_PROCESS_ATTACH {
+        INSTANCE.store(hinstance.0, Ordering::Relaxed);
+    }
+    BOOL(1)
+}
+
+#[no_mangle]
+#[allow(non_snake_case)]
+extern "system" fn DllGetClassObject(rclsid: *const GUID, riid: *const GUID, ppv: *mut *mut c_void) -> HRESULT {
+    let create: fn() -> IUnknown = match unsafe { *rclsid } {
+        preview_handler::CLSID => preview_handler::create,
+        property_handler::CLSID => property_handler::create,
+        _ => return CLASS_E_CLASSNOTAVAILABLE,
+    };
+    let factory: IClassFactory = ClassFactory(create).into();
+    unsafe { factory.query(&*riid, ppv as _) }
+}
+
+#[no_mangle]
+#[allow(non_snake_case)]
+extern "system" fn DllCanUnloadNow() -> HRESULT {
+    // 参照数を数えていないので、読み込まれたままにしておく
+    S_FALSE
+}
+
+fn register() -> Result<()> {
+    let dll = module_path()?;
+    preview_handler::register(&dll)?;
+    // プロパティハンドラーは HKLM にしか登録できないので、管理者として実行されたときだけ登録する
+    match property_handler::register(&dll) {
+        Err(e) if e.code() == E_ACCESSDENIED => Ok(()),
+        ret => ret,
+    }
+}
+
+fn unregister() {
+    preview_handler::unregister();
+    property_handler::unregister();
+}
+
+fn notify_association_changed() {
+    unsafe { SHChangeNotify(SHCNE_ASSOCCHANGED, SHCNF_IDLIST, None, None) };
+}
+
+#[no_mangle]
+#[allow(non_snake_case)]
+extern "system" fn DllRegisterServer() -> HRESULT {
+    let ret = match register() {
+        Ok(()) => S_OK,
+        Err(e) => {
+            unregister();
+            e.code()
+        }
+    };
+    notify_association_changed();
+    ret
+}
+
+#[no_mangle]
+#[allow(non_snake_case)]
+extern "system" fn DllUnregisterServer() -> HRESULT {
+    unregister();
+    notify_association_changed();
+    S_OK
+}
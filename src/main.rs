@@ -1,9 +1,50 @@
 #![windows_subsystem = "windows"]
 
-use std::fs::File;
+mod accessibility;
+mod association;
+mod batch;
+mod batch_strip;
+mod chunk_editor;
+mod civitai;
+mod clipboard;
+mod crash;
+mod dialog;
+mod download;
+mod drag_source;
+mod gallery;
+mod drop_target;
+mod duplicates;
+mod hashing;
+mod highlight;
+mod hex_view;
+mod history_view;
+mod hotkey;
+mod imaging;
+mod index;
+mod preview;
+mod policy_check;
+mod print;
+mod scripts;
+mod search;
+mod sqlite;
+mod size_report;
+mod strip;
+mod text_zoom;
+mod theme;
+mod tray;
+mod update;
+mod watcher;
+
 use std::ffi::OsStr;
-use std::{mem, ffi::OsString};
-use std::os::windows::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use std::mem;
+use std::ops::Range;
+use std::os::windows::ffi::OsStrExt;
+use metaview_core::{digest, encoding, exiftool, extract, fsutil, hashes, history, i18n, inflate, infotext, jpeg, json, metadata, params, plugins, policy, png_chunks, redact, settings, watermark};
+use i18n::{tr, Msg, Language};
+use encoding::{Newline, OutputEncoding, TextEncoding};
+use metadata::{ImageMetadata, Link, Source, format_markdown, format_metadata, format_metadata_with_links};
+use settings::Settings;
 use windows::{
     core::*,
     Win32::{
@@ -12,55 +53,1820 @@ use windows::{
         UI::{
             WindowsAndMessaging::*,
             Shell::*,
+            Controls::{*, RichEdit::*},
+            Input::KeyboardAndMouse::{GetFocus, GetKeyState, SetFocus, VK_ADD, VK_CONTROL, VK_OEM_MINUS, VK_OEM_PLUS, VK_SUBTRACT},
+        },
+        System::{
+            Com::{CoCreateInstance, CoTaskMemFree, CLSCTX_INPROC_SERVER},
+            LibraryLoader::{GetModuleHandleW, LoadLibraryW},
+            SystemServices::SS_BITMAP,
+            Ole::{OleInitialize, OleGetClipboard, RegisterDragDrop, RevokeDragDrop, IDropTarget},
+        },
+    }
+};
+
+#[derive(Debug)]
+pub struct App {
+    hedit: HWND,
+    hstatus: HWND,
+    // 埋め込みサムネイルを表示する STATIC
+    hthumbnail: HWND,
+    thumbnail: HBITMAP,
+    hpreview: HWND,
+    settings: Settings,
+    current: Option<ImageMetadata>,
+    // 画像から作ったウィンドウアイコン (小, 大)
+    icons: (HICON, HICON),
+    filter_menu: HMENU,
+    encoding_menu: HMENU,
+    // フィルターメニューに並んでいるキーワード
+    filter_keywords: Vec<String>,
+    // 最小化して通知領域に入っている
+    in_tray: bool,
+    // 通知を出すためだけに通知領域にアイコンを置いている
+    notify_icon: bool,
+    // 最後に通知した画像 (通知がクリックされたらこれを開く)
+    notified: Option<PathBuf>,
+    // ファイルのハッシュの計算を始めるたびに増やす
+    digest_job: usize,
+    // 表示欄のリンクの位置と、クリックされたときにすること
+    links: Vec<(Range<usize>, Link)>,
+    // 画像を開くたびに増やす番号 (別のスレッドでのデコードの結果が今の画像のものかを確かめる)
+    decode_job: usize,
+    // 表示欄に書き込めるようにしている (読んでいるときに誤って書き換えないように、既定では読み取り専用)
+    editing: bool,
+    // このセッションで開いたファイル (設定によっては前回までの分も)
+    history: Vec<history::HistoryEntry>,
+    scripts: scripts::Scripts,
+    // 監視しているフォルダー (止めるときは None にする)
+    watcher: Option<watcher::Watcher>,
+    // フォルダーを集計したあとのサムネイルの一覧
+    gallery: gallery::Gallery,
+    show_gallery: bool,
+    // 前回索引を検索したときの条件
+    index_query: String,
+    // フォルダーのメタデータをまとめて取り除いている最中
+    strip_job: Option<batch_strip::StripJob>,
+    // 表示欄の文字の倍率 (%)。ファイルを開き直しても変えない
+    text_zoom: u32,
+}
+
+impl Default for App {
+    fn default() -> Self {
+        App {
+            hedit: HWND(0),
+            hstatus: HWND(0),
+            hthumbnail: HWND(0),
+            thumbnail: HBITMAP(0),
+            hpreview: HWND(0),
+            settings: Settings::default(),
+            current: None,
+            icons: (HICON(0), HICON(0)),
+            filter_menu: HMENU(0),
+            encoding_menu: HMENU(0),
+            filter_keywords: Vec::new(),
+            in_tray: false,
+            notify_icon: false,
+            notified: None,
+            digest_job: 0,
+            links: Vec::new(),
+            decode_job: 0,
+            editing: false,
+            history: Vec::new(),
+            scripts: scripts::Scripts::default(),
+            watcher: None,
+            gallery: gallery::Gallery::default(),
+            show_gallery: false,
+            index_query: String::new(),
+            strip_job: None,
+            text_zoom: text_zoom::DEFAULT,
+        }
+    }
+}
+
+// メニューのコマンド ID
+const IDM_SHOW_ALL_CHUNKS: u32 = 401;
+const IDM_HIDE_BINARY_CHUNKS: u32 = 402;
+const IDM_SIZE_BREAKDOWN: u32 = 403;
+const IDM_SHOW_PREVIEW: u32 = 404;
+const IDM_ZOOM_FIT: u32 = 405;
+const IDM_ZOOM_ACTUAL: u32 = 406;
+const IDM_ZOOM_IN: u32 = 407;
+const IDM_ZOOM_OUT: u32 = 408;
+const IDM_HISTORY: u32 = 409;
+const IDM_SHOW_GALLERY: u32 = 410;
+const IDM_REDACT: u32 = 411;
+const IDM_TEXT_ZOOM_IN: u32 = 412;
+const IDM_TEXT_ZOOM_OUT: u32 = 413;
+const IDM_TEXT_ZOOM_RESET: u32 = 414;
+const IDM_ACCENT_COLORS: u32 = 415;
+// Ctrl++ と Ctrl+- のアクセラレーター (メニューにはない)
+const IDM_ZOOM_IN_KEY: u32 = 416;
+const IDM_ZOOM_OUT_KEY: u32 = 417;
+const IDM_HEX_VIEW: u32 = 418;
+const IDM_ENCODING_AUTO: u32 = 501;
+// TextEncoding::ALL の順に並べる
+const IDM_ENCODING_FIRST: u32 = 502;
+const IDM_FILTER_KEYWORD_FIRST: u32 = 2000;
+const IDM_FILTER_KEYWORD_LAST: u32 = 2999;
+const IDM_COPY: u32 = 301;
+const IDM_COPY_VALUE: u32 = 302;
+const IDM_COPY_KEY_VALUE: u32 = 303;
+const IDM_COPY_PROMPT: u32 = 304;
+const IDM_COPY_SHA256: u32 = 305;
+const IDM_COPY_BLAKE3: u32 = 306;
+const IDM_COPY_PHASH: u32 = 307;
+const IDM_SAVE_CLEAN_COPY: u32 = 201;
+const IDM_PRINT: u32 = 202;
+const IDM_OPEN_MAP: u32 = 203;
+const IDM_SAVE_THUMBNAIL: u32 = 204;
+const IDM_FOLDER_STATS: u32 = 205;
+const IDM_OPEN: u32 = 206;
+const IDM_WATCH_FOLDER: u32 = 207;
+const IDM_OPEN_IN_VIEWER: u32 = 208;
+const IDM_SHOW_IN_EXPLORER: u32 = 209;
+const IDM_SEARCH_FOLDER: u32 = 210;
+const IDM_INDEX_ADD_FOLDER: u32 = 211;
+const IDM_INDEX_UPDATE: u32 = 212;
+const IDM_INDEX_SEARCH: u32 = 213;
+const IDM_SAVE_WORKFLOW: u32 = 214;
+const IDM_EXPORT_EXIFTOOL_JSON: u32 = 215;
+const IDM_COMPARE_EXIFTOOL_JSON: u32 = 216;
+const IDM_BATCH_STRIP: u32 = 217;
+const IDM_CHECK_POLICY: u32 = 218;
+const IDM_CHECK_FOLDER_POLICY: u32 = 219;
+const IDM_SAVE_METADATA_TEXT: u32 = 220;
+const IDM_FIND_DUPLICATES: u32 = 221;
+const IDM_MOVE_TO_RECYCLE_BIN: u32 = 222;
+const IDM_SAVE_MOTION_PHOTO_VIDEO: u32 = 223;
+const IDM_PASTE: u32 = 101;
+const IDM_EDIT_CHUNK: u32 = 102;
+const IDM_ADD_CHUNK: u32 = 103;
+const IDM_COPY_MARKDOWN: u32 = 104;
+const IDM_ENABLE_EDITING: u32 = 105;
+const IDM_COPY_INFOTEXT: u32 = 106;
+const IDM_LANGUAGE_AUTO: u32 = 1001;
+const IDM_LANGUAGE_JAPANESE: u32 = 1002;
+const IDM_LANGUAGE_ENGLISH: u32 = 1003;
+const IDM_MINIMIZE_TO_TRAY: u32 = 1101;
+const IDM_HOTKEY: u32 = 1102;
+const IDM_CIVITAI_LOOKUP: u32 = 1103;
+const IDM_EDIT_HASH_LIST: u32 = 1104;
+const IDM_PERCEPTUAL_HASH: u32 = 1105;
+const IDM_KEEP_HISTORY: u32 = 1106;
+const IDM_CHECK_UPDATES: u32 = 1107;
+const IDM_CHECK_UPDATES_ON_STARTUP: u32 = 1108;
+const IDM_RELOAD_SCRIPTS: u32 = 1109;
+const IDM_FILE_ASSOCIATIONS: u32 = 1110;
+const IDM_EDIT_POLICY: u32 = 1111;
+const IDM_WATCH_NOTIFY: u32 = 1112;
+// OutputEncoding::ALL の順に並べる
+const IDM_EXPORT_ENCODING_FIRST: u32 = 1113;
+const IDM_EXPORT_NEWLINE_CRLF: u32 = 1117;
+const IDM_EXPORT_NEWLINE_LF: u32 = 1118;
+const IDM_TRAY_OPEN: u32 = 1201;
+const IDM_EXIT: u32 = 1202;
+
+unsafe fn get_app_from_window<'a>(hwnd: HWND) -> Option<&'a mut App> {
+    let user_data = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut App;
+    user_data.as_mut()
+}
+
+fn rgb(r: u8, g: u8, b: u8) -> COLORREF {
+    COLORREF(r as u32 | (g as u32) << 8 | (b as u32) << 16)
+}
+
+// ハイコントラストでは色を付けず、太字とリンクだけにする
+fn char_format(style: highlight::Style, palette: &theme::Palette) -> CHARFORMAT2W {
+    use highlight::Style;
+    let mut cf = CHARFORMAT2W::default();
+    cf.Base.cbSize = mem::size_of::<CHARFORMAT2W>() as u32;
+    cf.Base.dwMask = CFM_BOLD | CFM_COLOR;
+    let (bold, color) = match style {
+        Style::Header => (true, palette.accent),
+        Style::JsonKey => (false, Some(rgb(0, 0, 160))),
+        Style::JsonString => (false, Some(rgb(163, 21, 21))),
+        Style::JsonNumber => (false, Some(rgb(9, 134, 88))),
+        Style::JsonLiteral => (false, Some(rgb(128, 0, 128))),
+        Style::ParamKey => (true, Some(palette.accent.unwrap_or(rgb(0, 90, 170)))),
+        Style::Lora => (true, Some(rgb(200, 60, 0))),
+        Style::Embedding => (true, Some(rgb(0, 128, 96))),
+        Style::Attention => (false, Some(rgb(150, 100, 0))),
+        Style::Link => (false, Some(rgb(0, 102, 204))),
+    };
+    if bold {
+        cf.Base.dwEffects = CFE_BOLD;
+    }
+    if style == Style::Link {
+        cf.Base.dwMask |= CFM_LINK;
+        cf.Base.dwEffects |= CFE_LINK;
+    }
+    match color.filter(|_| !palette.high_contrast) {
+        Some(color) => cf.Base.crTextColor = color,
+        None => cf.Base.dwEffects |= CFE_AUTOCOLOR,
+    }
+    cf
+}
+
+// テキストを設定して色付けする
+fn set_edit_text(hedit: HWND, text: &str) {
+    set_edit_text_with_links(hedit, text, &[]);
+}
+
+// links はリンクとして表示する範囲 (format_metadata_with_links を参照)
+fn set_edit_text_with_links(hedit: HWND, text: &str, links: &[(Range<usize>, Link)]) {
+    unsafe { SendMessageW(hedit, WM_SETREDRAW, WPARAM(0), LPARAM(0)) };
+    unsafe { SetWindowTextW(hedit, &HSTRING::from(text)) };
+
+    let mut cf = CHARFORMAT2W::default();
+    cf.Base.cbSize = mem::size_of::<CHARFORMAT2W>() as u32;
+    cf.Base.dwMask = CFM_BOLD | CFM_COLOR;
+    cf.Base.dwEffects = CFE_AUTOCOLOR;
+    unsafe { SendMessageW(hedit, EM_SETCHARFORMAT, WPARAM(SCF_ALL as usize), LPARAM(&cf as *const _ as isize)) };
+
+    let palette = theme::Palette::current();
+    let links = links.iter().map(|(link, _)| highlight::Span { start: link.start, end: link.end, style: highlight::Style::Link });
+    for span in highlight::highlight(text).into_iter().chain(links) {
+        let range = CHARRANGE { cpMin: span.start as i32, cpMax: span.end as i32 };
+        let cf = char_format(span.style, &palette);
+        unsafe { SendMessageW(hedit, EM_EXSETSEL, WPARAM(0), LPARAM(&range as *const _ as isize)) };
+        unsafe { SendMessageW(hedit, EM_SETCHARFORMAT, WPARAM(SCF_SELECTION as usize), LPARAM(&cf as *const _ as isize)) };
+    }
+    let range = CHARRANGE { cpMin: 0, cpMax: 0 };
+    unsafe { SendMessageW(hedit, EM_EXSETSEL, WPARAM(0), LPARAM(&range as *const _ as isize)) };
+
+    unsafe { SendMessageW(hedit, WM_SETREDRAW, WPARAM(1), LPARAM(0)) };
+    unsafe { InvalidateRect(hedit, None, true) };
+}
+
+const APP_TITLE: &str = "MetaView";
+
+fn update_title(hwnd: HWND, filename: Option<&OsStr>) {
+    let title = match filename {
+        Some(filename) => {
+            let name = Path::new(filename).file_name().unwrap_or(filename);
+            format!("{} — {APP_TITLE}", name.to_string_lossy())
+        }
+        None => APP_TITLE.to_owned(),
+    };
+    unsafe { SetWindowTextW(hwnd, &HSTRING::from(title)) };
+}
+
+fn window_text(hwnd: HWND) -> String {
+    let len = unsafe { GetWindowTextLengthW(hwnd) } as usize;
+    let mut buf = vec![0u16; len + 1];
+    let len = unsafe { GetWindowTextW(hwnd, &mut buf) } as usize;
+    String::from_utf16_lossy(&buf[..len])
+}
+
+fn icon_sizes() -> (u32, u32) {
+    (unsafe { GetSystemMetrics(SM_CXSMICON) } as u32, unsafe { GetSystemMetrics(SM_CXICON) } as u32)
+}
+
+// ウィンドウアイコンを読み込んだ画像のサムネイル (preview::start_decode で縮小したもの) にする。None なら既定のアイコンに戻す
+fn update_icon(hwnd: HWND, app: &mut App, bitmaps: Option<&(imaging::Bitmap, imaging::Bitmap)>) {
+    let (small_size, big_size) = icon_sizes();
+    let icons = bitmaps.map_or((HICON(0), HICON(0)), |(small, big)| {
+        (imaging::create_icon(small, small_size).unwrap_or_default(), imaging::create_icon(big, big_size).unwrap_or_default())
+    });
+    unsafe { SendMessageW(hwnd, WM_SETICON, WPARAM(ICON_SMALL as usize), LPARAM(icons.0.0)) };
+    unsafe { SendMessageW(hwnd, WM_SETICON, WPARAM(ICON_BIG as usize), LPARAM(icons.1.0)) };
+    for old in [app.icons.0, app.icons.1] {
+        if !old.is_invalid() {
+            unsafe { DestroyIcon(old) };
+        }
+    }
+    app.icons = icons;
+}
+
+// ステータスバーの各パーツの右端の位置。最後は文字の倍率
+const STATUS_PARTS: [i32; 6] = [360, 480, 600, 700, 820, -1];
+const STATUS_PART_ZOOM: usize = 5;
+
+fn update_status_bar(hstatus: HWND, metadata: Option<&ImageMetadata>) {
+    let texts = match metadata {
+        Some(m) => {
+            let name = Path::new(&m.filename).file_name().unwrap_or(&m.filename);
+            [
+                name.to_string_lossy().into_owned(),
+                format!("{} {}", m.file_size, tr(Msg::StatusBytes)),
+                format!("{} x {}", m.width, m.height),
+                format!("{} {}", m.bit_depth, tr(Msg::StatusBits)),
+                format!("{} {}", m.text_chunks.len(), tr(Msg::StatusChunks)),
+            ]
+        }
+        None => Default::default(),
+    };
+    for (i, text) in texts.iter().enumerate() {
+        set_status_text(hstatus, i, text);
+    }
+
+    // 位置情報が入っていればファイル名の横に警告アイコンを出す
+    let has_gps = metadata.is_some_and(|m| m.gps.is_some());
+    let icon = if has_gps {
+        let size = unsafe { GetSystemMetrics(SM_CXSMICON) };
+        unsafe { LoadImageW(None, PCWSTR(IDI_WARNING as usize as *const u16), IMAGE_ICON, size, size, LR_SHARED) }.unwrap_or_default()
+    } else {
+        HANDLE(0)
+    };
+    let tip = HSTRING::from(if has_gps { tr(Msg::LocationEmbedded) } else { "" });
+    unsafe { SendMessageW(hstatus, SB_SETICON, WPARAM(0), LPARAM(icon.0)) };
+    unsafe { SendMessageW(hstatus, SB_SETTIPTEXTW, WPARAM(0), LPARAM(tip.as_ptr() as isize)) };
+}
+
+fn set_accessible_names(app: &App) {
+    let _ = accessibility::set_name(app.hedit, tr(Msg::AccessibleMetadata));
+    let _ = accessibility::set_name(app.hthumbnail, tr(Msg::AccessibleThumbnail));
+    let _ = accessibility::set_name(app.hpreview, tr(Msg::AccessiblePreview));
+    let _ = accessibility::set_name(app.gallery.hwnd, tr(Msg::AccessibleGallery));
+}
+
+// Ctrl++ と Ctrl+- はプレビューにフォーカスがあれば画像に、そうでなければ表示欄の文字に使う
+// メニューと Ctrl+ホイールはいつも表示欄の文字 (zoom_text) にする
+fn zoom_focused(app: &mut App, steps: i32) {
+    if unsafe { GetFocus() } == app.hpreview {
+        preview::zoom(app.hpreview, steps as f64);
+        return;
+    }
+    zoom_text(app, steps);
+}
+
+fn zoom_text(app: &mut App, steps: i32) {
+    set_text_zoom(app, text_zoom::step(app.text_zoom, steps));
+}
+
+fn set_text_zoom(app: &mut App, percent: u32) {
+    app.text_zoom = percent;
+    text_zoom::apply(app.hedit, percent);
+    let text = format!("{percent}%");
+    set_status_text(app.hstatus, STATUS_PART_ZOOM, &text);
+    accessibility::announce(app.hstatus, &format!("{}: {text}", tr(Msg::TextZoom)));
+}
+
+fn set_status_text(hstatus: HWND, part: usize, text: &str) {
+    let text = HSTRING::from(text);
+    unsafe { SendMessageW(hstatus, SB_SETTEXTW, WPARAM(part), LPARAM(text.as_ptr() as isize)) };
+}
+
+fn create_menu(app: &mut App) -> anyhow::Result<HMENU> {
+    let settings = &app.settings;
+    let menu = unsafe { CreateMenu() }?;
+    let file_menu = unsafe { CreatePopupMenu() }?;
+    let index_menu = unsafe { CreatePopupMenu() }?;
+    let edit_menu = unsafe { CreatePopupMenu() }?;
+    let view_menu = unsafe { CreatePopupMenu() }?;
+    let filter_menu = unsafe { CreatePopupMenu() }?;
+    let encoding_menu = unsafe { CreatePopupMenu() }?;
+    let settings_menu = unsafe { CreatePopupMenu() }?;
+    let language_menu = unsafe { CreatePopupMenu() }?;
+    let export_encoding_menu = unsafe { CreatePopupMenu() }?;
+    unsafe {
+        AppendMenuW(file_menu, MF_STRING, IDM_OPEN as usize, &HSTRING::from(tr(Msg::MenuOpen)));
+        let watch_flags = if app.watcher.is_some() { MF_STRING | MF_CHECKED } else { MF_STRING };
+        AppendMenuW(file_menu, watch_flags, IDM_WATCH_FOLDER as usize, &HSTRING::from(tr(Msg::MenuWatchFolder)));
+        AppendMenuW(file_menu, MF_SEPARATOR, 0, None);
+        let file_flags = if current_file(app).is_some() { MF_STRING } else { MF_STRING | MF_GRAYED };
+        AppendMenuW(file_menu, file_flags, IDM_OPEN_IN_VIEWER as usize, &HSTRING::from(tr(Msg::MenuOpenInViewer)));
+        AppendMenuW(file_menu, file_flags, IDM_SHOW_IN_EXPLORER as usize, &HSTRING::from(tr(Msg::MenuShowInExplorer)));
+        AppendMenuW(file_menu, file_flags, IDM_MOVE_TO_RECYCLE_BIN as usize, &HSTRING::from(tr(Msg::MenuMoveToRecycleBin)));
+        AppendMenuW(file_menu, MF_SEPARATOR, 0, None);
+        AppendMenuW(file_menu, MF_STRING, IDM_SAVE_CLEAN_COPY as usize, &HSTRING::from(tr(Msg::MenuSaveCleanCopy)));
+        AppendMenuW(file_menu, MF_STRING, IDM_BATCH_STRIP as usize, &HSTRING::from(tr(Msg::MenuBatchStrip)));
+        AppendMenuW(file_menu, MF_STRING, IDM_FOLDER_STATS as usize, &HSTRING::from(tr(Msg::MenuFolderStats)));
+        AppendMenuW(file_menu, MF_STRING, IDM_FIND_DUPLICATES as usize, &HSTRING::from(tr(Msg::MenuFindDuplicates)));
+        AppendMenuW(file_menu, MF_STRING, IDM_SEARCH_FOLDER as usize, &HSTRING::from(tr(Msg::MenuSearchFolder)));
+        AppendMenuW(index_menu, MF_STRING, IDM_INDEX_SEARCH as usize, &HSTRING::from(tr(Msg::MenuIndexSearch)));
+        AppendMenuW(index_menu, MF_SEPARATOR, 0, None);
+        AppendMenuW(index_menu, MF_STRING, IDM_INDEX_ADD_FOLDER as usize, &HSTRING::from(tr(Msg::MenuIndexAddFolder)));
+        AppendMenuW(index_menu, MF_STRING, IDM_INDEX_UPDATE as usize, &HSTRING::from(tr(Msg::MenuIndexUpdate)));
+        AppendMenuW(file_menu, MF_POPUP, index_menu.0 as usize, &HSTRING::from(tr(Msg::MenuIndex)));
+        AppendMenuW(file_menu, MF_SEPARATOR, 0, None);
+        AppendMenuW(file_menu, MF_STRING, IDM_PRINT as usize, &HSTRING::from(tr(Msg::MenuPrint)));
+        AppendMenuW(file_menu, MF_SEPARATOR, 0, None);
+        let map_flags = if app.current.as_ref().is_some_and(|m| m.gps.is_some()) { MF_STRING } else { MF_STRING | MF_GRAYED };
+        AppendMenuW(file_menu, map_flags, IDM_OPEN_MAP as usize, &HSTRING::from(tr(Msg::MenuOpenMap)));
+        let thumbnail_flags = if app.current.as_ref().is_some_and(|m| m.thumbnail.is_some()) { MF_STRING } else { MF_STRING | MF_GRAYED };
+        AppendMenuW(file_menu, thumbnail_flags, IDM_SAVE_THUMBNAIL as usize, &HSTRING::from(tr(Msg::MenuSaveThumbnail)));
+        let video_flags = if app.current.as_ref().is_some_and(|m| m.motion_photo.is_some()) { MF_STRING } else { MF_STRING | MF_GRAYED };
+        AppendMenuW(file_menu, video_flags, IDM_SAVE_MOTION_PHOTO_VIDEO as usize, &HSTRING::from(tr(Msg::MenuSaveMotionPhotoVideo)));
+        let workflow_flags = if workflow_chunks(app).is_empty() { MF_STRING | MF_GRAYED } else { MF_STRING };
+        AppendMenuW(file_menu, workflow_flags, IDM_SAVE_WORKFLOW as usize, &HSTRING::from(tr(Msg::MenuSaveWorkflow)));
+        let text_flags = if app.current.is_some() { MF_STRING } else { MF_STRING | MF_GRAYED };
+        AppendMenuW(file_menu, text_flags, IDM_SAVE_METADATA_TEXT as usize, &HSTRING::from(tr(Msg::MenuSaveMetadataText)));
+        AppendMenuW(file_menu, MF_SEPARATOR, 0, None);
+        let exiftool_flags = if app.current.is_some() { MF_STRING } else { MF_STRING | MF_GRAYED };
+        AppendMenuW(file_menu, exiftool_flags, IDM_EXPORT_EXIFTOOL_JSON as usize, &HSTRING::from(tr(Msg::MenuExportExiftoolJson)));
+        AppendMenuW(file_menu, exiftool_flags, IDM_COMPARE_EXIFTOOL_JSON as usize, &HSTRING::from(tr(Msg::MenuCompareExiftoolJson)));
+        AppendMenuW(file_menu, MF_SEPARATOR, 0, None);
+        let policy_flags = if app.current.is_some() { MF_STRING } else { MF_STRING | MF_GRAYED };
+        AppendMenuW(file_menu, policy_flags, IDM_CHECK_POLICY as usize, &HSTRING::from(tr(Msg::MenuCheckPolicy)));
+        AppendMenuW(file_menu, MF_STRING, IDM_CHECK_FOLDER_POLICY as usize, &HSTRING::from(tr(Msg::MenuCheckFolderPolicy)));
+        AppendMenuW(menu, MF_POPUP, file_menu.0 as usize, &HSTRING::from(tr(Msg::MenuFile)));
+        AppendMenuW(edit_menu, MF_STRING, IDM_PASTE as usize, &HSTRING::from(tr(Msg::MenuPaste)));
+        AppendMenuW(edit_menu, MF_STRING, IDM_COPY_MARKDOWN as usize, &HSTRING::from(tr(Msg::MenuCopyMarkdown)));
+        let infotext_flags = if current_infotext(app).is_some() { MF_STRING } else { MF_STRING | MF_GRAYED };
+        AppendMenuW(edit_menu, infotext_flags, IDM_COPY_INFOTEXT as usize, &HSTRING::from(tr(Msg::MenuCopyInfotext)));
+        AppendMenuW(edit_menu, MF_SEPARATOR, 0, None);
+        let editing_flags = if app.editing { MF_STRING | MF_CHECKED } else { MF_STRING };
+        AppendMenuW(edit_menu, editing_flags, IDM_ENABLE_EDITING as usize, &HSTRING::from(tr(Msg::MenuEnableEditing)));
+        AppendMenuW(edit_menu, MF_STRING, IDM_EDIT_CHUNK as usize, &HSTRING::from(tr(Msg::MenuEditChunk)));
+        AppendMenuW(edit_menu, MF_STRING, IDM_ADD_CHUNK as usize, &HSTRING::from(tr(Msg::MenuAddChunk)));
+        AppendMenuW(menu, MF_POPUP, edit_menu.0 as usize, &HSTRING::from(tr(Msg::MenuEdit)));
+        AppendMenuW(view_menu, MF_POPUP, filter_menu.0 as usize, &HSTRING::from(tr(Msg::MenuFilter)));
+        AppendMenuW(view_menu, MF_POPUP, encoding_menu.0 as usize, &HSTRING::from(tr(Msg::MenuEncoding)));
+        AppendMenuW(view_menu, MF_SEPARATOR, 0, None);
+        let size_flags = if app.current.is_some() { MF_STRING } else { MF_STRING | MF_GRAYED };
+        AppendMenuW(view_menu, size_flags, IDM_SIZE_BREAKDOWN as usize, &HSTRING::from(tr(Msg::MenuSizeBreakdown)));
+        AppendMenuW(view_menu, size_flags, IDM_HEX_VIEW as usize, &HSTRING::from(tr(Msg::MenuHexView)));
+        AppendMenuW(view_menu, MF_STRING, IDM_HISTORY as usize, &HSTRING::from(tr(Msg::MenuHistory)));
+        let redact_flags = if settings.redact { MF_STRING | MF_CHECKED } else { MF_STRING };
+        AppendMenuW(view_menu, redact_flags, IDM_REDACT as usize, &HSTRING::from(tr(Msg::MenuRedact)));
+        let accent_flags = if settings.accent_colors { MF_STRING | MF_CHECKED } else { MF_STRING };
+        AppendMenuW(view_menu, accent_flags, IDM_ACCENT_COLORS as usize, &HSTRING::from(tr(Msg::MenuAccentColors)));
+        AppendMenuW(view_menu, MF_SEPARATOR, 0, None);
+        AppendMenuW(view_menu, MF_STRING, IDM_TEXT_ZOOM_IN as usize, &HSTRING::from(tr(Msg::MenuTextZoomIn)));
+        AppendMenuW(view_menu, MF_STRING, IDM_TEXT_ZOOM_OUT as usize, &HSTRING::from(tr(Msg::MenuTextZoomOut)));
+        AppendMenuW(view_menu, MF_STRING, IDM_TEXT_ZOOM_RESET as usize, &HSTRING::from(tr(Msg::MenuTextZoomReset)));
+        AppendMenuW(view_menu, MF_SEPARATOR, 0, None);
+        let preview_flags = if settings.show_preview { MF_STRING | MF_CHECKED } else { MF_STRING };
+        AppendMenuW(view_menu, preview_flags, IDM_SHOW_PREVIEW as usize, &HSTRING::from(tr(Msg::MenuShowPreview)));
+        let gallery_flags = match (app.gallery.is_empty(), app.show_gallery) {
+            (true, _) => MF_STRING | MF_GRAYED,
+            (false, true) => MF_STRING | MF_CHECKED,
+            (false, false) => MF_STRING,
+        };
+        AppendMenuW(view_menu, gallery_flags, IDM_SHOW_GALLERY as usize, &HSTRING::from(tr(Msg::MenuShowGallery)));
+        let zoom_flags = if settings.show_preview { MF_STRING } else { MF_STRING | MF_GRAYED };
+        AppendMenuW(view_menu, zoom_flags, IDM_ZOOM_FIT as usize, &HSTRING::from(tr(Msg::MenuZoomFit)));
+        AppendMenuW(view_menu, zoom_flags, IDM_ZOOM_ACTUAL as usize, &HSTRING::from(tr(Msg::MenuZoomActual)));
+        AppendMenuW(view_menu, zoom_flags, IDM_ZOOM_IN as usize, &HSTRING::from(tr(Msg::MenuZoomIn)));
+        AppendMenuW(view_menu, zoom_flags, IDM_ZOOM_OUT as usize, &HSTRING::from(tr(Msg::MenuZoomOut)));
+        AppendMenuW(menu, MF_POPUP, view_menu.0 as usize, &HSTRING::from(tr(Msg::MenuView)));
+        AppendMenuW(language_menu, MF_STRING, IDM_LANGUAGE_AUTO as usize, &HSTRING::from(tr(Msg::MenuLanguageAuto)));
+        AppendMenuW(language_menu, MF_STRING, IDM_LANGUAGE_JAPANESE as usize, w!("日本語"));
+        AppendMenuW(language_menu, MF_STRING, IDM_LANGUAGE_ENGLISH as usize, w!("English"));
+        AppendMenuW(settings_menu, MF_POPUP, language_menu.0 as usize, &HSTRING::from(tr(Msg::MenuLanguage)));
+        AppendMenuW(settings_menu, MF_SEPARATOR, 0, None);
+        let tray_flags = if settings.minimize_to_tray { MF_STRING | MF_CHECKED } else { MF_STRING };
+        AppendMenuW(settings_menu, tray_flags, IDM_MINIMIZE_TO_TRAY as usize, &HSTRING::from(tr(Msg::MenuMinimizeToTray)));
+        let notify_flags = if settings.watch_notify { MF_STRING | MF_CHECKED } else { MF_STRING };
+        AppendMenuW(settings_menu, notify_flags, IDM_WATCH_NOTIFY as usize, &HSTRING::from(tr(Msg::MenuWatchNotify)));
+        let hotkey = match settings.hotkey {
+            Some(hotkey) => format!("{} ({hotkey})…", tr(Msg::MenuHotkey)),
+            None => format!("{}…", tr(Msg::MenuHotkey)),
+        };
+        AppendMenuW(settings_menu, MF_STRING, IDM_HOTKEY as usize, &HSTRING::from(hotkey));
+        AppendMenuW(settings_menu, MF_SEPARATOR, 0, None);
+        let civitai_flags = if settings.civitai_lookup { MF_STRING | MF_CHECKED } else { MF_STRING };
+        AppendMenuW(settings_menu, civitai_flags, IDM_CIVITAI_LOOKUP as usize, &HSTRING::from(tr(Msg::MenuCivitaiLookup)));
+        AppendMenuW(settings_menu, MF_STRING, IDM_EDIT_HASH_LIST as usize, &HSTRING::from(tr(Msg::MenuEditHashList)));
+        AppendMenuW(settings_menu, MF_STRING, IDM_EDIT_POLICY as usize, &HSTRING::from(tr(Msg::MenuEditPolicy)));
+        let phash_flags = if settings.perceptual_hash { MF_STRING | MF_CHECKED } else { MF_STRING };
+        AppendMenuW(settings_menu, phash_flags, IDM_PERCEPTUAL_HASH as usize, &HSTRING::from(tr(Msg::MenuPerceptualHash)));
+        let history_flags = if settings.keep_history { MF_STRING | MF_CHECKED } else { MF_STRING };
+        AppendMenuW(settings_menu, history_flags, IDM_KEEP_HISTORY as usize, &HSTRING::from(tr(Msg::MenuKeepHistory)));
+        AppendMenuW(settings_menu, MF_STRING, IDM_RELOAD_SCRIPTS as usize, &HSTRING::from(tr(Msg::MenuReloadScripts)));
+        AppendMenuW(settings_menu, MF_STRING, IDM_FILE_ASSOCIATIONS as usize, &HSTRING::from(tr(Msg::MenuFileAssociations)));
+        for (i, encoding) in OutputEncoding::ALL.iter().enumerate() {
+            AppendMenuW(export_encoding_menu, MF_STRING, IDM_EXPORT_ENCODING_FIRST as usize + i, &HSTRING::from(encoding.name()));
+        }
+        AppendMenuW(export_encoding_menu, MF_SEPARATOR, 0, None);
+        AppendMenuW(export_encoding_menu, MF_STRING, IDM_EXPORT_NEWLINE_CRLF as usize, &HSTRING::from(tr(Msg::MenuNewlineCrLf)));
+        AppendMenuW(export_encoding_menu, MF_STRING, IDM_EXPORT_NEWLINE_LF as usize, &HSTRING::from(tr(Msg::MenuNewlineLf)));
+        AppendMenuW(settings_menu, MF_POPUP, export_encoding_menu.0 as usize, &HSTRING::from(tr(Msg::MenuExportEncoding)));
+        AppendMenuW(settings_menu, MF_SEPARATOR, 0, None);
+        AppendMenuW(settings_menu, MF_STRING, IDM_CHECK_UPDATES as usize, &HSTRING::from(tr(Msg::MenuCheckUpdates)));
+        let update_flags = if settings.check_updates { MF_STRING | MF_CHECKED } else { MF_STRING };
+        AppendMenuW(settings_menu, update_flags, IDM_CHECK_UPDATES_ON_STARTUP as usize, &HSTRING::from(tr(Msg::MenuCheckUpdatesOnStartup)));
+        AppendMenuW(menu, MF_POPUP, settings_menu.0 as usize, &HSTRING::from(tr(Msg::MenuSettings)));
+    }
+    let checked = match settings.language {
+        None => IDM_LANGUAGE_AUTO,
+        Some(Language::Japanese) => IDM_LANGUAGE_JAPANESE,
+        Some(Language::English) => IDM_LANGUAGE_ENGLISH,
+    };
+    unsafe { CheckMenuRadioItem(language_menu, IDM_LANGUAGE_AUTO, IDM_LANGUAGE_ENGLISH, checked, MF_BYCOMMAND.0) };
+    let position = OutputEncoding::ALL.iter().position(|e| *e == settings.export_encoding).unwrap_or(0) as u32;
+    let last = IDM_EXPORT_ENCODING_FIRST + OutputEncoding::ALL.len() as u32 - 1;
+    unsafe { CheckMenuRadioItem(export_encoding_menu, IDM_EXPORT_ENCODING_FIRST, last, IDM_EXPORT_ENCODING_FIRST + position, MF_BYCOMMAND.0) };
+    let checked = match settings.export_newline {
+        Newline::CrLf => IDM_EXPORT_NEWLINE_CRLF,
+        Newline::Lf => IDM_EXPORT_NEWLINE_LF,
+    };
+    unsafe { CheckMenuRadioItem(export_encoding_menu, IDM_EXPORT_NEWLINE_CRLF, IDM_EXPORT_NEWLINE_LF, checked, MF_BYCOMMAND.0) };
+    app.filter_menu = filter_menu;
+    app.encoding_menu = encoding_menu;
+    Ok(menu)
+}
+
+// フィルターメニューを開くたびに、今のファイルのキーワードと隠しているキーワードで作り直す
+fn fill_filter_menu(app: &mut App) {
+    let menu = app.filter_menu;
+    while unsafe { GetMenuItemCount(menu) } > 0 {
+        unsafe { DeleteMenu(menu, 0, MF_BYPOSITION) };
+    }
+    let mut keywords: Vec<String> = Vec::new();
+    let current = app.current.iter().flat_map(|m| m.text_chunks.iter().map(|(k, _)| k));
+    for keyword in current.chain(app.settings.filter.hidden_keywords.iter()) {
+        if !keywords.contains(keyword) {
+            keywords.push(keyword.clone());
+        }
+    }
+    keywords.truncate((IDM_FILTER_KEYWORD_LAST - IDM_FILTER_KEYWORD_FIRST) as usize);
+
+    let filter = &app.settings.filter;
+    let checked = |b: bool| if b { MF_CHECKED } else { MF_UNCHECKED };
+    unsafe {
+        for (i, keyword) in keywords.iter().enumerate() {
+            let flags = MF_STRING | checked(!filter.is_hidden(keyword));
+            AppendMenuW(menu, flags, IDM_FILTER_KEYWORD_FIRST as usize + i, &HSTRING::from(keyword.as_str()));
+        }
+        if !keywords.is_empty() {
+            AppendMenuW(menu, MF_SEPARATOR, 0, None);
+        }
+        AppendMenuW(menu, MF_STRING | checked(filter.hide_binary), IDM_HIDE_BINARY_CHUNKS as usize, &HSTRING::from(tr(Msg::MenuHideBinaryChunks)));
+        AppendMenuW(menu, MF_STRING, IDM_SHOW_ALL_CHUNKS as usize, &HSTRING::from(tr(Msg::MenuShowAllChunks)));
+    }
+    app.filter_keywords = keywords;
+}
+
+// 文字コードメニューを開くたびに、今のファイルで推定した文字コードを表示し直す
+fn fill_encoding_menu(app: &App) {
+    let menu = app.encoding_menu;
+    while unsafe { GetMenuItemCount(menu) } > 0 {
+        unsafe { DeleteMenu(menu, 0, MF_BYPOSITION) };
+    }
+    let current = app.current.as_ref();
+    let detected = current.filter(|m| m.encoding_override.is_none()).and_then(|m| m.text_encoding);
+    let auto = match detected {
+        Some(encoding) => format!("{} ({})", tr(Msg::MenuEncodingAuto), encoding.name()),
+        None => tr(Msg::MenuEncodingAuto).to_owned(),
+    };
+    let flags = if current.is_some() { MF_STRING } else { MF_STRING | MF_GRAYED };
+    unsafe {
+        AppendMenuW(menu, flags, IDM_ENCODING_AUTO as usize, &HSTRING::from(auto));
+        AppendMenuW(menu, MF_SEPARATOR, 0, None);
+        for (i, encoding) in TextEncoding::ALL.iter().enumerate() {
+            AppendMenuW(menu, flags, IDM_ENCODING_FIRST as usize + i, &HSTRING::from(encoding.name()));
+        }
+    }
+    let checked = match current.and_then(|m| m.encoding_override) {
+        Some(encoding) => IDM_ENCODING_FIRST + TextEncoding::ALL.iter().position(|e| *e == encoding).unwrap_or(0) as u32,
+        None => IDM_ENCODING_AUTO,
+    };
+    let last = IDM_ENCODING_FIRST + TextEncoding::ALL.len() as u32 - 1;
+    unsafe { CheckMenuRadioItem(menu, IDM_ENCODING_AUTO, last, checked, MF_BYCOMMAND.0) };
+}
+
+// 今のファイルの tEXt チャンクを指定した文字コードで読み直す。None なら自動判定に戻す
+fn reinterpret(hwnd: HWND, app: &mut App, encoding: Option<TextEncoding>) {
+    let Some(current) = app.current.take() else {
+        return;
+    };
+    let path = current.path;
+    let result = metadata::parse_metadata_as(current.filename, current.data, encoding)
+        .map(|metadata| ImageMetadata { path, ..metadata });
+    show_result(hwnd, app, result);
+}
+
+fn change_filter(app: &mut App, f: impl FnOnce(&mut settings::ChunkFilter)) {
+    f(&mut app.settings.filter);
+    let _ = app.settings.save();
+    refresh_view(app);
+}
+
+fn rebuild_menu(hwnd: HWND, app: &mut App) {
+    if let Ok(menu) = create_menu(app) {
+        let old_menu = unsafe { GetMenu(hwnd) };
+        unsafe { SetMenu(hwnd, menu) };
+        unsafe { DestroyMenu(old_menu) };
+    }
+}
+
+// 表示欄への書き込みを許すかどうか。書き込んだ内容はファイルには保存されない
+fn toggle_editing(hwnd: HWND, app: &mut App) {
+    app.editing = !app.editing;
+    unsafe { SendMessageW(app.hedit, EM_SETREADONLY, WPARAM(!app.editing as usize), LPARAM(0)) };
+    rebuild_menu(hwnd, app);
+}
+
+fn toggle_preview(hwnd: HWND, app: &mut App) {
+    app.settings.show_preview = !app.settings.show_preview;
+    let _ = app.settings.save();
+    rebuild_menu(hwnd, app);
+    start_decode(hwnd, app, false);
+    unsafe { ShowWindow(app.hpreview, if app.settings.show_preview { SW_SHOWNA } else { SW_HIDE }) };
+    layout(hwnd, app);
+}
+
+// 見出しとパラメーター名をシステムのアクセントカラーで色付けする (ハイコントラストでは使わない)
+fn toggle_accent_colors(hwnd: HWND, app: &mut App) {
+    app.settings.accent_colors = !app.settings.accent_colors;
+    theme::set_accent_colors(app.settings.accent_colors);
+    let _ = app.settings.save();
+    rebuild_menu(hwnd, app);
+    refresh_view(app);
+}
+
+// 伏せ字モード。表示だけでなくコピーや書き出しにも使う
+fn toggle_redact(hwnd: HWND, app: &mut App) {
+    app.settings.redact = !app.settings.redact;
+    let _ = app.settings.save();
+    rebuild_menu(hwnd, app);
+    refresh_view(app);
+}
+
+fn set_gallery_visible(hwnd: HWND, app: &mut App, visible: bool) {
+    app.show_gallery = visible && !app.gallery.is_empty();
+    rebuild_menu(hwnd, app);
+    unsafe { ShowWindow(app.gallery.hwnd, if app.show_gallery { SW_SHOWNA } else { SW_HIDE }) };
+    layout(hwnd, app);
+}
+
+// 画像を開いたら、プレビュー (欄を表示しているときだけ)・透かし・ウィンドウアイコンを別のスレッドで作る
+// loaded でなければ (プレビュー欄を表示しただけなら) プレビューだけ作る。できるまではプレビューを空にしておく
+fn start_decode(hwnd: HWND, app: &mut App, loaded: bool) {
+    if loaded {
+        app.decode_job += 1;
+    }
+    preview::set_image(app.hpreview, None);
+    let Some(m) = &app.current else { return };
+    // 等倍でデコードするとメモリの上限を超える画像は透かしを調べない (縮小すると透かしが読めなくなる)
+    let full_size = m.width as u64 * m.height as u64 * 4 <= imaging::max_decode_size() as u64;
+    let request = preview::DecodeRequest {
+        data: m.data.clone(),
+        path: m.path.clone(),
+        orientation: m.orientation.unwrap_or(1),
+        preview: app.settings.show_preview,
+        watermarks: (loaded && full_size).then_some((m.width, m.height)),
+        icons: loaded.then(icon_sizes),
+    };
+    if request.preview || loaded {
+        preview::start_decode(hwnd, app.decode_job, request);
+    }
+}
+
+// 透かしは見つかったときだけ表示欄に書き足す
+fn show_decoded(hwnd: HWND, app: &mut App, job: usize, decoded: preview::Decoded) {
+    if job != app.decode_job {
+        return;
+    }
+    if decoded.requested_preview && app.settings.show_preview {
+        preview::set_image(app.hpreview, decoded.preview);
+    }
+    if let Some(icons) = &decoded.icons {
+        update_icon(hwnd, app, Some(icons));
+    }
+    if let Some(watermarks) = decoded.watermarks.filter(|w| !w.is_empty()) {
+        if let Some(metadata) = &mut app.current {
+            metadata.watermarks = watermarks;
+        }
+        refresh_view(app);
+    }
+}
+
+fn toggle_minimize_to_tray(hwnd: HWND, app: &mut App) {
+    app.settings.minimize_to_tray = !app.settings.minimize_to_tray;
+    let _ = app.settings.save();
+    rebuild_menu(hwnd, app);
+}
+
+fn toggle_watch_notify(hwnd: HWND, app: &mut App) {
+    app.settings.watch_notify = !app.settings.watch_notify;
+    let _ = app.settings.save();
+    rebuild_menu(hwnd, app);
+}
+
+fn set_export_encoding(hwnd: HWND, app: &mut App, encoding: OutputEncoding) {
+    app.settings.export_encoding = encoding;
+    let _ = app.settings.save();
+    rebuild_menu(hwnd, app);
+}
+
+fn set_export_newline(hwnd: HWND, app: &mut App, newline: Newline) {
+    app.settings.export_newline = newline;
+    let _ = app.settings.save();
+    rebuild_menu(hwnd, app);
+}
+
+fn change_hotkey(hwnd: HWND, app: &mut App) -> anyhow::Result<()> {
+    let Some(hotkey) = hotkey::show_dialog(hwnd, app.settings.hotkey) else {
+        return Ok(());
+    };
+    if let Err(e) = hotkey::register(hwnd, hotkey) {
+        // 登録できなければ元のホットキーに戻す
+        let _ = hotkey::register(hwnd, app.settings.hotkey);
+        return Err(e);
+    }
+    app.settings.hotkey = hotkey;
+    app.settings.save()?;
+    rebuild_menu(hwnd, app);
+    Ok(())
+}
+
+fn toggle_civitai_lookup(hwnd: HWND, app: &mut App) {
+    app.settings.civitai_lookup = !app.settings.civitai_lookup;
+    let _ = app.settings.save();
+    rebuild_menu(hwnd, app);
+    lookup_hashes(hwnd, app);
+}
+
+fn toggle_perceptual_hash(hwnd: HWND, app: &mut App) {
+    app.settings.perceptual_hash = !app.settings.perceptual_hash;
+    let _ = app.settings.save();
+    rebuild_menu(hwnd, app);
+    compute_digests(hwnd, app);
+}
+
+// 開いているファイルのハッシュを計算し直す。前の計算の結果は届いても捨てる
+// 有効にしたときは、それまでにこのセッションで開いた分も残す
+fn toggle_keep_history(hwnd: HWND, app: &mut App) {
+    app.settings.keep_history = !app.settings.keep_history;
+    let _ = app.settings.save();
+    if app.settings.keep_history {
+        for entry in &app.history {
+            let _ = history::append(entry);
+        }
+    }
+    rebuild_menu(hwnd, app);
+}
+
+fn record_history(app: &mut App, metadata: &ImageMetadata) {
+    let entry = history::HistoryEntry::new(metadata);
+    if app.settings.keep_history {
+        let _ = history::append(&entry);
+    }
+    app.history.push(entry);
+}
+
+fn show_history(hwnd: HWND, app: &mut App) {
+    let result = history_view::show(hwnd, &app.history, app.settings.export_encoding, app.settings.export_newline);
+    if result.cleared {
+        app.history.clear();
+        if let Err(e) = history::clear() {
+            show_error(hwnd, &e);
+        }
+    }
+    if let Some(path) = result.open {
+        open_source(hwnd, Ok(Source::File(path.into_os_string())));
+    }
+}
+
+fn start_watching(hwnd: HWND, app: &mut App, folder: PathBuf) -> anyhow::Result<()> {
+    // 前の監視を止めてから始める
+    app.watcher = None;
+    let watcher = watcher::Watcher::start(hwnd, folder)?;
+    let text = format!("{}: {}", tr(Msg::WatchingFolder), watcher.folder.display());
+    set_status_text(app.hstatus, 0, &text);
+    accessibility::announce(app.hstatus, &text);
+    app.watcher = Some(watcher);
+    Ok(())
+}
+
+// 監視中なら止め、そうでなければフォルダーを選んで始める
+fn toggle_watch_folder(hwnd: HWND, app: &mut App) {
+    if app.watcher.take().is_some() {
+        app.settings.watch_folder = None;
+    } else {
+        match pick_folder(hwnd, Msg::WatchFolder) {
+            Ok(Some(folder)) => match start_watching(hwnd, app, folder.clone()) {
+                Ok(()) => app.settings.watch_folder = Some(folder),
+                Err(e) => show_error(hwnd, &e),
+            },
+            Ok(None) => return,
+            Err(e) => {
+                show_error(hwnd, &e);
+                return;
+            }
+        }
+    }
+    let _ = app.settings.save();
+    rebuild_menu(hwnd, app);
+}
+
+fn toggle_check_updates(hwnd: HWND, app: &mut App) {
+    app.settings.check_updates = !app.settings.check_updates;
+    let _ = app.settings.save();
+    rebuild_menu(hwnd, app);
+}
+
+// 起動時に調べたときは、新しいバージョンがあるときだけ知らせる
+fn show_update_result(hwnd: HWND, manual: bool, result: anyhow::Result<Option<update::Release>>) {
+    match result {
+        Ok(Some(release)) => {
+            let text = format!("{}\r\n\r\n{}: {}\r\n{}: {}\r\n\r\n{}",
+                tr(Msg::UpdateAvailable),
+                tr(Msg::CurrentVersion), update::CURRENT_VERSION,
+                tr(Msg::LatestVersion), release.version,
+                tr(Msg::OpenDownloadPage));
+            let answer = unsafe { MessageBoxW(hwnd, &HSTRING::from(text), &HSTRING::from(APP_TITLE), MB_YESNO | MB_ICONINFORMATION) };
+            if answer == IDYES {
+                unsafe { ShellExecuteW(hwnd, w!("open"), &HSTRING::from(release.url), None, None, SW_SHOWNORMAL) };
+            }
+        }
+        Ok(None) if manual => show_message(hwnd, &format!("{} ({})", tr(Msg::UpToDate), update::CURRENT_VERSION)),
+        Err(e) if manual => show_error(hwnd, &e),
+        _ => {}
+    }
+}
+
+// 失敗したスクリプトのエラーは項目として表示する
+fn run_scripts(scripts: &scripts::Scripts, entries: Vec<(String, String)>) -> Vec<(String, String)> {
+    if scripts.is_empty() {
+        return entries;
+    }
+    let (mut entries, errors) = scripts.on_metadata(entries);
+    for (name, e) in errors {
+        entries.push((format!("{} ({name})", tr(Msg::ScriptError)), e.to_string()));
+    }
+    entries
+}
+
+fn show_load_errors(hwnd: HWND, title: Msg, errors: &[(String, anyhow::Error)]) {
+    if !errors.is_empty() {
+        let errors: Vec<String> = errors.iter().map(|(name, e)| format!("{name}: {e}")).collect();
+        show_error(hwnd, &anyhow::anyhow!("{}\r\n{}", tr(title), errors.join("\r\n")));
+    }
+}
+
+// 読み込み直したら今のファイルにも使う
+fn reload_scripts(hwnd: HWND, app: &mut App) {
+    let (scripts, errors) = scripts::Scripts::load();
+    app.scripts = scripts;
+    show_load_errors(hwnd, Msg::ScriptLoadFailed, &errors);
+    if let Some(path) = app.current.as_ref().and_then(|m| m.path.clone()) {
+        show_result(hwnd, app, Source::File(path.into_os_string()).read_metadata());
+    }
+}
+
+fn compute_digests(hwnd: HWND, app: &mut App) {
+    app.digest_job += 1;
+    if let Some(metadata) = &app.current {
+        hashing::start(hwnd, app.digest_job, metadata.data.clone(), app.settings.perceptual_hash);
+    }
+}
+
+fn show_digests(app: &mut App, job: usize, digests: digest::FileDigests) {
+    if job != app.digest_job {
+        return;
+    }
+    if let Some(metadata) = &mut app.current {
+        metadata.digests = Some(digests);
+    }
+    refresh_view(app);
+}
+
+// 手元の一覧やキャッシュで分からなかったハッシュを Civitai に問い合わせる
+fn lookup_hashes(hwnd: HWND, app: &App) {
+    if !app.settings.civitai_lookup {
+        return;
+    }
+    let unresolved = app.current.as_ref().map(|m| hashes::unresolved(&m.model_hashes)).unwrap_or_default();
+    if !unresolved.is_empty() {
+        civitai::start(hwnd, unresolved);
+    }
+}
+
+fn save_civitai_results(app: &mut App, results: Vec<(String, Option<String>)>) {
+    if results.is_empty() {
+        return;
+    }
+    let mut cache = hashes::HashDatabase::load(hashes::civitai_cache_path());
+    for (hash, name) in &results {
+        cache.insert(hash, name.as_deref().unwrap_or(""));
+    }
+    let _ = cache.save(hashes::civitai_cache_path());
+    if let Some(metadata) = &mut app.current {
+        hashes::resolve(&mut metadata.model_hashes);
+    }
+    refresh_view(app);
+}
+
+// ハッシュの一覧をメモ帳などで開く。なければ書き方の説明だけ入れて作る
+fn edit_hash_list(hwnd: HWND) -> anyhow::Result<()> {
+    let path = hashes::local_database_path().ok_or_else(|| anyhow::anyhow!("APPDATA is not set"))?;
+    if !path.exists() {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, format!("{}\r\n", tr(Msg::HashListHeader)))?;
+    }
+    unsafe { ShellExecuteW(hwnd, w!("open"), &HSTRING::from(path.as_os_str()), None, None, SW_SHOWNORMAL) };
+    Ok(())
+}
+
+// 決まりのファイルも同じように開く。なければ書き方の説明と例を入れて作る
+fn edit_policy(hwnd: HWND) -> anyhow::Result<()> {
+    let path = policy::policy_path().ok_or_else(|| anyhow::anyhow!("APPDATA is not set"))?;
+    if !path.exists() {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, format!("{}\r\n", tr(Msg::PolicyHeader)))?;
+    }
+    unsafe { ShellExecuteW(hwnd, w!("open"), &HSTRING::from(path.as_os_str()), None, None, SW_SHOWNORMAL) };
+    Ok(())
+}
+
+fn restore_window(hwnd: HWND, app: &mut App) {
+    if app.in_tray || app.notify_icon {
+        tray::remove(hwnd);
+        app.in_tray = false;
+        app.notify_icon = false;
+    }
+    unsafe { ShowWindow(hwnd, if IsIconic(hwnd).as_bool() { SW_RESTORE } else { SW_SHOW }) };
+    unsafe { SetForegroundWindow(hwnd) };
+}
+
+// ホットキーで呼び出されたら、クリップボードに読めるものがあればそれを、なければ今のファイルを読み直す
+fn load_on_hotkey(hwnd: HWND, app: &mut App) {
+    let data = unsafe { OleGetClipboard() };
+    if let Some(source) = data.ok().and_then(|data| drop_target::read_source(&data)) {
+        open_source(hwnd, source);
+    } else if let Some(path) = app.current.as_ref().and_then(|m| m.path.clone()) {
+        show_result(hwnd, app, Source::File(path.into_os_string()).read_metadata());
+    }
+}
+
+// 監視しているフォルダーの画像を開いたとき、ウィンドウが前面になければファイル名とプロンプトの 1 行目を通知する
+// 同じ画像が書き換えられただけなら通知しない
+fn notify_new_image(hwnd: HWND, app: &mut App, path: PathBuf) {
+    let active = unsafe { GetForegroundWindow() } == hwnd && !unsafe { IsIconic(hwnd) }.as_bool();
+    if !app.settings.watch_notify || active || app.notified.as_ref() == Some(&path) {
+        return;
+    }
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let prompt = app.current.as_ref()
+        .filter(|metadata| metadata.path.as_ref() == Some(&path))
+        .and_then(|metadata| params::find_prompt(&metadata.text_chunks).or_else(|| infotext::to_infotext(&metadata.text_chunks)));
+    // 本文が空だと通知が出ないので、プロンプトがなければフォルダーを出す
+    let text = match prompt.as_deref().and_then(|prompt| prompt.lines().map(str::trim).find(|line| !line.is_empty())) {
+        Some(line) => line.to_owned(),
+        None => path.parent().map(|dir| dir.display().to_string()).unwrap_or_default(),
+    };
+    let add = !app.in_tray && !app.notify_icon;
+    tray::notify(hwnd, app.icons.0, &window_text(hwnd), &name, &text, add);
+    app.notify_icon |= add;
+    app.notified = Some(path);
+}
+
+// 通知がクリックされたら、ウィンドウを戻して通知した画像を開く (その後に別の画像を開いていれば開き直す)
+fn open_notified(hwnd: HWND, app: &mut App) {
+    restore_window(hwnd, app);
+    let Some(path) = app.notified.clone() else {
+        return;
+    };
+    if app.current.as_ref().and_then(|metadata| metadata.path.as_ref()) != Some(&path) {
+        open_source(hwnd, Ok(Source::File(path.into_os_string())));
+    }
+}
+
+// 通知が閉じられたら、通知のためだけに置いたアイコンを消す
+fn remove_notify_icon(hwnd: HWND, app: &mut App) {
+    if app.notify_icon {
+        tray::remove(hwnd);
+        app.notify_icon = false;
+    }
+}
+
+fn show_tray_menu(hwnd: HWND, app: &mut App) {
+    let Ok(menu) = (unsafe { CreatePopupMenu() }) else {
+        return;
+    };
+    unsafe {
+        AppendMenuW(menu, MF_STRING, IDM_TRAY_OPEN as usize, &HSTRING::from(tr(Msg::MenuTrayOpen)));
+        AppendMenuW(menu, MF_SEPARATOR, 0, None);
+        AppendMenuW(menu, MF_STRING, IDM_EXIT as usize, &HSTRING::from(tr(Msg::MenuExit)));
+    }
+    let mut pt = POINT::default();
+    unsafe { GetCursorPos(&mut pt) };
+    // 前面にしておかないと、メニューの外をクリックしても閉じない
+    unsafe { SetForegroundWindow(hwnd) };
+    let id = unsafe { TrackPopupMenu(menu, TPM_RETURNCMD | TPM_RIGHTBUTTON, pt.x, pt.y, 0, hwnd, None) };
+    unsafe { DestroyMenu(menu) };
+    match id.0 as u32 {
+        IDM_TRAY_OPEN => restore_window(hwnd, app),
+        IDM_EXIT => unsafe { DestroyWindow(hwnd); },
+        _ => {}
+    }
+}
+
+// 表示言語を切り替えて UI の文字列を更新する
+fn change_language(hwnd: HWND, app: &mut App, language: Option<Language>) {
+    app.settings.language = language;
+    let _ = app.settings.save();
+    i18n::set_language(app.settings.effective_language());
+
+    rebuild_menu(hwnd, app);
+    set_accessible_names(app);
+    match &app.current {
+        Some(metadata) => update_status_bar(app.hstatus, Some(metadata)),
+        None => unsafe { SetWindowTextW(app.hedit, &HSTRING::from(tr(Msg::DropHere))); },
+    }
+}
+
+// 読み込み結果を画面に反映する。リンクの位置を返す
+fn set_metadata_text(hedit: HWND, metadata: &ImageMetadata, settings: &Settings) -> Vec<(Range<usize>, Link)> {
+    let (text, links) = format_metadata_with_links(metadata, settings);
+    set_edit_text_with_links(hedit, &text, &links);
+    links
+}
+
+// 今のファイルの内容を表示し直す
+fn refresh_view(app: &mut App) {
+    if let Some(metadata) = &app.current {
+        app.links = set_metadata_text(app.hedit, metadata, &app.settings);
+    }
+}
+
+// 開いている画像によって使えたり使えなかったりするメニュー項目
+fn update_menu_items(hwnd: HWND, app: &App) {
+    let items = [
+        (IDM_OPEN_MAP, app.current.as_ref().is_some_and(|m| m.gps.is_some())),
+        (IDM_SAVE_THUMBNAIL, app.current.as_ref().is_some_and(|m| m.thumbnail.is_some())),
+        (IDM_SAVE_MOTION_PHOTO_VIDEO, app.current.as_ref().is_some_and(|m| m.motion_photo.is_some())),
+        (IDM_SAVE_WORKFLOW, !workflow_chunks(app).is_empty()),
+        (IDM_SAVE_METADATA_TEXT, app.current.is_some()),
+        (IDM_EXPORT_EXIFTOOL_JSON, app.current.is_some()),
+        (IDM_COMPARE_EXIFTOOL_JSON, app.current.is_some()),
+        (IDM_CHECK_POLICY, app.current.is_some()),
+        (IDM_SIZE_BREAKDOWN, app.current.is_some()),
+        (IDM_HEX_VIEW, app.current.is_some()),
+        (IDM_COPY_INFOTEXT, current_infotext(app).is_some()),
+        (IDM_OPEN_IN_VIEWER, current_file(app).is_some()),
+        (IDM_SHOW_IN_EXPLORER, current_file(app).is_some()),
+        (IDM_MOVE_TO_RECYCLE_BIN, current_file(app).is_some()),
+    ];
+    for (id, enabled) in items {
+        let flags = if enabled { MF_BYCOMMAND | MF_ENABLED } else { MF_BYCOMMAND | MF_GRAYED };
+        unsafe { EnableMenuItem(GetMenu(hwnd), id, flags) };
+    }
+}
+
+// 開いているファイルを 16 進表示で見る。select ははじめに選んでおく範囲
+fn show_hex_view(hwnd: HWND, app: &App, select: Range<usize>) {
+    if let Some(metadata) = &app.current {
+        hex_view::show(hwnd, &metadata.filename, &metadata.data, metadata::byte_ranges(metadata), select);
+    }
+}
+
+// チャンクやセグメントごとの大きさを一覧にする
+fn show_size_breakdown(hwnd: HWND, app: &App) -> anyhow::Result<()> {
+    if let Some(metadata) = &app.current {
+        let report = size_report::analyze(&metadata.data)?;
+        show_message(hwnd, &report.format());
+    }
+    Ok(())
+}
+
+fn open_map(hwnd: HWND, app: &App) {
+    if let Some(gps) = app.current.as_ref().and_then(|m| m.gps) {
+        unsafe { ShellExecuteW(hwnd, w!("open"), &HSTRING::from(gps.map_url()), None, None, SW_SHOWNORMAL) };
+    }
+}
+
+// 貼り付けやブラウザからのドロップで開いたものはディスク上にない
+fn current_file(app: &App) -> Option<&Path> {
+    app.current.as_ref().and_then(|m| m.path.as_deref())
+}
+
+// 既定のアプリで開く (MetaView を既定にしていると MetaView がもう一つ開く)
+fn open_in_viewer(hwnd: HWND, app: &App) {
+    if let Some(path) = current_file(app) {
+        unsafe { ShellExecuteW(hwnd, w!("open"), &HSTRING::from(path.as_os_str()), None, None, SW_SHOWNORMAL) };
+    }
+}
+
+// ファイルを選んだ状態でエクスプローラーを開く (すでに開いているウィンドウがあればそれを使う)
+fn show_in_explorer(app: &App) -> anyhow::Result<()> {
+    let Some(path) = current_file(app) else {
+        return Ok(());
+    };
+    let pidl = unsafe { ILCreateFromPathW(&HSTRING::from(path.as_os_str())) };
+    anyhow::ensure!(!pidl.is_null(), "{} is not found", path.display());
+    let result = unsafe { SHOpenFolderAndSelectItems(pidl, None, 0) };
+    unsafe { ILFree(Some(pidl)) };
+    Ok(result?)
+}
+
+// 重複を探したあとなどに、残さない画像を消す。確認はエクスプローラーと同じダイアログで行う
+fn move_to_recycle_bin(hwnd: HWND, app: &mut App) -> anyhow::Result<()> {
+    let Some(path) = current_file(app).map(Path::to_owned) else {
+        return Ok(());
+    };
+    // pFrom は 2 つの NUL で終わる一覧
+    let from: Vec<u16> = path.as_os_str().encode_wide().chain([0, 0]).collect();
+    let mut op = SHFILEOPSTRUCTW {
+        hwnd,
+        wFunc: FO_DELETE,
+        pFrom: PCWSTR(from.as_ptr()),
+        fFlags: (FOF_ALLOWUNDO | FOF_WANTNUKEWARNING) as u16,
+        ..Default::default()
+    };
+    let result = unsafe { SHFileOperationW(&mut op) };
+    // 確認で「いいえ」を選ばれたときも 0 以外が返る
+    if op.fAnyOperationsAborted.as_bool() {
+        return Ok(());
+    }
+    anyhow::ensure!(result == 0, "SHFileOperationW failed ({result:#x})");
+    if path.exists() {
+        return Ok(());
+    }
+    app.gallery.remove(&path);
+    set_edit_text(app.hedit, "");
+    clear_current(hwnd, app);
+    Ok(())
+}
+
+pub fn show_result(hwnd: HWND, app: &mut App, result: anyhow::Result<ImageMetadata>) {
+    match result {
+        Ok(mut metadata) => {
+            let name = Path::new(&metadata.filename).file_name().unwrap_or(&metadata.filename).to_string_lossy().into_owned();
+            accessibility::announce(app.hstatus, &format!("{}: {name}", tr(Msg::Loaded)));
+            metadata.text_chunks = run_scripts(&app.scripts, mem::take(&mut metadata.text_chunks));
+            metadata.extracted = extract::run(&app.settings.extract_rules, &metadata.text_chunks);
+            app.links = set_metadata_text(app.hedit, &metadata, &app.settings);
+            update_status_bar(app.hstatus, Some(&metadata));
+            update_title(hwnd, Some(&metadata.filename));
+            update_icon(hwnd, app, None);
+            record_history(app, &metadata);
+            app.current = Some(metadata);
+            start_decode(hwnd, app, true);
+            update_thumbnail(hwnd, app);
+            update_menu_items(hwnd, app);
+            lookup_hashes(hwnd, app);
+            compute_digests(hwnd, app);
         },
-        System::LibraryLoader::GetModuleHandleW,
+        Err(e) => {
+            set_edit_text(app.hedit, &format!("{}: {e}", tr(Msg::Error)));
+            accessibility::announce(app.hstatus, &format!("{}: {e}", tr(Msg::Error)));
+            update_status_bar(app.hstatus, None);
+            update_title(hwnd, None);
+            update_icon(hwnd, app, None);
+            app.current = None;
+            start_decode(hwnd, app, true);
+            update_thumbnail(hwnd, app);
+            update_menu_items(hwnd, app);
+            compute_digests(hwnd, app);
+        }
+    }
+}
+
+pub fn open_source(hwnd: HWND, source: anyhow::Result<Source>) {
+    if let Some(app) = unsafe { get_app_from_window(hwnd) } {
+        match source {
+            Ok(Source::File(path)) if Path::new(&path).is_dir() => start_folder_scan(hwnd, app, PathBuf::from(path)),
+            Ok(Source::Url(url)) => {
+                set_status_text(app.hstatus, 0, tr(Msg::Downloading));
+                accessibility::announce(app.hstatus, tr(Msg::Downloading));
+                download::start(hwnd, url);
+            }
+            source => show_result(hwnd, app, source.and_then(Source::read_metadata)),
+        }
+    }
+}
+
+fn start_folder_scan(hwnd: HWND, app: &mut App, folder: PathBuf) {
+    set_status_text(app.hstatus, 0, tr(Msg::Scanning));
+    accessibility::announce(app.hstatus, tr(Msg::Scanning));
+    batch::start(hwnd, folder);
+}
+
+// 今のファイルのフォルダーを初めに入れておく
+fn search_folder(hwnd: HWND, app: &App) {
+    let folder = current_file(app).and_then(Path::parent);
+    if let Some((term, folder)) = search::show_dialog(hwnd, folder) {
+        set_status_text(app.hstatus, 0, tr(Msg::Scanning));
+        accessibility::announce(app.hstatus, tr(Msg::Scanning));
+        search::start(hwnd, folder, term);
+    }
+}
+
+// 一度に 1 つだけ。終わると結果を表示する
+fn batch_strip(hwnd: HWND, app: &mut App) {
+    if app.strip_job.is_some() {
+        return;
+    }
+    let folder = current_file(app).and_then(Path::parent);
+    if let Some(options) = batch_strip::show_dialog(hwnd, folder) {
+        app.strip_job = Some(batch_strip::start(hwnd, options));
+    }
+}
+
+fn show_strip_report(hwnd: HWND, app: &mut App, result: anyhow::Result<batch_strip::StripReport>) {
+    if let Some(job) = app.strip_job.take() {
+        job.finish();
+    }
+    match result {
+        Ok(report) => show_message(hwnd, &report.format()),
+        Err(e) => show_error(hwnd, &e),
+    }
+}
+
+// 今の画像が決まりを守っているか調べる
+fn check_policy(hwnd: HWND, app: &App) -> anyhow::Result<()> {
+    let Some(metadata) = &app.current else {
+        return Ok(());
+    };
+    let policy = policy::Policy::load()?;
+    let violations = policy.check(metadata);
+    let text = format!("【{}】\r\n{}\r\n\r\n{}", tr(Msg::PolicyCheck), metadata.filename.to_string_lossy(), policy::format_violations(&violations));
+    show_message(hwnd, &text);
+    Ok(())
+}
+
+// 決まりのファイルは始める前に読み、書き間違いがあればすぐに知らせる
+fn check_folder_policy(hwnd: HWND, app: &App) -> anyhow::Result<()> {
+    let policy = policy::Policy::load()?;
+    let Some(folder) = pick_folder(hwnd, Msg::PolicyCheck)? else {
+        return Ok(());
+    };
+    set_status_text(app.hstatus, 0, tr(Msg::Scanning));
+    accessibility::announce(app.hstatus, tr(Msg::Scanning));
+    policy_check::start(hwnd, policy, folder);
+    Ok(())
+}
+
+fn find_duplicates(hwnd: HWND, app: &App) -> anyhow::Result<()> {
+    let Some(folder) = pick_folder(hwnd, Msg::Duplicates)? else {
+        return Ok(());
+    };
+    set_status_text(app.hstatus, 0, tr(Msg::Scanning));
+    accessibility::announce(app.hstatus, tr(Msg::Scanning));
+    duplicates::start(hwnd, folder);
+    Ok(())
+}
+
+// 重複した画像をまとまりの順にサムネイルの一覧に並べる
+fn show_duplicates(hwnd: HWND, app: &mut App, result: anyhow::Result<duplicates::DuplicateReport>) {
+    match result {
+        Ok(report) => {
+            let text = report.format();
+            show_matches(hwnd, app, &text, report.groups.into_iter().flatten().map(|duplicate| duplicate.image).collect());
+        }
+        Err(e) => {
+            set_edit_text(app.hedit, &format!("{}: {e}", tr(Msg::Error)));
+            accessibility::announce(app.hstatus, &format!("{}: {e}", tr(Msg::Error)));
+            clear_current(hwnd, app);
+        }
+    }
+}
+
+// 決まりを守っていない画像をサムネイルの一覧に並べる
+fn show_policy_report(hwnd: HWND, app: &mut App, result: anyhow::Result<policy_check::PolicyReport>) {
+    match result {
+        Ok(report) => {
+            let text = report.format();
+            show_matches(hwnd, app, &text, report.failures.into_iter().map(|(image, _)| image).collect());
+        }
+        Err(e) => {
+            set_edit_text(app.hedit, &format!("{}: {e}", tr(Msg::Error)));
+            accessibility::announce(app.hstatus, &format!("{}: {e}", tr(Msg::Error)));
+            clear_current(hwnd, app);
+        }
+    }
+}
+
+// 一致した画像はサムネイルの一覧にも並べる
+fn show_matches(hwnd: HWND, app: &mut App, text: &str, images: Vec<batch::ScannedImage>) {
+    set_edit_text(app.hedit, text);
+    accessibility::announce(app.hstatus, &format!("{}: {}", tr(Msg::MatchingImages), images.len()));
+    app.gallery.set_files(hwnd, images);
+    set_gallery_visible(hwnd, app, true);
+    clear_current(hwnd, app);
+}
+
+fn show_search_result(hwnd: HWND, app: &mut App, result: anyhow::Result<search::SearchResult>) {
+    match result {
+        Ok(result) => {
+            let text = result.format();
+            show_matches(hwnd, app, &text, result.matches.into_iter().map(|found| found.image).collect());
+        }
+        Err(e) => {
+            set_edit_text(app.hedit, &format!("{}: {e}", tr(Msg::Error)));
+            accessibility::announce(app.hstatus, &format!("{}: {e}", tr(Msg::Error)));
+            clear_current(hwnd, app);
+        }
+    }
+}
+
+// 索引の検索はすぐに終わるので、ここで結果まで出す
+fn search_index(hwnd: HWND, app: &mut App) {
+    let Some(query) = index::show_query_dialog(hwnd, &app.index_query) else {
+        return;
+    };
+    app.index_query = query;
+    match index::search(&app.index_query) {
+        Ok(result) => {
+            let text = result.format();
+            show_matches(hwnd, app, &text, result.images.into_iter().map(|found| found.image).collect());
+        }
+        Err(e) => show_error(hwnd, &e),
+    }
+}
+
+fn start_index_update(app: &App, hwnd: HWND, folder: Option<PathBuf>) {
+    set_status_text(app.hstatus, 0, tr(Msg::Scanning));
+    accessibility::announce(app.hstatus, tr(Msg::Scanning));
+    index::start_update(hwnd, folder);
+}
+
+// 更新の結果は表示している画像を残したまま知らせる
+fn show_index_update_result(hwnd: HWND, app: &App, result: anyhow::Result<index::UpdateStats>) {
+    update_status_bar(app.hstatus, app.current.as_ref());
+    match result {
+        Ok(stats) => {
+            accessibility::announce(app.hstatus, tr(Msg::IndexUpdated));
+            show_message(hwnd, &stats.format());
+        }
+        Err(e) => show_error(hwnd, &e),
+    }
+}
+
+// 集計した結果は画像の代わりに表示する
+fn show_folder_stats(hwnd: HWND, app: &mut App, result: anyhow::Result<batch::FolderStats>) {
+    match result {
+        Ok(mut stats) => {
+            set_edit_text(app.hedit, &stats.format());
+            accessibility::announce(app.hstatus, tr(Msg::FolderStats));
+            // 読めた画像はサムネイルの一覧に並べる
+            app.gallery.set_files(hwnd, mem::take(&mut stats.files));
+            set_gallery_visible(hwnd, app, true);
+        }
+        Err(e) => {
+            set_edit_text(app.hedit, &format!("{}: {e}", tr(Msg::Error)));
+            accessibility::announce(app.hstatus, &format!("{}: {e}", tr(Msg::Error)));
+        }
+    }
+    clear_current(hwnd, app);
+}
+
+// 表示欄に画像以外のものを出したので、開いていた画像は閉じる
+fn clear_current(hwnd: HWND, app: &mut App) {
+    app.links.clear();
+    update_status_bar(app.hstatus, None);
+    update_title(hwnd, None);
+    update_icon(hwnd, app, None);
+    app.current = None;
+    start_decode(hwnd, app, true);
+    update_thumbnail(hwnd, app);
+    update_menu_items(hwnd, app);
+}
+
+// 集計や監視、検索をするフォルダーを選ぶ
+pub fn pick_folder(hwnd: HWND, title: Msg) -> anyhow::Result<Option<PathBuf>> {
+    let dialog: IFileOpenDialog = unsafe { CoCreateInstance(&FileOpenDialog, None, CLSCTX_INPROC_SERVER) }?;
+    let options = unsafe { dialog.GetOptions() }?;
+    unsafe { dialog.SetOptions(options | FOS_PICKFOLDERS | FOS_FORCEFILESYSTEM) }?;
+    unsafe { dialog.SetTitle(&HSTRING::from(tr(title))) }?;
+    // キャンセルされたときもエラーが返る
+    if unsafe { dialog.Show(hwnd) }.is_err() {
+        return Ok(None);
+    }
+    let item = unsafe { dialog.GetResult() }?;
+    let name = unsafe { item.GetDisplayName(SIGDN_FILESYSPATH) }?;
+    let path = unsafe { name.to_string() };
+    unsafe { CoTaskMemFree(Some(name.0 as _)) };
+    Ok(Some(PathBuf::from(path?)))
+}
+
+// キーボードだけでも開けるように、ドラッグアンドドロップの代わりのファイル選択
+fn open_file_dialog(hwnd: HWND) {
+    // プラグインで読める形式もあるので、すべてのファイルも選べるようにする
+    let filter = format!(
+        "{} (*.png;*.jpg;*.jpeg;*.bmp;*.svg;*.exr)\0*.png;*.jpg;*.jpeg;*.bmp;*.svg;*.exr\0{} (*.dds;*.ktx;*.ktx2)\0*.dds;*.ktx;*.ktx2\0{} (*.*)\0*.*\0",
+        tr(Msg::ImageFiles), tr(Msg::TextureFiles), tr(Msg::AllFiles));
+    let Some(path) = dialog::open_file_dialog(hwnd, &filter, None) else {
+        return;
+    };
+    open_source(hwnd, Ok(Source::File(path.into_os_string())));
+}
+
+// クリップボードの画像・ファイル・URL を開く
+fn paste(hwnd: HWND) {
+    let data = unsafe { OleGetClipboard() };
+    let source = data.ok().and_then(|data| drop_target::read_source(&data));
+    let source = source.unwrap_or_else(|| Err(anyhow::anyhow!(tr(Msg::NoClipboardData))));
+    open_source(hwnd, source);
+}
+
+fn show_message(hwnd: HWND, text: &str) {
+    unsafe { MessageBoxW(hwnd, &HSTRING::from(text), &HSTRING::from(APP_TITLE), MB_OK | MB_ICONINFORMATION) };
+}
+
+fn show_error(hwnd: HWND, e: &anyhow::Error) {
+    let text = HSTRING::from(format!("{}: {e}", tr(Msg::Error)));
+    unsafe { MessageBoxW(hwnd, &text, &HSTRING::from(APP_TITLE), MB_OK | MB_ICONERROR) };
+}
+
+// 埋め込みサムネイルの表示欄の大きさと余白
+const THUMBNAIL_SIZE: u32 = 160;
+const THUMBNAIL_MARGIN: i32 = 8;
+
+// 埋め込みサムネイルがあれば右側に表示する
+fn update_thumbnail(hwnd: HWND, app: &mut App) {
+    let bitmap = app.current.as_ref()
+        .and_then(|m| Some(&m.data[m.thumbnail.clone()?]))
+        .and_then(|data| imaging::create_bitmap(data, THUMBNAIL_SIZE, THUMBNAIL_SIZE).ok())
+        .unwrap_or_default();
+    unsafe { SendMessageW(app.hthumbnail, STM_SETIMAGE, WPARAM(IMAGE_BITMAP.0 as usize), LPARAM(bitmap.0)) };
+    if !app.thumbnail.is_invalid() {
+        unsafe { DeleteObject(app.thumbnail) };
+    }
+    app.thumbnail = bitmap;
+    unsafe { ShowWindow(app.hthumbnail, if bitmap.is_invalid() { SW_HIDE } else { SW_SHOWNA }) };
+    layout(hwnd, app);
+}
+
+fn layout(hwnd: HWND, app: &App) {
+    let mut rect = RECT::default();
+    unsafe { GetClientRect(hwnd, &mut rect) };
+    let mut status_rect = RECT::default();
+    unsafe { GetWindowRect(app.hstatus, &mut status_rect) };
+    let height = rect.bottom - (status_rect.bottom - status_rect.top);
+    let panel_width = if app.thumbnail.is_invalid() { 0 } else { THUMBNAIL_SIZE as i32 + THUMBNAIL_MARGIN * 2 };
+    // プレビュー欄はテキストと半分ずつに分ける
+    let preview_width = if app.settings.show_preview { (rect.right - panel_width) / 2 } else { 0 };
+    // サムネイルの一覧は左側に置く
+    let gallery_width = if app.show_gallery { (rect.right - panel_width - preview_width) / 3 } else { 0 };
+    let edit_width = rect.right - panel_width - preview_width - gallery_width;
+    unsafe { MoveWindow(app.gallery.hwnd, 0, 0, gallery_width, height, true) };
+    unsafe { MoveWindow(app.hedit, gallery_width, 0, edit_width, height, true) };
+    unsafe { MoveWindow(app.hpreview, gallery_width + edit_width, 0, preview_width, height, true) };
+    // STATIC はビットマップの大きさに合わせて自分で大きさを変えるので、位置だけ決める
+    unsafe { SetWindowPos(app.hthumbnail, None, rect.right - panel_width + THUMBNAIL_MARGIN, THUMBNAIL_MARGIN, 0, 0, SWP_NOSIZE | SWP_NOZORDER) };
+}
+
+// 埋め込みサムネイルを <name>_thumbnail.jpg として保存する (保存先は選ばせる)
+fn save_thumbnail(hwnd: HWND, app: &App) -> anyhow::Result<()> {
+    let Some((metadata, range)) = app.current.as_ref().and_then(|m| Some((m, m.thumbnail.clone()?))) else {
+        return Ok(());
+    };
+    let stem = Path::new(&metadata.filename).file_stem().unwrap_or_default().to_string_lossy();
+    let filter = format!("{} (*.jpg)\0*.jpg;*.jpeg\0", tr(Msg::JpegFiles));
+    let dir = metadata.path.as_ref().and_then(|path| path.parent());
+    let Some((out_path, _)) = dialog::save_file_dialog(hwnd, &format!("{stem}_thumbnail.jpg"), &filter, w!("jpg"), dir) else {
+        return Ok(());
+    };
+    std::fs::write(fsutil::long_path(&out_path), &metadata.data[range])?;
+    show_message(hwnd, &format!("{}: {}", tr(Msg::SavedTo), out_path.display()));
+    Ok(())
+}
+
+// モーションフォトの JPEG の後ろに付いている MP4 を <name>.mp4 として保存する
+fn save_motion_photo_video(hwnd: HWND, app: &App) -> anyhow::Result<()> {
+    let Some((metadata, range)) = app.current.as_ref().and_then(|m| Some((m, m.motion_photo.as_ref()?.video.clone()))) else {
+        return Ok(());
+    };
+    let stem = Path::new(&metadata.filename).file_stem().unwrap_or_default().to_string_lossy();
+    let filter = format!("{} (*.mp4)\0*.mp4\0", tr(Msg::Mp4Files));
+    let dir = metadata.path.as_ref().and_then(|path| path.parent());
+    let Some((out_path, _)) = dialog::save_file_dialog(hwnd, &format!("{stem}.mp4"), &filter, w!("mp4"), dir) else {
+        return Ok(());
+    };
+    std::fs::write(fsutil::long_path(&out_path), &metadata.data[range])?;
+    show_message(hwnd, &format!("{}: {}", tr(Msg::SavedTo), out_path.display()));
+    Ok(())
+}
+
+// ComfyUI が埋め込んだワークフロー ("workflow" チャンク) と API 形式のプロンプト ("prompt" チャンク)
+fn workflow_chunks(app: &App) -> Vec<(Msg, &str)> {
+    let Some(metadata) = &app.current else {
+        return Vec::new();
+    };
+    [("workflow", Msg::ComfyWorkflowFiles), ("prompt", Msg::ComfyPromptFiles)].into_iter()
+        .filter_map(|(keyword, msg)| {
+            let (_, text) = metadata.text_chunks.iter().find(|(k, text)| k == keyword && text.trim_start().starts_with('{'))?;
+            Some((msg, text.as_str()))
+        })
+        .collect()
+}
+
+// ComfyUI にドロップすればそのまま読み込める .json に書き出す。どちらのチャンクを書くかはファイルの種類で選ぶ
+fn save_workflow(hwnd: HWND, app: &App) -> anyhow::Result<()> {
+    let chunks = workflow_chunks(app);
+    let Some(metadata) = app.current.as_ref().filter(|_| !chunks.is_empty()) else {
+        return Ok(());
+    };
+    let stem = Path::new(&metadata.filename).file_stem().unwrap_or_default().to_string_lossy();
+    let suffix = if chunks[0].0 == Msg::ComfyWorkflowFiles { "workflow" } else { "prompt" };
+    let filter: String = chunks.iter().map(|(msg, _)| format!("{} (*.json)\0*.json\0", tr(*msg))).collect();
+    let dir = metadata.path.as_ref().and_then(|path| path.parent());
+    let Some((out_path, filter_index)) = dialog::save_file_dialog(hwnd, &format!("{stem}_{suffix}.json"), &filter, w!("json"), dir) else {
+        return Ok(());
+    };
+    // ファイルの種類の番号は 1 から数える
+    let (_, text) = chunks.get((filter_index as usize).saturating_sub(1)).unwrap_or(&chunks[0]);
+    std::fs::write(fsutil::long_path(&out_path), redact::redact_text(text, app.settings.active_redactions()).as_bytes())?;
+    show_message(hwnd, &format!("{}: {}", tr(Msg::SavedTo), out_path.display()));
+    Ok(())
+}
+
+// 表示しているメタデータ (伏せ字も反映したもの) を、設定した文字コードと改行で .txt か .md に保存する
+fn save_metadata_text(hwnd: HWND, app: &App) -> anyhow::Result<()> {
+    let Some(metadata) = &app.current else {
+        return Ok(());
+    };
+    let stem = Path::new(&metadata.filename).file_stem().unwrap_or_default().to_string_lossy();
+    let filter = format!("{} (*.txt)\0*.txt\0{} (*.md)\0*.md\0", tr(Msg::TextFiles), tr(Msg::MarkdownFiles));
+    let dir = metadata.path.as_ref().and_then(|path| path.parent());
+    let Some((out_path, _)) = dialog::save_file_dialog(hwnd, &format!("{stem}.txt"), &filter, w!("txt"), dir) else {
+        return Ok(());
+    };
+    let is_markdown = out_path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("md"));
+    let text = if is_markdown { format_markdown(metadata, &app.settings) } else { format_metadata(metadata, &app.settings) };
+    let bytes = encoding::encode_text(&text, app.settings.export_encoding, app.settings.export_newline);
+    std::fs::write(fsutil::long_path(&out_path), bytes)?;
+    show_message(hwnd, &format!("{}: {}", tr(Msg::SavedTo), out_path.display()));
+    Ok(())
+}
+
+// exiftool -j -G -n と同じ形式の JSON を <name>.json として保存する
+fn export_exiftool_json(hwnd: HWND, app: &App) -> anyhow::Result<()> {
+    let Some(metadata) = &app.current else {
+        return Ok(());
+    };
+    let stem = Path::new(&metadata.filename).file_stem().unwrap_or_default().to_string_lossy();
+    let filter = format!("{} (*.json)\0*.json\0", tr(Msg::ExiftoolJsonFiles));
+    let dir = metadata.path.as_ref().and_then(|path| path.parent());
+    let Some((out_path, _)) = dialog::save_file_dialog(hwnd, &format!("{stem}.json"), &filter, w!("json"), dir) else {
+        return Ok(());
+    };
+    std::fs::write(fsutil::long_path(&out_path), exiftool::to_json(metadata, app.settings.active_redactions()))?;
+    show_message(hwnd, &format!("{}: {}", tr(Msg::SavedTo), out_path.display()));
+    Ok(())
+}
+
+// ExifTool で書き出した JSON (exiftool -j -G -n) を選んで、開いている画像と違うタグを一覧にする
+fn compare_exiftool_json(hwnd: HWND, app: &App) -> anyhow::Result<()> {
+    let Some(metadata) = &app.current else {
+        return Ok(());
+    };
+    let filter = format!("{} (*.json)\0*.json\0{} (*.*)\0*.*\0", tr(Msg::ExiftoolJsonFiles), tr(Msg::AllFiles));
+    let dir = metadata.path.as_ref().and_then(|path| path.parent());
+    let Some(json_path) = dialog::open_file_dialog(hwnd, &filter, dir) else {
+        return Ok(());
+    };
+    // ExifTool は UTF-8 で書き出す
+    let text = String::from_utf8_lossy(&fsutil::read_shared(&json_path)?).into_owned();
+    let comparison = exiftool::compare(metadata, &json_path, &text)?;
+    show_message(hwnd, &comparison.format());
+    Ok(())
+}
+
+// 開いている PNG のテキストチャンクを編集して保存する
+fn edit_chunks(hwnd: HWND, app: &mut App) -> anyhow::Result<()> {
+    let Some(path) = current_path(hwnd, app) else {
+        return Ok(());
+    };
+    let file = fsutil::read_shared(&path)?;
+    let chunks = png_chunks::parse_chunks(&file)?;
+    let text_chunks = png_chunks::text_chunks(&file, &chunks);
+    if text_chunks.is_empty() {
+        show_message(hwnd, tr(Msg::NoEditableChunks));
+        return Ok(());
     }
-};
+    let Some(result) = chunk_editor::show(hwnd, text_chunks, app.settings.backup_on_save) else {
+        return Ok(());
+    };
+    remember_backup_choice(app, result.backup);
+    if !result.chunks.is_empty() {
+        chunk_editor::save(&path, &result.chunks, result.backup)?;
+        show_result(hwnd, app, Source::File(path.into_os_string()).read_metadata());
+    }
+    Ok(())
+}
 
-#[derive(Debug)]
-pub struct App {
-    hedit: HWND,
+fn remember_backup_choice(app: &mut App, backup: bool) {
+    if app.settings.backup_on_save != backup {
+        app.settings.backup_on_save = backup;
+        let _ = app.settings.save();
+    }
 }
 
-impl Default for App {
-    fn default() -> Self {
-        App {
-            hedit: HWND(0),
+// 開いている PNG にテキストチャンクを追加する
+fn add_chunk(hwnd: HWND, app: &mut App) -> anyhow::Result<()> {
+    let Some(path) = current_path(hwnd, app) else {
+        return Ok(());
+    };
+    let file = fsutil::read_shared(&path)?;
+    png_chunks::parse_chunks(&file)?;
+    let Some(result) = chunk_editor::show_add(hwnd, app.settings.backup_on_save) else {
+        return Ok(());
+    };
+    remember_backup_choice(app, result.backup);
+    chunk_editor::add(&path, &result.chunks[0], result.backup)?;
+    show_result(hwnd, app, Source::File(path.into_os_string()).read_metadata());
+    Ok(())
+}
+
+fn current_path(hwnd: HWND, app: &App) -> Option<PathBuf> {
+    let path = app.current.as_ref().and_then(|m| m.path.clone());
+    if path.is_none() {
+        show_message(hwnd, tr(Msg::NotAFile));
+    }
+    path
+}
+
+fn save_clean_copy(hwnd: HWND, app: &App) -> anyhow::Result<()> {
+    let Some(path) = current_path(hwnd, app) else {
+        return Ok(());
+    };
+    let out_path = strip::save_clean_copy(&path)?;
+    show_message(hwnd, &format!("{}: {}", tr(Msg::SavedTo), out_path.display()));
+    Ok(())
+}
+
+// 表示テキスト中の位置 (UTF-16 単位、改行は 1 文字) を含む行と、その行の中でのバイト位置を返す
+fn line_at(text: &str, index: usize) -> (&str, usize) {
+    let mut pos = 0;
+    for line in text.split('\n') {
+        let len: usize = line.chars().map(char::len_utf16).sum();
+        if index <= pos + len {
+            let mut column = 0;
+            let mut units = pos;
+            for c in line.chars() {
+                if units >= index {
+                    break;
+                }
+                units += c.len_utf16();
+                column += c.len_utf8();
+            }
+            return (line, column);
         }
+        pos += len + 1;
     }
+    ("", 0)
 }
 
-unsafe fn get_app_from_window<'a>(hwnd: HWND) -> Option<&'a mut App> {
-    let user_data = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut App;
-    user_data.as_mut()
+// 右クリックされた位置のパラメーターをコピーするメニューを表示する
+fn show_context_menu(hwnd: HWND, app: &App, lparam: LPARAM) -> anyhow::Result<()> {
+    // 座標は符号付きで入っている (マルチモニターでは負になりうる)
+    let mut x = (lparam.0 & 0xffff) as i16 as i32;
+    let mut y = ((lparam.0 >> 16) & 0xffff) as i16 as i32;
+    let index = if lparam.0 == -1 {
+        // キーボードから開いたときはキャレットの位置を使う
+        let mut range = CHARRANGE::default();
+        unsafe { SendMessageW(app.hedit, EM_EXGETSEL, WPARAM(0), LPARAM(&mut range as *mut _ as isize)) };
+        let mut rect = RECT::default();
+        unsafe { GetWindowRect(app.hedit, &mut rect) };
+        (x, y) = (rect.left, rect.top);
+        range.cpMin as usize
+    } else {
+        let mut pt = POINT { x, y };
+        unsafe { ScreenToClient(app.hedit, &mut pt) };
+        let pt = POINTL { x: pt.x, y: pt.y };
+        unsafe { SendMessageW(app.hedit, EM_CHARFROMPOS, WPARAM(0), LPARAM(&pt as *const _ as isize)) }.0 as usize
+    };
+
+    // 表示し直す前や編集した後でもずれないように、表示欄の今のテキストで位置を数える (RichEdit は改行を 1 文字として数える)
+    let text = window_text(app.hedit).replace("\r\n", "\n").replace('\r', "\n");
+    let (line, column) = line_at(&text, index);
+    let field = params::field_at(line, column);
+    let prompt = app.current.as_ref().and_then(|m| params::find_prompt(&m.text_chunks));
+    let infotext = current_infotext(app);
+
+    let menu = unsafe { CreatePopupMenu() }?;
+    let field_flags = if field.is_some() { MF_STRING } else { MF_STRING | MF_GRAYED };
+    let prompt_flags = if prompt.is_some() { MF_STRING } else { MF_STRING | MF_GRAYED };
+    let infotext_flags = if infotext.is_some() { MF_STRING } else { MF_STRING | MF_GRAYED };
+    let has_gps = app.current.as_ref().is_some_and(|m| m.gps.is_some());
+    let has_thumbnail = app.current.as_ref().is_some_and(|m| m.thumbnail.is_some());
+    let has_file = current_file(app).is_some();
+    let digests = app.current.as_ref().and_then(|m| m.digests.as_ref());
+    let hash_menu = unsafe { CreatePopupMenu() }?;
+    unsafe {
+        AppendMenuW(menu, MF_STRING, IDM_COPY as usize, &HSTRING::from(tr(Msg::MenuCopy)));
+        AppendMenuW(menu, MF_SEPARATOR, 0, None);
+        AppendMenuW(menu, field_flags, IDM_COPY_VALUE as usize, &HSTRING::from(tr(Msg::MenuCopyValue)));
+        AppendMenuW(menu, field_flags, IDM_COPY_KEY_VALUE as usize, &HSTRING::from(tr(Msg::MenuCopyKeyValue)));
+        AppendMenuW(menu, prompt_flags, IDM_COPY_PROMPT as usize, &HSTRING::from(tr(Msg::MenuCopyPrompt)));
+        AppendMenuW(menu, infotext_flags, IDM_COPY_INFOTEXT as usize, &HSTRING::from(tr(Msg::MenuCopyInfotext)));
+        AppendMenuW(menu, MF_STRING, IDM_COPY_MARKDOWN as usize, &HSTRING::from(tr(Msg::MenuCopyMarkdown)));
+        if let Some(digests) = digests {
+            AppendMenuW(hash_menu, MF_STRING, IDM_COPY_SHA256 as usize, w!("SHA-256"));
+            AppendMenuW(hash_menu, MF_STRING, IDM_COPY_BLAKE3 as usize, w!("BLAKE3"));
+            if digests.phash.is_some() {
+                AppendMenuW(hash_menu, MF_STRING, IDM_COPY_PHASH as usize, w!("pHash"));
+            }
+            AppendMenuW(menu, MF_POPUP, hash_menu.0 as usize, &HSTRING::from(tr(Msg::MenuCopyHash)));
+        }
+        if has_gps {
+            AppendMenuW(menu, MF_SEPARATOR, 0, None);
+            AppendMenuW(menu, MF_STRING, IDM_OPEN_MAP as usize, &HSTRING::from(tr(Msg::MenuOpenMap)));
+        }
+        if has_thumbnail {
+            if !has_gps {
+                AppendMenuW(menu, MF_SEPARATOR, 0, None);
+            }
+            AppendMenuW(menu, MF_STRING, IDM_SAVE_THUMBNAIL as usize, &HSTRING::from(tr(Msg::MenuSaveThumbnail)));
+        }
+        if has_file {
+            AppendMenuW(menu, MF_SEPARATOR, 0, None);
+            AppendMenuW(menu, MF_STRING, IDM_OPEN_IN_VIEWER as usize, &HSTRING::from(tr(Msg::MenuOpenInViewer)));
+            AppendMenuW(menu, MF_STRING, IDM_SHOW_IN_EXPLORER as usize, &HSTRING::from(tr(Msg::MenuShowInExplorer)));
+        }
+    }
+    let cmd = unsafe { TrackPopupMenu(menu, TPM_RETURNCMD | TPM_RIGHTBUTTON, x, y, 0, hwnd, None) }.0 as u32;
+    // メニューに入れたサブメニューは一緒に破棄される
+    unsafe { DestroyMenu(menu) };
+    if digests.is_none() {
+        unsafe { DestroyMenu(hash_menu) };
+    }
+
+    match (cmd, field, prompt) {
+        (IDM_COPY, _, _) => { unsafe { SendMessageW(app.hedit, WM_COPY, WPARAM(0), LPARAM(0)) }; }
+        (IDM_COPY_VALUE, Some(field), _) => clipboard::set_text(hwnd, &field.value)?,
+        (IDM_COPY_KEY_VALUE, Some(field), _) => clipboard::set_text(hwnd, &format!("{}: {}", field.key, field.value))?,
+        (IDM_COPY_PROMPT, _, Some(prompt)) => clipboard::set_text(hwnd, &prompt)?,
+        (IDM_COPY_INFOTEXT, _, _) => clipboard::set_text(hwnd, &infotext.unwrap_or_default())?,
+        (IDM_OPEN_MAP, _, _) => open_map(hwnd, app),
+        (IDM_COPY_MARKDOWN, _, _) => copy_markdown(hwnd, app)?,
+        (IDM_SAVE_THUMBNAIL, _, _) => save_thumbnail(hwnd, app)?,
+        (IDM_OPEN_IN_VIEWER, _, _) => open_in_viewer(hwnd, app),
+        (IDM_SHOW_IN_EXPLORER, _, _) => show_in_explorer(app)?,
+        (IDM_COPY_SHA256, _, _) => clipboard::set_text(hwnd, &digests.map(|d| d.sha256.clone()).unwrap_or_default())?,
+        (IDM_COPY_BLAKE3, _, _) => clipboard::set_text(hwnd, &digests.map(|d| d.blake3.clone()).unwrap_or_default())?,
+        (IDM_COPY_PHASH, _, _) => clipboard::set_text(hwnd, &digests.and_then(|d| d.phash.clone()).unwrap_or_default())?,
+        _ => {}
+    }
+    Ok(())
+}
+
+// クリックされたリンクを既定のブラウザーで開く。http(s) 以外は開かない
+// 「それでも展開する」のリンクなら、上限を超えた圧縮チャンクを展開する
+fn open_link(hwnd: HWND, app: &mut App, range: CHARRANGE) {
+    let len = (range.cpMax - range.cpMin).max(0) as usize;
+    let mut buf = vec![0u16; len + 1];
+    let mut text_range = TEXTRANGEW { chrg: range, lpstrText: PWSTR(buf.as_mut_ptr()) };
+    let copied = unsafe { SendMessageW(app.hedit, EM_GETTEXTRANGE, WPARAM(0), LPARAM(&mut text_range as *mut _ as isize)) }.0 as usize;
+    let url = String::from_utf16_lossy(&buf[..copied.min(len)]);
+    // 表示したときに記録した位置で探す (編集でずれていたら文言も違うはず)
+    let link = app.links.iter()
+        .find(|(link, _)| link.start as i32 == range.cpMin && link.end as i32 == range.cpMax)
+        .map(|(_, link)| link.clone());
+    match link.filter(|_| app.current.is_some()) {
+        Some(Link::Expand(index)) if url == tr(Msg::ExpandAnyway) => {
+            if let Err(e) = expand_oversized(hwnd, app, index) {
+                show_error(hwnd, &e);
+            }
+            return;
+        }
+        Some(Link::Bytes(bytes)) if url.starts_with("0x") => {
+            show_hex_view(hwnd, app, bytes);
+            return;
+        }
+        _ => {}
+    }
+    if download::is_http_url(&url) {
+        unsafe { ShellExecuteW(hwnd, w!("open"), &HSTRING::from(url.trim()), None, None, SW_SHOWNORMAL) };
+    }
 }
 
-fn get_png_metadata(filename: &OsStr) -> anyhow::Result<String> {
-    let f = File::open(filename)?;
-    let decoder = png::Decoder::new(f);
-    let reader = decoder.read_info()?;
-    let info = reader.info();
-    let mut ret = String::new();
-    for chunk in &info.uncompressed_latin1_text {
-        let text = chunk.text.replace("\n", "\r\n");
-        ret.push_str("【");
-        ret.push_str(&chunk.keyword);
-        ret.push_str("】\r\n");
-        ret.push_str(&text);
-        ret.push_str("\r\n\r\n");
+fn expand_oversized(hwnd: HWND, app: &mut App, index: usize) -> anyhow::Result<()> {
+    if let Some(metadata) = &mut app.current {
+        // 展開したチャンクだけをスクリプトに通す (ほかはもう通してある)
+        let before = metadata.text_chunks.len();
+        metadata::expand_oversized(metadata, index)?;
+        let expanded = metadata.text_chunks.split_off(before);
+        metadata.text_chunks.extend(run_scripts(&app.scripts, expanded));
+        metadata.extracted = extract::run(&app.settings.extract_rules, &metadata.text_chunks);
     }
-    Ok(ret)
+    refresh_view(app);
+    lookup_hashes(hwnd, app);
+    Ok(())
 }
 
-macro_rules! loword {
-    ( $x:expr ) => {
-        ((($x.0 as u32) & 0xffffu32) as u16).into()
+fn copy_markdown(hwnd: HWND, app: &App) -> anyhow::Result<()> {
+    if let Some(metadata) = &app.current {
+        clipboard::set_text(hwnd, &format_markdown(metadata, &app.settings))?;
+    }
+    Ok(())
+}
+
+// ComfyUI などのメタデータも A1111 形式の生成パラメーターに変換してコピーする
+fn current_infotext(app: &App) -> Option<String> {
+    app.current.as_ref().and_then(|m| infotext::to_infotext(&m.text_chunks))
+}
+
+fn copy_infotext(hwnd: HWND, app: &App) -> anyhow::Result<()> {
+    if let Some(text) = current_infotext(app) {
+        clipboard::set_text(hwnd, &redact::redact_text(&text, app.settings.active_redactions()))?;
+    }
+    Ok(())
+}
+
+fn print_current(hwnd: HWND, app: &App) -> anyhow::Result<()> {
+    let Some(metadata) = &app.current else {
+        return Ok(());
     };
+    let title = Path::new(&metadata.filename).file_name().unwrap_or(&metadata.filename).to_string_lossy();
+    print::print(hwnd, &title, &format_metadata(metadata, &app.settings))
 }
 
-macro_rules! hiword {
+macro_rules! loword {
     ( $x:expr ) => {
-        ((($x.0 as u32) >> 16u32) as u16).into()
+        ((($x.0 as u32) & 0xffffu32) as u16).into()
     };
 }
 
@@ -73,18 +1879,63 @@ extern "system" fn wndproc(hwnd: HWND, message: u32, wparam: WPARAM, lparam: LPA
 
             // TextBox 作成
             let app = unsafe { get_app_from_window(hwnd) }.unwrap();
+            // RichEdit が読めなければウィンドウを作れないので、エラーを出して作成を取りやめる
+            if let Err(e) = unsafe { LoadLibraryW(w!("Msftedit.dll")) } {
+                show_error(hwnd, &e.into());
+                return LRESULT(-1);
+            }
             let hedit = unsafe { CreateWindowExW(
                 WINDOW_EX_STYLE::default(),
-                w!("EDIT"),
+                MSFTEDIT_CLASS,
                 None,
                 WINDOW_STYLE(
-                    WS_CHILD.0 | WS_VISIBLE.0 |
+                    WS_CHILD.0 | WS_VISIBLE.0 | WS_TABSTOP.0 | ES_READONLY as u32 |
                     ES_WANTRETURN as u32 | ES_MULTILINE as u32 |
                     ES_AUTOVSCROLL as u32 | WS_VSCROLL.0),
                 0, 0, 0, 0,
                 hwnd, HMENU(1234), instance, None) };
             app.hedit = hedit;
-            unsafe { SetWindowTextW(hedit, w!("DRAG AND DROP HERE!!")) };
+            unsafe { SendMessageW(hedit, EM_EXLIMITTEXT, WPARAM(0), LPARAM(-1)) };
+            // URL をリンクにして、クリックされたら EN_LINK で知らせてもらう
+            unsafe { SendMessageW(hedit, EM_AUTOURLDETECT, WPARAM(AURL_ENABLEURL as usize), LPARAM(0)) };
+            unsafe { SendMessageW(hedit, EM_SETEVENTMASK, WPARAM(0), LPARAM(ENM_LINK as isize)) };
+            unsafe { SetWindowTextW(hedit, &HSTRING::from(tr(Msg::DropHere))) };
+            // 選んだテキストを外へドラッグできるようにする
+            drag_source::attach(hedit);
+            unsafe { SetFocus(hedit) };
+
+            // ステータスバー作成
+            let hstatus = unsafe { CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                STATUSCLASSNAMEW,
+                None,
+                WINDOW_STYLE(WS_CHILD.0 | WS_VISIBLE.0 | SBARS_SIZEGRIP),
+                0, 0, 0, 0,
+                hwnd, HMENU(1235), instance, None) };
+            app.hstatus = hstatus;
+
+            // 埋め込みサムネイルの表示欄 (サムネイルがあるときだけ表示する)
+            app.hthumbnail = unsafe { CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                w!("STATIC"),
+                None,
+                WINDOW_STYLE(WS_CHILD.0 | SS_BITMAP.0),
+                0, 0, 0, 0,
+                hwnd, HMENU(1236), instance, None) };
+            unsafe { SendMessageW(hstatus, SB_SETPARTS, WPARAM(STATUS_PARTS.len()), LPARAM(STATUS_PARTS.as_ptr() as isize)) };
+
+            // 画像のプレビュー欄 (設定で表示したときだけ)
+            app.hpreview = preview::create(hwnd, instance, 1237);
+            if app.settings.show_preview {
+                unsafe { ShowWindow(app.hpreview, SW_SHOWNA) };
+            }
+
+            // フォルダーを集計したあとのサムネイルの一覧
+            app.gallery = gallery::Gallery::create(hwnd, instance, 1238);
+
+            // スクリーンリーダー向けの名前。ステータスバーは読み込みやエラーを読み上げるのに使う
+            set_accessible_names(app);
+            let _ = accessibility::make_live_region(hstatus);
 
             // フォントの作成
             let hfont = unsafe { CreateFontW(
@@ -99,45 +1950,438 @@ extern "system" fn wndproc(hwnd: HWND, message: u32, wparam: WPARAM, lparam: LPA
                 w!("Georgia"),
             ) };
             unsafe { SendMessageW(hedit, WM_SETFONT, WPARAM(hfont.0 as usize), LPARAM(0)) };
+            text_zoom::attach(hedit, IDM_TEXT_ZOOM_IN, IDM_TEXT_ZOOM_OUT);
+            set_status_text(hstatus, STATUS_PART_ZOOM, &format!("{}%", app.text_zoom));
+
+            // メニュー作成
+            if let Ok(menu) = create_menu(app) {
+                unsafe { SetMenu(hwnd, menu) };
+            }
 
             // ファイルのドラッグアンドドロップを許可
             unsafe { DragAcceptFiles(hwnd, true) };
 
+            // ブラウザなどからの OLE ドラッグアンドドロップを受け付ける
+            let drop_target: IDropTarget = drop_target::DropTarget::new(hwnd).into();
+            unsafe { RegisterDragDrop(hwnd, &drop_target) }.ok();
+
+            if let Err(e) = hotkey::register(hwnd, app.settings.hotkey) {
+                set_status_text(app.hstatus, 0, &e.to_string());
+            }
+
+            LRESULT::default()
+        }
+        // ハイコントラストの切り替えやアクセントカラーの変更に合わせて色付けし直す
+        WM_SYSCOLORCHANGE | WM_DWMCOLORIZATIONCOLORCHANGED => {
+            if let Some(app) = unsafe { get_app_from_window(hwnd) } {
+                refresh_view(app);
+                unsafe { InvalidateRect(hwnd, None, true) };
+            }
+            LRESULT::default()
+        }
+        // ウィンドウがアクティブになったらキーボードの操作をテキストに向ける
+        WM_SETFOCUS => {
+            if let Some(app) = unsafe { get_app_from_window(hwnd) } {
+                unsafe { SetFocus(app.hedit) };
+            }
             LRESULT::default()
         }
         WM_SIZE => {
             if let Some(app) = unsafe { get_app_from_window(hwnd) } {
-                unsafe { MoveWindow(app.hedit, 0, 0, loword!(lparam), hiword!(lparam), true) };
+                if wparam.0 as u32 == SIZE_MINIMIZED && app.settings.minimize_to_tray {
+                    remove_notify_icon(hwnd, app);
+                    tray::add(hwnd, app.icons.0, &window_text(hwnd));
+                    unsafe { ShowWindow(hwnd, SW_HIDE) };
+                    app.in_tray = true;
+                    return LRESULT::default();
+                }
+                // ステータスバーは自分で位置を決めるので WM_SIZE を転送するだけでよい
+                unsafe { SendMessageW(app.hstatus, WM_SIZE, wparam, lparam) };
+                layout(hwnd, app);
             }
             unsafe { DefWindowProcW(hwnd, message, wparam, lparam) }
         }
         WM_DROPFILES => {
+            let hdrop = HDROP(wparam.0 as isize);
+            let n_files = unsafe { DragQueryFileW(hdrop, u32::MAX, None) };
+            if n_files > 0 {
+                // フォルダーが落とされたときはフォルダーの検索を始める
+                let filename = drop_target::drag_query_file(hdrop, 0);
+                open_source(hwnd, Ok(Source::File(filename)));
+            }
+            LRESULT::default()
+        }
+        WM_INITMENUPOPUP => {
+            if let Some(app) = unsafe { get_app_from_window(hwnd) } {
+                let menu = HMENU(wparam.0 as isize);
+                if menu == app.filter_menu {
+                    fill_filter_menu(app);
+                } else if menu == app.encoding_menu {
+                    fill_encoding_menu(app);
+                }
+            }
+            LRESULT::default()
+        }
+        WM_NOTIFY => {
+            let nmhdr = unsafe { &*(lparam.0 as *const NMHDR) };
+            if let Some(app) = unsafe { get_app_from_window(hwnd) } {
+                if nmhdr.hwndFrom == app.hedit && nmhdr.code == EN_LINK {
+                    let link = unsafe { &*(lparam.0 as *const ENLINK) };
+                    if link.msg == WM_LBUTTONUP {
+                        open_link(hwnd, app, link.chrg);
+                    }
+                    return LRESULT::default();
+                }
+                if let Some(path) = app.gallery.selected_file(lparam) {
+                    let path = path.as_os_str().to_owned();
+                    open_source(hwnd, Ok(Source::File(path)));
+                    return LRESULT::default();
+                }
+            }
+            unsafe { DefWindowProcW(hwnd, message, wparam, lparam) }
+        }
+        WM_CONTEXTMENU => {
+            if let Some(app) = unsafe { get_app_from_window(hwnd) } {
+                if let Err(e) = show_context_menu(hwnd, app, lparam) {
+                    show_error(hwnd, &e);
+                }
+            }
+            LRESULT::default()
+        }
+        WM_COMMAND => {
             if let Some(app) = unsafe { get_app_from_window(hwnd) } {
-                let hdrop = HDROP(wparam.0 as isize);
-                let n_files = unsafe { DragQueryFileW(hdrop, u32::MAX, None) };
-                if n_files > 0 {
-                    let mut buf: Vec<u16> = vec![0; 1024];
-                    unsafe { DragQueryFileW(hdrop, 0, Some(&mut buf)) };
-                    let last = buf.iter().position(|&x| x == 0).unwrap_or(buf.len());
-                    let filename = OsString::from_wide(&buf[0..last]);
-                    match get_png_metadata(&filename) {
-                        Ok(metadata) => {
-                            let new_text = HSTRING::from(metadata);
-                            unsafe { SetWindowTextW(app.hedit, &new_text) };
-                        },
-                        Err(e) => {
-                            let new_text = HSTRING::from(format!("ERROR: {e}"));
-                            unsafe { SetWindowTextW(app.hedit, &new_text) };
+                let id: u32 = loword!(wparam);
+                match id {
+                    IDM_SAVE_CLEAN_COPY => {
+                        if let Err(e) = save_clean_copy(hwnd, app) {
+                            show_error(hwnd, &e);
+                        }
+                    }
+                    IDM_PRINT => {
+                        if let Err(e) = print_current(hwnd, app) {
+                            show_error(hwnd, &e);
+                        }
+                    }
+                    IDM_OPEN_MAP => open_map(hwnd, app),
+                    IDM_SAVE_THUMBNAIL => {
+                        if let Err(e) = save_thumbnail(hwnd, app) {
+                            show_error(hwnd, &e);
+                        }
+                    }
+                    IDM_SAVE_MOTION_PHOTO_VIDEO => {
+                        if let Err(e) = save_motion_photo_video(hwnd, app) {
+                            show_error(hwnd, &e);
+                        }
+                    }
+                    IDM_SAVE_WORKFLOW => {
+                        if let Err(e) = save_workflow(hwnd, app) {
+                            show_error(hwnd, &e);
+                        }
+                    }
+                    IDM_SAVE_METADATA_TEXT => {
+                        if let Err(e) = save_metadata_text(hwnd, app) {
+                            show_error(hwnd, &e);
+                        }
+                    }
+                    IDM_EXPORT_EXIFTOOL_JSON => {
+                        if let Err(e) = export_exiftool_json(hwnd, app) {
+                            show_error(hwnd, &e);
+                        }
+                    }
+                    IDM_COMPARE_EXIFTOOL_JSON => {
+                        if let Err(e) = compare_exiftool_json(hwnd, app) {
+                            show_error(hwnd, &e);
+                        }
+                    }
+                    IDM_BATCH_STRIP => batch_strip(hwnd, app),
+                    IDM_CHECK_POLICY => {
+                        if let Err(e) = check_policy(hwnd, app) {
+                            show_error(hwnd, &e);
+                        }
+                    }
+                    IDM_CHECK_FOLDER_POLICY => {
+                        if let Err(e) = check_folder_policy(hwnd, app) {
+                            show_error(hwnd, &e);
+                        }
+                    }
+                    IDM_OPEN => open_file_dialog(hwnd),
+                    IDM_SEARCH_FOLDER => search_folder(hwnd, app),
+                    IDM_INDEX_SEARCH => search_index(hwnd, app),
+                    IDM_INDEX_ADD_FOLDER => match pick_folder(hwnd, Msg::IndexFolderTitle) {
+                        Ok(Some(folder)) => start_index_update(app, hwnd, Some(folder)),
+                        Ok(None) => {}
+                        Err(e) => show_error(hwnd, &e),
+                    },
+                    IDM_INDEX_UPDATE => start_index_update(app, hwnd, None),
+                    IDM_OPEN_IN_VIEWER => open_in_viewer(hwnd, app),
+                    IDM_SHOW_IN_EXPLORER => {
+                        if let Err(e) = show_in_explorer(app) {
+                            show_error(hwnd, &e);
+                        }
+                    }
+                    IDM_WATCH_FOLDER => toggle_watch_folder(hwnd, app),
+                    IDM_FOLDER_STATS => match pick_folder(hwnd, Msg::FolderStats) {
+                        Ok(Some(folder)) => start_folder_scan(hwnd, app, folder),
+                        Ok(None) => {}
+                        Err(e) => show_error(hwnd, &e),
+                    },
+                    IDM_FIND_DUPLICATES => {
+                        if let Err(e) = find_duplicates(hwnd, app) {
+                            show_error(hwnd, &e);
+                        }
+                    }
+                    IDM_MOVE_TO_RECYCLE_BIN => {
+                        if let Err(e) = move_to_recycle_bin(hwnd, app) {
+                            show_error(hwnd, &e);
+                        }
+                    }
+                    IDM_PASTE => paste(hwnd),
+                    IDM_ENABLE_EDITING => toggle_editing(hwnd, app),
+                    IDM_COPY_MARKDOWN => {
+                        if let Err(e) = copy_markdown(hwnd, app) {
+                            show_error(hwnd, &e);
+                        }
+                    }
+                    IDM_COPY_INFOTEXT => {
+                        if let Err(e) = copy_infotext(hwnd, app) {
+                            show_error(hwnd, &e);
+                        }
+                    }
+                    IDM_SHOW_ALL_CHUNKS => change_filter(app, |filter| *filter = Default::default()),
+                    IDM_HIDE_BINARY_CHUNKS => change_filter(app, |filter| filter.hide_binary = !filter.hide_binary),
+                    IDM_HISTORY => show_history(hwnd, app),
+                    IDM_KEEP_HISTORY => toggle_keep_history(hwnd, app),
+                    IDM_RELOAD_SCRIPTS => reload_scripts(hwnd, app),
+                    IDM_FILE_ASSOCIATIONS => {
+                        if let Err(e) = association::show_dialog(hwnd) {
+                            show_error(hwnd, &e);
+                        }
+                    }
+                    IDM_CHECK_UPDATES => update::start(hwnd, true),
+                    IDM_CHECK_UPDATES_ON_STARTUP => toggle_check_updates(hwnd, app),
+                    IDM_SHOW_PREVIEW => toggle_preview(hwnd, app),
+                    IDM_SHOW_GALLERY => set_gallery_visible(hwnd, app, !app.show_gallery),
+                    IDM_REDACT => toggle_redact(hwnd, app),
+                    IDM_ACCENT_COLORS => toggle_accent_colors(hwnd, app),
+                    IDM_ZOOM_FIT => preview::fit(app.hpreview),
+                    IDM_ZOOM_ACTUAL => preview::actual_size(app.hpreview),
+                    IDM_ZOOM_IN => preview::zoom(app.hpreview, 1.0),
+                    IDM_ZOOM_OUT => preview::zoom(app.hpreview, -1.0),
+                    IDM_TEXT_ZOOM_IN => zoom_text(app, 1),
+                    IDM_TEXT_ZOOM_OUT => zoom_text(app, -1),
+                    IDM_TEXT_ZOOM_RESET => set_text_zoom(app, text_zoom::DEFAULT),
+                    IDM_ZOOM_IN_KEY => zoom_focused(app, 1),
+                    IDM_ZOOM_OUT_KEY => zoom_focused(app, -1),
+                    IDM_HEX_VIEW => show_hex_view(hwnd, app, 0..0),
+                    IDM_SIZE_BREAKDOWN => {
+                        if let Err(e) = show_size_breakdown(hwnd, app) {
+                            show_error(hwnd, &e);
+                        }
+                    }
+                    IDM_ENCODING_AUTO => reinterpret(hwnd, app, None),
+                    _ if (IDM_ENCODING_FIRST..IDM_ENCODING_FIRST + TextEncoding::ALL.len() as u32).contains(&id) => {
+                        let encoding = TextEncoding::ALL[(id - IDM_ENCODING_FIRST) as usize];
+                        reinterpret(hwnd, app, Some(encoding));
+                    }
+                    IDM_FILTER_KEYWORD_FIRST..=IDM_FILTER_KEYWORD_LAST => {
+                        let index = (id - IDM_FILTER_KEYWORD_FIRST) as usize;
+                        if let Some(keyword) = app.filter_keywords.get(index).cloned() {
+                            change_filter(app, |filter| filter.toggle(&keyword));
+                        }
+                    }
+                    IDM_ADD_CHUNK => {
+                        if let Err(e) = add_chunk(hwnd, app) {
+                            show_error(hwnd, &e);
                         }
                     }
+                    IDM_EDIT_CHUNK => {
+                        if let Err(e) = edit_chunks(hwnd, app) {
+                            show_error(hwnd, &e);
+                        }
+                    }
+                    IDM_LANGUAGE_AUTO => change_language(hwnd, app, None),
+                    IDM_LANGUAGE_JAPANESE => change_language(hwnd, app, Some(Language::Japanese)),
+                    IDM_LANGUAGE_ENGLISH => change_language(hwnd, app, Some(Language::English)),
+                    IDM_MINIMIZE_TO_TRAY => toggle_minimize_to_tray(hwnd, app),
+                    IDM_WATCH_NOTIFY => toggle_watch_notify(hwnd, app),
+                    _ if (IDM_EXPORT_ENCODING_FIRST..IDM_EXPORT_ENCODING_FIRST + OutputEncoding::ALL.len() as u32).contains(&id) => {
+                        set_export_encoding(hwnd, app, OutputEncoding::ALL[(id - IDM_EXPORT_ENCODING_FIRST) as usize]);
+                    }
+                    IDM_EXPORT_NEWLINE_CRLF => set_export_newline(hwnd, app, Newline::CrLf),
+                    IDM_EXPORT_NEWLINE_LF => set_export_newline(hwnd, app, Newline::Lf),
+                    IDM_CIVITAI_LOOKUP => toggle_civitai_lookup(hwnd, app),
+                    IDM_PERCEPTUAL_HASH => toggle_perceptual_hash(hwnd, app),
+                    IDM_EDIT_HASH_LIST => {
+                        if let Err(e) = edit_hash_list(hwnd) {
+                            show_error(hwnd, &e);
+                        }
+                    }
+                    IDM_EDIT_POLICY => {
+                        if let Err(e) = edit_policy(hwnd) {
+                            show_error(hwnd, &e);
+                        }
+                    }
+                    IDM_HOTKEY => {
+                        if let Err(e) = change_hotkey(hwnd, app) {
+                            show_error(hwnd, &e);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            LRESULT::default()
+        }
+        download::WM_APP_DOWNLOAD_PROGRESS => {
+            if let Some(app) = unsafe { get_app_from_window(hwnd) } {
+                let received = wparam.0 / 1024;
+                let text = match lparam.0 as usize / 1024 {
+                    0 => format!("{}… {received} KB", tr(Msg::Downloading)),
+                    total => format!("{}… {received} / {total} KB", tr(Msg::Downloading)),
+                };
+                set_status_text(app.hstatus, 0, &text);
+            }
+            LRESULT::default()
+        }
+        download::WM_APP_DOWNLOAD_DONE => {
+            let source = unsafe { download::take_result(lparam) };
+            open_source(hwnd, source);
+            LRESULT::default()
+        }
+        batch::WM_APP_SCAN_PROGRESS => {
+            if let Some(app) = unsafe { get_app_from_window(hwnd) } {
+                set_status_text(app.hstatus, 0, &format!("{}… {} / {}", tr(Msg::Scanning), wparam.0, lparam.0));
+            }
+            LRESULT::default()
+        }
+        batch::WM_APP_SCAN_DONE => {
+            let result = unsafe { batch::take_result(lparam) };
+            if let Some(app) = unsafe { get_app_from_window(hwnd) } {
+                show_folder_stats(hwnd, app, result);
+            }
+            LRESULT::default()
+        }
+        batch_strip::WM_APP_STRIP_PROGRESS => {
+            if let Some(job) = unsafe { get_app_from_window(hwnd) }.and_then(|app| app.strip_job.as_ref()) {
+                job.update(wparam.0, lparam.0 as usize);
+            }
+            LRESULT::default()
+        }
+        batch_strip::WM_APP_STRIP_DONE => {
+            let result = unsafe { batch_strip::take_result(lparam) };
+            if let Some(app) = unsafe { get_app_from_window(hwnd) } {
+                show_strip_report(hwnd, app, result);
+            }
+            LRESULT::default()
+        }
+        preview::WM_APP_PREVIEW_DONE => {
+            let decoded = unsafe { preview::take_result(lparam) };
+            if let Some(app) = unsafe { get_app_from_window(hwnd) } {
+                show_decoded(hwnd, app, wparam.0, decoded);
+            }
+            LRESULT::default()
+        }
+        hashing::WM_APP_DIGESTS_DONE => {
+            let digests = unsafe { hashing::take_result(lparam) };
+            if let Some(app) = unsafe { get_app_from_window(hwnd) } {
+                show_digests(app, wparam.0, digests);
+            }
+            LRESULT::default()
+        }
+        watcher::WM_APP_WATCH_NEWEST => {
+            let path = unsafe { watcher::take_result(lparam) };
+            // 監視をやめる前に届いていたものは開かない
+            if unsafe { get_app_from_window(hwnd) }.is_some_and(|app| app.watcher.is_some()) {
+                open_source(hwnd, Ok(Source::File(path.clone().into_os_string())));
+                if let Some(app) = unsafe { get_app_from_window(hwnd) } {
+                    notify_new_image(hwnd, app, path);
+                }
+            }
+            LRESULT::default()
+        }
+        index::WM_APP_INDEX_DONE => {
+            let result = unsafe { index::take_result(lparam) };
+            if let Some(app) = unsafe { get_app_from_window(hwnd) } {
+                show_index_update_result(hwnd, app, result);
+            }
+            LRESULT::default()
+        }
+        duplicates::WM_APP_DUPLICATES_DONE => {
+            let result = unsafe { duplicates::take_result(lparam) };
+            if let Some(app) = unsafe { get_app_from_window(hwnd) } {
+                show_duplicates(hwnd, app, result);
+            }
+            LRESULT::default()
+        }
+        policy_check::WM_APP_POLICY_DONE => {
+            let result = unsafe { policy_check::take_result(lparam) };
+            if let Some(app) = unsafe { get_app_from_window(hwnd) } {
+                show_policy_report(hwnd, app, result);
+            }
+            LRESULT::default()
+        }
+        search::WM_APP_SEARCH_DONE => {
+            let result = unsafe { search::take_result(lparam) };
+            if let Some(app) = unsafe { get_app_from_window(hwnd) } {
+                show_search_result(hwnd, app, result);
+            }
+            LRESULT::default()
+        }
+        gallery::WM_APP_GALLERY_THUMBNAIL => {
+            let (index, bitmap) = unsafe { gallery::take_result(lparam) };
+            if let Some(app) = unsafe { get_app_from_window(hwnd) } {
+                app.gallery.set_thumbnail(wparam.0, index, bitmap);
+            }
+            LRESULT::default()
+        }
+        update::WM_APP_UPDATE_DONE => {
+            let result = unsafe { update::take_result(lparam) };
+            show_update_result(hwnd, wparam.0 != 0, result);
+            LRESULT::default()
+        }
+        civitai::WM_APP_CIVITAI_DONE => {
+            let results = unsafe { civitai::take_result(lparam) };
+            if let Some(app) = unsafe { get_app_from_window(hwnd) } {
+                save_civitai_results(app, results);
+            }
+            LRESULT::default()
+        }
+        WM_HOTKEY => {
+            if let Some(app) = unsafe { get_app_from_window(hwnd) } {
+                if wparam.0 as i32 == hotkey::HOTKEY_ID {
+                    restore_window(hwnd, app);
+                    load_on_hotkey(hwnd, app);
+                }
+            }
+            LRESULT::default()
+        }
+        tray::WM_APP_TRAY => {
+            if let Some(app) = unsafe { get_app_from_window(hwnd) } {
+                match lparam.0 as u32 {
+                    WM_LBUTTONUP | WM_LBUTTONDBLCLK => restore_window(hwnd, app),
+                    WM_RBUTTONUP => show_tray_menu(hwnd, app),
+                    NIN_BALLOONUSERCLICK => open_notified(hwnd, app),
+                    NIN_BALLOONTIMEOUT => remove_notify_icon(hwnd, app),
+                    _ => {}
                 }
             }
             LRESULT::default()
         }
         WM_DESTROY => {
             if let Some(app) = unsafe { get_app_from_window(hwnd) } {
+                if app.in_tray || app.notify_icon {
+                    tray::remove(hwnd);
+                }
+                hotkey::unregister(hwnd);
                 unsafe { DestroyWindow(app.hedit) };
+                unsafe { DestroyWindow(app.hstatus) };
+                unsafe { DestroyWindow(app.hthumbnail) };
+                unsafe { DestroyWindow(app.hpreview) };
+                unsafe { DestroyWindow(app.gallery.hwnd) };
+                if !app.thumbnail.is_invalid() {
+                    unsafe { DeleteObject(app.thumbnail) };
+                }
             }
+            unsafe { RevokeDragDrop(hwnd) }.ok();
             unsafe { PostQuitMessage(0) };
             LRESULT::default()
         }
@@ -145,7 +2389,7 @@ extern "system" fn wndproc(hwnd: HWND, message: u32, wparam: WPARAM, lparam: LPA
     }
 }
 
-pub fn create_window(app: &mut App, width: i32, height: i32) -> anyhow::Result<()> {
+pub fn create_window(app: &mut App, width: i32, height: i32) -> anyhow::Result<HWND> {
     let instance = unsafe { GetModuleHandleW(None) }?;
 
     let class_name = w!("MetaView");
@@ -157,11 +2401,12 @@ pub fn create_window(app: &mut App, width: i32, height: i32) -> anyhow::Result<(
         hInstance: instance,
         hCursor: unsafe { LoadCursorW(None, IDC_ARROW)? },
         lpszClassName: class_name,
-        hbrBackground: HBRUSH(unsafe { GetStockObject(WHITE_BRUSH) }.0),
+        hbrBackground: HBRUSH((COLOR_WINDOW.0 + 1) as isize),
         ..Default::default()
     };
     let atom = unsafe { RegisterClassExW(&wc) };
     anyhow::ensure!(atom != 0, "RegisterClassExW failed");
+    preview::register_class(instance)?;
 
     let mut window_rect = RECT {
         left: 0,
@@ -171,11 +2416,11 @@ pub fn create_window(app: &mut App, width: i32, height: i32) -> anyhow::Result<(
     };
     unsafe { AdjustWindowRect(&mut window_rect, WS_OVERLAPPEDWINDOW, false) };
 
-    unsafe {
+    let hwnd = unsafe {
         CreateWindowExW(
             WINDOW_EX_STYLE::default(),
             class_name,
-            w!("MetaView"),
+            &HSTRING::from(APP_TITLE),
             WS_OVERLAPPEDWINDOW | WS_VISIBLE,
             CW_USEDEFAULT,
             CW_USEDEFAULT,
@@ -187,10 +2432,42 @@ pub fn create_window(app: &mut App, width: i32, height: i32) -> anyhow::Result<(
             Some(app as *mut _ as _),
         )
     };
-    Ok(())
+    // WM_CREATE で作成を取りやめたとき (エラーはそこで表示している)
+    if hwnd.0 == 0 {
+        anyhow::bail!("CreateWindowExW failed");
+    }
+    theme::apply(hwnd, app.settings.backdrop);
+    Ok(hwnd)
+}
+
+fn create_accelerators() -> anyhow::Result<HACCEL> {
+    let accels = [
+        ACCEL { fVirt: FCONTROL | FVIRTKEY, key: b'O' as u16, cmd: IDM_OPEN as u16 },
+        ACCEL { fVirt: FCONTROL | FVIRTKEY, key: b'F' as u16, cmd: IDM_SEARCH_FOLDER as u16 },
+        ACCEL { fVirt: FCONTROL | FSHIFT | FVIRTKEY, key: b'F' as u16, cmd: IDM_INDEX_SEARCH as u16 },
+        ACCEL { fVirt: FCONTROL | FVIRTKEY, key: b'V' as u16, cmd: IDM_PASTE as u16 },
+        ACCEL { fVirt: FCONTROL | FVIRTKEY, key: b'P' as u16, cmd: IDM_PRINT as u16 },
+        ACCEL { fVirt: FCONTROL | FVIRTKEY, key: b'H' as u16, cmd: IDM_HISTORY as u16 },
+        ACCEL { fVirt: FCONTROL | FVIRTKEY, key: b'0' as u16, cmd: IDM_ZOOM_FIT as u16 },
+        ACCEL { fVirt: FCONTROL | FVIRTKEY, key: b'1' as u16, cmd: IDM_ZOOM_ACTUAL as u16 },
+        // プレビューにフォーカスがあれば画像を拡大縮小する (zoom_focused を参照)
+        ACCEL { fVirt: FCONTROL | FVIRTKEY, key: VK_OEM_PLUS.0, cmd: IDM_ZOOM_IN_KEY as u16 },
+        ACCEL { fVirt: FCONTROL | FVIRTKEY, key: VK_ADD.0, cmd: IDM_ZOOM_IN_KEY as u16 },
+        ACCEL { fVirt: FCONTROL | FVIRTKEY, key: VK_OEM_MINUS.0, cmd: IDM_ZOOM_OUT_KEY as u16 },
+        ACCEL { fVirt: FCONTROL | FVIRTKEY, key: VK_SUBTRACT.0, cmd: IDM_ZOOM_OUT_KEY as u16 },
+    ];
+    Ok(unsafe { CreateAcceleratorTableW(&accels) }?)
+}
+
+// 表示欄を編集しているときの Ctrl+V は、クリップボードの画像を開かずに表示欄に貼り付ける
+fn is_edit_paste(hwnd: HWND, message: &MSG) -> bool {
+    if message.message != WM_KEYDOWN || message.wParam.0 != b'V' as usize || unsafe { GetKeyState(VK_CONTROL.0 as i32) } >= 0 {
+        return false;
+    }
+    unsafe { get_app_from_window(hwnd) }.is_some_and(|app| app.editing && message.hwnd == app.hedit)
 }
 
-pub fn main_loop() -> anyhow::Result<()> {
+pub fn main_loop(hwnd: HWND, haccel: HACCEL) -> anyhow::Result<()> {
     loop {
         let mut message = MSG::default();
         let ret = unsafe { GetMessageW(&mut message, None, 0, 0) }.0;
@@ -200,15 +2477,56 @@ pub fn main_loop() -> anyhow::Result<()> {
         if ret == 0 {
             return Ok(());
         }
+        if !is_edit_paste(hwnd, &message) && unsafe { TranslateAcceleratorW(hwnd, haccel, &message) } != 0 {
+            continue;
+        }
         unsafe { TranslateMessage(&message) };
         unsafe { DispatchMessageW(&message) };
     }
 }
 
 fn main() -> anyhow::Result<()> {
+    crash::install();
+    unsafe { OleInitialize(std::ptr::null()) }?;
+
+    // ステータスバーや履歴の一覧などのコモンコントロールを使えるようにする
+    let icc = INITCOMMONCONTROLSEX {
+        dwSize: mem::size_of::<INITCOMMONCONTROLSEX>() as u32,
+        dwICC: ICC_BAR_CLASSES | ICC_HOTKEY_CLASS | ICC_LISTVIEW_CLASSES,
+    };
+    unsafe { InitCommonControlsEx(&icc) };
+
+    let settings = Settings::load();
+    i18n::set_language(settings.effective_language());
+    theme::set_accent_colors(settings.accent_colors);
+    inflate::set_limits(settings.inflate_limits);
+    imaging::set_max_decode_size(settings.decode_max_size);
+    let history = if settings.keep_history { history::load() } else { Vec::new() };
+    let plugin_errors = plugins::load();
+    let (scripts, script_errors) = scripts::Scripts::load();
     let mut app = App {
+        settings,
+        history,
+        scripts,
         ..Default::default()
     };
-    create_window(&mut app, 800, 800)?;
-    main_loop()
+    let hwnd = create_window(&mut app, 800, 800)?;
+    let plugin_errors: Vec<(String, anyhow::Error)> = plugin_errors.into_iter().map(|(path, e)| (path.display().to_string(), e)).collect();
+    show_load_errors(hwnd, Msg::PluginLoadFailed, &plugin_errors);
+    show_load_errors(hwnd, Msg::ScriptLoadFailed, &script_errors);
+    // 関連付けから開かれたときはコマンドラインにファイルが渡される
+    if let Some(path) = std::env::args_os().nth(1) {
+        open_source(hwnd, Ok(Source::File(path)));
+    }
+    // 前回監視していたフォルダーは続けて監視する (フォルダーがなくなっていたら黙ってやめる)
+    if let Some(folder) = app.settings.watch_folder.clone() {
+        if start_watching(hwnd, &mut app, folder).is_ok() {
+            rebuild_menu(hwnd, &mut app);
+        }
+    }
+    if app.settings.check_updates {
+        update::start(hwnd, false);
+    }
+    let haccel = create_accelerators()?;
+    main_loop(hwnd, haccel)
 }
@@ -0,0 +1,655 @@
+// 画像ファイルからメタデータを読み取る
+
+use std::ffi::{OsStr, OsString};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use crate::c2pa::{self, ManifestStore};
+use crate::digest::FileDigests;
+use crate::encoding::{self, TextEncoding};
+use crate::exif::{self, GpsPosition};
+use crate::exr::{self, ExrHeader};
+use crate::extract::Extracted;
+use crate::fsutil;
+use crate::hashes::{self, ModelHash};
+use crate::i18n::{tr, Msg};
+use crate::inflate::{self, InflateError, LimitExceeded};
+use crate::iptc;
+use crate::jpeg::{self, JpegDetails};
+use crate::motion_photo::{self, MotionPhoto};
+use crate::params;
+use crate::plugins;
+use crate::redact;
+use crate::png_chunks::{self, CompressedChunk, PNG_SIGNATURE};
+use crate::settings::{ChunkOrder, Settings};
+use crate::svg::{self, SvgInfo};
+use crate::texture::{self, TextureInfo};
+use crate::trailer::{self, Trailer};
+use crate::watermark::Watermark;
+
+// 読み込み元。ブラウザからのドロップなどではファイルではなくメモリ上のデータになる
+#[derive(Debug)]
+pub enum Source {
+    File(OsString),
+    Memory { name: OsString, data: Vec<u8> },
+    // http(s) の URL。読み込む前にダウンロードが必要
+    Url(String),
+}
+
+impl Source {
+    pub fn read_metadata(self) -> anyhow::Result<ImageMetadata> {
+        match self {
+            Source::File(filename) => {
+                let path = fsutil::resolve_link(Path::new(&filename))?;
+                let data = fsutil::read_shared(&path)?;
+                let mut metadata = parse_metadata(path.clone().into_os_string(), data)?;
+                metadata.path = Some(path);
+                Ok(metadata)
+            }
+            Source::Memory { name, data } => parse_metadata(name, data),
+            Source::Url(url) => anyhow::bail!("not downloaded yet: {url}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ImageMetadata {
+    pub filename: OsString,
+    // ファイルから読み込んだ場合のパス
+    pub path: Option<PathBuf>,
+    pub format: &'static str,
+    pub file_size: u64,
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    // 以下は PNG の IHDR, PLTE から読む
+    pub color_type: Option<png::ColorType>,
+    pub interlaced: Option<bool>,
+    pub palette_size: Option<usize>,
+    pub text_chunks: Vec<(String, String)>,
+    // 内容を解釈できないチャンクの種類と、data の中のデータ部分の位置
+    pub binary_chunks: Vec<(String, Range<usize>)>,
+    // ファイルの中身
+    pub data: Vec<u8>,
+    // ASCII 以外を含む tEXt チャンクの解釈に使った文字コード
+    pub text_encoding: Option<TextEncoding>,
+    // ユーザーが文字コードを指定して読み直したときの指定
+    pub encoding_override: Option<TextEncoding>,
+    // data の中の EXIF (TIFF 形式) の位置
+    pub exif: Option<Range<usize>>,
+    pub gps: Option<GpsPosition>,
+    // EXIF の Orientation (画素は回転していないので表示するときに合わせる)
+    pub orientation: Option<u16>,
+    // data の中の埋め込みサムネイル (JPEG) の位置
+    pub thumbnail: Option<Range<usize>>,
+    // 生成パラメーターに入っていたモデルや LoRA のハッシュ
+    pub model_hashes: Vec<ModelHash>,
+    // 画素から見つかった透かし。画像をデコードできるアプリ側で調べる
+    pub watermarks: Vec<Watermark>,
+    // 設定の正規表現で抜き出したもの
+    pub extracted: Vec<Extracted>,
+    // ファイルのハッシュ。大きなファイルでは時間がかかるのでアプリ側で後から計算する
+    pub digests: Option<FileDigests>,
+    pub icc_profile: Option<IccProfile>,
+    // 展開の上限を超えたので中身を読んでいない圧縮チャンク
+    pub oversized_chunks: Vec<(CompressedChunk, LimitExceeded)>,
+    // C2PA (Content Credentials) のマニフェスト
+    pub c2pa: Option<ManifestStore>,
+    // JPEG の圧縮方式、サブサンプリング、推定画質など
+    pub jpeg: Option<JpegDetails>,
+    // DDS, KTX のピクセル形式やミップマップの段数など
+    pub texture: Option<TextureInfo>,
+    // OpenEXR のチャンネルや圧縮方式など (文字列の属性は text_chunks に入れる)
+    pub exr: Option<ExrHeader>,
+    // JPEG の後ろに付け足された動画 (モーションフォト)
+    pub motion_photo: Option<MotionPhoto>,
+    // IEND や EOI の後ろに付け足されたデータ
+    pub trailer: Option<Trailer>,
+    // PNG のチャンクや JPEG のセグメントの data の中の位置 (16 進表示で使う)
+    pub layout: Vec<(String, Range<usize>)>,
+}
+
+impl ImageMetadata {
+    // 形式ごとの読み込みで共通の初期値。各形式はここから違うところだけを書き換える
+    pub fn new(filename: OsString, format: &'static str, data_len: usize) -> Self {
+        Self {
+            filename,
+            path: None,
+            format,
+            file_size: data_len as u64,
+            width: 0,
+            height: 0,
+            bit_depth: 0,
+            color_type: None,
+            interlaced: None,
+            palette_size: None,
+            text_chunks: Vec::new(),
+            binary_chunks: Vec::new(),
+            data: Vec::new(),
+            text_encoding: None,
+            encoding_override: None,
+            exif: None,
+            gps: None,
+            orientation: None,
+            thumbnail: None,
+            model_hashes: Vec::new(),
+            watermarks: Vec::new(),
+            extracted: Vec::new(),
+            digests: None,
+            icc_profile: None,
+            oversized_chunks: Vec::new(),
+            c2pa: None,
+            jpeg: None,
+            texture: None,
+            exr: None,
+            motion_photo: None,
+            trailer: None,
+            layout: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IccProfile {
+    pub name: String,
+    // 展開後の大きさ
+    pub size: usize,
+    // プロファイルの desc タグ
+    pub description: Option<String>,
+}
+
+pub fn parse_metadata(filename: OsString, data: Vec<u8>) -> anyhow::Result<ImageMetadata> {
+    parse_metadata_as(filename, data, None)
+}
+
+// encoding を指定すると tEXt チャンクを推定せずにその文字コードとして読む
+pub fn parse_metadata_as(filename: OsString, data: Vec<u8>, encoding: Option<TextEncoding>) -> anyhow::Result<ImageMetadata> {
+    let mut metadata = if data.starts_with(PNG_SIGNATURE) {
+        parse_png(filename, &data, encoding)?
+    } else if jpeg::is_jpeg(&data) {
+        parse_jpeg(filename, &data)?
+    } else if data.starts_with(b"BM") {
+        parse_bmp(filename, &data)?
+    } else if let Some(info) = svg::parse(&data) {
+        svg_metadata(filename, &data, info?)
+    } else if exr::is_exr(&data) {
+        exr_metadata(filename, &data, exr::parse(&data)?)
+    } else if let Some(info) = texture::parse(&data) {
+        texture_metadata(filename, &data, info?)
+    } else {
+        let name = display_name(&filename);
+        plugins::parse(filename, &data).unwrap_or_else(|| Err(anyhow::anyhow!("unsupported file format: {name}")))?
+    };
+    if let Some(range) = metadata.exif.clone() {
+        metadata.gps = exif::gps_position(&data[range.clone()]);
+        metadata.orientation = exif::orientation(&data[range.clone()]);
+        let thumbnail = exif::thumbnail(&data[range.clone()]);
+        metadata.thumbnail = metadata.thumbnail.take()
+            .or_else(|| thumbnail.map(|t| range.start + t.start..range.start + t.end));
+    }
+    metadata.c2pa = c2pa::read(&data);
+    find_model_hashes(&mut metadata);
+    metadata.data = data;
+    metadata.encoding_override = encoding;
+    Ok(metadata)
+}
+
+fn find_model_hashes(metadata: &mut ImageMetadata) {
+    if let Some(params) = params::find_parameters(&metadata.text_chunks) {
+        metadata.model_hashes = hashes::find_hashes(&params);
+        hashes::resolve(&mut metadata.model_hashes);
+    }
+}
+
+// 上限を超えて展開しなかったチャンクを、ユーザーの指示で上限なしに展開する
+pub fn expand_oversized(metadata: &mut ImageMetadata, index: usize) -> anyhow::Result<()> {
+    anyhow::ensure!(index < metadata.oversized_chunks.len(), "no such chunk");
+    let (chunk, _) = metadata.oversized_chunks.remove(index);
+    let bytes = inflate::inflate(&metadata.data[chunk.stream.clone()], inflate::UNLIMITED)
+        .map_err(|_| anyhow::anyhow!("invalid compressed data in {}", String::from_utf8_lossy(&chunk.kind)))?;
+    let encoding = metadata.encoding_override;
+    add_inflated(metadata, &chunk, bytes, encoding);
+    find_model_hashes(metadata);
+    Ok(())
+}
+
+// 展開した中身を種類に応じてテキストか ICC プロファイルとして加える
+fn add_inflated(metadata: &mut ImageMetadata, chunk: &CompressedChunk, bytes: Vec<u8>, encoding: Option<TextEncoding>) {
+    match &chunk.kind {
+        b"zTXt" => {
+            // zTXt も tEXt と同じく Latin-1 のはずだが、実際には他の文字コードのこともある
+            let chunk_encoding = encoding.unwrap_or_else(|| encoding::detect(&bytes));
+            if !bytes.is_ascii() {
+                metadata.text_encoding = metadata.text_encoding.or(Some(chunk_encoding));
+            }
+            metadata.text_chunks.push((chunk.keyword.clone(), encoding::decode(&bytes, chunk_encoding)));
+        }
+        b"iTXt" => metadata.text_chunks.push((chunk.keyword.clone(), String::from_utf8_lossy(&bytes).into_owned())),
+        _ => {
+            let description = icc_description(&bytes);
+            metadata.icc_profile = Some(IccProfile { name: chunk.keyword.clone(), size: bytes.len(), description });
+        }
+    }
+}
+
+// ICC プロファイルのタグ表から desc タグを探す。v2 の desc 型と v4 の mluc 型 (最初の言語) に対応する
+fn icc_description(profile: &[u8]) -> Option<String> {
+    let u32_at = |i: usize| Some(u32::from_be_bytes(profile.get(i..i + 4)?.try_into().unwrap()) as usize);
+    let count = u32_at(128)?;
+    let tag = (0..count.min(1000)).map(|i| 132 + i * 12).find(|&entry| profile.get(entry..entry + 4) == Some(b"desc"))?;
+    let (offset, size) = (u32_at(tag + 4)?, u32_at(tag + 8)?);
+    let data = profile.get(offset..offset.checked_add(size)?)?;
+    let text = match data.get(..4)? {
+        b"desc" => {
+            let len = u32::from_be_bytes(data.get(8..12)?.try_into().unwrap()) as usize;
+            let ascii = data.get(12..12 + len.min(data.len() - 12))?;
+            String::from_utf8_lossy(ascii).trim_end_matches('\0').to_owned()
+        }
+        b"mluc" => {
+            let record = data.get(16..28)?;
+            let len = u32::from_be_bytes(record[4..8].try_into().unwrap()) as usize;
+            let start = u32::from_be_bytes(record[8..12].try_into().unwrap()) as usize;
+            let utf16: Vec<u16> = data.get(start..start.checked_add(len)?)?
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&utf16).trim_end_matches('\0').to_owned()
+        }
+        _ => return None,
+    };
+    (!text.is_empty()).then_some(text)
+}
+
+fn display_name(filename: &OsStr) -> String {
+    filename.to_string_lossy().into_owned()
+}
+
+// PNG の仕様で定義されているチャンク
+const KNOWN_PNG_CHUNKS: &[&[u8; 4]] = &[
+    b"IHDR", b"PLTE", b"IDAT", b"IEND", b"tRNS", b"gAMA", b"cHRM", b"sRGB", b"iCCP", b"sBIT",
+    b"bKGD", b"pHYs", b"hIST", b"sPLT", b"tIME", b"tEXt", b"zTXt", b"iTXt", b"eXIf",
+    b"acTL", b"fcTL", b"fdAT",
+];
+
+// png クレートは iCCP を自分で上限付きで展開し、上限を超えるとファイル全体を読めなくするので、
+// 最初の IDAT までの iCCP 以外のチャンクだけを渡す (iCCP は他の圧縮されたチャンクと同じく自分で展開する)
+fn decoder_input(data: &[u8], chunks: &[png_chunks::RawChunk]) -> Vec<u8> {
+    let mut out = PNG_SIGNATURE.to_vec();
+    for chunk in chunks.iter().filter(|chunk| &chunk.kind != b"iCCP") {
+        out.extend_from_slice(&data[chunk.range.clone()]);
+        if &chunk.kind == b"IDAT" {
+            break;
+        }
+    }
+    out
+}
+
+fn parse_png(filename: OsString, data: &[u8], encoding: Option<TextEncoding>) -> anyhow::Result<ImageMetadata> {
+    let chunks = png_chunks::parse_chunks(data)?;
+    let input = decoder_input(data, &chunks);
+    let decoder = png::Decoder::new(input.as_slice());
+    let reader = decoder.read_info()?;
+    let info = reader.info();
+    let mut text_encoding = None;
+    let mut text_chunks: Vec<(String, String)> = Vec::new();
+    // png クレートは最初の IDAT までしか読まないので、IDAT の後ろのテキストチャンクも自分で読む
+    for chunk in png_chunks::text_chunks(data, &chunks) {
+        if chunk.kind == png_chunks::TextKind::ITXt {
+            text_chunks.push((chunk.keyword, chunk.text));
+            continue;
+        }
+        // Latin-1 として読んだ文字列を元のバイト列に戻してから読み直す
+        let bytes: Vec<u8> = chunk.text.chars().map(|c| c as u8).collect();
+        let chunk_encoding = encoding.unwrap_or_else(|| encoding::detect(&bytes));
+        if !bytes.is_ascii() {
+            text_encoding = text_encoding.or(Some(chunk_encoding));
+        }
+        text_chunks.push((chunk.keyword, encoding::decode(&bytes, chunk_encoding)));
+    }
+    let exif = chunks.iter().find(|chunk| &chunk.kind == b"eXIf").map(|chunk| chunk.data.clone());
+    let compressed_chunks = png_chunks::compressed_chunks(data, &chunks);
+    let trailer = chunks.iter().find(|chunk| &chunk.kind == b"IEND").and_then(|iend| trailer::find(data, iend.range.end));
+    let layout = chunks.iter().map(|chunk| (String::from_utf8_lossy(&chunk.kind).into_owned(), chunk.range.clone())).collect();
+    let binary_chunks = chunks.into_iter()
+        .filter(|chunk| !KNOWN_PNG_CHUNKS.contains(&&chunk.kind))
+        .map(|chunk| (String::from_utf8_lossy(&chunk.kind).into_owned(), chunk.data))
+        .collect();
+    let mut metadata = ImageMetadata {
+        width: info.width,
+        height: info.height,
+        bit_depth: info.bit_depth as u8,
+        color_type: Some(info.color_type),
+        interlaced: Some(info.interlaced),
+        palette_size: info.palette.as_ref().map(|palette| palette.len() / 3),
+        text_chunks,
+        binary_chunks,
+        text_encoding,
+        exif,
+        trailer,
+        layout,
+        ..ImageMetadata::new(filename, "PNG", data.len())
+    };
+    // 上限はチャンクごとではなくファイル全体での合計 (小さなチャンクを大量に並べられても上限を超えないように)
+    let mut budget = inflate::limits();
+    let start = Instant::now();
+    for chunk in compressed_chunks {
+        let limits = inflate::Limits { max_size: budget.max_size, max_time: budget.max_time.saturating_sub(start.elapsed()) };
+        match inflate::inflate(&data[chunk.stream.clone()], limits) {
+            Ok(bytes) => {
+                budget.max_size -= bytes.len();
+                add_inflated(&mut metadata, &chunk, bytes, encoding);
+            }
+            Err(InflateError::Limit(exceeded)) => metadata.oversized_chunks.push((chunk, exceeded)),
+            // 壊れたチャンクは読めるものだけ表示するために無視する
+            Err(InflateError::Invalid) => {}
+        }
+    }
+    Ok(metadata)
+}
+
+fn parse_jpeg(filename: OsString, data: &[u8]) -> anyhow::Result<ImageMetadata> {
+    let segments = jpeg::parse_segments(data)?;
+    let frame = jpeg::frame_info(data, &segments)
+        .ok_or_else(|| anyhow::anyhow!("JPEG frame header not found"))?;
+    let mut text_chunks: Vec<(String, String)> = segments.iter()
+        .filter(|s| s.marker == jpeg::COM)
+        .map(|s| ("Comment".to_owned(), String::from_utf8_lossy(&data[s.data.clone()]).into_owned()))
+        .collect();
+    let app13 = iptc::join_segments(segments.iter().filter(|s| s.marker == jpeg::APP13).map(|s| &data[s.data.clone()]));
+    text_chunks.extend(iptc::read(&app13));
+    // APP1 の "Exif\0\0" の後ろが TIFF 形式の EXIF
+    let exif = segments.iter()
+        .find(|s| s.marker == jpeg::APP1 && data[s.data.clone()].starts_with(b"Exif\0\0"))
+        .map(|s| s.data.start + 6..s.data.end);
+    // JFIF 拡張 (JFXX) の JPEG 形式のサムネイル
+    let thumbnail = segments.iter()
+        .find(|s| s.marker == jpeg::APP0 && data[s.data.clone()].starts_with(b"JFXX\0\x10"))
+        .map(|s| s.data.start + 6..s.data.end);
+    Ok(ImageMetadata {
+        width: frame.width,
+        height: frame.height,
+        bit_depth: frame.precision,
+        text_chunks,
+        exif,
+        thumbnail,
+        jpeg: jpeg::details(data, &segments),
+        motion_photo: motion_photo::find(data, &segments),
+        trailer: segments.last().filter(|s| s.marker == jpeg::EOI).and_then(|eoi| trailer::find(data, eoi.range.end)),
+        layout: segments.iter().map(|s| (jpeg::marker_name(s.marker), s.range.clone())).collect(),
+        ..ImageMetadata::new(filename, "JPEG", data.len())
+    })
+}
+
+// BITMAPFILEHEADER (14 バイト) の後に BITMAPINFOHEADER が続く
+fn parse_bmp(filename: OsString, data: &[u8]) -> anyhow::Result<ImageMetadata> {
+    anyhow::ensure!(data.len() >= 30, "BMP header is truncated");
+    let u32_at = |i: usize| u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+    let width = u32_at(18) as i32;
+    let height = u32_at(22) as i32;
+    let bit_count = u16::from_le_bytes([data[28], data[29]]);
+    Ok(ImageMetadata {
+        width: width.unsigned_abs(),
+        height: height.unsigned_abs(),
+        bit_depth: bit_count as u8,
+        ..ImageMetadata::new(filename, "BMP", data.len())
+    })
+}
+
+// 大きさは px に換算して丸める
+fn svg_metadata(filename: OsString, data: &[u8], info: SvgInfo) -> ImageMetadata {
+    ImageMetadata {
+        width: info.width.unwrap_or(0.0).round() as u32,
+        height: info.height.unwrap_or(0.0).round() as u32,
+        text_chunks: info.entries,
+        ..ImageMetadata::new(filename, "SVG", data.len())
+    }
+}
+
+fn texture_metadata(filename: OsString, data: &[u8], info: TextureInfo) -> ImageMetadata {
+    let format = info.container;
+    ImageMetadata {
+        width: info.width,
+        height: info.height,
+        texture: Some(info),
+        ..ImageMetadata::new(filename, format, data.len())
+    }
+}
+
+fn exr_metadata(filename: OsString, data: &[u8], mut header: ExrHeader) -> ImageMetadata {
+    let (width, height) = header.size();
+    ImageMetadata {
+        width,
+        height,
+        bit_depth: header.bit_depth(),
+        text_chunks: std::mem::take(&mut header.strings),
+        exr: Some(header),
+        ..ImageMetadata::new(filename, "OpenEXR", data.len())
+    }
+}
+
+fn color_type_name(color_type: png::ColorType) -> &'static str {
+    match color_type {
+        png::ColorType::Grayscale => "Grayscale",
+        png::ColorType::Rgb => "RGB",
+        png::ColorType::Indexed => "Indexed",
+        png::ColorType::GrayscaleAlpha => "Grayscale + Alpha",
+        png::ColorType::Rgba => "RGBA",
+    }
+}
+
+// テキストチャンクが 1 つもなくても空にならないように、画像そのものの情報は必ず出す
+fn format_image_info(metadata: &ImageMetadata) -> String {
+    let mut ret = format!("【{}】\r\n", tr(Msg::ImageInfo));
+    ret.push_str(&format!("{}: {}\r\n", tr(Msg::Format), metadata.format));
+    ret.push_str(&format!("{}: {} x {}\r\n", tr(Msg::Dimensions), metadata.width, metadata.height));
+    // SVG やプラグインで読んだ形式では 0 (不明)
+    if metadata.bit_depth != 0 {
+        ret.push_str(&format!("{}: {}\r\n", tr(Msg::BitDepth), metadata.bit_depth));
+    }
+    if let Some(color_type) = metadata.color_type {
+        ret.push_str(&format!("{}: {} ({})\r\n", tr(Msg::ColorType), color_type_name(color_type), color_type as u8));
+    }
+    if let Some(details) = &metadata.jpeg {
+        ret.push_str(&details.format());
+    }
+    if let Some(texture) = &metadata.texture {
+        ret.push_str(&texture.format());
+    }
+    if let Some(exr) = &metadata.exr {
+        ret.push_str(&exr.format());
+    }
+    if let Some(motion_photo) = &metadata.motion_photo {
+        ret.push_str(&motion_photo.format());
+    }
+    if let Some(interlaced) = metadata.interlaced {
+        let interlace = if interlaced { "Adam7" } else { tr(Msg::InterlaceNone) };
+        ret.push_str(&format!("{}: {interlace}\r\n", tr(Msg::Interlace)));
+    }
+    if let Some(palette_size) = metadata.palette_size {
+        ret.push_str(&format!("{}: {palette_size} {}\r\n", tr(Msg::Palette), tr(Msg::PaletteColors)));
+    }
+    if let Some(icc) = &metadata.icc_profile {
+        let name = icc.description.as_ref().unwrap_or(&icc.name);
+        ret.push_str(&format!("{}: {name} ({} {})\r\n", tr(Msg::IccProfile), icc.size, tr(Msg::StatusBytes)));
+    }
+    ret.push_str(&format!("{}: {} {}\r\n\r\n", tr(Msg::FileSize), metadata.file_size, tr(Msg::StatusBytes)));
+    ret
+}
+
+fn limit_message(exceeded: LimitExceeded) -> String {
+    let limits = inflate::limits();
+    match exceeded {
+        LimitExceeded::Size => format!("{} ({} {})", tr(Msg::InflateTooLarge), limits.max_size, tr(Msg::StatusBytes)),
+        LimitExceeded::Time => format!("{} ({} ms)", tr(Msg::InflateTooSlow), limits.max_time.as_millis()),
+    }
+}
+
+// 設定のテンプレートで 1 つのチャンクを表示する。改行は \r\n にそろえる
+fn format_chunk(template: &str, keyword: &str, text: &str) -> String {
+    let text = text.replace("\r\n", "\n");
+    let mut ret = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        ret.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("{keyword}") {
+            ret.push_str(keyword);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("{text}") {
+            ret.push_str(&text);
+            rest = after;
+        } else {
+            ret.push('{');
+            rest = &rest[1..];
+        }
+    }
+    ret.push_str(rest);
+    ret.replace('\n', "\r\n")
+}
+
+// 表示しているチャンク (設定の順)
+fn visible_chunks<'a>(metadata: &'a ImageMetadata, settings: &Settings) -> Vec<&'a (String, String)> {
+    let mut chunks: Vec<&(String, String)> = metadata.text_chunks.iter().filter(|(keyword, _)| !settings.filter.is_hidden(keyword)).collect();
+    if settings.chunk_order == ChunkOrder::Keyword {
+        chunks.sort_by(|a, b| a.0.cmp(&b.0));
+    }
+    chunks
+}
+
+// Discord や GitHub に貼れるように、キーワードを太字にして内容をコードブロックに入れる
+pub fn format_markdown(metadata: &ImageMetadata, settings: &Settings) -> String {
+    let mut ret = String::new();
+    for (keyword, text) in visible_chunks(metadata, settings) {
+        let text = redact::redact_value(keyword, text, settings.active_redactions()).replace("\r\n", "\n");
+        let text = text.trim_end_matches('\n');
+        // 内容にバッククォートが続いていても閉じないように、それより長いフェンスを使う
+        let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+        let fence = "`".repeat((longest + 1).max(3));
+        let trimmed = text.trim_start();
+        let language = if trimmed.starts_with('{') || trimmed.starts_with('[') { "json" } else { "" };
+        ret.push_str(&format!("**{keyword}**\n{fence}{language}\n{text}\n{fence}\n\n"));
+    }
+    ret.trim_end().replace('\n', "\r\n")
+}
+
+// 16 進表示の一覧に並べるもの。チャンクやセグメントに、EXIF やサムネイルなどその中の部分を加える
+pub fn byte_ranges(metadata: &ImageMetadata) -> Vec<(String, Range<usize>)> {
+    let mut ranges = metadata.layout.clone();
+    ranges.extend(metadata.exif.clone().map(|range| ("EXIF".to_owned(), range)));
+    ranges.extend(metadata.thumbnail.clone().map(|range| (tr(Msg::AccessibleThumbnail).to_owned(), range)));
+    ranges.extend(metadata.trailer.as_ref().map(|trailer| (tr(Msg::TrailingData).to_owned(), trailer.range.clone())));
+    ranges.sort_by_key(|(_, range)| range.start);
+    ranges
+}
+
+pub fn format_metadata(metadata: &ImageMetadata, settings: &Settings) -> String {
+    format_metadata_with_links(metadata, settings).0
+}
+
+// RichEdit と同じく改行 (\r\n) を 1 文字として数えた UTF-16 単位の長さ
+fn edit_len(text: &str) -> usize {
+    text.encode_utf16().count() - text.matches("\r\n").count()
+}
+
+// 表示欄のリンクをクリックしたときにすること
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Link {
+    // oversized_chunks の n 番目を展開する
+    Expand(usize),
+    // data の中のこの範囲を 16 進表示で見る
+    Bytes(Range<usize>),
+}
+
+// リンクの位置も返す
+// チャンクの中身に同じ文言があってもリンクと取り違えないように、書き出しながら位置を記録する
+pub fn format_metadata_with_links(metadata: &ImageMetadata, settings: &Settings) -> (String, Vec<(Range<usize>, Link)>) {
+    let mut ret = format_image_info(metadata);
+    let mut links = Vec::new();
+    // 位置情報は見落とすと困るので、チャンクより前に出す
+    if let Some(gps) = &metadata.gps {
+        let redact_gps = settings.active_redactions().contains(&redact::Field::Gps);
+        if redact_gps {
+            ret.push_str(&format!("【GPS】\r\n{}\r\n", redact::MASK));
+        } else {
+            ret.push_str(&format!("【GPS】\r\n{:.6}, {:.6}\r\n", gps.latitude, gps.longitude));
+        }
+        if let Some(altitude) = gps.altitude.filter(|_| !redact_gps) {
+            ret.push_str(&format!("{}: {altitude:.1} m\r\n", tr(Msg::Altitude)));
+        }
+        ret.push_str(&format!("⚠ {}\r\n\r\n", tr(Msg::LocationEmbedded)));
+    }
+    if !metadata.watermarks.is_empty() {
+        ret.push_str(&format!("【{}】\r\n", tr(Msg::Watermark)));
+        for watermark in &metadata.watermarks {
+            ret.push_str(&format!("{} (invisible-watermark dwtDct): {} ({}/{} bits)\r\n",
+                watermark.name, watermark.payload_text(), watermark.matched_bits, watermark.total_bits));
+        }
+        ret.push_str(&format!("⚠ {}\r\n\r\n", tr(Msg::WatermarkFound)));
+    }
+    if let Some(store) = &metadata.c2pa {
+        ret.push_str(&store.format(settings.active_redactions()));
+    }
+    if let Some(trailer) = &metadata.trailer {
+        let (text, offset) = trailer.format();
+        let start = edit_len(&ret) + edit_len(&text[..offset.start]);
+        links.push((start..start + edit_len(&text[offset]), Link::Bytes(trailer.range.clone())));
+        ret.push_str(&text);
+    }
+    for (keyword, text) in visible_chunks(metadata, settings) {
+        let text = redact::redact_value(keyword, text, settings.active_redactions());
+        ret.push_str(&format_chunk(&settings.chunk_template, keyword, &text));
+    }
+    for (index, (chunk, exceeded)) in metadata.oversized_chunks.iter().enumerate() {
+        ret.push_str(&format!("【{}】\r\n⚠ {}: {} — ",
+            chunk.keyword, String::from_utf8_lossy(&chunk.kind), limit_message(*exceeded)));
+        let start = edit_len(&ret);
+        ret.push_str(tr(Msg::ExpandAnyway));
+        links.push((start..edit_len(&ret), Link::Expand(index)));
+        ret.push_str("\r\n\r\n");
+    }
+    let resources = params::find_parameters(&metadata.text_chunks).map(|params| params::resources(&params)).unwrap_or_default();
+    if !resources.is_empty() {
+        ret.push_str(&format!("【{}】\r\n", tr(Msg::ResourcesUsed)));
+        for resource in &resources {
+            let weight = resource.weight.as_ref().map(|weight| format!(" ({weight})")).unwrap_or_default();
+            ret.push_str(&format!("{}: {}{weight}\r\n", resource.kind.name(), resource.name));
+        }
+        ret.push_str("\r\n");
+    }
+    if !metadata.model_hashes.is_empty() {
+        ret.push_str(&format!("【{}】\r\n", tr(Msg::ModelHashes)));
+        for hash in &metadata.model_hashes {
+            let name = hash.name.as_ref().map(|name| format!(" {name}")).unwrap_or_default();
+            let resolved = hash.resolved.as_deref().unwrap_or(tr(Msg::HashUnknown));
+            ret.push_str(&format!("{}{name} [{}] → {resolved}\r\n", hash.kind.name(), hash.hash));
+        }
+        ret.push_str("\r\n");
+    }
+    if !metadata.extracted.is_empty() {
+        ret.push_str(&format!("【{}】\r\n", tr(Msg::Extracted)));
+        for extracted in &metadata.extracted {
+            let value = redact::redact_value(&extracted.name, &extracted.value, settings.active_redactions());
+            ret.push_str(&format!("{}: {value}\r\n", extracted.name));
+        }
+        ret.push_str("\r\n");
+    }
+    if let Some(digests) = &metadata.digests {
+        ret.push_str(&format!("【{}】\r\n", tr(Msg::FileHashes)));
+        ret.push_str(&format!("SHA-256: {}\r\n", digests.sha256));
+        ret.push_str(&format!("BLAKE3: {}\r\n", digests.blake3));
+        if let Some(phash) = &digests.phash {
+            ret.push_str(&format!("pHash: {phash}\r\n"));
+        }
+        ret.push_str("\r\n");
+    }
+    if !settings.filter.hide_binary {
+        for (kind, range) in &metadata.binary_chunks {
+            ret.push_str(&format!("【{kind}】\r\n({} bytes, ", range.len()));
+            let start = edit_len(&ret);
+            ret.push_str(&format!("0x{:x}", range.start));
+            links.push((start..edit_len(&ret), Link::Bytes(range.clone())));
+            ret.push_str(")\r\n\r\n");
+        }
+    }
+    (ret, links)
+}
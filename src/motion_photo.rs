@@ -0,0 +1,155 @@
+// モーションフォト (Google の Motion Photo / MicroVideo、Samsung の Motion Photo) の動画を探す
+// どれも JPEG の後ろ (EOI の後) に MP4 をそのまま付け足している。位置は XMP の GCamera タグか、付け足した部分から探す
+
+use std::ops::Range;
+use crate::i18n::{tr, Msg};
+use crate::jpeg::{self, Segment};
+use crate::xml::{self, Event, Reader};
+
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+// Samsung は動画の前にこの印を置く
+const SAMSUNG_MARKER: &[u8] = b"MotionPhoto_Data";
+
+#[derive(Debug, Clone)]
+pub struct MotionPhoto {
+    // ファイルの中の MP4 の位置
+    pub video: Range<usize>,
+    // moov/mvhd から読んだ長さ (秒)
+    pub duration: Option<f64>,
+    // XMP の GCamera:* タグ (MotionPhoto, MotionPhotoPresentationTimestampUs など)
+    pub tags: Vec<(String, String)>,
+}
+
+// XMP から読むもの
+#[derive(Default)]
+struct XmpInfo {
+    tags: Vec<(String, String)>,
+    // Container:Directory の Item:Semantic="MotionPhoto" の Item:Length
+    item_length: Option<usize>,
+}
+
+fn read_xmp(xmp: &str) -> XmpInfo {
+    let mut info = XmpInfo::default();
+    let mut reader = Reader::new(xmp);
+    // 開いている GCamera:* 要素 (<GCamera:MotionPhoto>1</GCamera:MotionPhoto> の形)
+    let mut element: Option<(String, String)> = None;
+    while let Ok(Some(event)) = reader.next_event() {
+        match event {
+            Event::Start { name, attributes } => {
+                let attribute = |key: &str| attributes.iter().find(|(k, _)| xml::local_name(k) == key).map(|(_, v)| v.as_str());
+                if attribute("Semantic") == Some("MotionPhoto") {
+                    info.item_length = attribute("Length").and_then(|length| length.trim().parse().ok());
+                }
+                for (key, value) in &attributes {
+                    if key.starts_with("GCamera:") {
+                        info.tags.push((key.to_string(), value.clone()));
+                    }
+                }
+                element = name.starts_with("GCamera:").then(|| (name.to_owned(), String::new()));
+            }
+            Event::Text(text) => {
+                if let Some((_, value)) = &mut element {
+                    value.push_str(&text);
+                }
+            }
+            Event::End { .. } => {
+                if let Some((key, value)) = element.take() {
+                    info.tags.push((key, value.trim().to_owned()));
+                }
+            }
+        }
+    }
+    info
+}
+
+fn tag<'a>(tags: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    tags.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+}
+
+// MP4 は 2 つ目のボックスが ftyp で始まる
+fn is_mp4(data: &[u8]) -> bool {
+    data.get(4..8) == Some(b"ftyp")
+}
+
+// segments は jpeg::parse_segments の結果。動画が見つからなければ None
+pub fn find(data: &[u8], segments: &[Segment]) -> Option<MotionPhoto> {
+    let xmp = segments.iter()
+        .filter(|s| s.marker == jpeg::APP1)
+        .find_map(|s| data[s.data.clone()].strip_prefix(XMP_HEADER))
+        .map(|xmp| read_xmp(&String::from_utf8_lossy(xmp)))
+        .unwrap_or_default();
+    let end = segments.last().filter(|s| s.marker == jpeg::EOI).map_or(data.len(), |s| s.range.end);
+    let trailer = &data[end..];
+    // 新しい形式は Item:Length、古い形式 (MicroVideo) は GCamera:MicroVideoOffset がどちらもファイルの終わりからの長さ
+    let from_end = xmp.item_length
+        .or_else(|| tag(&xmp.tags, "GCamera:MicroVideoOffset").and_then(|offset| offset.trim().parse().ok()))
+        .filter(|&length: &usize| length > 0 && length <= trailer.len())
+        .map(|length| data.len() - length);
+    let samsung = || {
+        let marker = trailer.windows(SAMSUNG_MARKER.len()).position(|w| w == SAMSUNG_MARKER)?;
+        Some(end + marker + SAMSUNG_MARKER.len())
+    };
+    // タグがなくても ftyp があれば MP4 とみなす
+    let ftyp = || trailer.windows(4).position(|w| w == b"ftyp").filter(|&i| i >= 4).map(|i| end + i - 4);
+    let start = from_end.filter(|&start| is_mp4(&data[start..])).or_else(samsung).or_else(ftyp)?;
+    if !is_mp4(&data[start..]) {
+        return None;
+    }
+    let video = start..data.len();
+    Some(MotionPhoto { duration: duration(&data[video.clone()]), video, tags: xmp.tags })
+}
+
+// ボックスの並びから fourcc のものの中身を探す
+fn find_box<'a>(data: &'a [u8], fourcc: &[u8]) -> Option<&'a [u8]> {
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let size = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+        let (header, size) = match size {
+            // 64 ビットの大きさ
+            1 => (16, usize::try_from(u64::from_be_bytes(data.get(pos + 8..pos + 16)?.try_into().unwrap())).ok()?),
+            // ファイルの終わりまで
+            0 => (8, data.len() - pos),
+            size => (8, size),
+        };
+        if size < header {
+            return None;
+        }
+        let end = pos.checked_add(size)?.min(data.len());
+        if &data[pos + 4..pos + 8] == fourcc {
+            return data.get(pos + header..end);
+        }
+        pos = end;
+    }
+    None
+}
+
+fn duration(mp4: &[u8]) -> Option<f64> {
+    let mvhd = find_box(find_box(mp4, b"moov")?, b"mvhd")?;
+    // バージョン 1 は作成・更新時刻と長さが 64 ビット
+    let (timescale, duration) = match mvhd.first()? {
+        0 => (
+            u32::from_be_bytes(mvhd.get(12..16)?.try_into().unwrap()),
+            u32::from_be_bytes(mvhd.get(16..20)?.try_into().unwrap()) as u64,
+        ),
+        1 => (
+            u32::from_be_bytes(mvhd.get(20..24)?.try_into().unwrap()),
+            u64::from_be_bytes(mvhd.get(24..32)?.try_into().unwrap()),
+        ),
+        _ => return None,
+    };
+    (timescale != 0).then(|| duration as f64 / timescale as f64)
+}
+
+impl MotionPhoto {
+    pub fn format(&self) -> String {
+        let mut ret = format!("{}: MP4, {} {}", tr(Msg::MotionPhoto), self.video.len(), tr(Msg::StatusBytes));
+        if let Some(duration) = self.duration {
+            ret.push_str(&format!(", {duration:.2} {}", tr(Msg::Seconds)));
+        }
+        ret.push_str("\r\n");
+        for (key, value) in &self.tags {
+            ret.push_str(&format!("{key}: {value}\r\n"));
+        }
+        ret
+    }
+}
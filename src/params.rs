@@ -0,0 +1,256 @@
+// Stable Diffusion web UI (A1111) 形式の生成パラメーター ("infotext") の解析
+
+use std::ops::Range;
+
+#[derive(Debug, Clone, Default)]
+pub struct Parameters {
+    pub prompt: String,
+    pub negative_prompt: String,
+    // "Steps: 20, Sampler: Euler a, ..." の各項目
+    pub settings: Vec<Field>,
+}
+
+impl Parameters {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.settings.iter().find(|field| field.key == key).map(|field| field.value.as_str())
+    }
+}
+
+const NEGATIVE_PROMPT: &str = "Negative prompt:";
+
+pub fn parse_infotext(text: &str) -> Option<Parameters> {
+    let text = text.replace("\r\n", "\n");
+    let lines: Vec<&str> = text.lines().collect();
+    let settings_line = lines.iter().rposition(|line| line.starts_with("Steps: "))?;
+    let negative = lines[..settings_line].iter().position(|line| line.starts_with(NEGATIVE_PROMPT)).unwrap_or(settings_line);
+    let mut negative_prompt = lines[negative..settings_line].join("\n");
+    negative_prompt.drain(..negative_prompt.len().min(NEGATIVE_PROMPT.len()));
+    Some(Parameters {
+        prompt: lines[..negative].join("\n"),
+        negative_prompt: negative_prompt.trim_start().to_owned(),
+        settings: parse_settings_line(lines[settings_line]),
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub key: String,
+    pub value: String,
+    // 行の中での位置 (バイト単位)
+    pub range: Range<usize>,
+}
+
+fn is_key(key: &str) -> bool {
+    key.starts_with(|c: char| c.is_alphanumeric())
+        && key.chars().all(|c| c.is_alphanumeric() || " _-/".contains(c))
+}
+
+// "Key: value, Key: "quoted, value"" の形の行を分解する
+pub fn parse_settings_line(line: &str) -> Vec<Field> {
+    let mut fields = Vec::new();
+    let mut pos = 0;
+    while pos < line.len() {
+        let start = pos + (line[pos..].len() - line[pos..].trim_start().len());
+        let Some(colon) = line[start..].find(": ").map(|i| start + i) else { break };
+        let key = &line[start..colon];
+        let value_start = colon + 2;
+        let value_end = if line[value_start..].starts_with('"') {
+            closing_quote(line, value_start).map_or(line.len(), |i| i + 1)
+        } else {
+            line[value_start..].find(", ").map_or(line.len(), |i| value_start + i)
+        };
+        if is_key(key) {
+            let value = line[value_start..value_end].trim_matches('"').replace("\\\"", "\"");
+            fields.push(Field { key: key.to_owned(), value, range: start..value_end });
+        }
+        pos = line[value_end..].find(", ").map_or(line.len(), |i| value_end + i + 2);
+    }
+    fields
+}
+
+fn closing_quote(line: &str, open: usize) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in line[open + 1..].char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => return Some(open + 1 + i),
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+// 行の中の指定位置 (バイト単位) にある項目を探す
+pub fn field_at(line: &str, column: usize) -> Option<Field> {
+    if let Some(rest) = line.strip_prefix(NEGATIVE_PROMPT) {
+        return Some(Field {
+            key: NEGATIVE_PROMPT.trim_end_matches(':').to_owned(),
+            value: rest.trim_start().to_owned(),
+            range: 0..line.len(),
+        });
+    }
+    parse_settings_line(line).into_iter()
+        .find(|field| field.range.contains(&column) || field.range.end == column)
+}
+
+pub fn find_parameters(text_chunks: &[(String, String)]) -> Option<Parameters> {
+    text_chunks.iter()
+        .filter(|(keyword, _)| keyword == "parameters")
+        .find_map(|(_, text)| parse_infotext(text))
+}
+
+// プロンプトだけを取り出す。A1111 形式でなければ "prompt" チャンクがテキストならそれを使う
+pub fn find_prompt(text_chunks: &[(String, String)]) -> Option<String> {
+    if let Some(params) = find_parameters(text_chunks) {
+        return Some(params.prompt);
+    }
+    text_chunks.iter()
+        .find(|(keyword, text)| keyword == "prompt" && !text.trim_start().starts_with('{'))
+        .map(|(_, text)| text.clone())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagKind {
+    Lora,
+    Hypernetwork,
+    Embedding,
+    // (text:1.2) の形の強調
+    Attention,
+}
+
+impl TagKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            TagKind::Lora => "LoRA",
+            TagKind::Hypernetwork => "Hypernetwork",
+            TagKind::Embedding => "Embedding",
+            TagKind::Attention => "Attention",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PromptTag {
+    pub kind: TagKind,
+    pub name: String,
+    pub weight: Option<String>,
+    // プロンプトの中での位置 (バイト単位)
+    pub range: Range<usize>,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// <lora:name:weight>, <lyco:...>, <hypernet:...>
+fn extra_network_tags(prompt: &str, tags: &mut Vec<PromptTag>) {
+    let mut pos = 0;
+    while let Some(start) = prompt[pos..].find('<').map(|i| pos + i) {
+        let Some(end) = prompt[start + 1..].find(['>', '<', '\n']).map(|i| start + 1 + i) else {
+            break;
+        };
+        pos = end;
+        if !prompt[end..].starts_with('>') {
+            continue;
+        }
+        let mut parts = prompt[start + 1..end].split(':');
+        let kind = match parts.next() {
+            Some("lora" | "lyco") => TagKind::Lora,
+            Some("hypernet") => TagKind::Hypernetwork,
+            _ => continue,
+        };
+        let Some(name) = parts.next().filter(|name| !name.is_empty()) else { continue };
+        tags.push(PromptTag {
+            kind,
+            name: name.to_owned(),
+            weight: parts.next().map(str::to_owned),
+            range: start..end + 1,
+        });
+    }
+}
+
+// 重みが書かれている括弧だけを拾う。\( は括弧として扱わない
+fn attention_tags(prompt: &str, tags: &mut Vec<PromptTag>) {
+    let mut stack = Vec::new();
+    let mut escaped = false;
+    for (i, c) in prompt.char_indices() {
+        match c {
+            '\\' if !escaped => {
+                escaped = true;
+                continue;
+            }
+            '(' if !escaped => stack.push(i),
+            ')' if !escaped => {
+                let Some(start) = stack.pop() else { continue };
+                let Some((text, weight)) = prompt[start + 1..i].rsplit_once(':') else { continue };
+                let weight = weight.trim();
+                if !text.trim().is_empty() && weight.parse::<f32>().is_ok() {
+                    tags.push(PromptTag {
+                        kind: TagKind::Attention,
+                        name: text.trim().to_owned(),
+                        weight: Some(weight.to_owned()),
+                        range: start..i + 1,
+                    });
+                }
+            }
+            _ => {}
+        }
+        escaped = false;
+    }
+}
+
+// "embedding:name" (ComfyUI) と、名前が分かっている埋め込み (TI hashes に書かれているもの)
+fn embedding_tags(prompt: &str, embeddings: &[String], tags: &mut Vec<PromptTag>) {
+    const PREFIX: &str = "embedding:";
+    for (start, _) in prompt.match_indices(PREFIX) {
+        let name_start = start + PREFIX.len();
+        let len = prompt[name_start..].find(|c: char| !(is_word_char(c) || "-.".contains(c))).unwrap_or(prompt.len() - name_start);
+        if len > 0 {
+            let name = prompt[name_start..name_start + len].trim_end_matches('.');
+            tags.push(PromptTag { kind: TagKind::Embedding, name: name.to_owned(), weight: None, range: start..name_start + name.len() });
+        }
+    }
+    for name in embeddings.iter().filter(|name| !name.is_empty()) {
+        for (start, _) in prompt.match_indices(name.as_str()) {
+            let end = start + name.len();
+            let before = prompt[..start].chars().next_back();
+            let after = prompt[end..].chars().next();
+            let overlaps = tags.iter().any(|tag| tag.range.start < end && start < tag.range.end && tag.kind != TagKind::Attention);
+            if !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char) && !overlaps {
+                tags.push(PromptTag { kind: TagKind::Embedding, name: name.clone(), weight: None, range: start..end });
+            }
+        }
+    }
+}
+
+// プロンプト中の LoRA などのタグと重み付きの強調を、出てくる順に返す
+pub fn prompt_tags(prompt: &str, embeddings: &[String]) -> Vec<PromptTag> {
+    let mut tags = Vec::new();
+    extra_network_tags(prompt, &mut tags);
+    embedding_tags(prompt, embeddings, &mut tags);
+    attention_tags(prompt, &mut tags);
+    tags.sort_by_key(|tag| (tag.range.start, tag.range.end));
+    tags
+}
+
+// TI hashes に書かれている埋め込みの名前
+pub fn embedding_names(params: &Parameters) -> Vec<String> {
+    params.get("TI hashes").map(parse_settings_line).unwrap_or_default()
+        .into_iter()
+        .map(|field| field.key)
+        .collect()
+}
+
+// 生成に使われた LoRA・埋め込みなど。同じものは最初の 1 つだけにする
+pub fn resources(params: &Parameters) -> Vec<PromptTag> {
+    let embeddings = embedding_names(params);
+    let mut ret: Vec<PromptTag> = Vec::new();
+    for prompt in [&params.prompt, &params.negative_prompt] {
+        for tag in prompt_tags(prompt, &embeddings).into_iter().filter(|tag| tag.kind != TagKind::Attention) {
+            if !ret.iter().any(|t| t.kind == tag.kind && t.name == tag.name) {
+                ret.push(tag);
+            }
+        }
+    }
+    ret
+}
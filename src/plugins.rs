@@ -0,0 +1,121 @@
+// 対応していない形式を読むためのプラグイン
+// プラグインのフォルダーの DLL を起動時に読み込み、先頭のバイト列 (マジック) ごとに読み取り関数を登録してもらう
+// 関数の形は README の「プラグイン」を参照
+
+use std::ffi::{c_char, c_void, CStr, OsString};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use windows::{
+    core::*,
+    Win32::System::LibraryLoader::*,
+};
+use crate::fsutil;
+use crate::metadata::ImageMetadata;
+use crate::settings;
+
+// プラグインに渡すバージョン。関数の形を変えたら上げる
+pub const API_VERSION: u32 = 1;
+
+const INIT_SYMBOL: PCSTR = s!("metaview_plugin_init");
+
+type AddEntryFn = extern "C" fn(ctx: *mut c_void, key: *const u8, key_len: usize, value: *const u8, value_len: usize);
+type SetSizeFn = extern "C" fn(ctx: *mut c_void, width: u32, height: u32);
+// 0 を返したら成功
+type ParseFn = extern "C" fn(data: *const u8, len: usize, ctx: *mut c_void, add_entry: AddEntryFn, set_size: SetSizeFn) -> i32;
+type RegisterFn = extern "C" fn(registry: *mut c_void, magic: *const u8, magic_len: usize, format: *const c_char, parse: ParseFn);
+type InitFn = extern "C" fn(api_version: u32, registry: *mut c_void, register: RegisterFn) -> i32;
+
+struct Parser {
+    magic: Vec<u8>,
+    // ImageMetadata::format に入れるので 'static にする (プラグインは解放しない)
+    format: &'static str,
+    parse: ParseFn,
+}
+
+static PARSERS: RwLock<Vec<Parser>> = RwLock::new(Vec::new());
+
+pub fn plugins_dir() -> Option<PathBuf> {
+    settings::data_dir().map(|dir| dir.join("plugins"))
+}
+
+extern "C" fn register(registry: *mut c_void, magic: *const u8, magic_len: usize, format: *const c_char, parse: ParseFn) {
+    // マジックが空だとすべてのファイルに一致してしまう
+    if registry.is_null() || magic.is_null() || magic_len == 0 || format.is_null() {
+        return;
+    }
+    let parsers = unsafe { &mut *(registry as *mut Vec<Parser>) };
+    let magic = unsafe { std::slice::from_raw_parts(magic, magic_len) }.to_vec();
+    let format = unsafe { CStr::from_ptr(format) }.to_string_lossy().into_owned();
+    parsers.push(Parser { magic, format: Box::leak(format.into_boxed_str()), parse });
+}
+
+fn load_plugin(path: &Path) -> anyhow::Result<()> {
+    let module = unsafe { LoadLibraryW(&HSTRING::from(fsutil::long_path(path).as_os_str())) }?;
+    let Some(init) = (unsafe { GetProcAddress(module, INIT_SYMBOL) }) else {
+        unsafe { FreeLibrary(module) };
+        anyhow::bail!("metaview_plugin_init is not exported");
+    };
+    let init: InitFn = unsafe { std::mem::transmute(init) };
+    let mut parsers = Vec::new();
+    let ret = init(API_VERSION, &mut parsers as *mut Vec<Parser> as *mut c_void, register);
+    if ret != 0 {
+        unsafe { FreeLibrary(module) };
+        anyhow::bail!("metaview_plugin_init failed ({ret})");
+    }
+    PARSERS.write().unwrap().extend(parsers);
+    Ok(())
+}
+
+// 読み込めなかったプラグインとその理由を返す
+pub fn load() -> Vec<(PathBuf, anyhow::Error)> {
+    let Some(entries) = plugins_dir().and_then(|dir| fs::read_dir(fsutil::long_path(&dir)).ok()) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("dll")))
+        .collect();
+    // 同じマジックを登録したプラグインがあっても毎回同じものが使われるように
+    paths.sort();
+    paths.into_iter()
+        .filter_map(|path| load_plugin(&path).err().map(|e| (path, e)))
+        .collect()
+}
+
+struct ParseResult {
+    entries: Vec<(String, String)>,
+    size: (u32, u32),
+}
+
+extern "C" fn add_entry(ctx: *mut c_void, key: *const u8, key_len: usize, value: *const u8, value_len: usize) {
+    if ctx.is_null() || key.is_null() || value.is_null() {
+        return;
+    }
+    let result = unsafe { &mut *(ctx as *mut ParseResult) };
+    let key = unsafe { std::slice::from_raw_parts(key, key_len) };
+    let value = unsafe { std::slice::from_raw_parts(value, value_len) };
+    result.entries.push((String::from_utf8_lossy(key).into_owned(), String::from_utf8_lossy(value).into_owned()));
+}
+
+extern "C" fn set_size(ctx: *mut c_void, width: u32, height: u32) {
+    if let Some(result) = unsafe { (ctx as *mut ParseResult).as_mut() } {
+        result.size = (width, height);
+    }
+}
+
+// マジックが一致するプラグインがなければ None
+pub(crate) fn parse(filename: OsString, data: &[u8]) -> Option<anyhow::Result<ImageMetadata>> {
+    let parsers = PARSERS.read().unwrap();
+    let parser = parsers.iter().find(|parser| data.starts_with(&parser.magic))?;
+    let mut result = ParseResult { entries: Vec::new(), size: (0, 0) };
+    let ret = (parser.parse)(data.as_ptr(), data.len(), &mut result as *mut ParseResult as *mut c_void, add_entry, set_size);
+    if ret != 0 {
+        return Some(Err(anyhow::anyhow!("{} plugin failed to read the file ({ret})", parser.format)));
+    }
+    Some(Ok(ImageMetadata {
+        width: result.size.0,
+        height: result.size.1,
+        text_chunks: result.entries,
+        ..ImageMetadata::new(filename, parser.format, data.len())
+    }))
+}
@@ -0,0 +1,201 @@
+// PNG のチャンク単位の読み書き。書き換えないチャンクは元のバイト列をそのまま残す
+
+use std::ops::Range;
+
+pub const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+#[derive(Debug, Clone)]
+pub struct RawChunk {
+    pub kind: [u8; 4],
+    // ファイル全体の中での位置 (長さ・種類・CRC を含む)
+    pub range: Range<usize>,
+    // データ部分の位置
+    pub data: Range<usize>,
+}
+
+pub fn parse_chunks(file: &[u8]) -> anyhow::Result<Vec<RawChunk>> {
+    anyhow::ensure!(file.starts_with(PNG_SIGNATURE), "not a PNG file");
+    let mut chunks = Vec::new();
+    let mut pos = PNG_SIGNATURE.len();
+    while pos < file.len() {
+        anyhow::ensure!(pos + 12 <= file.len(), "truncated chunk at offset {pos}");
+        let len = u32::from_be_bytes(file[pos..pos + 4].try_into().unwrap()) as usize;
+        let kind: [u8; 4] = file[pos + 4..pos + 8].try_into().unwrap();
+        let end = pos.checked_add(12 + len).filter(|&end| end <= file.len());
+        let end = end.ok_or_else(|| anyhow::anyhow!("truncated chunk at offset {pos}"))?;
+        chunks.push(RawChunk { kind, range: pos..end, data: pos + 8..pos + 8 + len });
+        pos = end;
+        if &kind == b"IEND" {
+            break;
+        }
+    }
+    Ok(chunks)
+}
+
+pub fn encode_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 12);
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(kind);
+    hasher.update(data);
+    out.extend_from_slice(&hasher.finalize().to_be_bytes());
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextKind {
+    TEXt,
+    ITXt,
+}
+
+// 編集可能なテキストチャンク
+#[derive(Debug, Clone)]
+pub struct TextChunk {
+    // parse_chunks の結果の中での位置
+    pub index: usize,
+    pub kind: TextKind,
+    pub keyword: String,
+    pub language_tag: String,
+    pub translated_keyword: String,
+    pub text: String,
+}
+
+fn latin1_to_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+fn split_null(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let i = bytes.iter().position(|&b| b == 0)?;
+    Some((&bytes[..i], &bytes[i + 1..]))
+}
+
+// tEXt と非圧縮の iTXt を取り出す
+pub fn text_chunks(file: &[u8], chunks: &[RawChunk]) -> Vec<TextChunk> {
+    let mut ret = Vec::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let data = &file[chunk.data.clone()];
+        match &chunk.kind {
+            b"tEXt" => {
+                let Some((keyword, text)) = split_null(data) else { continue };
+                ret.push(TextChunk {
+                    index,
+                    kind: TextKind::TEXt,
+                    keyword: latin1_to_string(keyword),
+                    language_tag: String::new(),
+                    translated_keyword: String::new(),
+                    text: latin1_to_string(text),
+                });
+            }
+            b"iTXt" => {
+                let Some((keyword, rest)) = split_null(data) else { continue };
+                if rest.len() < 2 || rest[0] != 0 {
+                    continue;
+                }
+                let Some((language_tag, rest)) = split_null(&rest[2..]) else { continue };
+                let Some((translated_keyword, text)) = split_null(rest) else { continue };
+                ret.push(TextChunk {
+                    index,
+                    kind: TextKind::ITXt,
+                    keyword: latin1_to_string(keyword),
+                    language_tag: String::from_utf8_lossy(language_tag).into_owned(),
+                    translated_keyword: String::from_utf8_lossy(translated_keyword).into_owned(),
+                    text: String::from_utf8_lossy(text).into_owned(),
+                });
+            }
+            _ => {}
+        }
+    }
+    ret
+}
+
+// zTXt, 圧縮された iTXt, iCCP。展開は上限付きで呼び出し側が行う
+#[derive(Debug, Clone)]
+pub struct CompressedChunk {
+    pub kind: [u8; 4],
+    // iCCP ではプロファイル名
+    pub keyword: String,
+    // ファイル全体の中での zlib ストリームの位置
+    pub stream: Range<usize>,
+}
+
+pub fn compressed_chunks(file: &[u8], chunks: &[RawChunk]) -> Vec<CompressedChunk> {
+    let mut ret = Vec::new();
+    for chunk in chunks {
+        let data = &file[chunk.data.clone()];
+        let Some((keyword, rest)) = split_null(data) else { continue };
+        // 圧縮方式は 0 (zlib) しか定義されていない
+        let header_len = match &chunk.kind {
+            b"zTXt" | b"iCCP" if rest.first() == Some(&0) => 1,
+            b"iTXt" if rest.len() >= 2 && rest[0] == 1 && rest[1] == 0 => {
+                let Some((_, after_tag)) = split_null(&rest[2..]) else { continue };
+                let Some((_, text)) = split_null(after_tag) else { continue };
+                rest.len() - text.len()
+            }
+            _ => continue,
+        };
+        let start = chunk.data.start + (data.len() - rest.len()) + header_len;
+        ret.push(CompressedChunk { kind: chunk.kind, keyword: latin1_to_string(keyword), stream: start..chunk.data.end });
+    }
+    ret
+}
+
+impl TextChunk {
+    // Latin-1 で表せない文字を含む tEXt は iTXt として書き出す
+    pub fn encode(&self) -> Vec<u8> {
+        let is_latin1 = self.text.chars().all(|c| (c as u32) < 0x100);
+        let mut data: Vec<u8> = self.keyword.chars().map(|c| c as u8).collect();
+        data.push(0);
+        if self.kind == TextKind::TEXt && is_latin1 {
+            data.extend(self.text.chars().map(|c| c as u8));
+            encode_chunk(b"tEXt", &data)
+        } else {
+            data.extend_from_slice(&[0, 0]);
+            data.extend_from_slice(self.language_tag.as_bytes());
+            data.push(0);
+            data.extend_from_slice(self.translated_keyword.as_bytes());
+            data.push(0);
+            data.extend_from_slice(self.text.as_bytes());
+            encode_chunk(b"iTXt", &data)
+        }
+    }
+}
+
+// PNG のキーワードは 1〜79 文字の Latin-1 で、前後の空白や連続した空白は許されない
+pub fn is_valid_keyword(keyword: &str) -> bool {
+    let printable = keyword.chars().all(|c| matches!(c as u32, 32..=126 | 161..=255));
+    (1..=79).contains(&keyword.chars().count())
+        && printable
+        && !keyword.starts_with(' ')
+        && !keyword.ends_with(' ')
+        && !keyword.contains("  ")
+}
+
+// IEND の直前に新しいチャンクを挿入したファイルの内容を作る
+pub fn insert_before_iend(file: &[u8], chunks: &[RawChunk], new_chunk: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let iend = chunks.iter().find(|c| &c.kind == b"IEND")
+        .ok_or_else(|| anyhow::anyhow!("IEND chunk not found"))?;
+    let mut out = Vec::with_capacity(file.len() + new_chunk.len());
+    out.extend_from_slice(&file[..iend.range.start]);
+    out.extend_from_slice(new_chunk);
+    out.extend_from_slice(&file[iend.range.start..]);
+    Ok(out)
+}
+
+// 指定したチャンクだけを置き換えたファイルの内容を作る
+pub fn rewrite(file: &[u8], chunks: &[RawChunk], replacements: &[(usize, Vec<u8>)]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(file.len());
+    out.extend_from_slice(&file[..PNG_SIGNATURE.len()]);
+    for (index, chunk) in chunks.iter().enumerate() {
+        match replacements.iter().find(|(i, _)| *i == index) {
+            Some((_, bytes)) => out.extend_from_slice(bytes),
+            None => out.extend_from_slice(&file[chunk.range.clone()]),
+        }
+    }
+    // IEND の後ろのデータも残す
+    if let Some(last) = chunks.last() {
+        out.extend_from_slice(&file[last.range.end..]);
+    }
+    out
+}
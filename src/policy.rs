@@ -0,0 +1,206 @@
+// 納品物などのメタデータの決まりをファイルに書き、画像がそれを守っているか調べる
+//
+//   # 著作権表示は必ず入れる
+//   require copyright
+//   # 位置情報とプロンプトは入れない
+//   forbid gps
+//   forbid prompt
+//   # Author か Artist のどちらかがあり、著作権表示は © から始める
+//   require Author | Artist
+//   match copyright ^©
+//
+// 項目はテキストチャンクのキーワード (大文字と小文字は区別せず、* は任意の文字列) か、次のまとめた名前
+//   gps, exif, prompt (生成パラメーター、プロンプト、ワークフロー), c2pa, copyright (EXIF、IPTC、XMP、テキストチャンク)
+
+use std::fs;
+use std::path::PathBuf;
+use regex::{Regex, RegexBuilder};
+use crate::exif;
+use crate::i18n::{tr, Msg};
+use crate::infotext;
+use crate::metadata::ImageMetadata;
+use crate::params;
+use crate::settings;
+use crate::xml;
+
+// 違反の一覧で値を切り詰める文字数
+const MAX_VALUE_CHARS: usize = 60;
+
+#[derive(Debug, Clone)]
+enum Field {
+    Gps,
+    Exif,
+    Prompt,
+    C2pa,
+    Copyright,
+    // テキストチャンクのキーワード
+    Chunk(Regex),
+}
+
+#[derive(Debug, Clone)]
+enum Rule {
+    // どれか 1 つあればよい
+    Require(Vec<Field>),
+    Forbid(Field),
+    // 値があれば、どれもパターンに一致しなければならない
+    Match(Field, Regex),
+}
+
+#[derive(Debug, Clone)]
+pub struct Policy {
+    // 書かれたとおりの行と規則
+    rules: Vec<(String, Rule)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Violation {
+    // 違反した規則の行
+    pub rule: String,
+    // 見つかった値など
+    pub detail: String,
+}
+
+pub fn policy_path() -> Option<PathBuf> {
+    settings::data_dir().map(|dir| dir.join("policy.txt"))
+}
+
+fn parse_field(name: &str) -> anyhow::Result<Field> {
+    let field = match name.to_ascii_lowercase().as_str() {
+        "gps" => Field::Gps,
+        "exif" => Field::Exif,
+        "prompt" => Field::Prompt,
+        "c2pa" => Field::C2pa,
+        "copyright" => Field::Copyright,
+        _ => {
+            let pattern = format!("^{}$", regex::escape(name).replace(r"\*", ".*"));
+            Field::Chunk(RegexBuilder::new(&pattern).case_insensitive(true).build()?)
+        }
+    };
+    Ok(field)
+}
+
+fn parse_rule(line: &str) -> anyhow::Result<Rule> {
+    let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let rest = rest.trim();
+    if rest.is_empty() {
+        anyhow::bail!(tr(Msg::PolicyMissingField));
+    }
+    let rule = match command.to_ascii_lowercase().as_str() {
+        "require" => Rule::Require(rest.split('|').map(|name| parse_field(name.trim())).collect::<anyhow::Result<_>>()?),
+        "forbid" => Rule::Forbid(parse_field(rest)?),
+        "match" => {
+            let (name, pattern) = rest.split_once(char::is_whitespace).ok_or_else(|| anyhow::anyhow!(tr(Msg::PolicyMissingPattern)))?;
+            let pattern = Regex::new(pattern.trim()).map_err(|e| anyhow::anyhow!("{}: {}", tr(Msg::InvalidPattern), e.to_string().lines().last().unwrap_or("")))?;
+            Rule::Match(parse_field(name)?, pattern)
+        }
+        _ => anyhow::bail!("{}: {command}", tr(Msg::PolicyUnknownRule)),
+    };
+    Ok(rule)
+}
+
+impl Policy {
+    // 書き間違いはそのまま調べると見落としになるので、行番号を付けてエラーにする
+    pub fn parse(text: &str) -> anyhow::Result<Policy> {
+        let mut rules = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let rule = parse_rule(line).map_err(|e| anyhow::anyhow!("policy.txt ({} {}): {e}", tr(Msg::Line), i + 1))?;
+            rules.push((line.to_owned(), rule));
+        }
+        if rules.is_empty() {
+            anyhow::bail!(tr(Msg::NoPolicyRules));
+        }
+        Ok(Policy { rules })
+    }
+
+    pub fn load() -> anyhow::Result<Policy> {
+        let path = policy_path().ok_or_else(|| anyhow::anyhow!("APPDATA is not set"))?;
+        let text = fs::read_to_string(path).map_err(|_| anyhow::anyhow!(tr(Msg::NoPolicyRules)))?;
+        Policy::parse(&text)
+    }
+
+    pub fn check(&self, metadata: &ImageMetadata) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        for (line, rule) in &self.rules {
+            let detail = match rule {
+                Rule::Require(fields) if fields.iter().all(|field| values(metadata, field).is_empty()) => Some(tr(Msg::PolicyMissing).to_owned()),
+                Rule::Require(_) => None,
+                Rule::Forbid(field) => values(metadata, field).into_iter().next().map(|(key, value)| describe(&key, &value)),
+                Rule::Match(field, pattern) => values(metadata, field).into_iter()
+                    .find(|(_, value)| !pattern.is_match(value))
+                    .map(|(key, value)| describe(&key, &value)),
+            };
+            if let Some(detail) = detail {
+                violations.push(Violation { rule: line.clone(), detail });
+            }
+        }
+        violations
+    }
+}
+
+fn truncate(text: &str) -> String {
+    let text = text.replace(['\r', '\n'], " ");
+    match text.char_indices().nth(MAX_VALUE_CHARS) {
+        Some((i, _)) => format!("{}…", &text[..i]),
+        None => text,
+    }
+}
+
+fn describe(key: &str, value: &str) -> String {
+    if value.is_empty() { key.to_owned() } else { format!("{key} = {}", truncate(value)) }
+}
+
+// 項目に当たる (名前, 値)。値のない項目 (EXIF があること自体など) は空文字列にする
+fn values(metadata: &ImageMetadata, field: &Field) -> Vec<(String, String)> {
+    let chunks = |matches: &dyn Fn(&str) -> bool| -> Vec<(String, String)> {
+        metadata.text_chunks.iter().filter(|(key, _)| matches(key)).cloned().collect()
+    };
+    match field {
+        Field::Gps => metadata.gps.iter().map(|gps| ("GPS".to_owned(), format!("{:.6}, {:.6}", gps.latitude, gps.longitude))).collect(),
+        Field::Exif => metadata.exif.iter().map(|_| ("EXIF".to_owned(), String::new())).collect(),
+        Field::C2pa => metadata.c2pa.iter().map(|_| ("C2PA".to_owned(), String::new())).collect(),
+        Field::Prompt => {
+            let prompt = params::find_prompt(&metadata.text_chunks).or_else(|| infotext::to_infotext(&metadata.text_chunks));
+            let mut ret: Vec<(String, String)> = prompt.into_iter().map(|prompt| (tr(Msg::Prompt).to_owned(), prompt)).collect();
+            ret.extend(chunks(&|key| key == "workflow"));
+            ret
+        }
+        Field::Copyright => {
+            let exif = metadata.exif.clone().and_then(|range| exif::copyright(&metadata.data[range]));
+            let mut ret: Vec<(String, String)> = exif.into_iter().map(|copyright| ("EXIF:Copyright".to_owned(), copyright)).collect();
+            ret.extend(chunks(&|key| key.to_ascii_lowercase().contains("copyright")));
+            ret.extend(metadata.text_chunks.iter().filter_map(|(_, text)| xmp_rights(text)).map(|rights| ("XMP:Rights".to_owned(), rights)));
+            ret
+        }
+        Field::Chunk(pattern) => chunks(&|key| pattern.is_match(key)),
+    }
+}
+
+// XMP の <dc:rights> の中の文字列 (rdf:Alt の rdf:li など、タグを除いたもの)
+fn xmp_rights(xmp: &str) -> Option<String> {
+    let start = xmp.find("<dc:rights")?;
+    let end = start + xmp[start..].find("</dc:rights>")?;
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in xmp[start..end].chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    let text = xml::unescape(text.trim()).into_owned();
+    (!text.is_empty()).then_some(text)
+}
+
+// 1 つのファイルの結果。violations が空なら決まりを守っている
+pub fn format_violations(violations: &[Violation]) -> String {
+    if violations.is_empty() {
+        return format!("{}\r\n", tr(Msg::PolicyPassed));
+    }
+    violations.iter().map(|v| format!("✗ {} — {}\r\n", v.rule, v.detail)).collect()
+}
@@ -0,0 +1,81 @@
+// フォルダーの中の画像 (サブフォルダーも) が、policy.txt に書いたメタデータの決まりを守っているか調べる
+
+use std::path::PathBuf;
+use windows::Win32::{
+    Foundation::*,
+    UI::WindowsAndMessaging::*,
+};
+use crate::batch::{self, ScannedImage};
+use crate::i18n::{tr, Msg};
+use crate::metadata::Source;
+use crate::policy::{self, Policy, Violation};
+
+// lparam: Box<anyhow::Result<PolicyReport>> のポインタ
+pub const WM_APP_POLICY_DONE: u32 = WM_APP + 15;
+
+#[derive(Debug)]
+pub struct PolicyReport {
+    pub folder: PathBuf,
+    pub checked: usize,
+    pub errors: usize,
+    // 決まりを守っていない画像 (見つけた順)
+    pub failures: Vec<(ScannedImage, Vec<Violation>)>,
+}
+
+// 進み具合は集計と同じ batch::WM_APP_SCAN_PROGRESS で知らせる
+pub fn start(hwnd: HWND, policy: Policy, folder: PathBuf) {
+    std::thread::spawn(move || {
+        let result = check_folder(hwnd, &policy, folder);
+        let result = Box::into_raw(Box::new(result));
+        let posted = unsafe { PostMessageW(hwnd, WM_APP_POLICY_DONE, WPARAM(0), LPARAM(result as isize)) };
+        if !posted.as_bool() {
+            drop(unsafe { Box::from_raw(result) });
+        }
+    });
+}
+
+// WM_APP_POLICY_DONE の lparam から結果を取り出す
+pub unsafe fn take_result(lparam: LPARAM) -> anyhow::Result<PolicyReport> {
+    *Box::from_raw(lparam.0 as *mut anyhow::Result<PolicyReport>)
+}
+
+fn check_folder(hwnd: HWND, policy: &Policy, folder: PathBuf) -> anyhow::Result<PolicyReport> {
+    let mut files = Vec::new();
+    batch::collect_images(&folder, &mut files)?;
+    let mut report = PolicyReport { folder, checked: 0, errors: 0, failures: Vec::new() };
+    for (i, path) in files.iter().enumerate() {
+        match Source::File(path.clone().into_os_string()).read_metadata() {
+            Ok(metadata) => {
+                report.checked += 1;
+                let violations = policy.check(&metadata);
+                if !violations.is_empty() {
+                    let image = ScannedImage { path: path.clone(), orientation: metadata.orientation };
+                    report.failures.push((image, violations));
+                }
+            }
+            Err(_) => report.errors += 1,
+        }
+        unsafe { PostMessageW(hwnd, batch::WM_APP_SCAN_PROGRESS, WPARAM(i + 1), LPARAM(files.len() as isize)) };
+    }
+    Ok(report)
+}
+
+impl PolicyReport {
+    pub fn format(&self) -> String {
+        let mut ret = format!("【{}】\r\n", tr(Msg::PolicyCheck));
+        ret.push_str(&format!("{}: {}\r\n", tr(Msg::Folder), self.folder.display()));
+        ret.push_str(&format!("{}: {}\r\n", tr(Msg::FilesChecked), self.checked));
+        ret.push_str(&format!("{}: {}\r\n", tr(Msg::PolicyViolations), self.failures.len()));
+        if self.errors > 0 {
+            ret.push_str(&format!("{}: {}\r\n", tr(Msg::Error), self.errors));
+        }
+        for (image, violations) in &self.failures {
+            let name = image.path.strip_prefix(&self.folder).unwrap_or(&image.path);
+            ret.push_str(&format!("\r\n{}\r\n", name.display()));
+            for line in policy::format_violations(violations).lines() {
+                ret.push_str(&format!("  {line}\r\n"));
+            }
+        }
+        ret
+    }
+}
@@ -0,0 +1,378 @@
+// 画像のプレビュー欄。全体表示・等倍・ホイールでの拡大縮小とドラッグでの移動ができる
+// 透明な部分は市松模様の上に重ねて表示する
+
+use std::path::PathBuf;
+use windows::{
+    core::*,
+    Win32::{
+        Foundation::*,
+        Graphics::Gdi::*,
+        System::Com::*,
+        UI::{Input::KeyboardAndMouse::*, WindowsAndMessaging::*},
+    },
+};
+use crate::imaging::{self, Bitmap};
+use crate::watermark;
+
+const CLASS_NAME: PCWSTR = w!("MetaViewPreview");
+
+// wparam: デコードを始めたときの番号, lparam: Box<Decoded> のポインタ
+pub const WM_APP_PREVIEW_DONE: u32 = WM_APP + 17;
+
+// プレビューは大きすぎる画像を縮小して持つ (等倍表示はこの大きさまで)。メモリの上限でさらに縮小することもある
+const MAX_PREVIEW_SIZE: u32 = 8192;
+// WIC で読めない形式はエクスプローラーのサムネイルで代わりにする (ディスク上のファイルだけ)
+const SHELL_THUMBNAIL_SIZE: u32 = 1024;
+
+// 拡大縮小の範囲と、ホイール 1 段あたりの倍率
+const MIN_SCALE: f64 = 1.0 / 64.0;
+const MAX_SCALE: f64 = 32.0;
+const WHEEL_STEP: f64 = 1.25;
+// 市松模様の 1 マスの大きさ
+const CHECKER_SIZE: i32 = 8;
+
+struct Preview {
+    // アルファを乗算済みの DIB
+    image: HBITMAP,
+    width: u32,
+    height: u32,
+    // None なら欄に収まるように縮小する
+    scale: Option<f64>,
+    // 欄の中央に来る画像上の位置
+    center: (f64, f64),
+    // ドラッグを始めたときのカーソルの位置と center
+    drag: Option<(POINT, (f64, f64))>,
+    checker: HBRUSH,
+}
+
+impl Drop for Preview {
+    fn drop(&mut self) {
+        if !self.image.is_invalid() {
+            unsafe { DeleteObject(self.image) };
+        }
+        unsafe { DeleteObject(self.checker) };
+    }
+}
+
+pub fn register_class(instance: HINSTANCE) -> anyhow::Result<()> {
+    let wc = WNDCLASSEXW {
+        cbSize: std::mem::size_of::<WNDCLASSEXW>() as u32,
+        lpfnWndProc: Some(wndproc),
+        hInstance: instance,
+        hCursor: unsafe { LoadCursorW(None, IDC_ARROW)? },
+        lpszClassName: CLASS_NAME,
+        ..Default::default()
+    };
+    let atom = unsafe { RegisterClassExW(&wc) };
+    anyhow::ensure!(atom != 0, "RegisterClassExW failed");
+    Ok(())
+}
+
+pub fn create(parent: HWND, instance: HINSTANCE, id: isize) -> HWND {
+    unsafe { CreateWindowExW(
+        WINDOW_EX_STYLE::default(),
+        CLASS_NAME,
+        None,
+        WINDOW_STYLE(WS_CHILD.0 | WS_CLIPSIBLINGS.0),
+        0, 0, 0, 0,
+        parent, HMENU(id), instance, None) }
+}
+
+unsafe fn get_state<'a>(hwnd: HWND) -> Option<&'a mut Preview> {
+    (GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut Preview).as_mut()
+}
+
+// 表示する画像を入れ替える。向きは呼び出し側で直しておく
+pub fn set_image(hwnd: HWND, bitmap: Option<Bitmap>) {
+    let Some(state) = (unsafe { get_state(hwnd) }) else {
+        return;
+    };
+    if !state.image.is_invalid() {
+        unsafe { DeleteObject(state.image) };
+    }
+    state.image = HBITMAP(0);
+    (state.width, state.height) = (0, 0);
+    if let Some(mut bitmap) = bitmap {
+        // AlphaBlend は乗算済みのアルファを前提にしている
+        for pixel in bitmap.pixels.chunks_exact_mut(4) {
+            let alpha = pixel[3] as u32;
+            for c in &mut pixel[..3] {
+                *c = (*c as u32 * alpha / 255) as u8;
+            }
+        }
+        if let Ok(image) = imaging::create_dib(bitmap.width, bitmap.height, &bitmap.pixels) {
+            state.image = image;
+            (state.width, state.height) = (bitmap.width, bitmap.height);
+        }
+    }
+    state.scale = None;
+    state.center = (state.width as f64 / 2.0, state.height as f64 / 2.0);
+    unsafe { InvalidateRect(hwnd, None, false) };
+}
+
+// 読み込んだ画像の画素から作るもの。大きな画像のデコードで止まらないように、まとめて別のスレッドで作る
+pub struct DecodeRequest {
+    pub data: Vec<u8>,
+    pub path: Option<PathBuf>,
+    pub orientation: u16,
+    pub preview: bool,
+    // 透かしを調べるときは等倍の大きさ
+    pub watermarks: Option<(u32, u32)>,
+    // ウィンドウアイコンの小と大の大きさ
+    pub icons: Option<(u32, u32)>,
+}
+
+// 頼まなかったものは None (preview は頼んだかどうかを requested_preview で見る)
+pub struct Decoded {
+    pub requested_preview: bool,
+    pub preview: Option<Bitmap>,
+    pub watermarks: Option<Vec<watermark::Watermark>>,
+    pub icons: Option<(Bitmap, Bitmap)>,
+}
+
+fn decode(request: DecodeRequest) -> Decoded {
+    let preview = if request.preview {
+        match imaging::decode_scaled(&request.data, MAX_PREVIEW_SIZE, MAX_PREVIEW_SIZE) {
+            Ok(bitmap) => Some(imaging::apply_orientation(bitmap, request.orientation)),
+            Err(_) => request.path.as_deref().and_then(|path| imaging::shell_thumbnail(path, SHELL_THUMBNAIL_SIZE).ok()),
+        }
+    } else {
+        None
+    };
+    // 縮小すると透かしが読めなくなるので等倍でデコードする
+    let watermarks = request.watermarks.map(|(width, height)| {
+        match imaging::decode_scaled(&request.data, width, height) {
+            Ok(bitmap) => watermark::detect(&bitmap.pixels, bitmap.width, bitmap.height),
+            Err(_) => Vec::new(),
+        }
+    });
+    let icons = request.icons.and_then(|(small, big)| {
+        Some((imaging::decode_scaled(&request.data, small, small).ok()?, imaging::decode_scaled(&request.data, big, big).ok()?))
+    });
+    Decoded { requested_preview: request.preview, preview, watermarks, icons }
+}
+
+// 別のスレッドでデコードして notify に WM_APP_PREVIEW_DONE を送る
+// job は結果が届くまでに別のファイルが開かれていないかを確かめるための番号
+pub fn start_decode(notify: HWND, job: usize, request: DecodeRequest) {
+    std::thread::spawn(move || {
+        // シェルのサムネイルハンドラーはシングルスレッドアパートメントを前提にしているものがある
+        let initialized = unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED) }.is_ok();
+        let decoded = decode(request);
+        if initialized {
+            unsafe { CoUninitialize() };
+        }
+        let decoded = Box::into_raw(Box::new(decoded));
+        let posted = unsafe { PostMessageW(notify, WM_APP_PREVIEW_DONE, WPARAM(job), LPARAM(decoded as isize)) };
+        if !posted.as_bool() {
+            drop(unsafe { Box::from_raw(decoded) });
+        }
+    });
+}
+
+// WM_APP_PREVIEW_DONE の lparam から結果を取り出す
+pub unsafe fn take_result(lparam: LPARAM) -> Decoded {
+    *Box::from_raw(lparam.0 as *mut Decoded)
+}
+
+// 欄に収まるように表示する
+pub fn fit(hwnd: HWND) {
+    if let Some(state) = unsafe { get_state(hwnd) } {
+        state.scale = None;
+        state.center = (state.width as f64 / 2.0, state.height as f64 / 2.0);
+        unsafe { InvalidateRect(hwnd, None, false) };
+    }
+}
+
+// 画像の 1 ピクセルを画面の 1 ピクセルで表示する
+pub fn actual_size(hwnd: HWND) {
+    if let Some(state) = unsafe { get_state(hwnd) } {
+        state.scale = Some(1.0);
+        unsafe { InvalidateRect(hwnd, None, false) };
+    }
+}
+
+// 欄の中央を中心に拡大縮小する
+pub fn zoom(hwnd: HWND, steps: f64) {
+    let mut rect = RECT::default();
+    unsafe { GetClientRect(hwnd, &mut rect) };
+    zoom_at(hwnd, steps, POINT { x: rect.right / 2, y: rect.bottom / 2 });
+}
+
+fn client_size(hwnd: HWND) -> (f64, f64) {
+    let mut rect = RECT::default();
+    unsafe { GetClientRect(hwnd, &mut rect) };
+    (rect.right as f64, rect.bottom as f64)
+}
+
+fn current_scale(hwnd: HWND, state: &Preview) -> f64 {
+    state.scale.unwrap_or_else(|| {
+        let (cw, ch) = client_size(hwnd);
+        // 全体表示でも小さい画像は拡大しない
+        f64::min(1.0, f64::min(cw / state.width.max(1) as f64, ch / state.height.max(1) as f64))
+    })
+}
+
+// カーソルの下の点が動かないように拡大縮小する
+fn zoom_at(hwnd: HWND, steps: f64, cursor: POINT) {
+    let Some(state) = (unsafe { get_state(hwnd) }) else {
+        return;
+    };
+    let (cw, ch) = client_size(hwnd);
+    let scale = current_scale(hwnd, state);
+    let new_scale = (scale * WHEEL_STEP.powf(steps)).clamp(MIN_SCALE, MAX_SCALE);
+    let (dx, dy) = (cursor.x as f64 - cw / 2.0, cursor.y as f64 - ch / 2.0);
+    let point = (state.center.0 + dx / scale, state.center.1 + dy / scale);
+    state.center = (point.0 - dx / new_scale, point.1 - dy / new_scale);
+    state.scale = Some(new_scale);
+    unsafe { InvalidateRect(hwnd, None, false) };
+}
+
+// 8x8 のマスを 2x2 並べた模様のブラシ
+fn create_checker_brush() -> HBRUSH {
+    let size = (CHECKER_SIZE * 2) as usize;
+    let mut pixels = vec![0u8; size * size * 4];
+    for y in 0..size {
+        for x in 0..size {
+            let light = (x / CHECKER_SIZE as usize + y / CHECKER_SIZE as usize).is_multiple_of(2);
+            let v = if light { 0xff } else { 0xcc };
+            pixels[(y * size + x) * 4..][..4].copy_from_slice(&[v, v, v, 0xff]);
+        }
+    }
+    let Ok(bitmap) = imaging::create_dib(size as u32, size as u32, &pixels) else {
+        return HBRUSH(unsafe { GetStockObject(WHITE_BRUSH) }.0);
+    };
+    let brush = unsafe { CreatePatternBrush(bitmap) };
+    unsafe { DeleteObject(bitmap) };
+    brush
+}
+
+fn paint(hwnd: HWND, state: &Preview) {
+    let mut ps = PAINTSTRUCT::default();
+    let hdc = unsafe { BeginPaint(hwnd, &mut ps) };
+    let mut rect = RECT::default();
+    unsafe { GetClientRect(hwnd, &mut rect) };
+
+    // ちらつかないように裏で描いてからまとめて転送する
+    let mem_dc = unsafe { CreateCompatibleDC(hdc) };
+    let mem_bitmap = unsafe { CreateCompatibleBitmap(hdc, rect.right, rect.bottom) };
+    let old_bitmap = unsafe { SelectObject(mem_dc, mem_bitmap) };
+    unsafe { FillRect(mem_dc, &rect, GetSysColorBrush(COLOR_APPWORKSPACE)) };
+
+    if !state.image.is_invalid() {
+        let scale = current_scale(hwnd, state);
+        let left = rect.right as f64 / 2.0 - state.center.0 * scale;
+        let top = rect.bottom as f64 / 2.0 - state.center.1 * scale;
+        // 見えている範囲だけを転送する (拡大したときに転送先が大きくなりすぎないように)
+        let x0 = ((-left / scale).floor().max(0.0) as i32).min(state.width as i32);
+        let y0 = ((-top / scale).floor().max(0.0) as i32).min(state.height as i32);
+        let x1 = (((rect.right as f64 - left) / scale).ceil() as i32).clamp(x0, state.width as i32);
+        let y1 = (((rect.bottom as f64 - top) / scale).ceil() as i32).clamp(y0, state.height as i32);
+        let dest = RECT {
+            left: (left + x0 as f64 * scale).round() as i32,
+            top: (top + y0 as f64 * scale).round() as i32,
+            right: (left + x1 as f64 * scale).round() as i32,
+            bottom: (top + y1 as f64 * scale).round() as i32,
+        };
+        if dest.right > dest.left && dest.bottom > dest.top {
+            unsafe { FillRect(mem_dc, &dest, state.checker) };
+            let image_dc = unsafe { CreateCompatibleDC(hdc) };
+            let old_image = unsafe { SelectObject(image_dc, state.image) };
+            let blend = BLENDFUNCTION {
+                BlendOp: AC_SRC_OVER as u8,
+                BlendFlags: 0,
+                SourceConstantAlpha: 255,
+                AlphaFormat: AC_SRC_ALPHA as u8,
+            };
+            unsafe { AlphaBlend(mem_dc, dest.left, dest.top, dest.right - dest.left, dest.bottom - dest.top,
+                image_dc, x0, y0, x1 - x0, y1 - y0, blend) };
+            unsafe { SelectObject(image_dc, old_image) };
+            unsafe { DeleteDC(image_dc) };
+        }
+    }
+
+    unsafe { BitBlt(hdc, 0, 0, rect.right, rect.bottom, mem_dc, 0, 0, SRCCOPY) };
+    unsafe { SelectObject(mem_dc, old_bitmap) };
+    unsafe { DeleteObject(mem_bitmap) };
+    unsafe { DeleteDC(mem_dc) };
+    unsafe { EndPaint(hwnd, &ps) };
+}
+
+extern "system" fn wndproc(hwnd: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    match message {
+        WM_CREATE => {
+            let state = Box::new(Preview {
+                image: HBITMAP(0),
+                width: 0,
+                height: 0,
+                scale: None,
+                center: (0.0, 0.0),
+                drag: None,
+                checker: create_checker_brush(),
+            });
+            unsafe { SetWindowLongPtrW(hwnd, GWLP_USERDATA, Box::into_raw(state) as isize) };
+            LRESULT::default()
+        }
+        WM_NCDESTROY => {
+            let state = unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut Preview;
+            if !state.is_null() {
+                unsafe { SetWindowLongPtrW(hwnd, GWLP_USERDATA, 0) };
+                drop(unsafe { Box::from_raw(state) });
+            }
+            LRESULT::default()
+        }
+        WM_PAINT => {
+            match unsafe { get_state(hwnd) } {
+                Some(state) => paint(hwnd, state),
+                None => return unsafe { DefWindowProcW(hwnd, message, wparam, lparam) },
+            }
+            LRESULT::default()
+        }
+        // 全体を WM_PAINT で塗るので背景は消さない
+        WM_ERASEBKGND => LRESULT(1),
+        WM_SIZE => {
+            unsafe { InvalidateRect(hwnd, None, false) };
+            LRESULT::default()
+        }
+        WM_MOUSEWHEEL => {
+            let delta = (wparam.0 >> 16) as u16 as i16;
+            // 座標はスクリーン座標で入っている
+            let mut cursor = POINT { x: (lparam.0 & 0xffff) as i16 as i32, y: ((lparam.0 >> 16) & 0xffff) as i16 as i32 };
+            unsafe { ScreenToClient(hwnd, &mut cursor) };
+            zoom_at(hwnd, delta as f64 / WHEEL_DELTA as f64, cursor);
+            LRESULT::default()
+        }
+        WM_LBUTTONDOWN => {
+            if let Some(state) = unsafe { get_state(hwnd) } {
+                let cursor = POINT { x: (lparam.0 & 0xffff) as i16 as i32, y: ((lparam.0 >> 16) & 0xffff) as i16 as i32 };
+                state.drag = Some((cursor, state.center));
+                unsafe { SetFocus(hwnd) };
+                unsafe { SetCapture(hwnd) };
+            }
+            LRESULT::default()
+        }
+        WM_MOUSEMOVE => {
+            if let Some(state) = unsafe { get_state(hwnd) } {
+                if let Some((start, center)) = state.drag {
+                    let x = (lparam.0 & 0xffff) as i16 as i32;
+                    let y = ((lparam.0 >> 16) & 0xffff) as i16 as i32;
+                    let scale = current_scale(hwnd, state);
+                    // 全体表示のままでもドラッグしたら今の倍率に固定する
+                    state.scale = Some(scale);
+                    state.center = (center.0 - (x - start.x) as f64 / scale, center.1 - (y - start.y) as f64 / scale);
+                    unsafe { InvalidateRect(hwnd, None, false) };
+                }
+            }
+            LRESULT::default()
+        }
+        WM_LBUTTONUP | WM_CAPTURECHANGED => {
+            if let Some(state) = unsafe { get_state(hwnd) } {
+                if state.drag.take().is_some() && message == WM_LBUTTONUP {
+                    unsafe { ReleaseCapture() };
+                }
+            }
+            LRESULT::default()
+        }
+        _ => unsafe { DefWindowProcW(hwnd, message, wparam, lparam) },
+    }
+}
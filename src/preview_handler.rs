@@ -0,0 +1,188 @@
+// エクスプローラーのプレビューウィンドウにメタデータを表示するプレビューハンドラー
+// 現在のユーザーにだけ登録する
+
+use std::cell::RefCell;
+use std::ffi::{c_void, OsString};
+use windows::core::*;
+use windows::Win32::Foundation::*;
+use windows::Win32::Graphics::Gdi::*;
+use windows::Win32::System::Com::*;
+use windows::Win32::System::Ole::*;
+use windows::Win32::System::Registry::HKEY_CURRENT_USER;
+use windows::Win32::UI::Input::KeyboardAndMouse::{GetFocus, SetFocus};
+use windows::Win32::UI::Shell::PropertiesSystem::*;
+use windows::Win32::UI::Shell::*;
+use windows::Win32::UI::WindowsAndMessaging::*;
+use crate::{extract, i18n, inflate, metadata};
+use crate::settings::Settings;
+
+// {7C3E5A91-4B2D-4F8E-A6C1-9D0B2E7F3A54}
+pub const CLSID: GUID = GUID::from_u128(0x7c3e5a91_4b2d_4f8e_a6c1_9d0b2e7f3a54);
+const HANDLER_NAME: &str = "MetaView Preview Handler";
+// プレビューハンドラーを表す ShellEx のキー
+const PREVIEW_HANDLER_SHELLEX: &str = "{8895b1c6-b41f-4c1c-a562-0d564250836f}";
+// 64 ビット版 prevhost.exe の AppID
+const PREVHOST_APPID: &str = "{6d2b5079-2f0b-48dd-ab7f-97cec514d30b}";
+const PREVIEW_HANDLERS_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\PreviewHandlers";
+
+#[derive(Default)]
+struct State {
+    data: Option<Vec<u8>>,
+    parent: HWND,
+    rect: RECT,
+    hedit: HWND,
+    site: Option<IUnknown>,
+}
+
+#[implement(IPreviewHandler, IInitializeWithStream, IObjectWithSite, IOleWindow)]
+#[derive(Default)]
+struct PreviewHandler {
+    state: RefCell<State>,
+}
+
+impl IInitializeWithStream_Impl for PreviewHandler {
+    fn Initialize(&self, pstream: &Option<IStream>, _grfmode: u32) -> Result<()> {
+        let stream = pstream.as_ref().ok_or_else(|| Error::from(E_INVALIDARG))?;
+        self.state.borrow_mut().data = Some(crate::read_stream(stream)?);
+        Ok(())
+    }
+}
+
+impl IPreviewHandler_Impl for PreviewHandler {
+    fn SetWindow(&self, hwnd: HWND, prc: *const RECT) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        state.parent = hwnd;
+        state.rect = unsafe { *prc };
+        if state.hedit != HWND(0) {
+            unsafe { SetParent(state.hedit, hwnd) };
+        }
+        drop(state);
+        self.SetRect(prc)
+    }
+
+    fn SetRect(&self, prc: *const RECT) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        state.rect = unsafe { *prc };
+        let rect = state.rect;
+        if state.hedit != HWND(0) {
+            unsafe { MoveWindow(state.hedit, rect.left, rect.top, rect.right - rect.left, rect.bottom - rect.top, true) };
+        }
+        Ok(())
+    }
+
+    fn DoPreview(&self) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        let data = state.data.take().ok_or_else(|| Error::from(E_FAIL))?;
+        let settings = Settings::load();
+        i18n::set_language(settings.effective_language());
+        inflate::set_limits(settings.inflate_limits);
+        let text = match metadata::parse_metadata(OsString::new(), data) {
+            Ok(mut metadata) => {
+                metadata.extracted = extract::run(&settings.extract_rules, &metadata.text_chunks);
+                metadata::format_metadata(&metadata, &settings)
+            }
+            Err(e) => e.to_string(),
+        };
+
+        let rect = state.rect;
+        let hedit = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            w!("EDIT"),
+            &HSTRING::from(text),
+            WINDOW_STYLE(WS_CHILD.0 | WS_VISIBLE.0 | WS_VSCROLL.0 |
+                ES_MULTILINE as u32 | ES_READONLY as u32 | ES_AUTOVSCROLL as u32),
+            rect.left, rect.top, rect.right - rect.left, rect.bottom - rect.top,
+            state.parent, None, crate::instance(), None) };
+        if hedit == HWND(0) {
+            return Err(Error::from_win32());
+        }
+        let font = unsafe { GetStockObject(DEFAULT_GUI_FONT) };
+        unsafe { SendMessageW(hedit, WM_SETFONT, WPARAM(font.0 as usize), LPARAM(1)) };
+        state.hedit = hedit;
+        Ok(())
+    }
+
+    fn Unload(&self) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        if state.hedit != HWND(0) {
+            unsafe { DestroyWindow(state.hedit) };
+            state.hedit = HWND(0);
+        }
+        state.data = None;
+        Ok(())
+    }
+
+    fn SetFocus(&self) -> Result<()> {
+        let hedit = self.state.borrow().hedit;
+        if hedit != HWND(0) {
+            unsafe { SetFocus(hedit) };
+        }
+        Ok(())
+    }
+
+    fn QueryFocus(&self) -> Result<HWND> {
+        let hwnd = unsafe { GetFocus() };
+        if hwnd == HWND(0) {
+            return Err(Error::from_win32());
+        }
+        Ok(hwnd)
+    }
+
+    fn TranslateAccelerator(&self, pmsg: *const MSG) -> Result<()> {
+        // 自分では何も処理せず、エクスプローラー側に任せる
+        let frame = match &self.state.borrow().site {
+            Some(site) => site.cast::<IPreviewHandlerFrame>()?,
+            None => return Err(S_FALSE.into()),
+        };
+        unsafe { frame.TranslateAccelerator(pmsg) }
+    }
+}
+
+impl IObjectWithSite_Impl for PreviewHandler {
+    fn SetSite(&self, punksite: &Option<IUnknown>) -> Result<()> {
+        self.state.borrow_mut().site = punksite.clone();
+        Ok(())
+    }
+
+    fn GetSite(&self, riid: *const GUID, ppvsite: *mut *mut c_void) -> Result<()> {
+        match &self.state.borrow().site {
+            Some(site) => unsafe { site.query(&*riid, ppvsite as _) }.ok(),
+            None => Err(E_FAIL.into()),
+        }
+    }
+}
+
+impl IOleWindow_Impl for PreviewHandler {
+    fn GetWindow(&self) -> Result<HWND> {
+        Ok(self.state.borrow().parent)
+    }
+
+    fn ContextSensitiveHelp(&self, _fentermode: BOOL) -> Result<()> {
+        Err(E_NOTIMPL.into())
+    }
+}
+
+pub fn create() -> IUnknown {
+    PreviewHandler::default().into()
+}
+
+pub fn register(dll: &str) -> Result<()> {
+    let clsid = crate::guid_string(&CLSID);
+    let clsid_key = format!(r"Software\Classes\CLSID\{clsid}");
+    let server_key = format!(r"{clsid_key}\InprocServer32");
+    crate::set_value(HKEY_CURRENT_USER, &clsid_key, None, HANDLER_NAME)?;
+    crate::set_value(HKEY_CURRENT_USER, &clsid_key, Some("AppID"), PREVHOST_APPID)?;
+    crate::set_value(HKEY_CURRENT_USER, &server_key, None, dll)?;
+    crate::set_value(HKEY_CURRENT_USER, &server_key, Some("ThreadingModel"), "Apartment")?;
+    crate::set_value(HKEY_CURRENT_USER, &format!(r"Software\Classes\.png\ShellEx\{PREVIEW_HANDLER_SHELLEX}"), None, &clsid)?;
+    crate::set_value(HKEY_CURRENT_USER, PREVIEW_HANDLERS_KEY, Some(&clsid), HANDLER_NAME)?;
+    Ok(())
+}
+
+// 途中で失敗しても、消せるものはすべて消す
+pub fn unregister() {
+    let clsid = crate::guid_string(&CLSID);
+    crate::delete_tree(HKEY_CURRENT_USER, &format!(r"Software\Classes\CLSID\{clsid}"));
+    crate::delete_tree(HKEY_CURRENT_USER, &format!(r"Software\Classes\.png\ShellEx\{PREVIEW_HANDLER_SHELLEX}"));
+    crate::delete_value(HKEY_CURRENT_USER, PREVIEW_HANDLERS_KEY, Some(&clsid));
+}
@@ -0,0 +1,161 @@
+// 表示中のメタデータを印刷する
+
+use windows::{
+    core::*,
+    Win32::{
+        Foundation::*,
+        Graphics::Gdi::*,
+        Storage::Xps::*,
+        System::Memory::GlobalFree,
+        UI::Controls::Dialogs::*,
+    },
+};
+
+// ポイント単位の大きさを用紙の解像度に合わせる
+fn points_to_pixels(hdc: HDC, points: i32) -> i32 {
+    points * unsafe { GetDeviceCaps(hdc, LOGPIXELSY) } / 72
+}
+
+fn create_font(hdc: HDC, points: i32, weight: i32) -> HFONT {
+    unsafe {
+        CreateFontW(
+            -points_to_pixels(hdc, points), 0, 0, 0,
+            weight,
+            0, 0, 0,
+            DEFAULT_CHARSET.0 as u32,
+            OUT_DEFAULT_PRECIS.0 as u32,
+            CLIP_DEFAULT_PRECIS.0 as u32,
+            DEFAULT_QUALITY.0 as u32,
+            DEFAULT_PITCH.0 as u32,
+            w!("Yu Gothic UI"),
+        )
+    }
+}
+
+// 幅に収まるように 1 行を折り返す
+fn wrap_line(hdc: HDC, line: &[u16], width: i32) -> Vec<Vec<u16>> {
+    let mut ret = Vec::new();
+    let mut rest = line;
+    while !rest.is_empty() {
+        let mut fit = 0;
+        let mut size = SIZE::default();
+        unsafe { GetTextExtentExPointW(hdc, PCWSTR(rest.as_ptr()), rest.len() as i32, width, Some(&mut fit), None, &mut size) };
+        let mut fit = (fit as usize).clamp(1, rest.len());
+        // サロゲートペアの途中では切らない (1 文字も収まらなければペアごと入れる)
+        if fit < rest.len() && (0xdc00..0xe000).contains(&rest[fit]) {
+            fit = if fit > 1 { fit - 1 } else { fit + 1 };
+        }
+        ret.push(rest[..fit].to_vec());
+        rest = &rest[fit..];
+    }
+    if ret.is_empty() {
+        ret.push(Vec::new());
+    }
+    ret
+}
+
+// 印刷ダイアログを表示して印刷する。キャンセルされたら何もしない
+pub fn print(hwnd: HWND, title: &str, text: &str) -> anyhow::Result<()> {
+    let mut pd = PRINTDLGW {
+        lStructSize: std::mem::size_of::<PRINTDLGW>() as u32,
+        hwndOwner: hwnd,
+        Flags: PD_RETURNDC | PD_NOSELECTION | PD_USEDEVMODECOPIESANDCOLLATE,
+        nCopies: 1,
+        ..Default::default()
+    };
+    if !unsafe { PrintDlgW(&mut pd) }.as_bool() {
+        return Ok(());
+    }
+    let hdc = pd.hDC;
+    let result = print_to_dc(hdc, title, text);
+    unsafe { DeleteDC(CreatedHDC(hdc.0)) };
+    for handle in [pd.hDevMode, pd.hDevNames] {
+        if handle != 0 {
+            unsafe { GlobalFree(handle) };
+        }
+    }
+    result
+}
+
+// StartDoc の後で失敗したら、途中までの印刷ジョブを AbortDoc で取り消す
+struct PrintJob {
+    hdc: HDC,
+    finished: bool,
+}
+
+impl Drop for PrintJob {
+    fn drop(&mut self) {
+        if !self.finished {
+            unsafe { AbortDoc(self.hdc) };
+        }
+    }
+}
+
+fn print_to_dc(hdc: HDC, title: &str, text: &str) -> anyhow::Result<()> {
+    let page_width = unsafe { GetDeviceCaps(hdc, HORZRES) };
+    let page_height = unsafe { GetDeviceCaps(hdc, VERTRES) };
+    let margin = points_to_pixels(hdc, 36);
+    let body_width = page_width - margin * 2;
+
+    let body_font = create_font(hdc, 10, 400);
+    let bold_font = create_font(hdc, 10, 700);
+    let header_font = create_font(hdc, 9, 400);
+    let line_height = points_to_pixels(hdc, 14);
+    let header_height = points_to_pixels(hdc, 24);
+
+    // 先にページ分けをしておく
+    let old_font = unsafe { SelectObject(hdc, body_font) };
+    let mut pages: Vec<Vec<(bool, Vec<u16>)>> = vec![Vec::new()];
+    let lines_per_page = ((page_height - margin * 2 - header_height) / line_height).max(1) as usize;
+    for line in text.replace("\r\n", "\n").split('\n') {
+        let is_header = line.starts_with('【') && line.ends_with('】');
+        unsafe { SelectObject(hdc, if is_header { bold_font } else { body_font }) };
+        let wide: Vec<u16> = line.encode_utf16().collect();
+        for piece in wrap_line(hdc, &wide, body_width) {
+            if pages.last().unwrap().len() >= lines_per_page {
+                pages.push(Vec::new());
+            }
+            pages.last_mut().unwrap().push((is_header, piece));
+        }
+    }
+
+    let doc_name = HSTRING::from(title);
+    let doc_info = DOCINFOW {
+        cbSize: std::mem::size_of::<DOCINFOW>() as i32,
+        lpszDocName: PCWSTR(doc_name.as_ptr()),
+        ..Default::default()
+    };
+    let result = (|| {
+        anyhow::ensure!(unsafe { StartDocW(hdc, &doc_info) } > 0, "StartDoc failed");
+        let mut job = PrintJob { hdc, finished: false };
+        let n_pages = pages.len();
+        for (i, page) in pages.iter().enumerate() {
+            anyhow::ensure!(unsafe { StartPage(hdc) } > 0, "StartPage failed");
+
+            // ヘッダー: ファイル名とページ番号
+            unsafe { SelectObject(hdc, header_font) };
+            let header: Vec<u16> = format!("{title}    {}/{n_pages}", i + 1).encode_utf16().collect();
+            unsafe { TextOutW(hdc, margin, margin, &header) };
+            let rule_y = margin + header_height - line_height / 2;
+            unsafe { MoveToEx(hdc, margin, rule_y, None) };
+            unsafe { LineTo(hdc, page_width - margin, rule_y) };
+
+            let mut y = margin + header_height;
+            for (is_header, line) in page {
+                unsafe { SelectObject(hdc, if *is_header { bold_font } else { body_font }) };
+                unsafe { TextOutW(hdc, margin, y, line) };
+                y += line_height;
+            }
+            anyhow::ensure!(unsafe { EndPage(hdc) } > 0, "EndPage failed");
+        }
+        anyhow::ensure!(unsafe { EndDoc(hdc) } > 0, "EndDoc failed");
+        job.finished = true;
+        Ok(())
+    })();
+
+    unsafe { SelectObject(hdc, old_font) };
+    for font in [body_font, bold_font, header_font] {
+        unsafe { DeleteObject(font) };
+    }
+    result
+}
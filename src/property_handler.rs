@@ -0,0 +1,231 @@
+// エクスプローラーの詳細ペインや列に生成パラメーターを出すプロパティハンドラー
+// .png にはもともと Windows のハンドラーが登録されているので、それにチェーンして標準のプロパティも残す
+
+use std::cell::RefCell;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+use windows::core::*;
+use windows::Win32::Foundation::*;
+use windows::Win32::System::Com::StructuredStorage::*;
+use windows::Win32::System::Com::*;
+use windows::Win32::System::Registry::{HKEY_CLASSES_ROOT, HKEY_LOCAL_MACHINE};
+use windows::Win32::UI::Shell::PropertiesSystem::*;
+use crate::{metadata, params};
+
+// {2E8D4C7B-5A3F-4B1E-8D6C-7F9A0B1C2D3E}
+pub const CLSID: GUID = GUID::from_u128(0x2e8d4c7b_5a3f_4b1e_8d6c_7f9a0b1c2d3e);
+const HANDLER_NAME: &str = "MetaView Property Handler";
+const HANDLERS_KEY: &str = r"SOFTWARE\Microsoft\Windows\CurrentVersion\PropertySystem\PropertyHandlers\.png";
+const ASSOCIATION_KEY: &str = r"Software\Classes\SystemFileAssociations\.png";
+// 詳細ペインに何も設定されていなかったときに出していた項目
+const DEFAULT_PREVIEW_DETAILS: &str = "prop:System.DateModified;System.Image.Dimensions;System.Size";
+const EXTRA_PREVIEW_DETAILS: &str = "MetaView.Prompt;MetaView.Seed;MetaView.Model;MetaView.Sampler";
+
+// MetaView.propdesc と同じ FMTID, PID にすること
+const FMTID_METAVIEW: GUID = GUID::from_u128(0x3f1c6b2a_8d4e_4a7b_9c5d_1e2f3a4b5c6d);
+const PKEY_PROMPT: PROPERTYKEY = PROPERTYKEY { fmtid: FMTID_METAVIEW, pid: 2 };
+const PKEY_SEED: PROPERTYKEY = PROPERTYKEY { fmtid: FMTID_METAVIEW, pid: 3 };
+const PKEY_MODEL: PROPERTYKEY = PROPERTYKEY { fmtid: FMTID_METAVIEW, pid: 4 };
+const PKEY_SAMPLER: PROPERTYKEY = PROPERTYKEY { fmtid: FMTID_METAVIEW, pid: 5 };
+const SCHEMA: &str = include_str!("MetaView.propdesc");
+
+enum Value {
+    Text(String),
+    Number(u64),
+}
+
+impl Value {
+    fn to_propvariant(&self) -> Result<PROPVARIANT> {
+        let mut var = PROPVARIANT::default();
+        match self {
+            Value::Text(text) => {
+                // 呼び出し側が PropVariantClear で解放するので CoTaskMemAlloc で確保する
+                let wide: Vec<u16> = text.encode_utf16().chain(Some(0)).collect();
+                let ptr = unsafe { CoTaskMemAlloc(wide.len() * 2) } as *mut u16;
+                if ptr.is_null() {
+                    return Err(E_OUTOFMEMORY.into());
+                }
+                unsafe { std::ptr::copy_nonoverlapping(wide.as_ptr(), ptr, wide.len()) };
+                unsafe {
+                    (*var.Anonymous.Anonymous).vt = VT_LPWSTR;
+                    (*var.Anonymous.Anonymous).Anonymous.pwszVal = PWSTR(ptr);
+                }
+            }
+            Value::Number(n) => unsafe {
+                (*var.Anonymous.Anonymous).vt = VT_UI8;
+                (*var.Anonymous.Anonymous).Anonymous.uhVal = *n;
+            },
+        }
+        Ok(var)
+    }
+}
+
+#[derive(Default)]
+struct State {
+    values: Vec<(PROPERTYKEY, Value)>,
+    chained: Option<IPropertyStore>,
+}
+
+#[implement(IPropertyStore, IPropertyStoreCapabilities, IInitializeWithStream)]
+#[derive(Default)]
+struct PropertyHandler {
+    state: RefCell<State>,
+}
+
+fn read_values(data: Vec<u8>) -> Vec<(PROPERTYKEY, Value)> {
+    let Ok(metadata) = metadata::parse_metadata(OsString::new(), data) else { return Vec::new() };
+    let mut values = Vec::new();
+    if let Some(prompt) = params::find_prompt(&metadata.text_chunks) {
+        values.push((PKEY_PROMPT, Value::Text(prompt)));
+    }
+    if let Some(params) = params::find_parameters(&metadata.text_chunks) {
+        if let Some(seed) = params.get("Seed").and_then(|seed| seed.parse().ok()) {
+            values.push((PKEY_SEED, Value::Number(seed)));
+        }
+        if let Some(model) = params.get("Model") {
+            values.push((PKEY_MODEL, Value::Text(model.to_owned())));
+        }
+        if let Some(sampler) = params.get("Sampler") {
+            values.push((PKEY_SAMPLER, Value::Text(sampler.to_owned())));
+        }
+    }
+    values
+}
+
+fn clsid_key() -> String {
+    format!(r"Software\Classes\CLSID\{}", crate::guid_string(&CLSID))
+}
+
+// もともと登録されていたハンドラーにも同じストリームを読ませる
+fn chained_store(stream: &IStream, grfmode: u32) -> Result<IPropertyStore> {
+    let clsid = crate::get_value(HKEY_CLASSES_ROOT, &format!(r"CLSID\{}", crate::guid_string(&CLSID)), Some("ChainedHandler"))
+        .ok_or_else(|| Error::from(E_FAIL))?;
+    let clsid = unsafe { CLSIDFromString(&HSTRING::from(clsid)) }?;
+    unsafe { stream.Seek(0, STREAM_SEEK_SET) }?;
+    let init: IInitializeWithStream = unsafe { CoCreateInstance(&clsid, None, CLSCTX_INPROC_SERVER) }?;
+    unsafe { init.Initialize(stream, grfmode) }?;
+    init.cast()
+}
+
+impl IInitializeWithStream_Impl for PropertyHandler {
+    fn Initialize(&self, pstream: &Option<IStream>, grfmode: u32) -> Result<()> {
+        let stream = pstream.as_ref().ok_or_else(|| Error::from(E_INVALIDARG))?;
+        let mut state = self.state.borrow_mut();
+        state.values = read_values(crate::read_stream(stream)?);
+        state.chained = chained_store(stream, grfmode).ok();
+        Ok(())
+    }
+}
+
+impl IPropertyStore_Impl for PropertyHandler {
+    fn GetCount(&self) -> Result<u32> {
+        let state = self.state.borrow();
+        let chained = match &state.chained {
+            Some(store) => unsafe { store.GetCount() }.unwrap_or(0),
+            None => 0,
+        };
+        Ok(state.values.len() as u32 + chained)
+    }
+
+    fn GetAt(&self, iprop: u32) -> Result<PROPERTYKEY> {
+        let state = self.state.borrow();
+        if let Some((key, _)) = state.values.get(iprop as usize) {
+            return Ok(*key);
+        }
+        match &state.chained {
+            Some(store) => unsafe { store.GetAt(iprop - state.values.len() as u32) },
+            None => Err(E_INVALIDARG.into()),
+        }
+    }
+
+    fn GetValue(&self, key: *const PROPERTYKEY) -> Result<PROPVARIANT> {
+        let state = self.state.borrow();
+        let key = unsafe { &*key };
+        if let Some((_, value)) = state.values.iter().find(|(k, _)| k == key) {
+            return value.to_propvariant();
+        }
+        match &state.chained {
+            Some(store) => unsafe { store.GetValue(key) },
+            None => Ok(PROPVARIANT::default()),
+        }
+    }
+
+    fn SetValue(&self, _key: *const PROPERTYKEY, _propvar: *const PROPVARIANT) -> Result<()> {
+        Err(STG_E_ACCESSDENIED.into())
+    }
+
+    fn Commit(&self) -> Result<()> {
+        Err(STG_E_ACCESSDENIED.into())
+    }
+}
+
+impl IPropertyStoreCapabilities_Impl for PropertyHandler {
+    fn IsPropertyWritable(&self, _key: *const PROPERTYKEY) -> Result<()> {
+        Err(S_FALSE.into())
+    }
+}
+
+pub fn create() -> IUnknown {
+    PropertyHandler::default().into()
+}
+
+// スキーマファイルは DLL と同じフォルダーに置く
+fn schema_path(dll: &str) -> String {
+    Path::new(dll).with_file_name("MetaView.propdesc").to_string_lossy().into_owned()
+}
+
+pub fn register(dll: &str) -> Result<()> {
+    let clsid = crate::guid_string(&CLSID);
+    let clsid_key = clsid_key();
+    let server_key = format!(r"{clsid_key}\InprocServer32");
+    let chained = crate::get_value(HKEY_LOCAL_MACHINE, HANDLERS_KEY, None).filter(|previous| *previous != clsid);
+    let details = crate::get_value(HKEY_LOCAL_MACHINE, ASSOCIATION_KEY, Some("PreviewDetails"));
+
+    crate::set_value(HKEY_LOCAL_MACHINE, &clsid_key, None, HANDLER_NAME)?;
+    crate::set_value(HKEY_LOCAL_MACHINE, &server_key, None, dll)?;
+    crate::set_value(HKEY_LOCAL_MACHINE, &server_key, Some("ThreadingModel"), "Apartment")?;
+    if let Some(chained) = chained {
+        crate::set_value(HKEY_LOCAL_MACHINE, &clsid_key, Some("ChainedHandler"), &chained)?;
+    }
+    crate::set_value(HKEY_LOCAL_MACHINE, HANDLERS_KEY, None, &clsid)?;
+
+    let schema = schema_path(dll);
+    fs::write(&schema, SCHEMA)
+        .map_err(|e| e.raw_os_error().map_or(Error::from(E_FAIL), |code| WIN32_ERROR(code as u32).into()))?;
+    unsafe { PSRegisterPropertySchema(&HSTRING::from(schema)) }?;
+
+    // 詳細ペインにも出す。解除するときのために元の値を覚えておく
+    if !details.as_deref().is_some_and(|details| details.contains(EXTRA_PREVIEW_DETAILS)) {
+        crate::set_value(HKEY_LOCAL_MACHINE, &clsid_key, Some("PreviousPreviewDetails"), details.as_deref().unwrap_or(""))?;
+        let base = details.as_deref().unwrap_or(DEFAULT_PREVIEW_DETAILS);
+        crate::set_value(HKEY_LOCAL_MACHINE, ASSOCIATION_KEY, Some("PreviewDetails"), &format!("{base};{EXTRA_PREVIEW_DETAILS}"))?;
+    }
+    Ok(())
+}
+
+// 途中で失敗しても、消せるものはすべて消す
+pub fn unregister() {
+    let clsid_key = clsid_key();
+    match crate::get_value(HKEY_LOCAL_MACHINE, &clsid_key, Some("PreviousPreviewDetails")) {
+        Some(details) if !details.is_empty() => {
+            let _ = crate::set_value(HKEY_LOCAL_MACHINE, ASSOCIATION_KEY, Some("PreviewDetails"), &details);
+        }
+        Some(_) => crate::delete_value(HKEY_LOCAL_MACHINE, ASSOCIATION_KEY, Some("PreviewDetails")),
+        None => {}
+    }
+    if crate::get_value(HKEY_LOCAL_MACHINE, HANDLERS_KEY, None) == Some(crate::guid_string(&CLSID)) {
+        match crate::get_value(HKEY_LOCAL_MACHINE, &clsid_key, Some("ChainedHandler")) {
+            Some(chained) => {
+                let _ = crate::set_value(HKEY_LOCAL_MACHINE, HANDLERS_KEY, None, &chained);
+            }
+            None => crate::delete_value(HKEY_LOCAL_MACHINE, HANDLERS_KEY, None),
+        }
+    }
+    crate::delete_tree(HKEY_LOCAL_MACHINE, &clsid_key);
+    if let Ok(dll) = crate::module_path() {
+        let schema = schema_path(&dll);
+        let _ = unsafe { PSUnregisterPropertySchema(&HSTRING::from(&*schema)) };
+        let _ = fs::remove_file(schema);
+    }
+}
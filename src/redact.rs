@@ -0,0 +1,227 @@
+// 伏せ字モード。スクリーンショットや書き出したものを人に渡すときに、個人が特定できる値を ███ に置き換える
+// 値を読み取るときではなく、表示や書き出しのときに置き換える
+
+use std::borrow::Cow;
+
+pub const MASK: &str = "███";
+
+// 伏せる値の種類 (設定ファイルの redact_fields に書く)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    // 位置情報
+    Gps,
+    // カメラやレンズのシリアル番号
+    Serial,
+    // カメラの所有者名
+    Owner,
+    // ワークフローなどに入っているフォルダーのパス (ファイル名は残す)
+    Paths,
+}
+
+impl Field {
+    pub const ALL: [Field; 4] = [Field::Gps, Field::Serial, Field::Owner, Field::Paths];
+
+    pub fn from_code(code: &str) -> Option<Field> {
+        Field::ALL.into_iter().find(|field| field.code() == code)
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Field::Gps => "gps",
+            Field::Serial => "serial",
+            Field::Owner => "owner",
+            Field::Paths => "paths",
+        }
+    }
+
+    // 値ごと伏せるキーに含まれる語 (英数字だけにして小文字にしたキーと比べる)
+    fn key_words(self) -> &'static [&'static str] {
+        match self {
+            Field::Gps => &["gps"],
+            Field::Serial => &["serialnumber", "serialno"],
+            Field::Owner => &["ownername"],
+            Field::Paths => &[],
+        }
+    }
+}
+
+// キー (チャンクのキーワード、XMP の属性名、ExifTool のタグ名など) が伏せる対象か
+pub fn is_sensitive_key(key: &str, fields: &[Field]) -> bool {
+    let key: String = key.chars().filter(char::is_ascii_alphanumeric).map(|c| c.to_ascii_lowercase()).collect();
+    fields.iter().flat_map(|field| field.key_words()).any(|word| key.contains(word))
+}
+
+// テキストの中の伏せる値を置き換える。キーが伏せる対象ならテキスト全体を置き換える
+pub fn redact_value<'a>(key: &str, text: &'a str, fields: &[Field]) -> Cow<'a, str> {
+    if is_sensitive_key(key, fields) {
+        return Cow::Borrowed(MASK);
+    }
+    redact_text(text, fields)
+}
+
+// XMP や JSON の中の name="value"、<name>value</name>、"name": "value" の value と、フォルダーのパスを置き換える
+pub fn redact_text<'a>(text: &'a str, fields: &[Field]) -> Cow<'a, str> {
+    let mut ret = Cow::Borrowed(text);
+    if fields.iter().any(|field| !field.key_words().is_empty()) {
+        if let Some(replaced) = redact_keyed_values(&ret, fields) {
+            ret = Cow::Owned(replaced);
+        }
+    }
+    if fields.contains(&Field::Paths) {
+        if let Some(replaced) = redact_paths(&ret) {
+            ret = Cow::Owned(replaced);
+        }
+    }
+    ret
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | ':' | '.')
+}
+
+// 名前の後ろの値の範囲。名前の直後 (閉じ引用符や空白の後) に = か : と引用符、または > が続くものだけ
+fn value_range(text: &str, name_end: usize) -> Option<(usize, usize)> {
+    let rest = &text[name_end..];
+    let after_quote = rest.strip_prefix(['"', '\'']).unwrap_or(rest);
+    let after = after_quote.trim_start();
+    let offset = text.len() - after.len();
+    if let Some(value) = after.strip_prefix('>') {
+        let end = value.find('<')?;
+        return Some((offset + 1, offset + 1 + end));
+    }
+    let value = after.strip_prefix(['=', ':'])?.trim_start();
+    let quote = value.chars().next().filter(|&c| c == '"' || c == '\'')?;
+    let start = text.len() - value.len() + 1;
+    let end = text[start..].find(quote)?;
+    Some((start, start + end))
+}
+
+fn redact_keyed_values(text: &str, fields: &[Field]) -> Option<String> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    let mut pos = 0;
+    while pos < text.len() {
+        let Some(start) = text[pos..].find(is_name_char).map(|i| pos + i) else { break };
+        let end = text[start..].find(|c| !is_name_char(c)).map_or(text.len(), |i| start + i);
+        pos = end;
+        if !is_sensitive_key(&text[start..end], fields) {
+            continue;
+        }
+        // 開始タグの名前だけを見る (終了タグの </name> の後ろは値ではない)
+        if text[..start].ends_with("</") {
+            continue;
+        }
+        if let Some((value_start, value_end)) = value_range(text, end) {
+            if value_start < value_end && !text[value_start..value_end].trim().is_empty() {
+                ranges.push((value_start, value_end));
+                pos = value_end;
+            }
+        }
+    }
+    replace_ranges(text, &ranges)
+}
+
+// パスの始まりの前に来てよい文字
+fn is_path_boundary(c: Option<char>) -> bool {
+    c.is_none_or(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '(' | '[' | '=' | ',' | '>'))
+}
+
+// 絶対パス (C:\..., C:/..., \\server\..., /home/...) の始まりか
+fn is_path_start(rest: &str) -> bool {
+    let bytes = rest.as_bytes();
+    match bytes {
+        [drive, b':', b'\\' | b'/', ..] if drive.is_ascii_alphabetic() => true,
+        [b'\\', b'\\', c, ..] if c.is_ascii_alphanumeric() => true,
+        // JSON の中では \ が \\ になっている
+        [b'\\', b'\\', b'\\', b'\\', c, ..] if c.is_ascii_alphanumeric() => true,
+        [b'/', c, ..] if c.is_ascii_alphanumeric() => true,
+        _ => false,
+    }
+}
+
+// フォルダーの部分を伏せてファイル名だけを残す。フォルダーを 1 つも含まないもの (/model.ckpt) はそのまま
+fn redact_paths(text: &str) -> Option<String> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    let mut pos = 0;
+    while pos < text.len() {
+        let rest = &text[pos..];
+        let previous = text[..pos].chars().next_back();
+        if !is_path_boundary(previous) || !is_path_start(rest) {
+            pos += rest.chars().next().map_or(1, char::len_utf8);
+            continue;
+        }
+        // 引用符で囲まれていれば空白も含め、そうでなければ空白やカンマまで
+        let quoted = matches!(previous, Some('"' | '\''));
+        let len = rest.find(|c: char| match c {
+            '"' | '\'' | '<' | '>' | '|' | '*' | '?' | '\r' | '\n' | '\t' => true,
+            ' ' | ',' => !quoted,
+            _ => false,
+        }).unwrap_or(rest.len());
+        let path = &rest[..len];
+        // 最後の区切りの並び (JSON の \\ を含む) の前までを伏せる
+        let file_start = path.rfind(['\\', '/']).map_or(0, |i| i + 1);
+        let separator_start = path[..file_start].trim_end_matches(['\\', '/']).len();
+        if path[..separator_start].contains(['\\', '/']) {
+            ranges.push((pos, pos + separator_start));
+        }
+        pos += len.max(1);
+    }
+    replace_ranges(text, &ranges)
+}
+
+// 重ならない範囲を前から順に伏せ字にする。置き換えるものがなければ None
+fn replace_ranges(text: &str, ranges: &[(usize, usize)]) -> Option<String> {
+    if ranges.is_empty() {
+        return None;
+    }
+    let mut ret = String::with_capacity(text.len());
+    let mut last = 0;
+    for &(start, end) in ranges {
+        ret.push_str(&text[last..start]);
+        ret.push_str(MASK);
+        last = end;
+    }
+    ret.push_str(&text[last..]);
+    Some(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_keep_file_names() {
+        let fields = [Field::Paths];
+        assert_eq!(redact_text(r"Model: C:\Users\alice\models\v1.ckpt, Steps: 20", &fields), r"Model: ███\v1.ckpt, Steps: 20");
+        assert_eq!(redact_text("saved to /home/bob/out/img.png", &fields), "saved to ███/img.png");
+        assert_eq!(redact_text(r"from \\server\share\a.png", &fields), r"from ███\a.png");
+        // JSON の中では \ が \\ になっている
+        assert_eq!(redact_text(r#""path": "C:\\dir\\a b.png""#, &fields), r#""path": "███\\a b.png""#);
+    }
+
+    #[test]
+    fn paths_without_folders_are_kept() {
+        assert_eq!(redact_paths("file /model.ckpt"), None);
+        assert_eq!(redact_paths("no paths here"), None);
+        assert_eq!(redact_paths("ratio 1/2"), None);
+        assert_eq!(redact_text(r"C:\dir\a.png", &[]), r"C:\dir\a.png");
+    }
+
+    #[test]
+    fn keyed_values() {
+        let fields = [Field::Owner, Field::Serial, Field::Gps];
+        assert_eq!(redact_text("<exif:OwnerName>Alice</exif:OwnerName>", &fields), "<exif:OwnerName>███</exif:OwnerName>");
+        assert_eq!(redact_text(r#"<rdf:Description aux:SerialNumber="12345"/>"#, &fields), r#"<rdf:Description aux:SerialNumber="███"/>"#);
+        assert_eq!(redact_text(r#"{"SerialNumber": "ABC", "Make": "X"}"#, &fields), r#"{"SerialNumber": "███", "Make": "X"}"#);
+        assert_eq!(redact_text(r#"exif:GPSLatitude="35,40.5N""#, &fields), r#"exif:GPSLatitude="███""#);
+        // 対象でなければそのまま
+        assert_eq!(redact_text(r#"aux:SerialNumber="12345""#, &[Field::Owner]), r#"aux:SerialNumber="12345""#);
+    }
+
+    #[test]
+    fn sensitive_keys() {
+        assert_eq!(redact_value("Serial Number", "xyz", &[Field::Serial]), MASK);
+        assert_eq!(redact_value("Comment", "xyz", &[Field::Serial]), "xyz");
+        assert!(is_sensitive_key("Camera Owner Name", &[Field::Owner]));
+        assert!(!is_sensitive_key("Owner", &[Field::Owner]));
+    }
+}
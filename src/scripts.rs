@@ -0,0 +1,117 @@
+// 設定フォルダーの scripts\*.rhai で表示する内容を加工する
+// スクリプトに fn on_metadata(entries) があれば、#{ key, value } の配列を渡して返ってきた配列を表示する
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+use metaview_core::{fsutil, settings};
+
+const HOOK: &str = "on_metadata";
+
+// 無限ループなどで固まらないようにする
+const MAX_OPERATIONS: u64 = 10_000_000;
+const MAX_CALL_LEVELS: usize = 64;
+const MAX_STRING_SIZE: usize = 16 * 1024 * 1024;
+
+// スクリプトのファイル名とエラー
+pub type ScriptErrors = Vec<(String, anyhow::Error)>;
+
+#[derive(Debug)]
+pub struct Scripts {
+    engine: Engine,
+    // ファイル名の順に実行する
+    scripts: Vec<(String, AST)>,
+}
+
+impl Default for Scripts {
+    fn default() -> Self {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS)
+            .set_max_call_levels(MAX_CALL_LEVELS)
+            .set_max_string_size(MAX_STRING_SIZE);
+        Scripts { engine, scripts: Vec::new() }
+    }
+}
+
+pub fn scripts_dir() -> Option<PathBuf> {
+    settings::data_dir().map(|dir| dir.join("scripts"))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+fn to_array(entries: Vec<(String, String)>) -> Array {
+    entries.into_iter()
+        .map(|(key, value)| {
+            let mut map = Map::new();
+            map.insert("key".into(), key.into());
+            map.insert("value".into(), value.into());
+            map.into()
+        })
+        .collect()
+}
+
+// 文字列でない key や value は文字列にする。key のないものは捨てる
+fn from_array(array: Array) -> anyhow::Result<Vec<(String, String)>> {
+    array.into_iter()
+        .map(|entry| {
+            let map = entry.try_cast::<Map>().ok_or_else(|| anyhow::anyhow!("{HOOK} must return an array of #{{ key, value }}"))?;
+            let key = map.get("key").map(Dynamic::to_string);
+            let value = map.get("value").map(Dynamic::to_string).unwrap_or_default();
+            Ok(key.map(|key| (key, value)))
+        })
+        .filter_map(Result::transpose)
+        .collect()
+}
+
+impl Scripts {
+    // 読み込めなかったスクリプトとその理由も返す
+    pub fn load() -> (Scripts, ScriptErrors) {
+        let mut ret = Scripts::default();
+        let mut errors = Vec::new();
+        let Some(entries) = scripts_dir().and_then(|dir| fs::read_dir(fsutil::long_path(&dir)).ok()) else {
+            return (ret, errors);
+        };
+        let mut paths: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("rhai")))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let source = match fs::read_to_string(fsutil::long_path(&path)) {
+                Ok(source) => source,
+                Err(e) => {
+                    errors.push((file_name(&path), e.into()));
+                    continue;
+                }
+            };
+            match ret.engine.compile(source) {
+                Ok(ast) if ast.iter_functions().any(|f| f.name == HOOK && f.params.len() == 1) => {
+                    ret.scripts.push((file_name(&path), ast));
+                }
+                Ok(_) => {}
+                Err(e) => errors.push((file_name(&path), anyhow::anyhow!("{e}"))),
+            }
+        }
+        (ret, errors)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scripts.is_empty()
+    }
+
+    // 失敗したスクリプトは飛ばし、その名前とエラーを返す
+    pub fn on_metadata(&self, mut entries: Vec<(String, String)>) -> (Vec<(String, String)>, ScriptErrors) {
+        let mut errors = Vec::new();
+        for (name, ast) in &self.scripts {
+            let result = self.engine.call_fn::<Array>(&mut Scope::new(), ast, HOOK, (to_array(entries.clone()),))
+                .map_err(|e| anyhow::anyhow!("{e}"))
+                .and_then(from_array);
+            match result {
+                Ok(processed) => entries = processed,
+                Err(e) => errors.push((name.clone(), e)),
+            }
+        }
+        (entries, errors)
+    }
+}
@@ -0,0 +1,216 @@
+// フォルダーの中の画像 (サブフォルダーも) から、テキストチャンクに語を含むものを探す
+// 画像の読み込みは CPU の数だけスレッドを立てて分担する
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use windows::{
+    core::*,
+    Win32::{
+        Foundation::*,
+        UI::WindowsAndMessaging::*,
+    },
+};
+use crate::batch::{self, ScannedImage};
+use crate::dialog::{self, DialogTemplate};
+use crate::i18n::{tr, Msg};
+use crate::metadata::Source;
+
+// lparam: Box<anyhow::Result<SearchResult>> のポインタ
+pub const WM_APP_SEARCH_DONE: u32 = WM_APP + 11;
+
+// 一致した箇所の前後に表示する文字数
+const EXCERPT_CHARS: usize = 30;
+
+const IDC_TERM: i32 = 100;
+const IDC_FOLDER: i32 = 101;
+const IDC_BROWSE: i32 = 102;
+
+#[derive(Debug)]
+pub struct SearchMatch {
+    pub image: ScannedImage,
+    // 最初に一致したチャンクのキーワードと、一致した箇所の前後
+    pub key: String,
+    pub excerpt: String,
+}
+
+#[derive(Debug)]
+pub struct SearchResult {
+    pub folder: PathBuf,
+    pub term: String,
+    // 読めた画像の数
+    pub searched: usize,
+    // 見つけた順 (サブフォルダーを含めたファイルの並び順)
+    pub matches: Vec<SearchMatch>,
+}
+
+struct DialogState {
+    term: String,
+    folder: String,
+}
+
+// 探す語とフォルダーを入力してもらう
+pub fn show_dialog(parent: HWND, folder: Option<&Path>) -> Option<(String, PathBuf)> {
+    let mut state = DialogState {
+        term: String::new(),
+        folder: folder.map(|folder| folder.display().to_string()).unwrap_or_default(),
+    };
+    let template = DialogTemplate::new(tr(Msg::SearchFolderTitle), 300, 70)
+        .item(dialog::STATIC, tr(Msg::SearchFor), -1, 0, 7, 9, 50, 10)
+        .item(dialog::EDIT, "", IDC_TERM, ES_AUTOHSCROLL as u32 | WS_BORDER.0 | WS_TABSTOP.0, 60, 7, 233, 14)
+        .item(dialog::STATIC, tr(Msg::Folder), -1, 0, 7, 27, 50, 10)
+        .item(dialog::EDIT, "", IDC_FOLDER, ES_AUTOHSCROLL as u32 | WS_BORDER.0 | WS_TABSTOP.0, 60, 25, 179, 14)
+        .item(dialog::BUTTON, tr(Msg::Browse), IDC_BROWSE, WS_TABSTOP.0, 243, 25, 50, 14)
+        .item(dialog::BUTTON, tr(Msg::Search), IDOK.0, BS_DEFPUSHBUTTON as u32 | WS_TABSTOP.0, 189, 49, 50, 14)
+        .item(dialog::BUTTON, tr(Msg::Cancel), IDCANCEL.0, WS_TABSTOP.0, 243, 49, 50, 14);
+    let ret = template.show(parent, Some(dialog_proc), LPARAM(&mut state as *mut _ as isize));
+    if ret != IDOK.0 as isize {
+        return None;
+    }
+    Some((state.term, PathBuf::from(state.folder)))
+}
+
+fn warn(hdlg: HWND, msg: Msg) {
+    let text = HSTRING::from(tr(msg));
+    unsafe { MessageBoxW(hdlg, &text, None, MB_OK | MB_ICONWARNING) };
+}
+
+extern "system" fn dialog_proc(hdlg: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> isize {
+    match message {
+        WM_INITDIALOG => {
+            unsafe { SetWindowLongPtrW(hdlg, GWLP_USERDATA, lparam.0) };
+            let state = unsafe { (lparam.0 as *mut DialogState).as_mut() }.unwrap();
+            unsafe { SetDlgItemTextW(hdlg, IDC_FOLDER, &HSTRING::from(&state.folder)) };
+            1
+        }
+        WM_COMMAND => {
+            let state = unsafe { (GetWindowLongPtrW(hdlg, GWLP_USERDATA) as *mut DialogState).as_mut() };
+            let Some(state) = state else { return 0 };
+            let id = (wparam.0 & 0xffff) as i32;
+            if id == IDOK.0 {
+                let term = dialog::get_item_text(hdlg, IDC_TERM).trim().to_owned();
+                let folder = dialog::get_item_text(hdlg, IDC_FOLDER).trim().to_owned();
+                if term.is_empty() {
+                    warn(hdlg, Msg::EnterSearchTerm);
+                    return 1;
+                }
+                if !Path::new(&folder).is_dir() {
+                    warn(hdlg, Msg::FolderNotFound);
+                    return 1;
+                }
+                (state.term, state.folder) = (term, folder);
+                unsafe { EndDialog(hdlg, IDOK.0 as isize) };
+                1
+            } else if id == IDC_BROWSE {
+                if let Ok(Some(folder)) = crate::pick_folder(hdlg, Msg::SearchFolderTitle) {
+                    unsafe { SetDlgItemTextW(hdlg, IDC_FOLDER, &HSTRING::from(folder.as_os_str())) };
+                }
+                1
+            } else if id == IDCANCEL.0 {
+                unsafe { EndDialog(hdlg, IDCANCEL.0 as isize) };
+                1
+            } else {
+                0
+            }
+        }
+        _ => 0,
+    }
+}
+
+pub fn start(hwnd: HWND, folder: PathBuf, term: String) {
+    std::thread::spawn(move || {
+        let result = search(hwnd, folder, term);
+        let result = Box::into_raw(Box::new(result));
+        let posted = unsafe { PostMessageW(hwnd, WM_APP_SEARCH_DONE, WPARAM(0), LPARAM(result as isize)) };
+        if !posted.as_bool() {
+            drop(unsafe { Box::from_raw(result) });
+        }
+    });
+}
+
+// WM_APP_SEARCH_DONE の lparam から結果を取り出す
+pub unsafe fn take_result(lparam: LPARAM) -> anyhow::Result<SearchResult> {
+    *Box::from_raw(lparam.0 as *mut anyhow::Result<SearchResult>)
+}
+
+// 大文字と小文字を区別せずに探し、一致した位置 (text のバイト位置) を返す
+fn find_ignore_case(text: &str, term: &[char]) -> Option<usize> {
+    text.char_indices()
+        .map(|(i, _)| i)
+        .find(|&i| {
+            let mut chars = text[i..].chars().flat_map(char::to_lowercase);
+            term.iter().all(|&c| chars.next() == Some(c))
+        })
+}
+
+// 一致した箇所の前後を 1 行にして返す
+fn excerpt(text: &str, start: usize, term_len: usize) -> String {
+    let before: String = text[..start].chars().rev().take(EXCERPT_CHARS).collect::<Vec<_>>().into_iter().rev().collect();
+    let after: String = text[start..].chars().take(term_len + EXCERPT_CHARS).collect();
+    let prefix = if before.len() < start { "…" } else { "" };
+    let suffix = if start + after.len() < text.len() { "…" } else { "" };
+    format!("{prefix}{before}{after}{suffix}").replace(['\r', '\n'], " ")
+}
+
+// 一致しなければ Ok(None)
+fn search_file(path: &Path, term: &[char]) -> anyhow::Result<Option<SearchMatch>> {
+    let metadata = Source::File(path.into()).read_metadata()?;
+    let found = metadata.text_chunks.iter().find_map(|(key, value)| {
+        let start = find_ignore_case(value, term)?;
+        Some((key.clone(), excerpt(value, start, term.len())))
+    });
+    Ok(found.map(|(key, excerpt)| SearchMatch {
+        image: ScannedImage { path: path.to_owned(), orientation: metadata.orientation },
+        key,
+        excerpt,
+    }))
+}
+
+fn search(hwnd: HWND, folder: PathBuf, term: String) -> anyhow::Result<SearchResult> {
+    let mut files = Vec::new();
+    batch::collect_images(&folder, &mut files)?;
+    let term_chars: Vec<char> = term.chars().flat_map(char::to_lowercase).collect();
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let searched = AtomicUsize::new(0);
+    let matches = Mutex::new(Vec::new());
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get()).min(files.len().max(1));
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let Some(path) = files.get(i) else { break };
+                if let Ok(found) = search_file(path, &term_chars) {
+                    searched.fetch_add(1, Ordering::SeqCst);
+                    if let Some(found) = found {
+                        matches.lock().unwrap().push((i, found));
+                    }
+                }
+                let done = done.fetch_add(1, Ordering::SeqCst) + 1;
+                unsafe { PostMessageW(hwnd, batch::WM_APP_SCAN_PROGRESS, WPARAM(done), LPARAM(files.len() as isize)) };
+            });
+        }
+    });
+    let mut matches = matches.into_inner().unwrap();
+    matches.sort_by_key(|(i, _)| *i);
+    Ok(SearchResult {
+        folder,
+        term,
+        searched: searched.into_inner(),
+        matches: matches.into_iter().map(|(_, found)| found).collect(),
+    })
+}
+
+impl SearchResult {
+    pub fn format(&self) -> String {
+        let mut ret = format!("【{}】\r\n", tr(Msg::SearchResults));
+        ret.push_str(&format!("{}: {}\r\n", tr(Msg::Folder), self.folder.display()));
+        ret.push_str(&format!("{}: {}\r\n", tr(Msg::SearchTerm), self.term));
+        ret.push_str(&format!("{}: {} / {}\r\n\r\n", tr(Msg::MatchingImages), self.matches.len(), self.searched));
+        for found in &self.matches {
+            let path = found.image.path.strip_prefix(&self.folder).unwrap_or(&found.image.path);
+            ret.push_str(&format!("{}\r\n    {}: {}\r\n", path.display(), found.key, found.excerpt));
+        }
+        ret
+    }
+}
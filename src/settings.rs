@@ -0,0 +1,461 @@
+// 設定ファイル (%APPDATA%\MetaView\settings.ini) の読み書き
+// ポータブルモードでは exe と同じフォルダーの metaview.ini を使う
+
+use std::fmt;
+use std::fs;
+use std::mem;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+use windows::{
+    core::PCWSTR,
+    Win32::{Foundation::HINSTANCE, System::LibraryLoader::*},
+};
+use crate::encoding::{Newline, OutputEncoding};
+use crate::extract::ExtractRule;
+use crate::i18n::Language;
+use crate::inflate;
+use crate::redact;
+
+// 表示しないチャンクの設定
+#[derive(Debug, Clone, Default)]
+pub struct ChunkFilter {
+    pub hidden_keywords: Vec<String>,
+    // 種類のわからないバイナリのチャンクを隠す
+    pub hide_binary: bool,
+}
+
+impl ChunkFilter {
+    pub fn is_hidden(&self, keyword: &str) -> bool {
+        self.hidden_keywords.iter().any(|k| k == keyword)
+    }
+
+    pub fn toggle(&mut self, keyword: &str) {
+        if self.is_hidden(keyword) {
+            self.hidden_keywords.retain(|k| k != keyword);
+        } else {
+            self.hidden_keywords.push(keyword.to_owned());
+        }
+    }
+}
+
+// チャンクの表示形式。{keyword} と {text} を置き換える
+pub const DEFAULT_CHUNK_TEMPLATE: &str = "【{keyword}】\n{text}\n\n";
+// 16000x16000 のような画像でも 1 枚あたりこれ以上の画素は確保しない (約 5800x5800 に縮小する)
+pub const DEFAULT_DECODE_MAX_SIZE: usize = 128 * 1024 * 1024;
+
+// チャンクの並べ方
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChunkOrder {
+    // ファイルに入っている順
+    #[default]
+    File,
+    Keyword,
+}
+
+impl ChunkOrder {
+    fn from_code(code: &str) -> Option<ChunkOrder> {
+        match code {
+            "file" => Some(ChunkOrder::File),
+            "keyword" => Some(ChunkOrder::Keyword),
+            _ => None,
+        }
+    }
+
+    fn code(self) -> &'static str {
+        match self {
+            ChunkOrder::File => "file",
+            ChunkOrder::Keyword => "keyword",
+        }
+    }
+}
+
+// Windows 11 のタイトルバーの背景
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backdrop {
+    None,
+    #[default]
+    Mica,
+    Acrylic,
+    // Mica を少し濃くしたもの (タブのあるウィンドウ向け)
+    Tabbed,
+}
+
+impl Backdrop {
+    fn from_code(code: &str) -> Option<Backdrop> {
+        match code {
+            "none" => Some(Backdrop::None),
+            "mica" => Some(Backdrop::Mica),
+            "acrylic" => Some(Backdrop::Acrylic),
+            "tabbed" => Some(Backdrop::Tabbed),
+            _ => None,
+        }
+    }
+
+    fn code(self) -> &'static str {
+        match self {
+            Backdrop::None => "none",
+            Backdrop::Mica => "mica",
+            Backdrop::Acrylic => "acrylic",
+            Backdrop::Tabbed => "tabbed",
+        }
+    }
+}
+
+// 設定ファイルの 1 行に書けるように、改行とタブを \n, \t で表す
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\n', "\\n").replace('\t', "\\t")
+}
+
+fn unescape(s: &str) -> String {
+    let mut ret = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            ret.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => ret.push('\n'),
+            Some('t') => ret.push('\t'),
+            Some(c) => ret.push(c),
+            None => ret.push('\\'),
+        }
+    }
+    ret
+}
+
+// カンマ区切りのリスト。項目の中のカンマは \, と書く (チャンクのキーワードにはカンマも使える)
+fn join_list(items: &[String]) -> String {
+    items.iter().map(|item| escape(item).replace(',', "\\,")).collect::<Vec<_>>().join(",")
+}
+
+fn split_list(s: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut item = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                item.push(c);
+                item.extend(chars.next());
+            }
+            ',' => items.push(unescape(mem::take(&mut item).trim())),
+            _ => item.push(c),
+        }
+    }
+    items.push(unescape(item.trim()));
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+// グローバルホットキー。modifiers は RegisterHotKey の MOD_* と同じ値
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hotkey {
+    pub modifiers: u32,
+    pub vk: u32,
+}
+
+pub const MOD_ALT: u32 = 0x1;
+pub const MOD_CONTROL: u32 = 0x2;
+pub const MOD_SHIFT: u32 = 0x4;
+pub const MOD_WIN: u32 = 0x8;
+const MODIFIER_NAMES: [(u32, &str); 4] = [(MOD_CONTROL, "Ctrl"), (MOD_ALT, "Alt"), (MOD_SHIFT, "Shift"), (MOD_WIN, "Win")];
+
+impl Hotkey {
+    // "Ctrl+Shift+M" の形式
+    pub fn parse(s: &str) -> Option<Hotkey> {
+        let mut modifiers = 0;
+        let mut vk = None;
+        for part in s.split('+').map(str::trim) {
+            match MODIFIER_NAMES.iter().find(|(_, name)| name.eq_ignore_ascii_case(part)) {
+                Some((modifier, _)) => modifiers |= modifier,
+                None => vk = Some(key_code(part)?),
+            }
+        }
+        Some(Hotkey { modifiers, vk: vk? })
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (modifier, name) in MODIFIER_NAMES {
+            if self.modifiers & modifier != 0 {
+                write!(f, "{name}+")?;
+            }
+        }
+        match self.vk {
+            0x30..=0x39 | 0x41..=0x5a => write!(f, "{}", char::from(self.vk as u8)),
+            0x70..=0x87 => write!(f, "F{}", self.vk - 0x6f),
+            vk => write!(f, "0x{vk:02X}"),
+        }
+    }
+}
+
+// 英数字と F1～F24 は名前で、それ以外は仮想キーコードを 16 進数で書く
+fn key_code(name: &str) -> Option<u32> {
+    if let Some(hex) = name.strip_prefix("0x") {
+        return u32::from_str_radix(hex, 16).ok();
+    }
+    if let Some(n) = name.strip_prefix(['F', 'f']).and_then(|n| n.parse::<u32>().ok()) {
+        return (1..=24).contains(&n).then_some(0x6f + n);
+    }
+    match name.as_bytes() {
+        [c] if c.is_ascii_alphanumeric() => Some(c.to_ascii_uppercase() as u32),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    // None のときはユーザーのロケールから自動で決める
+    pub language: Option<Language>,
+    // 書き込み前に .bak を作るか
+    pub backup_on_save: bool,
+    pub filter: ChunkFilter,
+    // 最小化したときにタスクバーではなく通知領域に入れる
+    pub minimize_to_tray: bool,
+    // 画像のプレビュー欄を表示する
+    pub show_preview: bool,
+    pub hotkey: Option<Hotkey>,
+    // モデルのハッシュを Civitai に問い合わせる
+    pub civitai_lookup: bool,
+    // ファイルのハッシュと一緒に画素の知覚ハッシュも計算する
+    pub perceptual_hash: bool,
+    // 開いたファイルの履歴を history.csv に残し、次に起動したときも表示する
+    pub keep_history: bool,
+    // 起動したときに新しいバージョンが出ていないかを調べる
+    pub check_updates: bool,
+    // 新しい画像ができたら開くフォルダー (次に起動したときも監視する)
+    pub watch_folder: Option<PathBuf>,
+    // 監視しているフォルダーに新しい画像ができたとき、ウィンドウが前面になければ通知する
+    pub watch_notify: bool,
+    // テキストや CSV に書き出すときの文字コードと改行
+    pub export_encoding: OutputEncoding,
+    pub export_newline: Newline,
+    // テキストから抜き出す正規表現 (画面からは編集しない)
+    pub extract_rules: Vec<ExtractRule>,
+    pub chunk_template: String,
+    pub chunk_order: ChunkOrder,
+    // 圧縮されたチャンクを展開するときの上限 (画面からは編集しない)
+    pub inflate_limits: inflate::Limits,
+    // プレビューや透かしの検出でデコードする画素のバイト数の上限
+    pub decode_max_size: usize,
+    // 伏せ字モード。表示や書き出しで redact_fields の値を ███ にする
+    pub redact: bool,
+    // 見出しとパラメーター名をアクセントカラーで色付けする
+    pub accent_colors: bool,
+    // 伏せる値の種類 (画面からは編集しない)
+    pub redact_fields: Vec<redact::Field>,
+    // Windows 11 のタイトルバーの背景 (画面からは編集しない)
+    pub backdrop: Backdrop,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            language: None,
+            backup_on_save: true,
+            filter: ChunkFilter::default(),
+            minimize_to_tray: false,
+            show_preview: false,
+            hotkey: None,
+            civitai_lookup: false,
+            perceptual_hash: false,
+            keep_history: false,
+            check_updates: false,
+            watch_folder: None,
+            watch_notify: false,
+            export_encoding: OutputEncoding::default(),
+            export_newline: Newline::default(),
+            extract_rules: Vec::new(),
+            chunk_template: DEFAULT_CHUNK_TEMPLATE.to_owned(),
+            chunk_order: ChunkOrder::File,
+            inflate_limits: inflate::Limits::default(),
+            decode_max_size: DEFAULT_DECODE_MAX_SIZE,
+            redact: false,
+            accent_colors: false,
+            redact_fields: redact::Field::ALL.to_vec(),
+            backdrop: Backdrop::default(),
+        }
+    }
+}
+
+// このどちらかが exe と同じフォルダーにあればポータブルモードにする
+const PORTABLE_MARKERS: [&str; 2] = ["metaview.ini", "portable.txt"];
+const PORTABLE_SETTINGS_FILE: &str = "metaview.ini";
+
+// このコードを含むモジュールのフォルダー
+// エクスプローラー拡張では prevhost.exe などではなく DLL のフォルダーになる
+fn module_dir() -> Option<PathBuf> {
+    let mut module = HINSTANCE::default();
+    let flags = GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS | GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT;
+    let found = unsafe { GetModuleHandleExW(flags, PCWSTR(module_dir as *const u16), &mut module) };
+    if !found.as_bool() {
+        return None;
+    }
+    let mut buf = vec![0u16; 32768];
+    let len = unsafe { GetModuleFileNameW(module, &mut buf) } as usize;
+    let path = PathBuf::from(String::from_utf16_lossy(&buf[..len]));
+    path.parent().map(PathBuf::from)
+}
+
+// ポータブルモードなら exe のフォルダー。起動中に変わることはないので一度だけ調べる
+pub fn portable_dir() -> Option<&'static PathBuf> {
+    static PORTABLE_DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+    PORTABLE_DIR.get_or_init(|| {
+        let dir = module_dir()?;
+        PORTABLE_MARKERS.iter().any(|name| dir.join(name).is_file()).then_some(dir)
+    }).as_ref()
+}
+
+// 設定ファイルなどを置くフォルダー
+pub fn data_dir() -> Option<PathBuf> {
+    if let Some(dir) = portable_dir() {
+        return Some(dir.clone());
+    }
+    let appdata = std::env::var_os("APPDATA")?;
+    Some(PathBuf::from(appdata).join("MetaView"))
+}
+
+fn settings_path() -> Option<PathBuf> {
+    match portable_dir() {
+        Some(dir) => Some(dir.join(PORTABLE_SETTINGS_FILE)),
+        None => data_dir().map(|dir| dir.join("settings.ini")),
+    }
+}
+
+impl Settings {
+    pub fn load() -> Settings {
+        let mut settings = Settings::default();
+        let Some(content) = settings_path().and_then(|path| fs::read_to_string(path).ok()) else {
+            return settings;
+        };
+        for line in content.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "language" => settings.language = Language::from_code(value),
+                "backup_on_save" => settings.backup_on_save = value == "true",
+                "hidden_keywords" => settings.filter.hidden_keywords = split_list(value),
+                "hide_binary_chunks" => settings.filter.hide_binary = value == "true",
+                "minimize_to_tray" => settings.minimize_to_tray = value == "true",
+                "show_preview" => settings.show_preview = value == "true",
+                "hotkey" => settings.hotkey = Hotkey::parse(value),
+                "civitai_lookup" => settings.civitai_lookup = value == "true",
+                "perceptual_hash" => settings.perceptual_hash = value == "true",
+                "keep_history" => settings.keep_history = value == "true",
+                "check_updates" => settings.check_updates = value == "true",
+                "watch_folder" => settings.watch_folder = (!value.is_empty()).then(|| PathBuf::from(value)),
+                "watch_notify" => settings.watch_notify = value == "true",
+                "export_encoding" => settings.export_encoding = OutputEncoding::from_code(value).unwrap_or_default(),
+                "export_newline" => settings.export_newline = Newline::from_code(value).unwrap_or_default(),
+                "chunk_template" => settings.chunk_template = unescape(value),
+                "chunk_order" => settings.chunk_order = ChunkOrder::from_code(value).unwrap_or_default(),
+                "redact" => settings.redact = value == "true",
+                "accent_colors" => settings.accent_colors = value == "true",
+                "redact_fields" => {
+                    settings.redact_fields = value.split(',').filter_map(|code| redact::Field::from_code(code.trim())).collect();
+                }
+                "backdrop" => settings.backdrop = Backdrop::from_code(value).unwrap_or_default(),
+                "inflate_max_size" => {
+                    if let Ok(size) = value.parse() {
+                        settings.inflate_limits.max_size = size;
+                    }
+                }
+                "decode_max_size" => {
+                    if let Ok(size) = value.parse() {
+                        settings.decode_max_size = size;
+                    }
+                }
+                "inflate_max_time_ms" => {
+                    if let Ok(ms) = value.parse() {
+                        settings.inflate_limits.max_time = Duration::from_millis(ms);
+                    }
+                }
+                key => {
+                    if let Some(name) = key.strip_prefix("extract.") {
+                        settings.set_extract_rule(name, value);
+                    }
+                }
+            }
+        }
+        settings
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = settings_path().ok_or_else(|| anyhow::anyhow!("APPDATA is not set"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut content = String::new();
+        content.push_str(&format!("language={}\r\n", self.language.map_or("auto", Language::code)));
+        content.push_str(&format!("backup_on_save={}\r\n", self.backup_on_save));
+        content.push_str(&format!("hidden_keywords={}\r\n", join_list(&self.filter.hidden_keywords)));
+        content.push_str(&format!("hide_binary_chunks={}\r\n", self.filter.hide_binary));
+        content.push_str(&format!("minimize_to_tray={}\r\n", self.minimize_to_tray));
+        content.push_str(&format!("show_preview={}\r\n", self.show_preview));
+        content.push_str(&format!("hotkey={}\r\n", self.hotkey.map(|h| h.to_string()).unwrap_or_default()));
+        content.push_str(&format!("civitai_lookup={}\r\n", self.civitai_lookup));
+        content.push_str(&format!("perceptual_hash={}\r\n", self.perceptual_hash));
+        content.push_str(&format!("keep_history={}\r\n", self.keep_history));
+        content.push_str(&format!("check_updates={}\r\n", self.check_updates));
+        content.push_str(&format!("watch_folder={}\r\n", self.watch_folder.as_ref().map(|dir| dir.display().to_string()).unwrap_or_default()));
+        content.push_str(&format!("watch_notify={}\r\n", self.watch_notify));
+        content.push_str(&format!("export_encoding={}\r\n", self.export_encoding.code()));
+        content.push_str(&format!("export_newline={}\r\n", self.export_newline.code()));
+        content.push_str(&format!("chunk_template={}\r\n", escape(&self.chunk_template)));
+        content.push_str(&format!("chunk_order={}\r\n", self.chunk_order.code()));
+        content.push_str(&format!("inflate_max_size={}\r\n", self.inflate_limits.max_size));
+        content.push_str(&format!("inflate_max_time_ms={}\r\n", self.inflate_limits.max_time.as_millis()));
+        content.push_str(&format!("decode_max_size={}\r\n", self.decode_max_size));
+        content.push_str(&format!("redact={}\r\n", self.redact));
+        content.push_str(&format!("accent_colors={}\r\n", self.accent_colors));
+        let fields: Vec<&str> = self.redact_fields.iter().map(|field| field.code()).collect();
+        content.push_str(&format!("redact_fields={}\r\n", fields.join(",")));
+        content.push_str(&format!("backdrop={}\r\n", self.backdrop.code()));
+        for rule in &self.extract_rules {
+            content.push_str(&format!("extract.{}={}\r\n", rule.name, rule.pattern));
+            if !rule.label.is_empty() {
+                content.push_str(&format!("extract.{}.label={}\r\n", rule.name, rule.label));
+            }
+        }
+        fs::write(path, content)?;
+        Ok(())
+    }
+
+    // "extract.<name>=<pattern>" と "extract.<name>.label=<label>" の行。どちらが先に来てもよい
+    fn set_extract_rule(&mut self, key: &str, value: &str) {
+        let (name, is_label) = match key.strip_suffix(".label") {
+            Some(name) => (name, true),
+            None => (key, false),
+        };
+        if name.is_empty() {
+            return;
+        }
+        let index = match self.extract_rules.iter().position(|rule| rule.name == name) {
+            Some(index) => index,
+            None => {
+                self.extract_rules.push(ExtractRule { name: name.to_owned(), pattern: String::new(), label: String::new() });
+                self.extract_rules.len() - 1
+            }
+        };
+        let rule = &mut self.extract_rules[index];
+        if is_label {
+            rule.label = value.to_owned();
+        } else {
+            rule.pattern = value.to_owned();
+        }
+    }
+
+    // 伏せ字モードで伏せる種類。オフなら空
+    pub fn active_redactions(&self) -> &[redact::Field] {
+        if self.redact { &self.redact_fields } else { &[] }
+    }
+
+    pub fn effective_language(&self) -> Language {
+        self.language.unwrap_or_else(Language::from_user_locale)
+    }
+}
@@ -0,0 +1,95 @@
+// メタデータのチャンクやセグメントごとの大きさと、画像データに対する割合
+// 何をメタデータとみなすかは「メタデータを除いたコピー」で捨てるものと同じ
+
+use crate::i18n::{tr, Msg};
+use crate::jpeg;
+use crate::png_chunks::{self, PNG_SIGNATURE};
+use crate::strip;
+
+#[derive(Debug, Default)]
+pub struct SizeReport {
+    // (名前, 長さやマーカーを含むバイト数)
+    pub metadata: Vec<(String, usize)>,
+    // IDAT, fdAT や SOS 以降の符号化データ
+    pub image_data: usize,
+    pub total: usize,
+}
+
+// チャンクやセグメントの中身の先頭にある NUL 終端の名前 (キーワードや APPn の識別子)
+fn leading_name(data: &[u8]) -> Option<String> {
+    let end = data.iter().take(80).position(|&b| b == 0)?;
+    let name = &data[..end];
+    (!name.is_empty() && name.iter().all(|b| b.is_ascii_graphic() || *b == b' '))
+        .then(|| String::from_utf8_lossy(name).into_owned())
+}
+
+fn with_name(kind: String, name: Option<String>) -> String {
+    match name {
+        Some(name) => format!("{kind} ({name})"),
+        None => kind,
+    }
+}
+
+pub fn analyze(file: &[u8]) -> anyhow::Result<SizeReport> {
+    let mut report = SizeReport { total: file.len(), ..Default::default() };
+    if file.starts_with(PNG_SIGNATURE) {
+        for chunk in png_chunks::parse_chunks(file)? {
+            if matches!(&chunk.kind, b"IDAT" | b"fdAT") {
+                report.image_data += chunk.range.len();
+            } else if strip::is_metadata_chunk(&chunk.kind) {
+                let kind = String::from_utf8_lossy(&chunk.kind).into_owned();
+                let name = match &chunk.kind {
+                    b"tEXt" | b"zTXt" | b"iTXt" => leading_name(&file[chunk.data.clone()]),
+                    _ => None,
+                };
+                report.metadata.push((with_name(kind, name), chunk.range.len()));
+            }
+        }
+    } else if jpeg::is_jpeg(file) {
+        for segment in jpeg::parse_segments(file)? {
+            if segment.marker == jpeg::SOS {
+                report.image_data += segment.range.len();
+            } else if strip::is_metadata_segment(file, &segment) {
+                let kind = match segment.marker {
+                    jpeg::COM => "COM".to_owned(),
+                    marker => format!("APP{}", marker - jpeg::APP0),
+                };
+                let name = match segment.marker {
+                    jpeg::COM => None,
+                    _ => leading_name(&file[segment.data.clone()]),
+                };
+                report.metadata.push((with_name(kind, name), segment.range.len()));
+            }
+        }
+    } else {
+        anyhow::bail!("unsupported file format");
+    }
+    Ok(report)
+}
+
+impl SizeReport {
+    fn percent(&self, size: usize) -> f64 {
+        if self.total == 0 { 0.0 } else { size as f64 * 100.0 / self.total as f64 }
+    }
+
+    pub fn format(&self) -> String {
+        let bytes = tr(Msg::StatusBytes);
+        let metadata_total: usize = self.metadata.iter().map(|(_, size)| size).sum();
+        let mut ret = format!("【{}】\r\n", tr(Msg::SizeBreakdown));
+        for (name, size) in &self.metadata {
+            ret.push_str(&format!("{name}: {size} {bytes} ({:.1}%)\r\n", self.percent(*size)));
+        }
+        if !self.metadata.is_empty() {
+            ret.push_str("\r\n");
+        }
+        ret.push_str(&format!("{}: {metadata_total} {bytes} ({:.1}%)\r\n", tr(Msg::MetadataTotal), self.percent(metadata_total)));
+        ret.push_str(&format!("{}: {} {bytes} ({:.1}%)\r\n", tr(Msg::ImageData), self.image_data, self.percent(self.image_data)));
+        let other = self.total - metadata_total - self.image_data;
+        ret.push_str(&format!("{}: {other} {bytes} ({:.1}%)\r\n", tr(Msg::OtherData), self.percent(other)));
+        ret.push_str(&format!("{}: {} {bytes}\r\n", tr(Msg::FileSize), self.total));
+        if self.image_data > 0 {
+            ret.push_str(&format!("{}: {:.1}%\r\n", tr(Msg::MetadataOverhead), metadata_total as f64 * 100.0 / self.image_data as f64));
+        }
+        ret
+    }
+}
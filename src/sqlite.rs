@@ -0,0 +1,269 @@
+// Windows 10 以降に入っている winsqlite3.dll を使う SQLite の最小限のラッパー
+// SQLite を同梱しないので、DLL がない環境では索引を使う機能だけがエラーになる
+
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::path::Path;
+use std::sync::OnceLock;
+use windows::{
+    core::*,
+    Win32::System::LibraryLoader::*,
+};
+
+const SQLITE_OK: c_int = 0;
+const SQLITE_ROW: c_int = 100;
+const SQLITE_DONE: c_int = 101;
+const SQLITE_INTEGER: c_int = 1;
+const SQLITE_FLOAT: c_int = 2;
+const SQLITE_NULL: c_int = 5;
+const SQLITE_OPEN_READWRITE: c_int = 0x2;
+const SQLITE_OPEN_CREATE: c_int = 0x4;
+// 渡した文字列は SQLite 側で複製させる
+const SQLITE_TRANSIENT: isize = -1;
+
+// ほかのスレッドが書き込み中のときに待つ時間
+const BUSY_TIMEOUT_MS: c_int = 5000;
+
+type Db = *mut c_void;
+type Stmt = *mut c_void;
+
+// 関数の形は sqlite3.h に合わせてある
+type OpenFn = extern "system" fn(*const c_char, *mut Db, c_int, *const c_char) -> c_int;
+type DbFn = extern "system" fn(Db) -> c_int;
+type ErrmsgFn = extern "system" fn(Db) -> *const c_char;
+type BusyTimeoutFn = extern "system" fn(Db, c_int) -> c_int;
+type PrepareFn = extern "system" fn(Db, *const c_char, c_int, *mut Stmt, *mut *const c_char) -> c_int;
+type StmtFn = extern "system" fn(Stmt) -> c_int;
+// 引数や列の番号を取るもの
+type StmtIndexFn = extern "system" fn(Stmt, c_int) -> c_int;
+type BindInt64Fn = extern "system" fn(Stmt, c_int, i64) -> c_int;
+type BindDoubleFn = extern "system" fn(Stmt, c_int, f64) -> c_int;
+type BindTextFn = extern "system" fn(Stmt, c_int, *const c_char, c_int, isize) -> c_int;
+type ColumnInt64Fn = extern "system" fn(Stmt, c_int) -> i64;
+type ColumnDoubleFn = extern "system" fn(Stmt, c_int) -> f64;
+type ColumnTextFn = extern "system" fn(Stmt, c_int) -> *const u8;
+
+struct Api {
+    open_v2: OpenFn,
+    close: DbFn,
+    errmsg: ErrmsgFn,
+    busy_timeout: BusyTimeoutFn,
+    prepare_v2: PrepareFn,
+    finalize: StmtFn,
+    step: StmtFn,
+    bind_int64: BindInt64Fn,
+    bind_double: BindDoubleFn,
+    bind_text: BindTextFn,
+    bind_null: StmtIndexFn,
+    column_count: StmtFn,
+    column_type: StmtIndexFn,
+    column_int64: ColumnInt64Fn,
+    column_double: ColumnDoubleFn,
+    column_text: ColumnTextFn,
+    column_bytes: StmtIndexFn,
+}
+
+fn load_api() -> Option<Api> {
+    let module = unsafe { LoadLibraryW(w!("winsqlite3.dll")) }.ok()?;
+    macro_rules! get {
+        ($name:literal, $type:ty) => {
+            unsafe { std::mem::transmute::<unsafe extern "system" fn() -> isize, $type>(GetProcAddress(module, s!($name))?) }
+        };
+    }
+    Some(Api {
+        open_v2: get!("sqlite3_open_v2", OpenFn),
+        close: get!("sqlite3_close", DbFn),
+        errmsg: get!("sqlite3_errmsg", ErrmsgFn),
+        busy_timeout: get!("sqlite3_busy_timeout", BusyTimeoutFn),
+        prepare_v2: get!("sqlite3_prepare_v2", PrepareFn),
+        finalize: get!("sqlite3_finalize", StmtFn),
+        step: get!("sqlite3_step", StmtFn),
+        bind_int64: get!("sqlite3_bind_int64", BindInt64Fn),
+        bind_double: get!("sqlite3_bind_double", BindDoubleFn),
+        bind_text: get!("sqlite3_bind_text", BindTextFn),
+        bind_null: get!("sqlite3_bind_null", StmtIndexFn),
+        column_count: get!("sqlite3_column_count", StmtFn),
+        column_type: get!("sqlite3_column_type", StmtIndexFn),
+        column_int64: get!("sqlite3_column_int64", ColumnInt64Fn),
+        column_double: get!("sqlite3_column_double", ColumnDoubleFn),
+        column_text: get!("sqlite3_column_text", ColumnTextFn),
+        column_bytes: get!("sqlite3_column_bytes", StmtIndexFn),
+    })
+}
+
+fn api() -> anyhow::Result<&'static Api> {
+    static API: OnceLock<Option<Api>> = OnceLock::new();
+    API.get_or_init(load_api).as_ref().ok_or_else(|| anyhow::anyhow!("winsqlite3.dll is not available"))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+}
+
+impl Value {
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Integer(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Text(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Value {
+        Value::Integer(v)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Value {
+        Value::Real(v)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Value {
+        Value::Text(v.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Value {
+        Value::Text(v)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Value {
+        v.map_or(Value::Null, Into::into)
+    }
+}
+
+pub struct Connection {
+    api: &'static Api,
+    db: Db,
+}
+
+impl std::fmt::Debug for Connection {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Connection").finish_non_exhaustive()
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        (self.api.close)(self.db);
+    }
+}
+
+// prepare したものは途中でエラーになっても必ず finalize する
+struct Statement<'a> {
+    connection: &'a Connection,
+    stmt: Stmt,
+}
+
+impl Drop for Statement<'_> {
+    fn drop(&mut self) {
+        (self.connection.api.finalize)(self.stmt);
+    }
+}
+
+impl Connection {
+    // なければ作る
+    pub fn open(path: &Path) -> anyhow::Result<Connection> {
+        let api = api()?;
+        let filename = CString::new(path.to_string_lossy().as_bytes())?;
+        let mut db = std::ptr::null_mut();
+        let ret = (api.open_v2)(filename.as_ptr(), &mut db, SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE, std::ptr::null());
+        // 失敗しても db が返ることがあるので、いったん Connection にして閉じさせる
+        let connection = Connection { api, db };
+        if ret != SQLITE_OK {
+            anyhow::bail!("cannot open {}: {}", path.display(), connection.error_message());
+        }
+        (api.busy_timeout)(db, BUSY_TIMEOUT_MS);
+        Ok(connection)
+    }
+
+    fn error_message(&self) -> String {
+        if self.db.is_null() {
+            return "out of memory".to_owned();
+        }
+        unsafe { CStr::from_ptr((self.api.errmsg)(self.db)) }.to_string_lossy().into_owned()
+    }
+
+    fn check(&self, ret: c_int) -> anyhow::Result<()> {
+        anyhow::ensure!(ret == SQLITE_OK, "SQLite error: {}", self.error_message());
+        Ok(())
+    }
+
+    fn prepare(&self, sql: &str, params: &[Value]) -> anyhow::Result<Statement<'_>> {
+        let sql = CString::new(sql)?;
+        let mut stmt = std::ptr::null_mut();
+        self.check((self.api.prepare_v2)(self.db, sql.as_ptr(), -1, &mut stmt, std::ptr::null_mut()))?;
+        let statement = Statement { connection: self, stmt };
+        for (i, param) in params.iter().enumerate() {
+            let index = i as c_int + 1;
+            let ret = match param {
+                Value::Null => (self.api.bind_null)(stmt, index),
+                Value::Integer(v) => (self.api.bind_int64)(stmt, index, *v),
+                Value::Real(v) => (self.api.bind_double)(stmt, index, *v),
+                Value::Text(v) => (self.api.bind_text)(stmt, index, v.as_ptr() as *const c_char, v.len() as c_int, SQLITE_TRANSIENT),
+            };
+            self.check(ret)?;
+        }
+        Ok(statement)
+    }
+
+    fn column(&self, stmt: Stmt, i: c_int) -> Value {
+        match (self.api.column_type)(stmt, i) {
+            SQLITE_NULL => Value::Null,
+            SQLITE_INTEGER => Value::Integer((self.api.column_int64)(stmt, i)),
+            SQLITE_FLOAT => Value::Real((self.api.column_double)(stmt, i)),
+            // SQLITE_TEXT。BLOB も文字列として読む (索引には入れていない)
+            _ => {
+                let text = (self.api.column_text)(stmt, i);
+                let len = (self.api.column_bytes)(stmt, i) as usize;
+                if text.is_null() {
+                    return Value::Null;
+                }
+                let bytes = unsafe { std::slice::from_raw_parts(text, len) };
+                Value::Text(String::from_utf8_lossy(bytes).into_owned())
+            }
+        }
+    }
+
+    // 結果の行は読み捨てる
+    pub fn execute(&self, sql: &str, params: &[Value]) -> anyhow::Result<()> {
+        let statement = self.prepare(sql, params)?;
+        loop {
+            match (self.api.step)(statement.stmt) {
+                SQLITE_ROW => continue,
+                SQLITE_DONE => return Ok(()),
+                _ => anyhow::bail!("SQLite error: {}", self.error_message()),
+            }
+        }
+    }
+
+    pub fn query(&self, sql: &str, params: &[Value]) -> anyhow::Result<Vec<Vec<Value>>> {
+        let statement = self.prepare(sql, params)?;
+        let columns = (self.api.column_count)(statement.stmt);
+        let mut rows = Vec::new();
+        loop {
+            match (self.api.step)(statement.stmt) {
+                SQLITE_ROW => rows.push((0..columns).map(|i| self.column(statement.stmt, i)).collect()),
+                SQLITE_DONE => return Ok(rows),
+                _ => anyhow::bail!("SQLite error: {}", self.error_message()),
+            }
+        }
+    }
+}
@@ -0,0 +1,185 @@
+// メタデータを取り除いたコピーを作る。画素データには手を付けない
+
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use crate::fsutil;
+use crate::jpeg;
+use crate::png_chunks::{self, PNG_SIGNATURE};
+
+// 表示に必要な PNG のチャンク。これ以外 (tEXt, zTXt, iTXt, eXIf, tIME, 独自チャンクなど) は捨てる
+const PNG_KEEP: &[&[u8; 4]] = &[
+    b"IHDR", b"PLTE", b"IDAT", b"IEND", b"tRNS", b"gAMA", b"cHRM", b"sRGB", b"iCCP",
+    b"sBIT", b"bKGD", b"acTL", b"fcTL", b"fdAT",
+];
+
+pub fn is_metadata_chunk(kind: &[u8; 4]) -> bool {
+    !PNG_KEEP.contains(&kind)
+}
+
+pub fn strip_png(file: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let chunks = png_chunks::parse_chunks(file)?;
+    let mut out = Vec::with_capacity(file.len());
+    out.extend_from_slice(PNG_SIGNATURE);
+    for chunk in chunks.iter().filter(|c| !is_metadata_chunk(&c.kind)) {
+        out.extend_from_slice(&file[chunk.range.clone()]);
+    }
+    Ok(out)
+}
+
+// JPEG は APPn (EXIF, XMP, IPTC など) と COM を捨てる。
+// ただし色の解釈に関わる JFIF (APP0), ICC (APP2), Adobe (APP14) は残す
+pub fn is_metadata_segment(file: &[u8], segment: &jpeg::Segment) -> bool {
+    let data = &file[segment.data.clone()];
+    match segment.marker {
+        0xe0 => !data.starts_with(b"JFIF\0"),
+        0xe2 => !data.starts_with(b"ICC_PROFILE\0"),
+        0xee => !data.starts_with(b"Adobe"),
+        0xe1..=0xef | jpeg::COM => true,
+        _ => false,
+    }
+}
+
+// JFIF の APP0 の "JFIF\0"、版、単位、解像度の長さ。この後ろにサムネイルの幅と高さ、画素が続く
+const JFIF_HEADER_LEN: usize = 12;
+
+// JFIF の APP0 に埋め込まれたサムネイルを取り除き、幅と高さを 0 にしたセグメント。サムネイルがなければ None
+fn strip_jfif_thumbnail(data: &[u8]) -> Option<Vec<u8>> {
+    if !data.starts_with(b"JFIF\0") || data.len() < JFIF_HEADER_LEN + 2 || data[JFIF_HEADER_LEN..] == [0, 0] {
+        return None;
+    }
+    let mut out = vec![0xff, 0xe0];
+    out.extend_from_slice(&(JFIF_HEADER_LEN as u16 + 4).to_be_bytes());
+    out.extend_from_slice(&data[..JFIF_HEADER_LEN]);
+    out.extend_from_slice(&[0, 0]);
+    Some(out)
+}
+
+pub fn strip_jpeg(file: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let segments = jpeg::parse_segments(file)?;
+    let mut out = Vec::with_capacity(file.len());
+    for segment in &segments {
+        if is_metadata_segment(file, segment) {
+            continue;
+        }
+        match strip_jfif_thumbnail(&file[segment.data.clone()]).filter(|_| segment.marker == 0xe0) {
+            Some(app0) => out.extend_from_slice(&app0),
+            None => out.extend_from_slice(&file[segment.range.clone()]),
+        }
+    }
+    Ok(out)
+}
+
+// <name>_clean.<ext>。n が 2 以上なら <name>_clean (n).<ext>。拡張子がなければ . も付けない
+pub fn clean_copy_path(path: &Path, n: u32) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(if n > 1 { format!("_clean ({n})") } else { "_clean".to_owned() });
+    if let Some(ext) = path.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+    path.with_file_name(name)
+}
+
+// <name>_clean.<ext> を書き出してそのパスを返す。同じ名前のファイルがあれば上書きせずに番号を付ける
+pub fn save_clean_copy(path: &Path) -> anyhow::Result<PathBuf> {
+    let file = fsutil::read_shared(path)?;
+    let stripped = if file.starts_with(PNG_SIGNATURE) {
+        strip_png(&file)?
+    } else if jpeg::is_jpeg(&file) {
+        strip_jpeg(&file)?
+    } else {
+        anyhow::bail!("unsupported file format: {}", path.display())
+    };
+    for n in 1.. {
+        let out_path = clean_copy_path(path, n);
+        match OpenOptions::new().write(true).create_new(true).open(fsutil::long_path(&out_path)) {
+            Ok(mut out) => {
+                out.write_all(&stripped)?;
+                return Ok(out_path);
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    unreachable!()
+}
+
+// フォルダーをまとめて処理するときに選べる、取り除くものの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    // PNG のテキストチャンク、JPEG のコメントと APP13 (IPTC)
+    Text,
+    Exif,
+    Xmp,
+    Icc,
+}
+
+impl Category {
+    pub const ALL: [Category; 4] = [Category::Text, Category::Exif, Category::Xmp, Category::Icc];
+}
+
+// ImageMagick は EXIF などを "Raw profile type exif" のようなキーワードのテキストチャンクに入れる
+fn raw_profile_category(keyword: &[u8]) -> Option<Category> {
+    let lower = keyword.to_ascii_lowercase();
+    match lower.strip_prefix(b"raw profile type ")? {
+        b"exif" | b"app1" => Some(Category::Exif),
+        b"xmp" => Some(Category::Xmp),
+        b"icc" | b"icm" => Some(Category::Icc),
+        _ => None,
+    }
+}
+
+fn png_category(kind: &[u8; 4], data: &[u8]) -> Option<Category> {
+    let keyword = data.split(|&b| b == 0).next().unwrap_or_default();
+    match kind {
+        b"eXIf" => Some(Category::Exif),
+        b"iCCP" => Some(Category::Icc),
+        b"iTXt" if keyword == b"XML:com.adobe.xmp" => Some(Category::Xmp),
+        b"tEXt" | b"zTXt" | b"iTXt" => Some(raw_profile_category(keyword).unwrap_or(Category::Text)),
+        _ => None,
+    }
+}
+
+fn jpeg_category(data: &[u8], marker: u8) -> Option<Category> {
+    match marker {
+        jpeg::APP1 if data.starts_with(b"Exif\0") => Some(Category::Exif),
+        // 標準の XMP (http://ns.adobe.com/xap/1.0/) と拡張 XMP (http://ns.adobe.com/xmp/extension/)
+        jpeg::APP1 if data.starts_with(b"http://ns.adobe.com/") => Some(Category::Xmp),
+        0xe2 if data.starts_with(b"ICC_PROFILE\0") => Some(Category::Icc),
+        jpeg::APP13 | jpeg::COM => Some(Category::Text),
+        _ => None,
+    }
+}
+
+// 選んだ種類だけを取り除く。取り除いたものがあった種類も返す
+pub fn strip_categories(file: &[u8], categories: &[Category]) -> anyhow::Result<(Vec<u8>, Vec<Category>)> {
+    let mut removed = Vec::new();
+    let mut out = Vec::with_capacity(file.len());
+    let mut keep = |category: Option<Category>| match category.filter(|c| categories.contains(c)) {
+        Some(category) => {
+            if !removed.contains(&category) {
+                removed.push(category);
+            }
+            false
+        }
+        None => true,
+    };
+    if file.starts_with(PNG_SIGNATURE) {
+        out.extend_from_slice(PNG_SIGNATURE);
+        for chunk in png_chunks::parse_chunks(file)? {
+            if keep(png_category(&chunk.kind, &file[chunk.data.clone()])) {
+                out.extend_from_slice(&file[chunk.range.clone()]);
+            }
+        }
+    } else if jpeg::is_jpeg(file) {
+        for segment in jpeg::parse_segments(file)? {
+            if keep(jpeg_category(&file[segment.data.clone()], segment.marker)) {
+                out.extend_from_slice(&file[segment.range.clone()]);
+            }
+        }
+    } else {
+        anyhow::bail!("unsupported file format");
+    }
+    Ok((out, removed))
+}
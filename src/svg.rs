@@ -0,0 +1,167 @@
+// SVG の <title>、<desc>、<metadata> の中の RDF (Inkscape が書く Creative Commons の情報など) と大きさを読む
+
+use crate::xml::{self, Event, Reader};
+
+#[derive(Debug, Default)]
+pub struct SvgInfo {
+    // px に換算した大きさ。単位が % などで換算できなければ viewBox の大きさ
+    pub width: Option<f64>,
+    pub height: Option<f64>,
+    pub entries: Vec<(String, String)>,
+}
+
+// 単位を px に換算する (CSS と同じく 1in = 96px)
+fn length(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let unit_start = value.find(|c: char| c.is_ascii_alphabetic() || c == '%').unwrap_or(value.len());
+    let number: f64 = value[..unit_start].trim().parse().ok()?;
+    let scale = match &value[unit_start..] {
+        "" | "px" => 1.0,
+        "pt" => 96.0 / 72.0,
+        "pc" => 16.0,
+        "mm" => 96.0 / 25.4,
+        "cm" => 96.0 / 2.54,
+        "in" => 96.0,
+        _ => return None,
+    };
+    Some(number * scale)
+}
+
+// "min-x min-y width height" (カンマ区切りでもよい) の幅と高さ
+fn view_box_size(value: &str) -> Option<(f64, f64)> {
+    let numbers: Vec<f64> = value.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|s| !s.is_empty())
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .ok()?;
+    match numbers[..] {
+        [_, _, width, height] => Some((width, height)),
+        _ => None,
+    }
+}
+
+// RDF の入れ物にあたる要素。型を表すノード (cc:Work, cc:Agent など) は大文字で始まる
+fn is_container(name: &str) -> bool {
+    let prefix = name.split_once(':').map(|(prefix, _)| prefix);
+    matches!(prefix, Some("rdf" | "x")) || xml::local_name(name).starts_with(|c: char| c.is_ascii_uppercase())
+}
+
+// 名前空間の宣言や RDF の構文のための属性
+fn is_syntax_attribute(name: &str) -> bool {
+    name == "xmlns" || ["xmlns:", "rdf:", "xml:"].iter().any(|prefix| name.starts_with(prefix))
+}
+
+// 同じキーの値 (dc:subject の rdf:li など) はまとめる
+fn add_entry(entries: &mut Vec<(String, Vec<String>)>, key: &str, value: &str) {
+    let value = value.trim();
+    if value.is_empty() {
+        return;
+    }
+    match entries.iter_mut().find(|(k, _)| k == key) {
+        Some((_, values)) => values.push(value.to_owned()),
+        None => entries.push((key.to_owned(), vec![value.to_owned()])),
+    }
+}
+
+// <metadata> の中の要素。property は値のキーにする名前 (入れ物なら None)
+struct Element {
+    property: Option<String>,
+    text: String,
+}
+
+// 外側から見て最初のプロパティ (dc:creator/cc:Agent/dc:title なら dc:creator)
+fn outer_property(elements: &[Element]) -> Option<&str> {
+    elements.iter().find_map(|element| element.property.as_deref())
+}
+
+// 最初の要素が svg でなければ None
+pub fn parse(data: &[u8]) -> Option<anyhow::Result<SvgInfo>> {
+    let data = data.strip_prefix(b"\xef\xbb\xbf").unwrap_or(data);
+    if !data.trim_ascii_start().starts_with(b"<") {
+        return None;
+    }
+    let text = std::str::from_utf8(data).ok()?;
+    let mut reader = Reader::new(text);
+    let attributes = loop {
+        match reader.next_event() {
+            Ok(Some(Event::Start { name, attributes })) if xml::local_name(name) == "svg" => break attributes,
+            Ok(Some(Event::Text(_))) => continue,
+            _ => return None,
+        }
+    };
+    Some(read(reader, &attributes))
+}
+
+fn read(mut reader: Reader, attributes: &[(&str, String)]) -> anyhow::Result<SvgInfo> {
+    let attribute = |key: &str| attributes.iter().find(|(k, _)| *k == key).map(|(_, v)| v.as_str());
+    let view_box = attribute("viewBox").and_then(view_box_size);
+    let mut info = SvgInfo {
+        width: attribute("width").and_then(length).or(view_box.map(|size| size.0)),
+        height: attribute("height").and_then(length).or(view_box.map(|size| size.1)),
+        entries: Vec::new(),
+    };
+    let mut entries = Vec::new();
+    // 単位付きの大きさは換算前の値も出す
+    for key in ["width", "height", "viewBox"] {
+        if let Some(value) = attribute(key).filter(|value| key == "viewBox" || value.trim().parse::<f64>().is_err()) {
+            add_entry(&mut entries, key, value);
+        }
+    }
+    // svg 直下の <title> と <desc> (最初のもの)
+    let mut caption: Option<(&str, String)> = None;
+    let mut metadata: Option<Vec<Element>> = None;
+    while let Some(event) = reader.next_event()? {
+        let depth = reader.depth();
+        match (event, &mut metadata) {
+            (Event::Start { name, .. }, None) if depth == 2 && xml::local_name(name) == "metadata" => metadata = Some(Vec::new()),
+            (Event::Start { name, .. }, None) if depth == 2 && matches!(xml::local_name(name), "title" | "desc") => {
+                let key = xml::local_name(name);
+                if entries.iter().all(|(k, _)| k != key) {
+                    caption = Some((key, String::new()));
+                }
+            }
+            (Event::Text(text), None) => {
+                if let Some((_, caption)) = &mut caption {
+                    caption.push_str(&text);
+                }
+            }
+            (Event::End { .. }, None) if depth == 1 => {
+                if let Some((key, text)) = caption.take() {
+                    add_entry(&mut entries, key, &text);
+                }
+            }
+            (Event::Start { name, attributes }, Some(elements)) => {
+                let property = (!is_container(name)).then(|| name.to_owned());
+                elements.push(Element { property, text: String::new() });
+                for (key, value) in attributes.iter().filter(|(key, _)| !is_syntax_attribute(key) || *key == "rdf:resource") {
+                    // <cc:license rdf:resource="..."/> や <rdf:Description dc:title="..."> の形
+                    let outer = outer_property(elements);
+                    match (outer, *key) {
+                        (Some(outer), _) => add_entry(&mut entries, outer, value),
+                        (None, "rdf:resource") => {}
+                        (None, key) => add_entry(&mut entries, key, value),
+                    }
+                }
+            }
+            (Event::Text(text), Some(elements)) => {
+                if let Some(element) = elements.last_mut() {
+                    element.text.push_str(&text);
+                }
+            }
+            (Event::End { .. }, Some(elements)) => {
+                if depth == 1 {
+                    metadata = None;
+                    continue;
+                }
+                let Some(element) = elements.pop() else { continue };
+                let key = outer_property(elements).or(element.property.as_deref());
+                if let Some(key) = key {
+                    add_entry(&mut entries, key, &element.text);
+                }
+            }
+            _ => {}
+        }
+    }
+    info.entries = entries.into_iter().map(|(key, values)| (key, values.join(", "))).collect();
+    Ok(info)
+}
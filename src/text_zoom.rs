@@ -0,0 +1,60 @@
+// 表示欄の文字の拡大縮小。フォントはそのままで、リッチエディットの表示倍率 (EM_SETZOOM) を変える
+// リッチエディットは自分でも Ctrl+ホイールで拡大するが、倍率を知るためにここで受け取って親に知らせる
+
+use std::cell::Cell;
+use windows::Win32::{
+    Foundation::*,
+    System::SystemServices::MK_CONTROL,
+    UI::{
+        Controls::RichEdit::EM_SETZOOM,
+        Shell::{DefSubclassProc, RemoveWindowSubclass, SetWindowSubclass},
+        WindowsAndMessaging::*,
+    },
+};
+
+// 選べる倍率 (%)
+const STEPS: [u32; 12] = [50, 67, 75, 90, 100, 110, 125, 150, 175, 200, 250, 300];
+pub const DEFAULT: u32 = 100;
+
+const SUBCLASS_ID: usize = 2;
+
+thread_local! {
+    // タッチパッドは細かい量で送ってくるので、WHEEL_DELTA たまるごとに 1 段動かす
+    static WHEEL: Cell<i32> = const { Cell::new(0) };
+}
+
+// 今の倍率から steps 段 (負なら縮小) 動かした倍率
+pub fn step(percent: u32, steps: i32) -> u32 {
+    let index = STEPS.iter().position(|&p| p >= percent).unwrap_or(STEPS.len() - 1) as i32;
+    STEPS[(index + steps).clamp(0, STEPS.len() as i32 - 1) as usize]
+}
+
+pub fn apply(hedit: HWND, percent: u32) {
+    // 分子と分母が 0 なら倍率を元に戻す
+    let (numerator, denominator) = if percent == DEFAULT { (0, 0) } else { (percent, DEFAULT) };
+    unsafe { SendMessageW(hedit, EM_SETZOOM, WPARAM(numerator as usize), LPARAM(denominator as isize)) };
+}
+
+// Ctrl+ホイールを、親ウィンドウへの command (上なら zoom_in、下なら zoom_out) に変える
+pub fn attach(hedit: HWND, zoom_in: u32, zoom_out: u32) {
+    let commands = (zoom_in as usize) | ((zoom_out as usize) << 16);
+    unsafe { SetWindowSubclass(hedit, Some(edit_subclass_proc), SUBCLASS_ID, commands) };
+}
+
+extern "system" fn edit_subclass_proc(hwnd: HWND, message: u32, wparam: WPARAM, lparam: LPARAM, _id: usize, commands: usize) -> LRESULT {
+    if message == WM_NCDESTROY {
+        unsafe { RemoveWindowSubclass(hwnd, Some(edit_subclass_proc), SUBCLASS_ID) };
+    } else if message == WM_MOUSEWHEEL && (wparam.0 & 0xffff) as u32 & MK_CONTROL.0 != 0 {
+        let delta = (wparam.0 >> 16) as u16 as i16 as i32;
+        let total = WHEEL.with(|wheel| wheel.get()) + delta;
+        let steps = total / WHEEL_DELTA as i32;
+        WHEEL.with(|wheel| wheel.set(total - steps * WHEEL_DELTA as i32));
+        let command = if steps > 0 { commands & 0xffff } else { commands >> 16 };
+        let parent = unsafe { GetParent(hwnd) };
+        for _ in 0..steps.abs() {
+            unsafe { PostMessageW(parent, WM_COMMAND, WPARAM(command), LPARAM(0)) };
+        }
+        return LRESULT::default();
+    }
+    unsafe { DefSubclassProc(hwnd, message, wparam, lparam) }
+}
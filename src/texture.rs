@@ -0,0 +1,336 @@
+// ゲームなどで使うテクスチャ (DDS, KTX, KTX 2) のヘッダーを読む。画素はデコードしない
+
+use crate::i18n::{tr, Msg};
+
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+const KTX1_IDENTIFIER: &[u8; 12] = b"\xabKTX 11\xbb\r\n\x1a\n";
+const KTX2_IDENTIFIER: &[u8; 12] = b"\xabKTX 20\xbb\r\n\x1a\n";
+
+// DDS_PIXELFORMAT の dwFlags
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_LUMINANCE: u32 = 0x20000;
+// DDS_HEADER の dwCaps2
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_VOLUME: u32 = 0x20_0000;
+// DDS_HEADER_DXT10 の miscFlag
+const DDS_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+const DDS_DIMENSION_TEXTURE3D: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureKind {
+    Texture2d,
+    Cube,
+    Volume,
+}
+
+#[derive(Debug, Clone)]
+pub struct TextureInfo {
+    // "DDS", "KTX", "KTX 2"
+    pub container: &'static str,
+    // "BC7_UNORM_SRGB (DXGI 99)" など
+    pub pixel_format: String,
+    pub width: u32,
+    pub height: u32,
+    // ボリュームテクスチャでなければ 1
+    pub depth: u32,
+    pub mip_levels: u32,
+    // 配列でなければ 1
+    pub array_size: u32,
+    pub kind: TextureKind,
+    // KTX 2 の超圧縮 (Basis Universal など)
+    pub supercompression: Option<String>,
+    // KTX の key/value データ (KTXwriter, KTXorientation など)
+    pub key_values: Vec<(String, String)>,
+}
+
+// テクスチャの形式でなければ None
+pub fn parse(data: &[u8]) -> Option<anyhow::Result<TextureInfo>> {
+    if data.starts_with(DDS_MAGIC) {
+        Some(parse_dds(data).ok_or_else(|| anyhow::anyhow!("DDS header is truncated")))
+    } else if data.starts_with(KTX1_IDENTIFIER) {
+        Some(parse_ktx1(data).ok_or_else(|| anyhow::anyhow!("KTX header is truncated")))
+    } else if data.starts_with(KTX2_IDENTIFIER) {
+        Some(parse_ktx2(data).ok_or_else(|| anyhow::anyhow!("KTX 2 header is truncated")))
+    } else {
+        None
+    }
+}
+
+fn u32_le(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().unwrap()))
+}
+
+// 4 文字の FourCC。D3DFORMAT の数値が入っていることもある
+fn four_cc(value: u32) -> String {
+    let bytes = value.to_le_bytes();
+    if bytes.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+        String::from_utf8_lossy(&bytes).trim_end().to_owned()
+    } else {
+        let name = match value {
+            36 => "A16B16G16R16",
+            111 => "R16F",
+            112 => "G16R16F",
+            113 => "A16B16G16R16F",
+            114 => "R32F",
+            115 => "G32R32F",
+            116 => "A32B32G32R32F",
+            _ => "D3DFMT",
+        };
+        format!("{name} ({value})")
+    }
+}
+
+fn dxgi_format_name(format: u32) -> &'static str {
+    match format {
+        2 => "R32G32B32A32_FLOAT",
+        10 => "R16G16B16A16_FLOAT",
+        11 => "R16G16B16A16_UNORM",
+        24 => "R10G10B10A2_UNORM",
+        26 => "R11G11B10_FLOAT",
+        28 => "R8G8B8A8_UNORM",
+        29 => "R8G8B8A8_UNORM_SRGB",
+        41 => "R32_FLOAT",
+        49 => "R8G8_UNORM",
+        54 => "R16_FLOAT",
+        56 => "R16_UNORM",
+        61 => "R8_UNORM",
+        67 => "R9G9B9E5_SHAREDEXP",
+        71 => "BC1_UNORM",
+        72 => "BC1_UNORM_SRGB",
+        74 => "BC2_UNORM",
+        75 => "BC2_UNORM_SRGB",
+        77 => "BC3_UNORM",
+        78 => "BC3_UNORM_SRGB",
+        80 => "BC4_UNORM",
+        81 => "BC4_SNORM",
+        83 => "BC5_UNORM",
+        84 => "BC5_SNORM",
+        87 => "B8G8R8A8_UNORM",
+        88 => "B8G8R8X8_UNORM",
+        91 => "B8G8R8A8_UNORM_SRGB",
+        95 => "BC6H_UF16",
+        96 => "BC6H_SF16",
+        98 => "BC7_UNORM",
+        99 => "BC7_UNORM_SRGB",
+        _ => "DXGI_FORMAT",
+    }
+}
+
+// DDS_HEADER (124 バイト) の後に、FourCC が "DX10" なら DDS_HEADER_DXT10 (20 バイト) が続く
+fn parse_dds(data: &[u8]) -> Option<TextureInfo> {
+    let header = data.get(4..128)?;
+    let field = |offset: usize| u32_le(header, offset);
+    let (height, width, depth, mip_count) = (field(8)?, field(12)?, field(20)?, field(24)?);
+    let (pf_flags, four_cc_value, bit_count) = (field(76)?, field(80)?, field(84)?);
+    let caps2 = field(108)?;
+    let mut kind = if caps2 & DDSCAPS2_CUBEMAP != 0 {
+        TextureKind::Cube
+    } else if caps2 & DDSCAPS2_VOLUME != 0 {
+        TextureKind::Volume
+    } else {
+        TextureKind::Texture2d
+    };
+    let mut array_size = 1;
+    let pixel_format = if pf_flags & DDPF_FOURCC != 0 && &four_cc_value.to_le_bytes() == b"DX10" {
+        let dx10 = data.get(128..148)?;
+        let (format, dimension, misc, size) = (u32_le(dx10, 0)?, u32_le(dx10, 4)?, u32_le(dx10, 8)?, u32_le(dx10, 12)?);
+        if misc & DDS_RESOURCE_MISC_TEXTURECUBE != 0 {
+            kind = TextureKind::Cube;
+        } else if dimension == DDS_DIMENSION_TEXTURE3D {
+            kind = TextureKind::Volume;
+        }
+        array_size = size.max(1);
+        format!("{} (DXGI {format})", dxgi_format_name(format))
+    } else if pf_flags & DDPF_FOURCC != 0 {
+        four_cc(four_cc_value)
+    } else {
+        let masks = [field(88)?, field(92)?, field(96)?, field(100)?];
+        let name = if pf_flags & DDPF_LUMINANCE != 0 {
+            "Luminance"
+        } else if pf_flags & DDPF_RGB != 0 && pf_flags & DDPF_ALPHAPIXELS != 0 {
+            "RGBA"
+        } else if pf_flags & DDPF_RGB != 0 {
+            "RGB"
+        } else {
+            "Alpha"
+        };
+        format!("{name} {bit_count} bit ({:08x} {:08x} {:08x} {:08x})", masks[0], masks[1], masks[2], masks[3])
+    };
+    Some(TextureInfo {
+        container: "DDS",
+        pixel_format,
+        width,
+        height,
+        depth: if kind == TextureKind::Volume { depth.max(1) } else { 1 },
+        mip_levels: mip_count.max(1),
+        array_size,
+        kind,
+        supercompression: None,
+        key_values: Vec::new(),
+    })
+}
+
+fn gl_format_name(format: u32) -> &'static str {
+    match format {
+        0x8051 => "GL_RGB8",
+        0x8058 => "GL_RGBA8",
+        0x8C41 => "GL_SRGB8",
+        0x8C43 => "GL_SRGB8_ALPHA8",
+        0x881A => "GL_RGBA16F",
+        0x8814 => "GL_RGBA32F",
+        0x83F0 => "GL_COMPRESSED_RGB_S3TC_DXT1",
+        0x83F1 => "GL_COMPRESSED_RGBA_S3TC_DXT1",
+        0x83F2 => "GL_COMPRESSED_RGBA_S3TC_DXT3",
+        0x83F3 => "GL_COMPRESSED_RGBA_S3TC_DXT5",
+        0x8E8C => "GL_COMPRESSED_RGBA_BPTC_UNORM",
+        0x8E8D => "GL_COMPRESSED_SRGB_ALPHA_BPTC_UNORM",
+        0x8D64 => "GL_ETC1_RGB8_OES",
+        0x9274 => "GL_COMPRESSED_RGB8_ETC2",
+        0x9278 => "GL_COMPRESSED_RGBA8_ETC2_EAC",
+        0x93B0 => "GL_COMPRESSED_RGBA_ASTC_4x4",
+        0x93D0 => "GL_COMPRESSED_SRGB8_ALPHA8_ASTC_4x4",
+        _ => "glInternalFormat",
+    }
+}
+
+fn vk_format_name(format: u32) -> &'static str {
+    match format {
+        // Basis Universal などで超圧縮されているときは UNDEFINED になる
+        0 => "VK_FORMAT_UNDEFINED",
+        23 => "VK_FORMAT_R8G8B8_UNORM",
+        29 => "VK_FORMAT_R8G8B8_SRGB",
+        37 => "VK_FORMAT_R8G8B8A8_UNORM",
+        43 => "VK_FORMAT_R8G8B8A8_SRGB",
+        97 => "VK_FORMAT_R16G16B16A16_SFLOAT",
+        109 => "VK_FORMAT_R32G32B32A32_SFLOAT",
+        131 => "VK_FORMAT_BC1_RGB_UNORM_BLOCK",
+        133 => "VK_FORMAT_BC1_RGBA_UNORM_BLOCK",
+        137 => "VK_FORMAT_BC3_UNORM_BLOCK",
+        139 => "VK_FORMAT_BC4_UNORM_BLOCK",
+        141 => "VK_FORMAT_BC5_UNORM_BLOCK",
+        143 => "VK_FORMAT_BC6H_UFLOAT_BLOCK",
+        145 => "VK_FORMAT_BC7_UNORM_BLOCK",
+        146 => "VK_FORMAT_BC7_SRGB_BLOCK",
+        147 => "VK_FORMAT_ETC2_R8G8B8_UNORM_BLOCK",
+        151 => "VK_FORMAT_ETC2_R8G8B8A8_UNORM_BLOCK",
+        157 => "VK_FORMAT_ASTC_4x4_UNORM_BLOCK",
+        158 => "VK_FORMAT_ASTC_4x4_SRGB_BLOCK",
+        _ => "VkFormat",
+    }
+}
+
+// 値はふつう NUL で終わる UTF-8 の文字列。そうでなければ 16 進数で表示する
+fn key_values(data: &[u8], read_u32: impl Fn(&[u8], usize) -> Option<u32>) -> Vec<(String, String)> {
+    let mut ret = Vec::new();
+    let mut offset = 0;
+    while let Some(size) = read_u32(data, offset) {
+        let Some(entry) = data.get(offset + 4..(offset + 4).saturating_add(size as usize)) else { break };
+        if let Some(nul) = entry.iter().position(|&b| b == 0) {
+            let key = String::from_utf8_lossy(&entry[..nul]).into_owned();
+            let value = &entry[nul + 1..];
+            let text = value.strip_suffix(b"\0").unwrap_or(value);
+            let value = match std::str::from_utf8(text) {
+                Ok(text) if !text.contains('\0') => text.to_owned(),
+                _ => value.iter().map(|b| format!("{b:02x}")).collect::<Vec<_>>().join(" "),
+            };
+            ret.push((key, value));
+        }
+        // 次の項目は 4 バイト境界から始まる
+        offset += 4 + (size as usize).div_ceil(4) * 4;
+    }
+    ret
+}
+
+// 識別子 (12 バイト) の後に、エンディアンを表す 0x04030201 と 12 個の u32 (glType から bytesOfKeyValueData まで) が続く
+fn parse_ktx1(data: &[u8]) -> Option<TextureInfo> {
+    let big_endian = u32_le(data, 12)? == 0x0102_0304;
+    let read_u32 = move |data: &[u8], offset: usize| {
+        let bytes: [u8; 4] = data.get(offset..offset + 4)?.try_into().unwrap();
+        Some(if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    };
+    let field = |index: usize| read_u32(data, 16 + index * 4);
+    let internal_format = field(3)?;
+    let (width, height, depth) = (field(5)?, field(6)?, field(7)?);
+    let (array_elements, faces, mip_levels, kv_length) = (field(8)?, field(9)?, field(10)?, field(11)?);
+    let kv = data.get(64..64 + kv_length as usize)?;
+    Some(TextureInfo {
+        container: "KTX",
+        pixel_format: format!("{} (0x{internal_format:04X})", gl_format_name(internal_format)),
+        width,
+        height: height.max(1),
+        depth: depth.max(1),
+        mip_levels: mip_levels.max(1),
+        array_size: array_elements.max(1),
+        kind: texture_kind(faces, depth),
+        supercompression: None,
+        key_values: key_values(kv, read_u32),
+    })
+}
+
+fn texture_kind(faces: u32, depth: u32) -> TextureKind {
+    if faces == 6 {
+        TextureKind::Cube
+    } else if depth > 1 {
+        TextureKind::Volume
+    } else {
+        TextureKind::Texture2d
+    }
+}
+
+// 識別子の後にヘッダー (u32 x 9) と、DFD, KVD, SGD の位置 (索引) が続く。すべてリトルエンディアン
+fn parse_ktx2(data: &[u8]) -> Option<TextureInfo> {
+    let field = |index: usize| u32_le(data, 12 + index * 4);
+    let vk_format = field(0)?;
+    let (width, height, depth) = (field(2)?, field(3)?, field(4)?);
+    let (layers, faces, levels, scheme) = (field(5)?, field(6)?, field(7)?, field(8)?);
+    let (kv_offset, kv_length) = (field(11)? as usize, field(12)? as usize);
+    let kv = data.get(kv_offset..kv_offset.checked_add(kv_length)?)?;
+    let supercompression = match scheme {
+        0 => None,
+        1 => Some("BasisLZ".to_owned()),
+        2 => Some("Zstandard".to_owned()),
+        3 => Some("ZLIB".to_owned()),
+        _ => Some(format!("? ({scheme})")),
+    };
+    Some(TextureInfo {
+        container: "KTX 2",
+        pixel_format: format!("{} ({vk_format})", vk_format_name(vk_format)),
+        width,
+        height: height.max(1),
+        depth: depth.max(1),
+        // 0 はミップマップを読み込むときに作るという意味
+        mip_levels: levels.max(1),
+        array_size: layers.max(1),
+        kind: texture_kind(faces, depth),
+        supercompression,
+        key_values: key_values(kv, u32_le),
+    })
+}
+
+impl TextureInfo {
+    pub fn format(&self) -> String {
+        let mut ret = format!("{}: {} ({})\r\n", tr(Msg::TexturePixelFormat), self.pixel_format, self.container);
+        let kind = match self.kind {
+            TextureKind::Texture2d => "2D",
+            TextureKind::Cube => tr(Msg::TextureCube),
+            TextureKind::Volume => tr(Msg::TextureVolume),
+        };
+        ret.push_str(&format!("{}: {kind}\r\n", tr(Msg::TextureKind)));
+        if self.kind == TextureKind::Volume {
+            ret.push_str(&format!("{}: {}\r\n", tr(Msg::TextureDepth), self.depth));
+        }
+        if self.array_size > 1 {
+            ret.push_str(&format!("{}: {}\r\n", tr(Msg::TextureArraySize), self.array_size));
+        }
+        ret.push_str(&format!("{}: {}\r\n", tr(Msg::TextureMipLevels), self.mip_levels));
+        if let Some(scheme) = &self.supercompression {
+            ret.push_str(&format!("{}: {scheme}\r\n", tr(Msg::TextureSupercompression)));
+        }
+        for (key, value) in &self.key_values {
+            ret.push_str(&format!("{key}: {value}\r\n"));
+        }
+        ret
+    }
+}
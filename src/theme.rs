@@ -0,0 +1,71 @@
+// Windows 11 のウィンドウの見た目。タイトルバーの背景 (Mica など) と角の丸めを DWM に指定する
+// それより前の Windows はこれらの属性を知らないので失敗するが、そのときは何もしない
+// 表示欄の色付けに使う色もここで決める (ハイコントラストではシステムの色だけを使う)
+
+use std::ffi::c_void;
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use windows::Win32::{
+    Foundation::*,
+    Graphics::Dwm::*,
+    UI::{Accessibility::*, WindowsAndMessaging::*},
+};
+use crate::settings::Backdrop;
+
+// 見出しなどをアクセントカラーで色付けするか (設定の accent_colors)
+static ACCENT_COLORS: AtomicBool = AtomicBool::new(false);
+
+// windows クレートのこの版にはまだない (Windows 11 22H2 以降)
+const DWMWA_SYSTEMBACKDROP_TYPE: DWMWINDOWATTRIBUTE = DWMWINDOWATTRIBUTE(38);
+
+fn backdrop_type(backdrop: Backdrop) -> i32 {
+    match backdrop {
+        Backdrop::None => 1,
+        Backdrop::Mica => 2,
+        Backdrop::Acrylic => 3,
+        Backdrop::Tabbed => 4,
+    }
+}
+
+pub fn apply(hwnd: HWND, backdrop: Backdrop) {
+    let corner = DWMWCP_ROUND;
+    let _ = unsafe { DwmSetWindowAttribute(hwnd, DWMWA_WINDOW_CORNER_PREFERENCE, &corner as *const _ as *const c_void, mem::size_of_val(&corner) as u32) };
+    let backdrop = backdrop_type(backdrop);
+    let _ = unsafe { DwmSetWindowAttribute(hwnd, DWMWA_SYSTEMBACKDROP_TYPE, &backdrop as *const _ as *const c_void, mem::size_of_val(&backdrop) as u32) };
+}
+
+pub fn set_accent_colors(enabled: bool) {
+    ACCENT_COLORS.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_high_contrast() -> bool {
+    let mut hc = HIGHCONTRASTW { cbSize: mem::size_of::<HIGHCONTRASTW>() as u32, ..Default::default() };
+    let ok = unsafe { SystemParametersInfoW(SPI_GETHIGHCONTRAST, hc.cbSize, Some(&mut hc as *mut _ as *mut c_void), SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0)) };
+    ok.as_bool() && hc.dwFlags.0 & HCF_HIGHCONTRASTON.0 != 0
+}
+
+// DWM の色 (0xAARRGGBB) を白い背景で読める濃さにした COLORREF
+fn accent_color() -> Option<COLORREF> {
+    let (mut argb, mut opaque) = (0u32, BOOL::default());
+    unsafe { DwmGetColorizationColor(&mut argb, &mut opaque) }.ok()?;
+    let [b, g, r, _] = argb.to_le_bytes();
+    let luminance = 0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64;
+    let scale = if luminance > 128.0 { 128.0 / luminance } else { 1.0 };
+    let [r, g, b] = [r, g, b].map(|c| (c as f64 * scale) as u32);
+    Some(COLORREF(r | g << 8 | b << 16))
+}
+
+// 表示欄の色。None はシステムの文字色 (CFE_AUTOCOLOR) のまま
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Palette {
+    pub high_contrast: bool,
+    pub accent: Option<COLORREF>,
+}
+
+impl Palette {
+    pub fn current() -> Palette {
+        let high_contrast = is_high_contrast();
+        let accent = if high_contrast || !ACCENT_COLORS.load(Ordering::Relaxed) { None } else { accent_color() };
+        Palette { high_contrast, accent }
+    }
+}
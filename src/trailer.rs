@@ -0,0 +1,115 @@
+// PNG の IEND や JPEG の EOI の後ろに付け足されたデータ
+// ビューアーは読まずに捨てるので、アーカイブを隠したり、ツールが消し残したりする場所になる
+
+use std::ops::Range;
+use crate::hex;
+use crate::i18n::{tr, Msg};
+
+// 16 進で表示するバイト数
+const PREVIEW_LEN: usize = 64;
+
+// 先頭の数バイトで形式がわかるもの
+const SIGNATURES: [(&[u8], &str); 16] = [
+    (b"PK\x03\x04", "ZIP"),
+    (b"PK\x05\x06", "ZIP"),
+    (b"Rar!\x1a\x07", "RAR"),
+    (b"7z\xbc\xaf\x27\x1c", "7z"),
+    (b"\x1f\x8b", "gzip"),
+    (b"BZh", "bzip2"),
+    (b"\xfd7zXZ\0", "xz"),
+    (b"%PDF", "PDF"),
+    (b"\x89PNG\r\n\x1a\n", "PNG"),
+    (b"\xff\xd8\xff", "JPEG"),
+    (b"GIF8", "GIF"),
+    (b"RIFF", "RIFF"),
+    (b"OggS", "Ogg"),
+    (b"MZ", "EXE"),
+    (b"MotionPhoto_Data", "Samsung Motion Photo"),
+    (b"SQLite format 3\0", "SQLite"),
+];
+// 途中から始まっていても探すもの (隠したアーカイブ)
+const EMBEDDED: [&str; 4] = ["ZIP", "RAR", "7z", "PDF"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Guess {
+    // offset は付け足した部分の先頭からの位置
+    Format { name: &'static str, offset: usize },
+    Zeros,
+    Text,
+    Unknown,
+}
+
+#[derive(Debug, Clone)]
+pub struct Trailer {
+    // ファイルの中の位置
+    pub range: Range<usize>,
+    pub guess: Guess,
+    // 先頭の PREVIEW_LEN バイト
+    head: Vec<u8>,
+}
+
+fn signature(data: &[u8]) -> Option<&'static str> {
+    if data.get(4..8) == Some(b"ftyp") {
+        return Some("MP4");
+    }
+    SIGNATURES.iter().find(|(magic, _)| data.starts_with(magic)).map(|(_, name)| *name)
+}
+
+fn guess(data: &[u8]) -> Guess {
+    if let Some(name) = signature(data) {
+        return Guess::Format { name, offset: 0 };
+    }
+    if data.iter().all(|&b| b == 0) {
+        return Guess::Zeros;
+    }
+    let embedded = SIGNATURES.iter()
+        .filter(|(_, name)| EMBEDDED.contains(name))
+        .filter_map(|(magic, name)| Some((data.windows(magic.len()).position(|w| w == *magic)?, *name)))
+        .min();
+    if let Some((offset, name)) = embedded {
+        return Guess::Format { name, offset };
+    }
+    // 改行とタブ以外の制御文字がない UTF-8 ならテキスト
+    match std::str::from_utf8(data) {
+        Ok(text) if !text.chars().any(|c| c.is_control() && !matches!(c, '\r' | '\n' | '\t')) => Guess::Text,
+        _ => Guess::Unknown,
+    }
+}
+
+// end は画像として意味のある部分の終わり (IEND チャンクや EOI マーカーの直後)
+pub fn find(data: &[u8], end: usize) -> Option<Trailer> {
+    let trailer = data.get(end..).filter(|trailer| !trailer.is_empty())?;
+    Some(Trailer {
+        range: end..data.len(),
+        guess: guess(trailer),
+        head: trailer[..trailer.len().min(PREVIEW_LEN)].to_vec(),
+    })
+}
+
+impl Trailer {
+    // 位置 (0x...) の部分の ret の中の範囲も返す。表示欄ではそこを 16 進表示へのリンクにする
+    pub fn format(&self) -> (String, Range<usize>) {
+        let mut ret = format!("【{}】\r\n", tr(Msg::TrailingData));
+        ret.push_str(&format!("{}: {} {} (", tr(Msg::FileSize), self.range.len(), tr(Msg::StatusBytes)));
+        let start = ret.len();
+        ret.push_str(&format!("0x{:x}", self.range.start));
+        let offset = start..ret.len();
+        ret.push_str(" -)\r\n");
+        let guess = match &self.guess {
+            Guess::Format { name, offset: 0 } => name.to_string(),
+            Guess::Format { name, offset } => format!("{name} (+0x{offset:x})"),
+            Guess::Zeros => tr(Msg::TrailingZeros).to_owned(),
+            Guess::Text => tr(Msg::TrailingText).to_owned(),
+            Guess::Unknown => tr(Msg::TrailingUnknown).to_owned(),
+        };
+        ret.push_str(&format!("{}: {guess}\r\n", tr(Msg::TrailingGuess)));
+        for (i, row) in self.head.chunks(hex::ROW_LEN).enumerate() {
+            ret.push_str(&hex::row(self.range.start + i * hex::ROW_LEN, row));
+        }
+        if self.range.len() > self.head.len() {
+            ret.push_str("…\r\n");
+        }
+        ret.push_str(&format!("⚠ {}\r\n\r\n", tr(Msg::TrailingDataFound)));
+        (ret, offset)
+    }
+}
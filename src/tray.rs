@@ -0,0 +1,61 @@
+// 通知領域 (タスクトレイ) のアイコン
+
+use std::mem;
+use windows::{
+    Win32::{
+        Foundation::*,
+        UI::{Shell::*, WindowsAndMessaging::*},
+    },
+};
+
+// アイコンがクリックされたときに届くメッセージ。lparam にマウスのメッセージが入る
+pub const WM_APP_TRAY: u32 = WM_APP + 3;
+
+fn notify_data(hwnd: HWND) -> NOTIFYICONDATAW {
+    NOTIFYICONDATAW {
+        cbSize: mem::size_of::<NOTIFYICONDATAW>() as u32,
+        hWnd: hwnd,
+        uID: 1,
+        ..Default::default()
+    }
+}
+
+// 末尾の NUL の分を残して切り詰める
+fn copy_text(dst: &mut [u16], text: &str) {
+    let len = dst.len() - 1;
+    for (dst, src) in dst.iter_mut().zip(text.encode_utf16().take(len)) {
+        *dst = src;
+    }
+}
+
+fn icon_data(hwnd: HWND, icon: HICON, tip: &str) -> NOTIFYICONDATAW {
+    let mut data = notify_data(hwnd);
+    data.uFlags = NIF_MESSAGE | NIF_ICON | NIF_TIP;
+    data.uCallbackMessage = WM_APP_TRAY;
+    data.hIcon = if icon.is_invalid() {
+        unsafe { LoadIconW(None, IDI_APPLICATION) }.unwrap_or_default()
+    } else {
+        icon
+    };
+    copy_text(&mut data.szTip, tip);
+    data
+}
+
+pub fn add(hwnd: HWND, icon: HICON, tip: &str) {
+    unsafe { Shell_NotifyIconW(NIM_ADD, &icon_data(hwnd, icon, tip)) };
+}
+
+// 通知 (Windows 10 以降ではトーストとして表示される) を出す。アイコンがなければ add も行う
+// クリックされると WM_APP_TRAY の lparam に NIN_BALLOONUSERCLICK、閉じられると NIN_BALLOONTIMEOUT が届く
+pub fn notify(hwnd: HWND, icon: HICON, tip: &str, title: &str, text: &str, add: bool) {
+    let mut data = icon_data(hwnd, icon, tip);
+    data.uFlags |= NIF_INFO;
+    data.dwInfoFlags = NOTIFY_ICON_INFOTIP_FLAGS(NIIF_INFO.0 | NIIF_RESPECT_QUIET_TIME.0);
+    copy_text(&mut data.szInfoTitle, title);
+    copy_text(&mut data.szInfo, text);
+    unsafe { Shell_NotifyIconW(if add { NIM_ADD } else { NIM_MODIFY }, &data) };
+}
+
+pub fn remove(hwnd: HWND) {
+    unsafe { Shell_NotifyIconW(NIM_DELETE, &notify_data(hwnd)) };
+}
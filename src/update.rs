@@ -0,0 +1,78 @@
+// GitHub のリリースから新しいバージョンが出ていないかを調べる (メニューから、または設定で起動時に)
+
+use std::time::Duration;
+use windows::Win32::{
+    Foundation::*,
+    UI::WindowsAndMessaging::*,
+};
+use crate::download;
+use crate::json;
+
+// wparam: メニューから調べたときは 1, lparam: Box<anyhow::Result<Option<Release>>> のポインタ
+pub const WM_APP_UPDATE_DONE: u32 = WM_APP + 8;
+
+const API_URL: &str = "https://api.github.com/repos/iori-komatsu/MetaView/releases/latest";
+const RELEASES_URL: &str = "https://github.com/iori-komatsu/MetaView/releases/";
+// 起動時にも調べるので長くは待たない
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug)]
+pub struct Release {
+    pub version: String,
+    // ダウンロードページ
+    pub url: String,
+}
+
+pub fn start(hwnd: HWND, manual: bool) {
+    std::thread::spawn(move || {
+        let result = check();
+        let result = Box::into_raw(Box::new(result));
+        let posted = unsafe { PostMessageW(hwnd, WM_APP_UPDATE_DONE, WPARAM(manual as usize), LPARAM(result as isize)) };
+        if !posted.as_bool() {
+            drop(unsafe { Box::from_raw(result) });
+        }
+    });
+}
+
+// WM_APP_UPDATE_DONE の lparam から結果を取り出す
+pub unsafe fn take_result(lparam: LPARAM) -> anyhow::Result<Option<Release>> {
+    *Box::from_raw(lparam.0 as *mut anyhow::Result<Option<Release>>)
+}
+
+// "v1.2.3" や "1.2" を数の並びにする。"-beta" などの後ろは無視する
+fn parse_version(s: &str) -> Option<Vec<u32>> {
+    let s = s.trim().trim_start_matches(['v', 'V']);
+    let s = s.split(['-', '+']).next()?;
+    s.split('.').map(|part| part.parse().ok()).collect()
+}
+
+fn is_newer(latest: &str, current: &str) -> bool {
+    let (Some(mut latest), Some(mut current)) = (parse_version(latest), parse_version(current)) else {
+        return false;
+    };
+    // 1.2 と 1.2.0 を同じに扱う
+    let len = latest.len().max(current.len());
+    latest.resize(len, 0);
+    current.resize(len, 0);
+    latest > current
+}
+
+// 新しいバージョンがあれば返す
+fn check() -> anyhow::Result<Option<Release>> {
+    let (status, body) = download::fetch_with_timeout(API_URL, TIMEOUT, |_, _| {})?;
+    anyhow::ensure!(status == 200, "HTTP {status}");
+    let release = json::parse(&String::from_utf8_lossy(&body))
+        .ok_or_else(|| anyhow::anyhow!("invalid response from GitHub"))?;
+    let version = release.get("tag_name").and_then(json::Value::as_str)
+        .ok_or_else(|| anyhow::anyhow!("invalid response from GitHub"))?;
+    if !is_newer(version, CURRENT_VERSION) {
+        return Ok(None);
+    }
+    // 応答に変なものが入っていても、GitHub 以外のページは開かない
+    let url = release.get("html_url").and_then(json::Value::as_str)
+        .filter(|url| url.starts_with(RELEASES_URL))
+        .unwrap_or(RELEASES_URL);
+    Ok(Some(Release { version: version.trim_start_matches(['v', 'V']).to_owned(), url: url.to_owned() }))
+}
@@ -0,0 +1,124 @@
+// フォルダーを監視して、画像が増えたり書き換わったりしたらいちばん新しいものを開く
+// 生成ツールの出力フォルダーを指定しておくと、最後に生成した画像のプロンプトを常に表示できる
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+use windows::{
+    core::*,
+    Win32::{
+        Foundation::*,
+        Storage::FileSystem::*,
+        System::Threading::*,
+        UI::WindowsAndMessaging::*,
+    },
+};
+use crate::batch;
+use crate::fsutil;
+
+// lparam: Box<PathBuf> のポインタ
+pub const WM_APP_WATCH_NEWEST: u32 = WM_APP + 9;
+
+// 書き込みが続いている間は待ち、この時間変化がなくなってから開く
+const DEBOUNCE: Duration = Duration::from_millis(500);
+// windows クレートでは INFINITE が別の feature にある
+const INFINITE: u32 = u32::MAX;
+
+#[derive(Debug)]
+pub struct Watcher {
+    pub folder: PathBuf,
+    stop: HANDLE,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watcher {
+    pub fn start(hwnd: HWND, folder: PathBuf) -> anyhow::Result<Watcher> {
+        let filter = FILE_NOTIFY_CHANGE_FILE_NAME | FILE_NOTIFY_CHANGE_LAST_WRITE | FILE_NOTIFY_CHANGE_SIZE;
+        let change = unsafe { FindFirstChangeNotificationW(&HSTRING::from(fsutil::long_path(&folder).as_os_str()), false, filter) }?;
+        let stop = match unsafe { CreateEventW(None, true, false, None) } {
+            Ok(stop) => stop,
+            Err(e) => {
+                unsafe { FindCloseChangeNotification(change) };
+                return Err(e.into());
+            }
+        };
+        let watched = folder.clone();
+        let thread = std::thread::spawn(move || {
+            watch(hwnd, &watched, change, stop);
+            unsafe { FindCloseChangeNotification(change) };
+        });
+        Ok(Watcher { folder, stop, thread: Some(thread) })
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        unsafe { SetEvent(self.stop) };
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        unsafe { CloseHandle(self.stop) };
+    }
+}
+
+// いちばん新しく書き込まれた画像 (サブフォルダーは見ない)
+fn newest_image(folder: &Path) -> Option<(PathBuf, SystemTime)> {
+    fs::read_dir(fsutil::long_path(folder)).ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| batch::is_image(&entry.path()))
+        .filter_map(|entry| Some((folder.join(entry.file_name()), entry.metadata().ok()?.modified().ok()?)))
+        .max_by_key(|(_, modified)| *modified)
+}
+
+// 書き込んでいる途中で開けないファイルは、UI のスレッドを止めないようにここで開けるまで待ってから送る
+fn post_newest(hwnd: HWND, path: PathBuf) {
+    fsutil::wait_until_readable(&path);
+    let path = Box::into_raw(Box::new(path));
+    let posted = unsafe { PostMessageW(hwnd, WM_APP_WATCH_NEWEST, WPARAM(0), LPARAM(path as isize)) };
+    if !posted.as_bool() {
+        drop(unsafe { Box::from_raw(path) });
+    }
+}
+
+// WM_APP_WATCH_NEWEST の lparam から結果を取り出す
+pub unsafe fn take_result(lparam: LPARAM) -> PathBuf {
+    *Box::from_raw(lparam.0 as *mut PathBuf)
+}
+
+// 変更の通知が来なくなるまで待つ。止めるように言われたら false
+fn wait_quiet(change: FindChangeNotificationHandle, stop: HANDLE) -> bool {
+    let handles = [stop, HANDLE(change.0)];
+    loop {
+        unsafe { FindNextChangeNotification(change) };
+        let ret = unsafe { WaitForMultipleObjects(&handles, false, DEBOUNCE.as_millis() as u32) };
+        if ret == WAIT_TIMEOUT {
+            return true;
+        }
+        if ret != WIN32_ERROR(WAIT_OBJECT_0.0 + 1) {
+            return false;
+        }
+    }
+}
+
+// 監視を始めたときにも、その時点でいちばん新しい画像を開く
+fn watch(hwnd: HWND, folder: &Path, change: FindChangeNotificationHandle, stop: HANDLE) {
+    let mut last = newest_image(folder);
+    if let Some((path, _)) = &last {
+        post_newest(hwnd, path.clone());
+    }
+    let handles = [stop, HANDLE(change.0)];
+    loop {
+        let ret = unsafe { WaitForMultipleObjects(&handles, false, INFINITE) };
+        if ret != WIN32_ERROR(WAIT_OBJECT_0.0 + 1) || !wait_quiet(change, stop) {
+            return;
+        }
+        let newest = newest_image(folder);
+        if newest != last {
+            if let Some((path, _)) = &newest {
+                post_newest(hwnd, path.clone());
+            }
+            last = newest;
+        }
+    }
+}
@@ -0,0 +1,148 @@
+// 画素に埋め込まれた見えない透かしの検出
+// invisible-watermark (imwatermark) の dwtDct 方式: YUV の U, V を Haar ウェーブレットで分解し、
+// 低域を 4x4 のブロックに分けて、DCT 係数の絶対値が最大のものを scale で割った余りに 1 ビットずつ入れる
+
+const BLOCK: usize = 4;
+// imwatermark の既定値 (Y には入れない)
+const SCALE: f64 = 36.0;
+
+// 生成ツールが入れることが分かっている透かし
+const KNOWN_WATERMARKS: &[(&str, &[u8])] = &[
+    ("Stable Diffusion XL", &[0xb3, 0xec, 0x90, 0x7b, 0xb1, 0x9e]),
+    ("Stable Diffusion 2", b"SDV2"),
+    ("Stable Diffusion 1", b"StableDiffusionV1"),
+];
+// 画像の劣化でいくらかのビットが変わっていても、この割合が一致すれば見つかったとみなす
+const MIN_MATCH_RATIO: f64 = 0.9;
+
+#[derive(Debug, Clone)]
+pub struct Watermark {
+    pub name: &'static str,
+    pub payload: &'static [u8],
+    pub matched_bits: usize,
+    pub total_bits: usize,
+}
+
+impl Watermark {
+    // 表示できる文字列ならそのまま、そうでなければ 16 進数で
+    pub fn payload_text(&self) -> String {
+        if self.payload.iter().all(|b| b.is_ascii_graphic()) {
+            String::from_utf8_lossy(self.payload).into_owned()
+        } else {
+            self.payload.iter().map(|b| format!("{b:02X}")).collect()
+        }
+    }
+}
+
+// Haar ウェーブレットの低域 (pywt.dwt2 の cA と同じく 2x2 の和の半分)
+fn haar_low(channel: &[f64], width: usize, height: usize) -> Vec<f64> {
+    let (w, h) = (width / 2, height / 2);
+    let mut low = vec![0.0; w * h];
+    for y in 0..h {
+        for x in 0..w {
+            let i = 2 * y * width + 2 * x;
+            low[y * w + x] = (channel[i] + channel[i + 1] + channel[i + width] + channel[i + width + 1]) / 2.0;
+        }
+    }
+    low
+}
+
+// 正規直交の 2 次元 DCT-II (OpenCV の cv2.dct と同じ)
+fn dct(block: &[f64; BLOCK * BLOCK]) -> [f64; BLOCK * BLOCK] {
+    let n = BLOCK as f64;
+    let coef = |k: usize, i: usize| {
+        let a = if k == 0 { (1.0 / n).sqrt() } else { (2.0 / n).sqrt() };
+        a * (std::f64::consts::PI * (2 * i + 1) as f64 * k as f64 / (2.0 * n)).cos()
+    };
+    let mut out = [0.0; BLOCK * BLOCK];
+    for u in 0..BLOCK {
+        for v in 0..BLOCK {
+            let mut sum = 0.0;
+            for y in 0..BLOCK {
+                for x in 0..BLOCK {
+                    sum += coef(u, y) * coef(v, x) * block[y * BLOCK + x];
+                }
+            }
+            out[u * BLOCK + v] = sum;
+        }
+    }
+    out
+}
+
+// ブロックごとの判定 (0 か 1)。ブロックの順番が何ビット目かに対応する
+fn block_bits(low: &[f64], width: usize, height: usize) -> Vec<f64> {
+    let mut bits = Vec::new();
+    for by in 0..height / BLOCK {
+        for bx in 0..width / BLOCK {
+            let mut block = [0.0; BLOCK * BLOCK];
+            for y in 0..BLOCK {
+                for x in 0..BLOCK {
+                    block[y * BLOCK + x] = low[(by * BLOCK + y) * width + bx * BLOCK + x];
+                }
+            }
+            let coefs = dct(&block);
+            // 直流成分は除いて、絶対値が最大の係数 (同じなら先のもの)
+            let mut pos = 1;
+            for i in 2..coefs.len() {
+                if coefs[i].abs() > coefs[pos].abs() {
+                    pos = i;
+                }
+            }
+            let bit = coefs[pos].abs() % SCALE > 0.5 * SCALE;
+            bits.push(if bit { 1.0 } else { 0.0 });
+        }
+    }
+    bits
+}
+
+// 上から下に並んだ 32bpp BGRA の画素から、U, V それぞれのブロックの判定を求める
+fn decode_blocks(pixels: &[u8], width: u32, height: u32) -> Vec<Vec<f64>> {
+    // imwatermark は 4 の倍数に切り詰めてから処理する
+    let (w, h) = (width as usize / 4 * 4, height as usize / 4 * 4);
+    let mut u = vec![0.0; w * h];
+    let mut v = vec![0.0; w * h];
+    for y in 0..h {
+        for x in 0..w {
+            let p = &pixels[(y * width as usize + x) * 4..];
+            let (b, g, r) = (p[0] as f64, p[1] as f64, p[2] as f64);
+            // OpenCV の COLOR_BGR2YUV (8 ビットに丸める)
+            let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+            u[y * w + x] = (0.492 * (b - luma) + 128.0).round().clamp(0.0, 255.0);
+            v[y * w + x] = (0.877 * (r - luma) + 128.0).round().clamp(0.0, 255.0);
+        }
+    }
+    [u, v].iter().map(|channel| block_bits(&haar_low(channel, w, h), w / 2, h / 2)).collect()
+}
+
+// ブロックを len ビットごとに繰り返したものとして平均を取る
+fn bits_of_length(blocks: &[Vec<f64>], len: usize) -> Vec<bool> {
+    let mut sums = vec![0.0; len];
+    let mut counts = vec![0usize; len];
+    for channel in blocks {
+        for (i, bit) in channel.iter().enumerate() {
+            sums[i % len] += bit;
+            counts[i % len] += 1;
+        }
+    }
+    sums.iter().zip(&counts).map(|(sum, &count)| count > 0 && sum / count as f64 * 255.0 > 127.0).collect()
+}
+
+pub fn detect(pixels: &[u8], width: u32, height: u32) -> Vec<Watermark> {
+    let blocks = decode_blocks(pixels, width, height);
+    let mut found = Vec::new();
+    for &(name, payload) in KNOWN_WATERMARKS {
+        let total_bits = payload.len() * 8;
+        // 全部のビットが 1 回ずつ入るだけの大きさがなければ判定しない
+        if blocks.iter().map(Vec::len).sum::<usize>() < total_bits {
+            continue;
+        }
+        let bits = bits_of_length(&blocks, total_bits);
+        let matched_bits = bits.iter().enumerate()
+            .filter(|&(i, &bit)| bit == (payload[i / 8] >> (7 - i % 8) & 1 == 1))
+            .count();
+        if matched_bits as f64 >= total_bits as f64 * MIN_MATCH_RATIO {
+            found.push(Watermark { name, payload, matched_bits, total_bits });
+        }
+    }
+    found
+}
@@ -0,0 +1,208 @@
+// 最小限のストリーミング XML リーダー (SVG のメタデータを読むのに使う)
+// 木を作らずに開始タグ、終了タグ、テキストを順に返す
+// DTD で宣言されたエンティティは展開しない。展開を繰り返して膨らませる入力 (billion laughs) でもメモリを使い切らない
+
+use std::borrow::Cow;
+
+// 入れ子が深すぎる入力で要素の名前を積みすぎないようにする
+const MAX_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event<'a> {
+    // 空要素 (<a/>) のときは続けて End も返す
+    Start { name: &'a str, attributes: Vec<(&'a str, String)> },
+    End { name: &'a str },
+    Text(Cow<'a, str>),
+}
+
+#[derive(Debug)]
+pub struct Reader<'a> {
+    text: &'a str,
+    pos: usize,
+    stack: Vec<&'a str>,
+    // 空要素の開始タグを返したあと、次に返す終了タグ
+    pending_end: Option<&'a str>,
+}
+
+// 名前空間の接頭辞を除いた名前
+pub fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+// 定義済みのエンティティと文字参照だけを展開する。それ以外の参照はそのまま残す
+pub fn unescape(text: &str) -> Cow<'_, str> {
+    if !text.contains('&') {
+        return Cow::Borrowed(text);
+    }
+    let mut ret = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        ret.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let Some(semicolon) = rest.char_indices().take(16).find(|&(_, c)| c == ';').map(|(i, _)| i) else {
+            ret.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let entity = &rest[1..semicolon];
+        let c = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X"))
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        };
+        match c {
+            Some(c) => {
+                ret.push(c);
+                rest = &rest[semicolon + 1..];
+            }
+            None => {
+                ret.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    ret.push_str(rest);
+    Cow::Owned(ret)
+}
+
+fn is_name_char(c: char) -> bool {
+    !c.is_whitespace() && !"/>=<\"'".contains(c)
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(text: &'a str) -> Reader<'a> {
+        let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+        Reader { text, pos: 0, stack: Vec::new(), pending_end: None }
+    }
+
+    // 開いている要素の数 (Start を返した直後はその要素も数える)
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    // until の直後まで読み飛ばす
+    fn skip_past(&mut self, until: &str) -> anyhow::Result<&'a str> {
+        let rest = self.rest();
+        let end = rest.find(until).ok_or_else(|| anyhow::anyhow!("unexpected end of XML"))?;
+        self.pos += end + until.len();
+        Ok(&rest[..end])
+    }
+
+    // <!DOCTYPE ...> は内部サブセット ([ ... ]) ごと読み飛ばす。中のエンティティ宣言は使わない
+    fn skip_doctype(&mut self) -> anyhow::Result<()> {
+        let mut quote = None;
+        let mut brackets = 0usize;
+        for (i, c) in self.rest().char_indices() {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '"' | '\'') => quote = Some(c),
+                (None, '[') => brackets += 1,
+                (None, ']') => brackets = brackets.saturating_sub(1),
+                (None, '>') if brackets == 0 => {
+                    self.pos += i + 1;
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+        anyhow::bail!("unexpected end of XML")
+    }
+
+    fn name(&mut self) -> &'a str {
+        let rest = self.rest();
+        let len = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn start_tag(&mut self) -> anyhow::Result<Event<'a>> {
+        self.pos += 1;
+        let name = self.name();
+        anyhow::ensure!(!name.is_empty(), "invalid XML tag");
+        let mut attributes = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            let empty = rest.starts_with("/>");
+            if empty || rest.starts_with('>') {
+                self.pos += if empty { 2 } else { 1 };
+                anyhow::ensure!(self.stack.len() < MAX_DEPTH, "XML is nested too deeply");
+                self.stack.push(name);
+                if empty {
+                    self.pending_end = Some(name);
+                }
+                break;
+            }
+            let key = self.name();
+            self.skip_whitespace();
+            anyhow::ensure!(!key.is_empty() && self.rest().starts_with('='), "invalid XML attribute");
+            self.pos += 1;
+            self.skip_whitespace();
+            let quote = self.rest().chars().next().filter(|&c| c == '"' || c == '\'')
+                .ok_or_else(|| anyhow::anyhow!("invalid XML attribute"))?;
+            self.pos += 1;
+            let value = self.skip_past(if quote == '"' { "\"" } else { "'" })?;
+            attributes.push((key, unescape(value).into_owned()));
+        }
+        Ok(Event::Start { name, attributes })
+    }
+
+    fn end_tag(&mut self) -> anyhow::Result<Event<'a>> {
+        self.pos += 2;
+        let name = self.name();
+        self.skip_past(">")?;
+        anyhow::ensure!(self.stack.pop() == Some(name), "mismatched XML end tag: {name}");
+        Ok(Event::End { name })
+    }
+
+    // 文書の終わりなら None
+    pub fn next_event(&mut self) -> anyhow::Result<Option<Event<'a>>> {
+        if let Some(name) = self.pending_end.take() {
+            self.stack.pop();
+            return Ok(Some(Event::End { name }));
+        }
+        loop {
+            let rest = self.rest();
+            if rest.is_empty() {
+                anyhow::ensure!(self.stack.is_empty(), "unexpected end of XML");
+                return Ok(None);
+            }
+            if !rest.starts_with('<') {
+                let len = rest.find('<').unwrap_or(rest.len());
+                self.pos += len;
+                return Ok(Some(Event::Text(unescape(&rest[..len]))));
+            }
+            if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<![CDATA[") {
+                self.pos += "<![CDATA[".len();
+                return Ok(Some(Event::Text(Cow::Borrowed(self.skip_past("]]>")?))));
+            } else if rest.starts_with("<!") {
+                self.skip_doctype()?;
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with("</") {
+                return self.end_tag().map(Some);
+            } else {
+                return self.start_tag().map(Some);
+            }
+        }
+    }
+}